@@ -0,0 +1,118 @@
+//! Compares `process_osm_file` on one worker thread with the full pool, on a
+//! synthetic extract written to a temp directory
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use riemap_backend::osm::{OsmProcessor, ProcessingOptions};
+use riemap_backend::pbf::{self, OsmData, OsmNode, OsmWay, PbfHeader};
+use std::path::Path;
+
+/// Nodes per side of the synthetic grid
+const GRID_SIDE: i64 = 500;
+
+fn tags(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+    pairs
+        .iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect()
+}
+
+/// A grid of nodes, every fourth one a tagged point, with a closed building
+/// on each grid cell of every other row and a road along every row
+fn synthetic_extract(path: &Path) {
+    let mut data = OsmData::default();
+    let id = |row: i64, col: i64| row * GRID_SIDE + col + 1;
+
+    for row in 0..GRID_SIDE {
+        for col in 0..GRID_SIDE {
+            let node_tags = if col % 4 == 0 {
+                tags(&[("amenity", "bench")])
+            } else {
+                Vec::new()
+            };
+            data.nodes.insert(
+                id(row, col),
+                OsmNode {
+                    id: id(row, col),
+                    version: 1,
+                    timestamp: 1_700_000_000_000,
+                    lat: 47.0 + row as f64 * 1e-4,
+                    lon: 9.0 + col as f64 * 1e-4,
+                    tags: node_tags,
+                },
+            );
+        }
+    }
+
+    let mut way_id = 0;
+    let mut add_way = |data: &mut OsmData, refs: Vec<i64>, way_tags: &[(&str, &str)]| {
+        way_id += 1;
+        data.ways.insert(
+            way_id,
+            OsmWay {
+                id: way_id,
+                version: 1,
+                timestamp: 1_700_000_000_000,
+                refs,
+                tags: tags(way_tags),
+            },
+        );
+    };
+    for row in (0..GRID_SIDE - 1).step_by(2) {
+        for col in 0..GRID_SIDE - 1 {
+            let ring = vec![
+                id(row, col),
+                id(row, col + 1),
+                id(row + 1, col + 1),
+                id(row + 1, col),
+                id(row, col),
+            ];
+            add_way(&mut data, ring, &[("building", "yes")]);
+        }
+    }
+    for row in 0..GRID_SIDE {
+        let refs = (0..GRID_SIDE).map(|col| id(row, col)).collect();
+        add_way(
+            &mut data,
+            refs,
+            &[("highway", "residential"), ("name", "Row")],
+        );
+    }
+
+    pbf::write_pbf(path, &data, &PbfHeader::default()).expect("write synthetic extract");
+}
+
+fn process(c: &mut Criterion) {
+    let dir = tempfile::tempdir().expect("temp dir");
+    let path = dir.path().join("synthetic.osm.pbf");
+    synthetic_extract(&path);
+
+    let runtime = tokio::runtime::Runtime::new().expect("tokio runtime");
+    let cores = std::thread::available_parallelism().map_or(1, |cores| cores.get());
+    let mut thread_counts = vec![1, cores];
+    thread_counts.dedup();
+
+    let mut group = c.benchmark_group("process_osm_file");
+    group.sample_size(10);
+    for (name, options) in [
+        ("all_checks", ProcessingOptions::default()),
+        ("aggregates_only", ProcessingOptions::aggregates_only()),
+    ] {
+        for &threads in &thread_counts {
+            let processor = OsmProcessor::new(dir.path().join("data"), dir.path().join("tmp"))
+                .expect("processor")
+                .with_processing_options(ProcessingOptions { threads, ..options });
+
+            group.bench_with_input(BenchmarkId::new(name, threads), &threads, |b, _| {
+                b.iter(|| {
+                    runtime
+                        .block_on(processor.process_osm_file(&path))
+                        .expect("process synthetic extract")
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, process);
+criterion_main!(benches);
@@ -0,0 +1,105 @@
+use axum::{
+    extract::{Request, State},
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::sync::Arc;
+use tracing::warn;
+
+use super::error::ApiError;
+
+/// Keys accepted by `require_api_key`; an empty set disables authentication
+#[derive(Clone, Default)]
+pub struct ApiKeys {
+    keys: Arc<Vec<String>>,
+}
+
+impl ApiKeys {
+    pub fn new(keys: impl IntoIterator<Item = String>) -> Self {
+        let keys = keys
+            .into_iter()
+            .map(|key| key.trim().to_string())
+            .filter(|key| !key.is_empty())
+            .collect();
+
+        Self {
+            keys: Arc::new(keys),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.keys.is_empty()
+    }
+
+    /// Check a presented key against every configured key without an early exit
+    fn accepts(&self, presented: &str) -> bool {
+        self.keys
+            .iter()
+            .fold(false, |found, key| found | constant_time_eq(key, presented))
+    }
+}
+
+/// Compare two strings in time that depends only on their lengths
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0u8, |diff, (x, y)| diff | (x ^ y))
+            == 0
+}
+
+/// Key presented as `Authorization: Bearer <key>` or `X-API-Key: <key>`
+fn presented_key(request: &Request) -> Option<&str> {
+    let headers = request.headers();
+
+    let bearer = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+
+    bearer
+        .or_else(|| {
+            headers
+                .get("x-api-key")
+                .and_then(|value| value.to_str().ok())
+        })
+        .map(str::trim)
+}
+
+/// Middleware answering `401` unless the request carries one of the configured keys
+pub async fn require_api_key(
+    State(keys): State<ApiKeys>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if !keys.is_enabled() {
+        return next.run(request).await;
+    }
+
+    match presented_key(&request) {
+        Some(key) if keys.accepts(key) => next.run(request).await,
+        presented => {
+            warn!(
+                "Rejected {} {}: {}",
+                request.method(),
+                request.uri(),
+                if presented.is_some() {
+                    "invalid API key"
+                } else {
+                    "missing API key"
+                }
+            );
+            (
+                [(header::WWW_AUTHENTICATE, "Bearer")],
+                ApiError::new(
+                    StatusCode::UNAUTHORIZED,
+                    "unauthorized",
+                    "A valid API key is required",
+                )
+                .with_detail("Send it as `Authorization: Bearer <key>` or `X-API-Key: <key>`"),
+            )
+                .into_response()
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, Weak};
+use tokio::sync::Semaphore;
+
+use crate::Result;
+
+/// Builds of files cached on disk for API requests, like bounding box extracts
+///
+/// Requests for the same cache file wait for the one build already under way
+/// instead of starting their own, and at most `max_concurrent` different
+/// files are built at once.
+#[derive(Clone)]
+pub struct CacheBuilds {
+    /// Cache path -> lock held while it is built; entries die with their last waiter
+    locks: Arc<Mutex<HashMap<PathBuf, Weak<tokio::sync::Mutex<()>>>>>,
+    permits: Arc<Semaphore>,
+}
+
+impl CacheBuilds {
+    pub fn new(max_concurrent: usize) -> Self {
+        Self {
+            locks: Arc::new(Mutex::new(HashMap::new())),
+            permits: Arc::new(Semaphore::new(max_concurrent.max(1))),
+        }
+    }
+
+    /// Run `build` to create `path` unless it exists; concurrent calls for the
+    /// same path run one build and find the file afterwards
+    ///
+    /// `build` must only make the file appear once it is complete (e.g. by
+    /// persisting a temp file), as readers do not take the lock.
+    pub async fn get_or_build<F, Fut>(&self, path: &Path, build: F) -> Result<()>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<()>>,
+    {
+        let lock = {
+            let mut locks = self.locks.lock().unwrap();
+            locks.retain(|_, lock| lock.strong_count() > 0);
+            match locks.get(path).and_then(Weak::upgrade) {
+                Some(lock) => lock,
+                None => {
+                    let lock = Arc::new(tokio::sync::Mutex::new(()));
+                    locks.insert(path.to_path_buf(), Arc::downgrade(&lock));
+                    lock
+                }
+            }
+        };
+
+        let _building = lock.lock().await;
+        if path.exists() {
+            return Ok(());
+        }
+        let _permit = self.permits.acquire().await?;
+        build().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn concurrent_requests_share_one_build() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("extract.osm.pbf");
+        let builds = CacheBuilds::new(2);
+        let runs = Arc::new(AtomicUsize::new(0));
+
+        let requests = (0..8).map(|_| {
+            let (builds, path, runs) = (builds.clone(), path.clone(), runs.clone());
+            tokio::spawn(async move {
+                builds
+                    .get_or_build(&path, || async {
+                        runs.fetch_add(1, Ordering::SeqCst);
+                        tokio::time::sleep(Duration::from_millis(50)).await;
+                        std::fs::write(&path, b"extract")?;
+                        Ok(())
+                    })
+                    .await
+            })
+        });
+        for request in futures::future::join_all(requests).await {
+            request.unwrap().unwrap();
+        }
+
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+        assert!(builds
+            .locks
+            .lock()
+            .unwrap()
+            .values()
+            .all(|lock| lock.strong_count() == 0));
+    }
+
+    #[tokio::test]
+    async fn builds_of_different_files_are_limited() {
+        let dir = tempfile::tempdir().unwrap();
+        let builds = CacheBuilds::new(2);
+        let (running, peak) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+
+        let requests = (0..6).map(|i| {
+            let path = dir.path().join(format!("extract-{}.osm.pbf", i));
+            let (builds, running, peak) = (builds.clone(), running.clone(), peak.clone());
+            tokio::spawn(async move {
+                builds
+                    .get_or_build(&path, || async {
+                        let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                        peak.fetch_max(now, Ordering::SeqCst);
+                        tokio::time::sleep(Duration::from_millis(20)).await;
+                        running.fetch_sub(1, Ordering::SeqCst);
+                        std::fs::write(&path, b"extract")?;
+                        Ok(())
+                    })
+                    .await
+            })
+        });
+        for request in futures::future::join_all(requests).await {
+            request.unwrap().unwrap();
+        }
+
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,148 @@
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+};
+use serde::Serialize;
+use tracing::error;
+use utoipa::ToSchema;
+
+use crate::RiemapError;
+
+/// Error returned by API handlers, rendered as `{ error, code, detail }` JSON
+///
+/// `error` is a human readable summary, `code` a stable machine readable
+/// identifier and `detail` the underlying cause, when there is one.
+#[derive(Debug)]
+pub struct ApiError {
+    status: StatusCode,
+    code: &'static str,
+    message: String,
+    detail: Option<String>,
+}
+
+/// JSON body of an error response
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ErrorBody {
+    /// Human readable summary
+    pub error: String,
+    /// Stable machine readable identifier, e.g. `not_found`
+    pub code: &'static str,
+    /// Underlying cause, when there is one
+    pub detail: Option<String>,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            code,
+            message: message.into(),
+            detail: None,
+        }
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::NOT_FOUND, "not_found", message)
+    }
+
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, "bad_request", message)
+    }
+
+    /// Attach the underlying cause
+    pub fn with_detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+
+    /// Replace the summary while keeping the status, code and detail
+    pub fn context(mut self, message: impl Into<String>) -> Self {
+        self.message = message.into();
+        self
+    }
+}
+
+/// Turn any handler-side failure into an `ApiError` with a summary of what failed
+pub trait ApiResultExt<T> {
+    fn api_context(self, message: impl FnOnce() -> String) -> Result<T, ApiError>;
+}
+
+impl<T, E: Into<ApiError>> ApiResultExt<T> for Result<T, E> {
+    fn api_context(self, message: impl FnOnce() -> String) -> Result<T, ApiError> {
+        self.map_err(|e| e.into().context(message()))
+    }
+}
+
+/// Map application errors to HTTP statuses; anything unrecognised is a 500
+impl From<anyhow::Error> for ApiError {
+    fn from(err: anyhow::Error) -> Self {
+        let (status, code, message) = match err.downcast_ref::<RiemapError>() {
+            Some(RiemapError::InvalidBoundingBox(_)) => (
+                StatusCode::BAD_REQUEST,
+                "invalid_bounding_box",
+                "Invalid bounding box",
+            ),
+            Some(RiemapError::Network(_)) => (
+                StatusCode::BAD_GATEWAY,
+                "upstream_error",
+                "Upstream request failed",
+            ),
+            Some(RiemapError::Config(_)) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "config_error",
+                "Server misconfigured",
+            ),
+            Some(RiemapError::Storage(_)) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "storage_error",
+                "Storage error",
+            ),
+            Some(RiemapError::OsmProcessing(_)) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "processing_error",
+                "OSM processing failed",
+            ),
+            Some(RiemapError::Quality(_)) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "quality_error",
+                "Quality analysis failed",
+            ),
+            None => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "internal_error",
+                "Internal server error",
+            ),
+        };
+
+        Self::new(status, code, message).with_detail(format!("{:#}", err))
+    }
+}
+
+impl From<std::io::Error> for ApiError {
+    fn from(err: std::io::Error) -> Self {
+        Self::from(anyhow::Error::from(err))
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        // Client errors are expected; only log what needs a look on our side
+        if self.status.is_server_error() {
+            error!(
+                "{}: {}",
+                self.message,
+                self.detail.as_deref().unwrap_or("no detail")
+            );
+        }
+
+        (
+            self.status,
+            Json(ErrorBody {
+                error: self.message,
+                code: self.code,
+                detail: self.detail,
+            }),
+        )
+            .into_response()
+    }
+}
@@ -1,114 +1,1115 @@
 use axum::{
-    extract::{Path, Query, State},
-    http::{header, StatusCode},
-    response::{IntoResponse, Json},
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Path, Query, State,
+    },
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Json, Response},
 };
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
 use serde_json::json;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use tokio::sync::broadcast;
 use tokio_util::io::ReaderStream;
-use tracing::error;
+use tracing::{error, warn};
+use utoipa::IntoParams;
 
-use crate::{models::*, storage::Storage};
+use super::{
+    error::{ApiError, ApiResultExt},
+    AppState, CompareQuery,
+};
+use crate::{jobs::JobQueue, models::*, osm::OsmProcessor, storage::Storage, temp_files::TempFile};
+
+/// Readiness check: verifies storage and disk, returning 503 when any check fails
+#[utoipa::path(
+    get,
+    path = "/api/health",
+    tag = "health",
+    responses(
+        (status = 200, description = "Storage and disk checks passed", content_type = "application/json"),
+        (status = 503, description = "At least one check failed", content_type = "application/json")
+    )
+)]
+pub async fn health_check(State(state): State<AppState>) -> impl IntoResponse {
+    let checks = state.storage.check_health(state.min_free_disk_bytes).await;
+    let healthy = checks.values().all(|check| check.ok);
+    if !healthy {
+        warn!("Health check failed: {:?}", checks);
+    }
+
+    let status = if healthy {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    (
+        status,
+        Json(json!({
+            "status": if healthy { "healthy" } else { "unhealthy" },
+            "checks": checks,
+            "timestamp": chrono::Utc::now().to_rfc3339(),
+            "version": env!("CARGO_PKG_VERSION")
+        })),
+    )
+}
 
-/// Health check endpoint
-pub async fn health_check() -> impl IntoResponse {
+/// Liveness check: only confirms the server is answering requests
+#[utoipa::path(
+    get,
+    path = "/api/health/live",
+    tag = "health",
+    responses((status = 200, description = "The server is answering requests", content_type = "application/json"))
+)]
+pub async fn liveness_check() -> impl IntoResponse {
     Json(json!({
-        "status": "healthy",
+        "status": "alive",
         "timestamp": chrono::Utc::now().to_rfc3339(),
         "version": env!("CARGO_PKG_VERSION")
     }))
 }
 
 /// Get all regions in hierarchical structure
-pub async fn get_regions(State(storage): State<Storage>) -> impl IntoResponse {
-    match storage.get_region_tree().await {
-        Ok(tree) => Json(tree).into_response(),
-        Err(e) => {
-            error!("Failed to get regions: {}", e);
-            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+#[utoipa::path(
+    get,
+    path = "/api/regions",
+    tag = "regions",
+    responses((status = 200, description = "The region hierarchy, one tree per root", body = [RegionTree]))
+)]
+pub async fn get_regions(State(storage): State<Storage>) -> Result<Response, ApiError> {
+    let tree = storage
+        .get_region_tree()
+        .await
+        .api_context(|| "Failed to get regions".to_string())?;
+    Ok(Json(tree.as_ref()).into_response())
+}
+
+/// Query parameters for regions changed since a point in time
+#[derive(serde::Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ChangedQuery {
+    /// RFC 3339 timestamp, e.g. `2024-06-01T00:00:00Z`
+    since: String,
+}
+
+/// Regions updated after `since`, oldest change first, for incremental sync
+///
+/// `updated_at` advances when a region's index entry changes or its data
+/// files are downloaded or pruned.
+#[utoipa::path(
+    get,
+    path = "/api/regions/changed",
+    tag = "regions",
+    params(ChangedQuery),
+    responses(
+        (status = 200, description = "Regions updated after `since`, possibly none", body = [Region]),
+        (status = 400, description = "Invalid timestamp", body = super::error::ErrorBody)
+    )
+)]
+pub async fn get_changed_regions(
+    Query(query): Query<ChangedQuery>,
+    State(storage): State<Storage>,
+) -> Result<Json<Vec<Region>>, ApiError> {
+    // An unencoded `+` in the offset arrives as a space
+    let since = DateTime::parse_from_rfc3339(&query.since.trim().replace(' ', "+"))
+        .map_err(|e| {
+            ApiError::bad_request("Invalid since, expected an RFC 3339 timestamp")
+                .with_detail(format!("got '{}': {}", query.since, e))
+        })?
+        .with_timezone(&Utc);
+
+    let mut regions: Vec<Region> = storage
+        .load_regions()
+        .await
+        .api_context(|| "Failed to load regions".to_string())?
+        .into_iter()
+        .filter(|region| region.updated_at > since)
+        .collect();
+    regions.sort_by(|a, b| {
+        a.updated_at
+            .cmp(&b.updated_at)
+            .then_with(|| a.id.cmp(&b.id))
+    });
+
+    Ok(Json(regions))
+}
+
+/// Stream all regions as newline-delimited JSON, one flattened region per line
+#[utoipa::path(
+    get,
+    path = "/api/regions/stream",
+    tag = "regions",
+    responses((status = 200, description = "One region per line", body = Region, content_type = "application/x-ndjson"))
+)]
+pub async fn stream_regions(State(storage): State<Storage>) -> Result<Response, ApiError> {
+    let regions = storage
+        .load_regions()
+        .await
+        .api_context(|| "Failed to stream regions".to_string())?;
+
+    // Serialize lazily so only one region's JSON is buffered at a time
+    let lines = futures::stream::iter(regions.into_iter().map(|region| {
+        serde_json::to_vec(&region).map(|mut line| {
+            line.push(b'\n');
+            line
+        })
+    }));
+
+    Ok((
+        [(
+            header::CONTENT_TYPE,
+            header::HeaderValue::from_static("application/x-ndjson"),
+        )],
+        axum::body::Body::from_stream(lines),
+    )
+        .into_response())
+}
+
+/// Columns of the region catalog CSV
+const REGION_CSV_COLUMNS: &[&str] = &[
+    "id",
+    "name",
+    "admin_level",
+    "parent_id",
+    "min_lon",
+    "min_lat",
+    "max_lon",
+    "max_lat",
+    "area_km2",
+    "population",
+    "iso3166_1",
+    "iso3166_2",
+    "has_data",
+];
+
+/// Stream all regions as CSV for spreadsheets, one row per region ordered by ID
+///
+/// Multiple ISO codes are joined with `;`; `has_data` tells whether a latest
+/// extract is held locally.
+#[utoipa::path(
+    get,
+    path = "/api/regions.csv",
+    tag = "regions",
+    responses((status = 200, description = "One row per region", content_type = "text/csv"))
+)]
+pub async fn export_regions_csv(State(storage): State<Storage>) -> Result<Response, ApiError> {
+    let mut regions = storage
+        .load_regions()
+        .await
+        .api_context(|| "Failed to export regions".to_string())?;
+    regions.sort_by(|a, b| a.id.cmp(&b.id));
+
+    let header_row = futures::stream::once(async { Ok(csv_row(REGION_CSV_COLUMNS)) });
+    // Rows are built as the body is sent, so only one is buffered at a time
+    let rows = futures::stream::iter(regions).then(move |region| {
+        let storage = storage.clone();
+        async move {
+            let has_data = storage.get_file_path(&region.id, "latest").await?.is_some();
+            let optional = |value: Option<String>| value.unwrap_or_default();
+            let codes = |codes: &Option<Vec<String>>| optional(codes.as_ref().map(|c| c.join(";")));
+            let bbox = &region.bounding_box;
+
+            Ok::<_, anyhow::Error>(csv_row(&[
+                region.id.as_str(),
+                region.name.as_str(),
+                &format!("{:?}", region.admin_level),
+                region.parent_id.as_deref().unwrap_or_default(),
+                &bbox.min_lon.to_string(),
+                &bbox.min_lat.to_string(),
+                &bbox.max_lon.to_string(),
+                &bbox.max_lat.to_string(),
+                &optional(region.area_km2.map(|area| format!("{:.2}", area))),
+                &optional(region.population.map(|population| population.to_string())),
+                &codes(&region.iso3166_1),
+                &codes(&region.iso3166_2),
+                &has_data.to_string(),
+            ]))
+        }
+    });
+
+    Ok((
+        [
+            (
+                header::CONTENT_TYPE,
+                header::HeaderValue::from_static("text/csv; charset=utf-8"),
+            ),
+            (
+                header::CONTENT_DISPOSITION,
+                header::HeaderValue::from_static("attachment; filename=\"regions.csv\""),
+            ),
+        ],
+        axum::body::Body::from_stream(header_row.chain(rows)),
+    )
+        .into_response())
+}
+
+/// One CSV line, quoting fields that contain separators, quotes or line breaks
+fn csv_row(fields: &[&str]) -> String {
+    let mut row = String::new();
+    for (i, field) in fields.iter().enumerate() {
+        if i > 0 {
+            row.push(',');
+        }
+        if field.contains([',', '"', '\n', '\r']) {
+            row.push('"');
+            row.push_str(&field.replace('"', "\"\""));
+            row.push('"');
+        } else {
+            row.push_str(field);
         }
     }
+    row.push_str("\r\n");
+    row
+}
+
+/// Drop the cached region tree so the next request rebuilds it
+pub async fn refresh_region_tree(State(storage): State<Storage>) -> impl IntoResponse {
+    storage.invalidate_region_tree().await;
+    StatusCode::NO_CONTENT
 }
 
 /// Get specific region with children and files
+#[utoipa::path(
+    get,
+    path = "/api/regions/{region_id}",
+    tag = "regions",
+    params(("region_id" = String, Path, description = "Region ID, e.g. `germany`")),
+    responses(
+        (status = 200, description = "The region with its children and files", body = RegionTree),
+        (status = 404, description = "Region not found", body = super::error::ErrorBody)
+    )
+)]
 pub async fn get_region(
     Path(region_id): Path<String>,
     State(storage): State<Storage>,
-) -> impl IntoResponse {
-    match storage.get_region(&region_id).await {
-        Ok(Some(region)) => Json(region).into_response(),
-        Ok(None) => {
-            error!("Region {} not found", region_id);
-            StatusCode::NOT_FOUND.into_response()
-        }
-        Err(e) => {
-            error!("Failed to get region {}: {}", region_id, e);
-            StatusCode::INTERNAL_SERVER_ERROR.into_response()
-        }
-    }
+) -> Result<Json<RegionTree>, ApiError> {
+    storage
+        .get_region(&region_id)
+        .await
+        .api_context(|| format!("Failed to get region {}", region_id))?
+        .map(Json)
+        .ok_or_else(|| region_not_found(&region_id))
+}
+
+/// Get the direct children of a region (one level of the hierarchy)
+#[utoipa::path(
+    get,
+    path = "/api/regions/{region_id}/children",
+    tag = "regions",
+    params(("region_id" = String, Path, description = "Region ID, e.g. `germany`")),
+    responses(
+        (status = 200, description = "Direct children of the region", body = [Region]),
+        (status = 404, description = "Region not found", body = super::error::ErrorBody)
+    )
+)]
+pub async fn get_region_children(
+    Path(region_id): Path<String>,
+    State(storage): State<Storage>,
+) -> Result<Json<Vec<Region>>, ApiError> {
+    storage
+        .get_region_children(&region_id)
+        .await
+        .api_context(|| format!("Failed to get children of region {}", region_id))?
+        .map(Json)
+        .ok_or_else(|| region_not_found(&region_id))
 }
 
 /// Get files for a specific region
+#[utoipa::path(
+    get,
+    path = "/api/regions/{region_id}/files",
+    tag = "regions",
+    params(("region_id" = String, Path, description = "Region ID, e.g. `germany`")),
+    responses((status = 200, description = "Stored data files of the region", body = [DataFile]))
+)]
 pub async fn get_region_files(
     Path(region_id): Path<String>,
     State(storage): State<Storage>,
-) -> impl IntoResponse {
-    match storage.get_region_files(&region_id).await {
-        Ok(files) => Json(files).into_response(),
-        Err(e) => {
-            error!("Failed to get files for region {}: {}", region_id, e);
-            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+) -> Result<Json<Vec<DataFile>>, ApiError> {
+    let files = storage
+        .get_region_files(&region_id)
+        .await
+        .api_context(|| format!("Failed to get files for region {}", region_id))?;
+    Ok(Json(files))
+}
+
+/// Store a PBF extract generated elsewhere as a `YYYY-MM-DD` version of a region
+///
+/// The body is streamed to a temp file and only stored once its SHA-256
+/// matches the `Digest: sha-256=<base64>` header. It must start with a PBF
+/// header block, whose replication timestamp (if any) falls on the version's
+/// date; this is checked as soon as the block arrives. A rejected upload
+/// leaves nothing behind. Uploads are not resumable: an interrupted one has
+/// to be sent again in full. An existing file of the same version is
+/// replaced, and `latest` follows the newest version.
+pub async fn upload_region_file(
+    Path((region_id, version)): Path<(String, String)>,
+    headers: HeaderMap,
+    State(storage): State<Storage>,
+    State(processor): State<OsmProcessor>,
+    body: axum::body::Body,
+) -> Result<(StatusCode, Json<DataFile>), ApiError> {
+    use sha2::{Digest, Sha256};
+    use tokio::io::AsyncWriteExt;
+
+    if storage
+        .find_region(&region_id)
+        .await
+        .api_context(|| format!("Failed to get region {}", region_id))?
+        .is_none()
+    {
+        return Err(region_not_found(&region_id));
+    }
+    let date = chrono::NaiveDate::parse_from_str(&version, "%Y-%m-%d").map_err(|e| {
+        ApiError::bad_request(format!("Version '{}' is not a YYYY-MM-DD date", version))
+            .with_detail(e.to_string())
+    })?;
+    let expected = headers
+        .get("digest")
+        .and_then(|value| value.to_str().ok())
+        .and_then(sha256_from_digest)
+        .ok_or_else(|| ApiError::bad_request("A 'Digest: sha-256=<base64>' header is required"))?;
+
+    let filename = format!("{}.osm.pbf", date.format("%Y-%m-%d"));
+    let temp_file = TempFile::new(&processor.temp_dir, &filename);
+    let mut file = tokio::fs::File::create(temp_file.path())
+        .await
+        .api_context(|| "Failed to create upload file".to_string())?;
+    let mut sha256 = Sha256::new();
+    let mut received = 0u64;
+
+    // The start of the body, until its header block is complete and checked
+    let mut header_probe = Some(crate::pbf::HeaderProbe::default());
+    let mut stream = body.into_data_stream();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(|e| {
+            ApiError::bad_request("Failed to read the upload").with_detail(e.to_string())
+        })?;
+        received += chunk.len() as u64;
+        if let Some(max) = processor.max_download_bytes.filter(|max| received > *max) {
+            return Err(ApiError::new(
+                StatusCode::PAYLOAD_TOO_LARGE,
+                "too_large",
+                format!("Upload exceeds the size limit of {} bytes", max),
+            ));
         }
+        if let Some(probe) = &mut header_probe {
+            if let Some(header) = probe.push(&chunk).map_err(invalid_pbf)? {
+                check_upload_version(&header, date)?;
+                header_probe = None;
+            }
+        }
+        sha256.update(&chunk);
+        file.write_all(&chunk)
+            .await
+            .api_context(|| "Failed to write upload file".to_string())?;
+    }
+    if let Some(probe) = header_probe {
+        check_upload_version(&probe.finish().map_err(invalid_pbf)?, date)?;
+    }
+    file.flush()
+        .await
+        .api_context(|| "Failed to write upload file".to_string())?;
+    drop(file);
+
+    let actual = format!("{:x}", sha256.finalize());
+    if actual != expected {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            "checksum_mismatch",
+            "Upload does not match its Digest header",
+        )
+        .with_detail(format!("expected SHA-256 {}, got {}", expected, actual)));
+    }
+    let region_dir = storage
+        .get_region_path(&region_id)
+        .await
+        .api_context(|| format!("Failed to resolve directory of {}", region_id))?;
+    let file_path = region_dir.join(&filename);
+    tokio::fs::create_dir_all(&region_dir)
+        .await
+        .api_context(|| format!("Failed to create {:?}", region_dir))?;
+    processor
+        .save_upload(temp_file, &file_path, &actual)
+        .await
+        .api_context(|| format!("Failed to store upload as {:?}", file_path))?;
+
+    storage
+        .repair_latest(&region_id)
+        .await
+        .api_context(|| format!("Failed to update latest of {}", region_id))?;
+    if let Err(e) = storage.touch_region(&region_id).await {
+        warn!("Failed to mark {} as updated: {}", region_id, e);
+    }
+
+    let file = storage
+        .get_region_files(&region_id)
+        .await
+        .api_context(|| format!("Failed to list files of {}", region_id))?
+        .into_iter()
+        .find(|file| std::path::Path::new(&file.file_path) == file_path)
+        .ok_or_else(|| version_not_found(&region_id, &version))?;
+
+    Ok((StatusCode::CREATED, Json(file)))
+}
+
+/// Rejection of an upload whose start is not a PBF header block
+fn invalid_pbf(e: anyhow::Error) -> ApiError {
+    ApiError::new(
+        StatusCode::BAD_REQUEST,
+        "invalid_pbf",
+        "Upload is not an OSM PBF file",
+    )
+    .with_detail(format!("{:#}", e))
+}
+
+/// Check the PBF header of an upload against its `version`
+///
+/// The stored version is read back from the header's replication
+/// timestamp, so one on another day is rejected.
+fn check_upload_version(
+    header: &crate::pbf::PbfHeader,
+    version: chrono::NaiveDate,
+) -> Result<(), ApiError> {
+    let stamped = header
+        .replication_timestamp
+        .and_then(|ts| DateTime::from_timestamp(ts, 0))
+        .map(|ts| ts.date_naive());
+    if let Some(stamped) = stamped.filter(|stamped| *stamped != version) {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            "version_mismatch",
+            "Upload header is dated differently from its version",
+        )
+        .with_detail(format!(
+            "version {} but the header's replication timestamp is on {}",
+            version, stamped
+        )));
     }
+
+    Ok(())
 }
 
 /// Download a specific file
+#[utoipa::path(
+    get,
+    path = "/download/{region_id}/{version}",
+    tag = "downloads",
+    params(
+        ("region_id" = String, Path, description = "Region ID, e.g. `germany`"),
+        ("version" = String, Path, description = "`latest` or a `YYYY-MM-DD` date")
+    ),
+    responses(
+        (status = 200, description = "The data file as an attachment"),
+        (status = 304, description = "Unchanged since the copy named in `If-None-Match` or `If-Modified-Since`"),
+        (status = 404, description = "No file for this region and version", body = super::error::ErrorBody)
+    )
+)]
 pub async fn download_file(
     Path((region_id, version)): Path<(String, String)>,
     State(storage): State<Storage>,
-) -> impl IntoResponse {
-    match storage.get_file_path(&region_id, &version).await {
-        Ok(Some(path)) => match tokio::fs::File::open(&path).await {
-            Ok(file) => {
-                let stream = ReaderStream::new(file);
-                let body = axum::body::Body::from_stream(stream);
-
-                let filename = format!("{}-{}.osm.pbf", region_id, version);
-
-                let headers = [
-                    (
-                        header::CONTENT_TYPE,
-                        header::HeaderValue::from_static("application/octet-stream"),
-                    ),
-                    (
-                        header::CONTENT_DISPOSITION,
-                        header::HeaderValue::from_str(&format!(
-                            "attachment; filename=\"{}\"",
-                            filename
-                        ))
-                        .unwrap(),
-                    ),
-                ];
-
-                (headers, body).into_response()
-            }
-            Err(e) => {
-                error!("Failed to open file {:?}: {}", path, e);
-                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+    request_headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let path = storage
+        .get_file_path(&region_id, &version)
+        .await
+        .api_context(|| format!("Failed to get file path for {} {}", region_id, version))?
+        .ok_or_else(|| version_not_found(&region_id, &version))?;
+
+    let file = tokio::fs::File::open(&path)
+        .await
+        .api_context(|| format!("Failed to open file {:?}", path))?;
+    let metadata = file
+        .metadata()
+        .await
+        .api_context(|| format!("Failed to read metadata for {:?}", path))?;
+
+    let modified = metadata.modified().ok().map(DateTime::<Utc>::from);
+    let etag = file_etag(&path, &metadata);
+    let headers = cache_headers(&etag, modified);
+
+    if is_not_modified(&request_headers, &etag, modified) {
+        return Ok((StatusCode::NOT_MODIFIED, headers).into_response());
+    }
+
+    if let Err(e) = storage.increment_download_count(&region_id, &version).await {
+        warn!(
+            "Failed to record download of {} {}: {}",
+            region_id, version, e
+        );
+    }
+
+    let filename = format!("{}-{}.osm.pbf", region_id, version);
+    Ok(data_attachment(
+        file,
+        &path,
+        metadata.len(),
+        &filename,
+        headers,
+    ))
+}
+
+/// Query parameters for downloads that may be redirected upstream
+#[derive(serde::Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DownloadRedirectQuery {
+    /// `latest` (the default) or a `YYYY-MM-DD` date
+    version: Option<String>,
+}
+
+/// Download a file, redirecting to Geofabrik when it is not held locally
+///
+/// Local files always take precedence. Otherwise, when redirects are enabled,
+/// `latest` is answered with a `302` to the region's extract URL and a date
+/// with one to the dated historical extract; with redirects disabled a
+/// missing file is a `404` as on `/download/{region}/{version}`.
+#[utoipa::path(
+    get,
+    path = "/api/regions/{region_id}/download-redirect",
+    tag = "downloads",
+    params(("region_id" = String, Path, description = "Region ID, e.g. `germany`"), DownloadRedirectQuery),
+    responses(
+        (status = 200, description = "The locally held data file as an attachment"),
+        (status = 302, description = "Redirect to the upstream extract"),
+        (status = 404, description = "Neither held locally nor available upstream", body = super::error::ErrorBody)
+    )
+)]
+pub async fn download_or_redirect(
+    Path(region_id): Path<String>,
+    Query(query): Query<DownloadRedirectQuery>,
+    State(state): State<AppState>,
+    request_headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let version = query.version.unwrap_or_else(|| "latest".to_string());
+
+    let local = state
+        .storage
+        .get_file_path(&region_id, &version)
+        .await
+        .api_context(|| format!("Failed to get file path for {} {}", region_id, version))?;
+    if local.is_some() || !state.redirect_missing_downloads {
+        return download_file(
+            Path((region_id, version)),
+            State(state.storage),
+            request_headers,
+        )
+        .await;
+    }
+
+    let region = state
+        .storage
+        .find_region(&region_id)
+        .await
+        .api_context(|| "Failed to load regions".to_string())?
+        .ok_or_else(|| region_not_found(&region_id))?;
+    let latest_url = crate::osm::utils::download_url(&region)
+        .map_err(|e| version_not_found(&region_id, &version).with_detail(e.to_string()))?;
+
+    let url = if version == "latest" {
+        latest_url.to_string()
+    } else {
+        chrono::NaiveDate::parse_from_str(&version, "%Y-%m-%d")
+            .ok()
+            .and_then(|date| crate::osm::utils::historical_extract_url(latest_url, date))
+            .ok_or_else(|| {
+                version_not_found(&region_id, &version)
+                    .with_detail("Only `latest` and YYYY-MM-DD versions are available upstream")
+            })?
+    };
+
+    Ok((StatusCode::FOUND, [(header::LOCATION, url)]).into_response())
+}
+
+/// Query parameters for bounding box extracts
+#[derive(serde::Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct BboxQuery {
+    /// `min_lon,min_lat,max_lon,max_lat`
+    bbox: String,
+}
+
+/// Download the part of a region's latest data inside a bounding box
+///
+/// Extracts are cached per region, source file and box, so repeating a
+/// request streams the earlier result instead of filtering again.
+#[utoipa::path(
+    get,
+    path = "/download/{region_id}/bbox",
+    tag = "downloads",
+    params(("region_id" = String, Path, description = "Region ID, e.g. `germany`"), BboxQuery),
+    responses(
+        (status = 200, description = "Extract of the region's latest data inside the box"),
+        (status = 400, description = "Invalid bounding box", body = super::error::ErrorBody),
+        (status = 404, description = "Region has no data", body = super::error::ErrorBody)
+    )
+)]
+pub async fn download_bbox_extract(
+    Path(region_id): Path<String>,
+    Query(query): Query<BboxQuery>,
+    State(state): State<AppState>,
+) -> Result<Response, ApiError> {
+    let bbox = parse_bbox(&query.bbox).ok_or_else(|| invalid_bbox(&query.bbox))?;
+
+    let extract_path = bbox_extract(&state, &region_id, &bbox, &query.bbox).await?;
+
+    let opened = async {
+        let file = tokio::fs::File::open(&extract_path).await?;
+        let len = file.metadata().await?.len();
+        std::io::Result::Ok((file, len))
+    };
+    let (file, len) = opened
+        .await
+        .api_context(|| format!("Failed to open file {:?}", extract_path))?;
+
+    let filename = format!("{}-bbox.osm.pbf", region_id.replace('/', "_"));
+    Ok(data_attachment(
+        file,
+        &extract_path,
+        len,
+        &filename,
+        HeaderMap::new(),
+    ))
+}
+
+/// Path of the cached extract of a region's latest data inside `bbox`,
+/// filtering it first when it is not cached yet
+///
+/// Fails with 400 when the box does not intersect the region; `raw` is the
+/// box as given in the request, for error messages.
+async fn bbox_extract(
+    state: &AppState,
+    region_id: &str,
+    bbox: &BoundingBox,
+    raw: &str,
+) -> Result<std::path::PathBuf, ApiError> {
+    let region = state
+        .storage
+        .find_region(region_id)
+        .await
+        .api_context(|| "Failed to load regions".to_string())?
+        .ok_or_else(|| region_not_found(region_id))?;
+
+    if !bbox.intersects(&region.bounding_box) {
+        let region_bbox = &region.bounding_box;
+        return Err(
+            ApiError::bad_request("Bounding box does not intersect the region").with_detail(
+                format!(
+                    "bbox {} is outside the region bounds {},{},{},{}",
+                    raw,
+                    region_bbox.min_lon,
+                    region_bbox.min_lat,
+                    region_bbox.max_lon,
+                    region_bbox.max_lat
+                ),
+            ),
+        );
+    }
+
+    let source = state
+        .storage
+        .get_file_path(region_id, "latest")
+        .await
+        .api_context(|| format!("Failed to get file path for {}", region_id))?
+        .ok_or_else(|| version_not_found(region_id, "latest"))?;
+
+    let extract_path = state
+        .storage
+        .bbox_extract_path(region_id, &source, bbox)
+        .api_context(|| format!("Failed to resolve extract path for {:?}", source))?;
+
+    // Filter into a temporary file next to the extract first so requests
+    // never stream a partially written extract
+    state
+        .cache_builds
+        .get_or_build(&extract_path, || async {
+            let dir = extract_path.parent().unwrap_or(std::path::Path::new("."));
+            tokio::fs::create_dir_all(dir).await?;
+            let temp_file = TempFile::new(dir, "bbox.tmp");
+            state
+                .processor
+                .filter_by_bounds(&source, bbox, temp_file.path())
+                .await?;
+            temp_file.persist(&extract_path)
+        })
+        .await
+        .api_context(|| format!("Failed to filter {:?} by {}", source, raw))?;
+
+    Ok(extract_path)
+}
+
+/// Layout of the checksum manifest
+#[derive(Debug, Clone, Copy, Default, serde::Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ManifestFormat {
+    /// A JSON array of entries with path, size, version and SHA-256
+    #[default]
+    Json,
+    /// `sha256sum -c` input: `<sha256>  <path>` per file
+    Sha256sums,
+}
+
+/// Query parameters for the checksum manifest
+#[derive(serde::Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ManifestQuery {
+    #[param(inline)]
+    format: Option<ManifestFormat>,
+}
+
+/// List every stored data file with its size, version and SHA-256 for mirrors to verify
+///
+/// Paths are relative to the data directory, the way a mirror lays them out.
+/// Files are listed as each region's directory is scanned, so the response
+/// is never built in memory. Files without a stored SHA-256 have `null` in
+/// JSON and are left out of `sha256sums`.
+#[utoipa::path(
+    get,
+    path = "/api/manifest",
+    tag = "downloads",
+    params(ManifestQuery),
+    responses((status = 200, description = "Every stored data file with its checksum", content_type = "application/json"))
+)]
+pub async fn get_manifest(
+    Query(query): Query<ManifestQuery>,
+    State(storage): State<Storage>,
+) -> Result<Response, ApiError> {
+    use futures::TryStreamExt;
+
+    let mut regions = storage
+        .load_regions()
+        .await
+        .api_context(|| "Failed to build manifest".to_string())?;
+    regions.sort_by(|a, b| a.id.cmp(&b.id));
+
+    let data_dir = storage.data_dir.clone();
+    let entries = futures::stream::iter(regions)
+        .then(move |region| {
+            let storage = storage.clone();
+            async move { storage.scan_region_files(&region.id).await }
+        })
+        .map_ok(|files| futures::stream::iter(files.into_iter().map(anyhow::Ok)))
+        .try_flatten()
+        .map_ok(move |file| {
+            let path = std::path::Path::new(&file.file_path);
+            let relative = path.strip_prefix(&data_dir).unwrap_or(path);
+            json!({
+                "region_id": file.region_id,
+                "version": file.version,
+                "path": relative.to_string_lossy().replace('\\', "/"),
+                "size_bytes": file.file_size,
+                "sha256": crate::osm::utils::read_sha256(path),
+            })
+        });
+
+    let (content_type, body) = match query.format.unwrap_or_default() {
+        ManifestFormat::Json => {
+            // One entry per chunk, wrapped into a single array
+            let items = entries.enumerate().map(|(i, entry)| {
+                let separator = if i == 0 { "\n" } else { ",\n" };
+                entry.map(|entry| format!("{}{}", separator, entry))
+            });
+            let open = futures::stream::once(async { Ok("[".to_string()) });
+            let close = futures::stream::once(async { Ok("\n]\n".to_string()) });
+            (
+                "application/json",
+                axum::body::Body::from_stream(open.chain(items).chain(close)),
+            )
+        }
+        ManifestFormat::Sha256sums => {
+            let lines = entries.try_filter_map(|entry| async move {
+                Ok(entry["sha256"].as_str().map(|sha256| {
+                    format!(
+                        "{}  {}\n",
+                        sha256,
+                        entry["path"].as_str().unwrap_or_default()
+                    )
+                }))
+            });
+            (
+                "text/plain; charset=utf-8",
+                axum::body::Body::from_stream(lines),
+            )
+        }
+    };
+
+    Ok((
+        [(
+            header::CONTENT_TYPE,
+            header::HeaderValue::from_static(content_type),
+        )],
+        body,
+    )
+        .into_response())
+}
+
+/// Stream the latest extracts of a region and all its descendants as one ZIP
+///
+/// The archive is written on the fly into a bounded pipe, so memory use does
+/// not grow with its size. Regions without local data are skipped; a
+/// `manifest.json` entry lists the included files.
+#[utoipa::path(
+    get,
+    path = "/download/{region_id}/bundle",
+    tag = "downloads",
+    params(("region_id" = String, Path, description = "Region ID, e.g. `germany`")),
+    responses(
+        (status = 200, description = "ZIP of the latest extracts of the region and its descendants", content_type = "application/zip"),
+        (status = 404, description = "Region not found", body = super::error::ErrorBody)
+    )
+)]
+pub async fn download_bundle(
+    Path(region_id): Path<String>,
+    State(storage): State<Storage>,
+) -> Result<Response, ApiError> {
+    let files = storage
+        .bundle_files(&region_id)
+        .await
+        .api_context(|| format!("Failed to collect bundle files for {}", region_id))?
+        .ok_or_else(|| region_not_found(&region_id))?;
+
+    if files.is_empty() {
+        return Err(ApiError::not_found(format!(
+            "No local data for {} or any of its child regions",
+            region_id
+        )));
+    }
+
+    let entries: Vec<(String, std::path::PathBuf)> = files
+        .iter()
+        .map(|(region, path)| {
+            let filename = path
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_else(|| "latest.osm.pbf".to_string());
+            (format!("{}/{}", region.id, filename), path.clone())
+        })
+        .collect();
+
+    let manifest = json!({
+        "region_id": region_id,
+        "created_at": Utc::now().to_rfc3339(),
+        "files": files
+            .iter()
+            .zip(&entries)
+            .map(|((region, path), (entry, _))| json!({
+                "region_id": region.id,
+                "name": region.name,
+                "file": entry,
+                "size_bytes": std::fs::metadata(path).map(|m| m.len()).ok(),
+                "sha256": crate::osm::utils::read_sha256(path),
+            }))
+            .collect::<Vec<_>>()
+    });
+
+    let (writer, reader) = tokio::io::duplex(256 * 1024);
+    let bundle_id = region_id.clone();
+    tokio::spawn(async move {
+        if let Err(e) = write_bundle(writer, &manifest, &entries).await {
+            // The client sees a truncated archive; nothing else can be sent at this point
+            error!("Failed to stream bundle for {}: {}", bundle_id, e);
+        }
+    });
+
+    let filename = format!("{}-bundle.zip", region_id.replace('/', "_"));
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/zip".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", filename),
+            ),
+        ],
+        axum::body::Body::from_stream(ReaderStream::new(reader)),
+    )
+        .into_response())
+}
+
+/// Write a manifest and data files as stored (uncompressed) ZIP entries
+async fn write_bundle(
+    writer: tokio::io::DuplexStream,
+    manifest: &serde_json::Value,
+    entries: &[(String, std::path::PathBuf)],
+) -> crate::Result<()> {
+    use async_zip::{tokio::write::ZipFileWriter, Compression, ZipEntryBuilder};
+    use tokio_util::compat::FuturesAsyncWriteCompatExt;
+
+    let mut zip = ZipFileWriter::with_tokio(writer);
+
+    let manifest = serde_json::to_vec_pretty(manifest)?;
+    zip.write_entry_whole(
+        ZipEntryBuilder::new("manifest.json".into(), Compression::Stored),
+        &manifest,
+    )
+    .await?;
+
+    // PBF data is already compressed, so entries are stored as-is
+    for (name, path) in entries {
+        let mut file = tokio::fs::File::open(path).await?;
+        let entry = zip
+            .write_entry_stream(ZipEntryBuilder::new(
+                name.clone().into(),
+                Compression::Stored,
+            ))
+            .await?;
+        let mut entry = entry.compat_write();
+        tokio::io::copy(&mut file, &mut entry).await?;
+        entry.into_inner().close().await?;
+    }
+
+    zip.close().await?;
+    Ok(())
+}
+
+/// Registered media type for OSM PBF data
+const PBF_CONTENT_TYPE: &str = "application/vnd.openstreetmap.data+pbf";
+
+/// Stream a data file as an attachment with the given download filename
+///
+/// Sets `Content-Length`, a PBF content type (octet-stream for other formats)
+/// and, when a SHA-256 was stored at download time, `Digest` and
+/// `x-checksum-sha256` headers so clients can verify the transfer.
+fn data_attachment(
+    file: tokio::fs::File,
+    path: &std::path::Path,
+    len: u64,
+    filename: &str,
+    mut headers: HeaderMap,
+) -> axum::response::Response {
+    let body = axum::body::Body::from_stream(ReaderStream::new(file));
+
+    let content_type = match crate::osm::utils::detect_format(path) {
+        Some(DataFormat::OsmPbf) => PBF_CONTENT_TYPE,
+        _ => "application/octet-stream",
+    };
+    headers.insert(
+        header::CONTENT_TYPE,
+        header::HeaderValue::from_static(content_type),
+    );
+    headers.insert(header::CONTENT_LENGTH, header::HeaderValue::from(len));
+
+    // Resolve `latest` symlinks so the sidecar of the real file is found
+    let sha256 = std::fs::canonicalize(path)
+        .ok()
+        .and_then(|real_path| crate::osm::utils::read_sha256(&real_path));
+    if let Some(sha256) = sha256 {
+        if let Some(digest) = sha256_digest(&sha256) {
+            if let Ok(value) = header::HeaderValue::from_str(&format!("sha-256={}", digest)) {
+                headers.insert("digest", value);
             }
-        },
-        Ok(None) => {
-            error!(
-                "File not found for region {} version {}",
-                region_id, version
-            );
-            StatusCode::NOT_FOUND.into_response()
         }
-        Err(e) => {
-            error!("Failed to get file path: {}", e);
-            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        if let Ok(value) = header::HeaderValue::from_str(&sha256) {
+            headers.insert("x-checksum-sha256", value);
+        }
+    }
+
+    if let Ok(value) =
+        header::HeaderValue::from_str(&format!("attachment; filename=\"{}\"", filename))
+    {
+        headers.insert(header::CONTENT_DISPOSITION, value);
+    }
+
+    (headers, body).into_response()
+}
+
+/// Base64 form of a hex SHA-256, as used by the `Digest` header
+fn sha256_digest(hex: &str) -> Option<String> {
+    use base64::Engine;
+
+    if hex.len() != 64 {
+        return None;
+    }
+    let bytes = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    Some(base64::engine::general_purpose::STANDARD.encode(bytes))
+}
+
+/// Hex SHA-256 from a `Digest` header value such as `sha-256=<base64>`,
+/// which may list other algorithms as well
+fn sha256_from_digest(value: &str) -> Option<String> {
+    use base64::Engine;
+
+    let encoded = value.split(',').find_map(|entry| {
+        let (algorithm, digest) = entry.trim().split_once('=')?;
+        algorithm.eq_ignore_ascii_case("sha-256").then_some(digest)
+    })?;
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(encoded.trim())
+        .ok()?;
+    (bytes.len() == 32).then(|| bytes.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+/// Strong ETag for a data file: its stored SHA-256 if recorded, else size and mtime
+///
+/// The SHA-256 is written for every stored file, verified against
+/// Geofabrik or not, so only files from older versions fall back.
+fn file_etag(path: &std::path::Path, metadata: &std::fs::Metadata) -> String {
+    // Resolve `latest` symlinks so the checksum sidecar of the real file is found
+    let checksum = std::fs::canonicalize(path)
+        .ok()
+        .and_then(|real_path| crate::osm::utils::read_sha256(&real_path));
+
+    match checksum {
+        Some(checksum) => format!("\"{}\"", checksum),
+        None => {
+            let mtime = metadata
+                .modified()
+                .ok()
+                .and_then(|modified| modified.duration_since(std::time::UNIX_EPOCH).ok())
+                .map(|duration| duration.as_secs())
+                .unwrap_or(0);
+            format!("\"{:x}-{:x}\"", metadata.len(), mtime)
+        }
+    }
+}
+
+/// ETag and Last-Modified headers shared by full and 304 responses
+fn cache_headers(etag: &str, modified: Option<DateTime<Utc>>) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+
+    if let Ok(value) = header::HeaderValue::from_str(etag) {
+        headers.insert(header::ETAG, value);
+    }
+    if let Some(modified) = modified {
+        let http_date = modified.format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+        if let Ok(value) = header::HeaderValue::from_str(&http_date) {
+            headers.insert(header::LAST_MODIFIED, value);
         }
     }
+
+    headers
+}
+
+/// Evaluate If-None-Match / If-Modified-Since against the current file state
+fn is_not_modified(
+    request_headers: &HeaderMap,
+    etag: &str,
+    modified: Option<DateTime<Utc>>,
+) -> bool {
+    // If-None-Match takes precedence over If-Modified-Since when both are sent
+    if let Some(if_none_match) = request_headers.get(header::IF_NONE_MATCH) {
+        let Ok(if_none_match) = if_none_match.to_str() else {
+            return false;
+        };
+
+        return if_none_match
+            .split(',')
+            .map(str::trim)
+            .any(|candidate| candidate == "*" || candidate.trim_start_matches("W/") == etag);
+    }
+
+    let since = request_headers
+        .get(header::IF_MODIFIED_SINCE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| DateTime::parse_from_rfc2822(value).ok());
+
+    match (since, modified) {
+        // HTTP dates only have second precision
+        (Some(since), Some(modified)) => modified.timestamp() <= since.timestamp(),
+        _ => false,
+    }
 }
 
 /// Get quality report for a specific region/file
@@ -142,254 +1143,1385 @@ pub async fn get_quality_report(
     Json(quality_report).into_response()
 }
 
+/// Get the newest quality report for a region's latest data file
+#[utoipa::path(
+    get,
+    path = "/api/regions/{region_id}/quality",
+    tag = "quality",
+    params(("region_id" = String, Path, description = "Region ID, e.g. `germany`")),
+    responses(
+        (status = 200, description = "Newest quality report of the region's latest data", body = QualityReport),
+        (status = 404, description = "No quality report for the region", body = super::error::ErrorBody)
+    )
+)]
+pub async fn get_region_quality(
+    Path(region_id): Path<String>,
+    State(storage): State<Storage>,
+) -> Result<Json<QualityReport>, ApiError> {
+    storage
+        .get_latest_quality_report(&region_id)
+        .await
+        .api_context(|| format!("Failed to get quality report for region {}", region_id))?
+        .map(Json)
+        .ok_or_else(|| {
+            ApiError::not_found(format!("No quality report found for region {}", region_id))
+        })
+}
+
+/// Time series of a region's quality across all its stored reports, oldest first
+#[utoipa::path(
+    get,
+    path = "/api/regions/{region_id}/history",
+    tag = "quality",
+    params(("region_id" = String, Path, description = "Region ID, e.g. `germany`")),
+    responses(
+        (status = 200, description = "Quality of each stored report, oldest first", body = QualityHistory),
+        (status = 404, description = "Region not found", body = super::error::ErrorBody)
+    )
+)]
+pub async fn get_region_history(
+    Path(region_id): Path<String>,
+    State(storage): State<Storage>,
+) -> Result<Json<QualityHistory>, ApiError> {
+    storage
+        .find_region(&region_id)
+        .await
+        .api_context(|| format!("Failed to load region {}", region_id))?
+        .ok_or_else(|| region_not_found(&region_id))?;
+
+    let points = storage
+        .get_quality_history(&region_id)
+        .await
+        .api_context(|| format!("Failed to load quality history for {}", region_id))?;
+
+    Ok(Json(QualityHistory { region_id, points }))
+}
+
 /// Search regions by name or criteria
-#[derive(serde::Deserialize)]
+#[derive(serde::Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct SearchQuery {
+    /// Case-insensitive substring of the region name
     q: Option<String>,
+    /// 0 (world) to 4 (subregion)
     admin_level: Option<u8>,
+    /// ID of the parent region
     continent: Option<String>,
-    has_data: Option<bool>,
+    /// `min_lon,min_lat,max_lon,max_lat`; keeps regions intersecting the box
+    bbox: Option<String>,
+    /// `true` keeps regions with their own extract, `false` only container regions
+    downloadable: Option<bool>,
+    /// ISO 3166-1 alpha-2 or ISO 3166-2 code, case-insensitive
+    iso: Option<String>,
 }
 
+/// Regions matching all of the given filters
+#[utoipa::path(
+    get,
+    path = "/api/regions/search",
+    tag = "regions",
+    params(SearchQuery),
+    responses(
+        (status = 200, description = "Regions matching all given filters", body = [Region]),
+        (status = 400, description = "Invalid bounding box", body = super::error::ErrorBody)
+    )
+)]
 pub async fn search_regions(
     Query(query): Query<SearchQuery>,
     State(storage): State<Storage>,
-) -> impl IntoResponse {
-    match storage.load_regions().await {
-        Ok(regions) => {
-            let filtered_regions: Vec<_> = regions
-                .into_iter()
-                .filter(|region| {
-                    if let Some(ref search_term) = query.q {
-                        if !region
-                            .name
-                            .to_lowercase()
-                            .contains(&search_term.to_lowercase())
-                        {
-                            return false;
-                        }
-                    }
+) -> Result<Json<Vec<Region>>, ApiError> {
+    let regions = match query.bbox.as_deref() {
+        Some(bbox) => {
+            let bbox = parse_bbox(bbox).ok_or_else(|| invalid_bbox(bbox))?;
+            storage.regions_intersecting(&bbox).await
+        }
+        None => storage.load_regions().await,
+    }
+    .api_context(|| "Failed to search regions".to_string())?;
 
-                    if let Some(admin_level) = query.admin_level {
-                        if region.admin_level_num() != admin_level {
-                            return false;
-                        }
-                    }
+    let filtered_regions: Vec<_> = regions
+        .into_iter()
+        .filter(|region| {
+            if let Some(ref search_term) = query.q {
+                if !region
+                    .name
+                    .to_lowercase()
+                    .contains(&search_term.to_lowercase())
+                {
+                    return false;
+                }
+            }
 
-                    if let Some(ref continent) = query.continent {
-                        if region.parent_id.as_deref() != Some(continent) {
-                            return false;
-                        }
-                    }
+            if let Some(admin_level) = query.admin_level {
+                if region.admin_level_num() != admin_level {
+                    return false;
+                }
+            }
 
-                    true
-                })
-                .collect();
+            if let Some(ref continent) = query.continent {
+                if region.parent_id.as_deref() != Some(continent) {
+                    return false;
+                }
+            }
 
-            Json(filtered_regions).into_response()
-        }
-        Err(e) => {
-            error!("Failed to search regions: {}", e);
-            StatusCode::INTERNAL_SERVER_ERROR.into_response()
-        }
-    }
+            if let Some(downloadable) = query.downloadable {
+                if region.provides_data_services != downloadable {
+                    return false;
+                }
+            }
+
+            if let Some(ref iso) = query.iso {
+                if !region.has_iso_code(iso) {
+                    return false;
+                }
+            }
+
+            true
+        })
+        .collect();
+
+    Ok(Json(filtered_regions))
 }
 
-/// Get statistics about the entire dataset
-pub async fn get_stats(State(storage): State<Storage>) -> impl IntoResponse {
-    match storage.load_regions().await {
-        Ok(regions) => {
-            let mut stats = HashMap::new();
-            stats.insert("total_regions", json!(regions.len()));
-
-            let by_level: HashMap<String, usize> =
-                regions.iter().fold(HashMap::new(), |mut acc, region| {
-                    let level = match region.admin_level {
-                        AdminLevel::World => "world",
-                        AdminLevel::Continent => "continents",
-                        AdminLevel::Country => "countries",
-                        AdminLevel::Region => "regions",
-                        AdminLevel::Subregion => "subregions",
-                    };
-                    *acc.entry(level.to_string()).or_insert(0) += 1;
-                    acc
-                });
-
-            stats.insert("by_level", json!(by_level));
-
-            // Calculate total coverage area
-            let total_area: f64 = regions.iter().filter_map(|r| r.area_km2).sum();
-            stats.insert("total_area_km2", json!(total_area));
-
-            // Calculate total population
-            let total_population: u64 = regions.iter().filter_map(|r| r.population).sum();
-            stats.insert("total_population", json!(total_population));
-
-            Json(stats).into_response()
-        }
-        Err(e) => {
-            error!("Failed to get stats: {}", e);
-            StatusCode::INTERNAL_SERVER_ERROR.into_response()
-        }
+/// Regions carrying an ISO 3166-1 alpha-2 (`LI`) or ISO 3166-2 (`DE-BY`) code,
+/// largest first
+#[utoipa::path(
+    get,
+    path = "/api/regions/by-iso/{code}",
+    tag = "regions",
+    params(("code" = String, Path, description = "ISO 3166-1 alpha-2 or ISO 3166-2 code, e.g. `DE-BY`")),
+    responses(
+        (status = 200, description = "Matching regions, largest first", body = [Region]),
+        (status = 404, description = "No region carries the code", body = super::error::ErrorBody)
+    )
+)]
+pub async fn get_regions_by_iso(
+    Path(code): Path<String>,
+    State(storage): State<Storage>,
+) -> Result<Json<Vec<Region>>, ApiError> {
+    let mut regions: Vec<Region> = storage
+        .load_regions()
+        .await
+        .api_context(|| format!("Failed to look up ISO code {}", code))?
+        .into_iter()
+        .filter(|region| region.has_iso_code(&code))
+        .collect();
+
+    if regions.is_empty() {
+        return Err(ApiError::not_found(format!(
+            "No region with ISO code {}",
+            code
+        )));
     }
-}
 
-/// Trigger processing for a specific region
-pub async fn trigger_processing(
-    Path(region_id): Path<String>,
-    State(_storage): State<Storage>,
-) -> impl IntoResponse {
-    // This would normally trigger a background job
-    // For now, just return a mock job status
-
-    let job = ProcessingJob {
-        id: uuid::Uuid::new_v4().to_string(),
-        region_id: region_id.clone(),
-        job_type: JobType::Process,
-        status: JobStatus::Pending,
-        progress: 0.0,
-        message: Some("Processing queued".to_string()),
-        created_at: chrono::Utc::now(),
-        started_at: None,
-        completed_at: None,
-        error_message: None,
-    };
+    regions.sort_by(|a, b| {
+        a.admin_level_num()
+            .cmp(&b.admin_level_num())
+            .then_with(|| a.id.cmp(&b.id))
+    });
+    Ok(Json(regions))
+}
 
-    Json(job).into_response()
+/// Query parameters for locating a point
+#[derive(serde::Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct LocateQuery {
+    /// Latitude in degrees, -90 to 90
+    lat: f64,
+    /// Longitude in degrees, -180 to 180
+    lon: f64,
+}
+
+/// Regions containing a point, most specific first
+///
+/// Regions with a stored boundary are matched by point-in-polygon, others
+/// by their bounding box.
+#[utoipa::path(
+    get,
+    path = "/api/locate",
+    tag = "regions",
+    params(LocateQuery),
+    responses(
+        (status = 200, description = "The most specific region and all regions containing the point", content_type = "application/json"),
+        (status = 400, description = "Coordinates out of range", body = super::error::ErrorBody),
+        (status = 404, description = "No region covers the point", body = super::error::ErrorBody)
+    )
+)]
+pub async fn locate_point(
+    Query(query): Query<LocateQuery>,
+    State(storage): State<Storage>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let LocateQuery { lat, lon } = query;
+    if !(-90.0..=90.0).contains(&lat) || !(-180.0..=180.0).contains(&lon) {
+        return Err(ApiError::bad_request("Coordinates out of range")
+            .with_detail(format!("got lat={}, lon={}", lat, lon)));
+    }
+
+    let regions = storage
+        .regions_containing(lat, lon)
+        .await
+        .api_context(|| format!("Failed to locate {},{}", lat, lon))?;
+    let Some(best) = regions.first() else {
+        return Err(ApiError::not_found(format!(
+            "No region covers {},{}",
+            lat, lon
+        )));
+    };
+
+    Ok(Json(json!({
+        "lat": lat,
+        "lon": lon,
+        "region": best,
+        "regions": regions
+    })))
+}
+
+fn region_not_found(region_id: &str) -> ApiError {
+    ApiError::not_found(format!("Region {} not found", region_id))
+}
+
+fn version_not_found(region_id: &str, version: &str) -> ApiError {
+    ApiError::not_found(format!(
+        "No file found for region {} version '{}'",
+        region_id, version
+    ))
+}
+
+fn invalid_bbox(value: &str) -> ApiError {
+    ApiError::bad_request("Invalid bbox, expected min_lon,min_lat,max_lon,max_lat")
+        .with_detail(format!("got '{}'", value))
+}
+
+/// `202` for a newly queued job, `200` when one for the region was already pending or running
+fn job_status(created: bool) -> StatusCode {
+    if created {
+        StatusCode::ACCEPTED
+    } else {
+        StatusCode::OK
+    }
+}
+
+/// Parse a `min_lon,min_lat,max_lon,max_lat` query parameter into a bounding box
+fn parse_bbox(value: &str) -> Option<BoundingBox> {
+    let coords: Vec<f64> = value
+        .split(',')
+        .map(|part| part.trim().parse::<f64>())
+        .collect::<std::result::Result<_, _>>()
+        .ok()?;
+
+    if coords.len() != 4 {
+        return None;
+    }
+
+    let (min_lon, min_lat, max_lon, max_lat) = (coords[0], coords[1], coords[2], coords[3]);
+    BoundingBox::try_new(min_lat, min_lon, max_lat, max_lon).ok()
+}
+
+/// Get statistics about the entire dataset
+#[utoipa::path(
+    get,
+    path = "/api/stats",
+    tag = "stats",
+    responses((status = 200, description = "Region counts, total area, population, downloads and job queue depth", content_type = "application/json"))
+)]
+pub async fn get_stats(
+    State(storage): State<Storage>,
+    State(jobs): State<JobQueue>,
+) -> Result<Json<HashMap<&'static str, serde_json::Value>>, ApiError> {
+    let regions = storage
+        .load_regions()
+        .await
+        .api_context(|| "Failed to get stats".to_string())?;
+
+    let mut stats = HashMap::new();
+    stats.insert("total_regions", json!(regions.len()));
+
+    let by_level: HashMap<String, usize> =
+        regions.iter().fold(HashMap::new(), |mut acc, region| {
+            let level = match region.admin_level {
+                AdminLevel::World => "world",
+                AdminLevel::Continent => "continents",
+                AdminLevel::Country => "countries",
+                AdminLevel::Region => "regions",
+                AdminLevel::Subregion => "subregions",
+            };
+            *acc.entry(level.to_string()).or_insert(0) += 1;
+            acc
+        });
+
+    stats.insert("by_level", json!(by_level));
+
+    // Container regions only group their children and have no extract
+    let downloadable = regions.iter().filter(|r| r.provides_data_services).count();
+    stats.insert(
+        "by_data_services",
+        json!({
+            "downloadable": downloadable,
+            "container_only": regions.len() - downloadable
+        }),
+    );
+
+    // Calculate total coverage area
+    let total_area: f64 = regions.iter().filter_map(|r| r.area_km2).sum();
+    stats.insert("total_area_km2", json!(total_area));
+
+    // Calculate total population
+    let total_population: u64 = regions.iter().filter_map(|r| r.population).sum();
+    stats.insert("total_population", json!(total_population));
+
+    // Aggregate recorded downloads across all regions
+    match storage.load_download_totals().await {
+        Ok(totals) => {
+            let total_downloads: u64 = totals.values().sum();
+            stats.insert("total_downloads", json!(total_downloads));
+        }
+        Err(e) => warn!("Failed to load download statistics: {}", e),
+    }
+
+    stats.insert("job_queue", json!(jobs.depth().await));
+
+    Ok(Json(stats))
+}
+
+/// Bucket for regions that sit under no continent
+const OTHER_CONTINENT: &str = "other";
+
+/// Region counts, area and downloadable regions per continent
+///
+/// Regions are grouped under the continent found by following their
+/// `parent_id` links; continents themselves are the buckets and are not
+/// counted. Regions with no continent ancestor (e.g. the world) go under `other`.
+#[utoipa::path(
+    get,
+    path = "/api/stats/by-continent",
+    tag = "stats",
+    responses((status = 200, description = "Totals keyed by continent ID, plus `other`", body = HashMap<String, ContinentStats>))
+)]
+pub async fn get_stats_by_continent(
+    State(storage): State<Storage>,
+) -> Result<Json<BTreeMap<String, ContinentStats>>, ApiError> {
+    let regions = storage
+        .load_regions()
+        .await
+        .api_context(|| "Failed to get stats".to_string())?;
+    let by_id: HashMap<&str, &Region> = regions.iter().map(|r| (r.id.as_str(), r)).collect();
+
+    let mut stats: BTreeMap<String, ContinentStats> = regions
+        .iter()
+        .filter(|r| r.admin_level == AdminLevel::Continent)
+        .map(|r| {
+            let continent = ContinentStats {
+                name: r.name.clone(),
+                ..Default::default()
+            };
+            (r.id.clone(), continent)
+        })
+        .collect();
+    stats.insert(
+        OTHER_CONTINENT.to_string(),
+        ContinentStats {
+            name: "Other".to_string(),
+            ..Default::default()
+        },
+    );
+
+    for region in regions
+        .iter()
+        .filter(|r| r.admin_level != AdminLevel::Continent)
+    {
+        let continent = continent_ancestor(region, &by_id).unwrap_or(OTHER_CONTINENT);
+        if let Some(totals) = stats.get_mut(continent) {
+            totals.region_count += 1;
+            totals.total_area_km2 += region.area_km2.unwrap_or(0.0);
+            if region.provides_data_services {
+                totals.downloadable_count += 1;
+            }
+        }
+    }
+
+    Ok(Json(stats))
+}
+
+/// ID of the nearest continent above a region, stopping at a missing
+/// parent or a cycle in the hierarchy
+fn continent_ancestor<'a>(region: &Region, by_id: &HashMap<&str, &'a Region>) -> Option<&'a str> {
+    let mut seen = HashSet::new();
+    let mut parent_id = region.parent_id.as_deref();
+    while let Some(id) = parent_id {
+        let parent = *by_id.get(id)?;
+        if !seen.insert(id) {
+            return None;
+        }
+        if parent.admin_level == AdminLevel::Continent {
+            return Some(&parent.id);
+        }
+        parent_id = parent.parent_id.as_deref();
+    }
+    None
+}
+
+/// Default number of sample element IDs returned per diff category
+const DEFAULT_DIFF_SAMPLE_SIZE: usize = 100;
+/// Largest `limit` the diff endpoint honours, keeping responses small
+const MAX_DIFF_SAMPLE_SIZE: usize = 1000;
+
+/// Element-level diff between two stored versions of a region
+pub async fn diff_versions(
+    Path(region_id): Path<String>,
+    Query(query): Query<CompareQuery>,
+    State(storage): State<Storage>,
+    State(processor): State<OsmProcessor>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_DIFF_SAMPLE_SIZE)
+        .min(MAX_DIFF_SAMPLE_SIZE);
+
+    let mut paths = Vec::new();
+    for version in [&query.from, &query.to] {
+        let path = storage
+            .get_file_path(&region_id, version)
+            .await
+            .api_context(|| format!("Failed to get file path for {} {}", region_id, version))?
+            .ok_or_else(|| version_not_found(&region_id, version))?;
+        paths.push(path);
+    }
+
+    let diff = processor
+        .diff_files(&paths[0], &paths[1], limit)
+        .await
+        .api_context(|| format!("Failed to diff region {}", region_id))?;
+
+    Ok(Json(json!({
+        "region_id": region_id,
+        "from_version": query.from,
+        "to_version": query.to,
+        "added": diff.added,
+        "modified": diff.modified,
+        "deleted": diff.deleted
+    })))
+}
+
+/// Query parameters for re-running quality analysis
+#[derive(serde::Deserialize)]
+pub struct AnalyzeQuery {
+    version: Option<String>,
+}
+
+/// Queue a quality analysis of an already downloaded version (latest by default)
+pub async fn trigger_analysis(
+    Path(region_id): Path<String>,
+    Query(query): Query<AnalyzeQuery>,
+    State(state): State<AppState>,
+) -> Result<(StatusCode, Json<ProcessingJob>), ApiError> {
+    let version = query.version.unwrap_or_else(|| "latest".to_string());
+
+    let region = state
+        .storage
+        .find_region(&region_id)
+        .await
+        .api_context(|| "Failed to load regions".to_string())?
+        .ok_or_else(|| region_not_found(&region_id))?;
+
+    state
+        .storage
+        .get_file_path(&region_id, &version)
+        .await
+        .api_context(|| format!("Failed to get file path for {} {}", region_id, version))?
+        .ok_or_else(|| version_not_found(&region_id, &version))?;
+
+    let (job, created) = state
+        .jobs
+        .submit_analysis(region, version)
+        .await
+        .api_context(|| format!("Failed to queue analysis for region {}", region_id))?;
+
+    Ok((job_status(created), Json(job)))
+}
+
+/// Analyze the part of a region's latest data inside a bounding box and save the report
+///
+/// The report's `region_id` is `{region}@{min_lon},{min_lat},{max_lon},{max_lat}`,
+/// so sub-area reports stay apart from the region's own. The box cuts through
+/// boundaries and coastline, so it is analyzed as a subregion.
+pub async fn analyze_bbox(
+    Path(region_id): Path<String>,
+    Query(query): Query<BboxQuery>,
+    State(state): State<AppState>,
+) -> Result<Json<QualityReport>, ApiError> {
+    let bbox = parse_bbox(&query.bbox).ok_or_else(|| invalid_bbox(&query.bbox))?;
+    let extract_path = bbox_extract(&state, &region_id, &bbox, &query.bbox).await?;
+
+    let sub_area_id = format!(
+        "{}@{},{},{},{}",
+        region_id, bbox.min_lon, bbox.min_lat, bbox.max_lon, bbox.max_lat
+    );
+    let report = crate::jobs::analyze_data_file(
+        &state.storage,
+        &state.processor,
+        &crate::quality::QualityAnalyzer::default(),
+        &sub_area_id,
+        &AdminLevel::Subregion,
+        &extract_path,
+        &crate::jobs::JobLog::disabled(),
+    )
+    .await
+    .api_context(|| format!("Failed to analyze {}", sub_area_id))?;
+
+    Ok(Json(report))
+}
+
+/// Trigger processing for a specific region
+pub async fn trigger_processing(
+    Path(region_id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<(StatusCode, Json<ProcessingJob>), ApiError> {
+    let region = state
+        .storage
+        .find_region(&region_id)
+        .await
+        .api_context(|| "Failed to load regions".to_string())?
+        .ok_or_else(|| region_not_found(&region_id))?;
+
+    if let Err(e) = crate::osm::utils::download_url(&region) {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            "not_downloadable",
+            e.to_string(),
+        ));
+    }
+
+    let (job, created) = state
+        .jobs
+        .submit_download(region)
+        .await
+        .api_context(|| format!("Failed to queue job for region {}", region_id))?;
+
+    Ok((job_status(created), Json(job)))
+}
+
+/// Jobs listed when no `limit` is given
+const DEFAULT_JOB_LIST_SIZE: usize = 50;
+
+/// Query parameters for the job list
+#[derive(serde::Deserialize)]
+pub struct JobsQuery {
+    /// Only jobs in this state, e.g. `Running`
+    status: Option<JobStatus>,
+    limit: Option<usize>,
+}
+
+/// Most recently created jobs, newest first
+pub async fn list_jobs(
+    Query(query): Query<JobsQuery>,
+    State(storage): State<Storage>,
+) -> Result<Json<Vec<ProcessingJob>>, ApiError> {
+    let mut jobs = storage
+        .list_jobs(query.status)
+        .await
+        .api_context(|| "Failed to list jobs".to_string())?;
+    jobs.truncate(query.limit.unwrap_or(DEFAULT_JOB_LIST_SIZE));
+
+    Ok(Json(jobs))
 }
 
 /// Get processing status
 pub async fn get_processing_status(
     Path(job_id): Path<String>,
-    State(_storage): State<Storage>,
-) -> impl IntoResponse {
-    // Mock implementation - in reality, you'd look up the actual job
-    let job = ProcessingJob {
-        id: job_id,
-        region_id: "liechtenstein".to_string(),
-        job_type: JobType::Process,
-        status: JobStatus::Completed,
-        progress: 100.0,
-        message: Some("Processing completed successfully".to_string()),
-        created_at: chrono::Utc::now(),
-        started_at: Some(chrono::Utc::now()),
-        completed_at: Some(chrono::Utc::now()),
-        error_message: None,
+    State(storage): State<Storage>,
+) -> Result<Json<ProcessingJob>, ApiError> {
+    storage
+        .get_processing_job(&job_id)
+        .await
+        .api_context(|| format!("Failed to get job {}", job_id))?
+        .map(Json)
+        .ok_or_else(|| ApiError::not_found(format!("Job {} not found", job_id)))
+}
+
+/// Cancel a pending or running job
+///
+/// Answers `202 Accepted` with the job's current state; a running job is
+/// marked `Cancelled` once it reaches its next phase or download chunk.
+/// Jobs that already finished give `409 Conflict`.
+pub async fn cancel_job(
+    Path(job_id): Path<String>,
+    State(storage): State<Storage>,
+    State(jobs): State<JobQueue>,
+) -> Result<(StatusCode, Json<ProcessingJob>), ApiError> {
+    let job = storage
+        .get_processing_job(&job_id)
+        .await
+        .api_context(|| format!("Failed to get job {}", job_id))?
+        .ok_or_else(|| ApiError::not_found(format!("Job {} not found", job_id)))?;
+
+    if job.status.is_finished() || !jobs.cancel(&job_id) {
+        return Err(ApiError::new(
+            StatusCode::CONFLICT,
+            "job_finished",
+            format!("Job {} has already finished", job_id),
+        )
+        .with_detail(format!("status is {:?}", job.status)));
+    }
+
+    Ok((StatusCode::ACCEPTED, Json(job)))
+}
+
+/// Push a job's state over a WebSocket each time it changes
+///
+/// The current state is sent on connect; the socket is closed by the server
+/// once the job has finished. Jobs that are no longer active get their
+/// stored state once.
+pub async fn job_updates_ws(
+    Path(job_id): Path<String>,
+    ws: WebSocketUpgrade,
+    State(storage): State<Storage>,
+    State(jobs): State<JobQueue>,
+) -> Result<Response, ApiError> {
+    // Subscribe before loading so no update between the two is missed
+    let updates = jobs.subscribe(&job_id);
+    let job = storage
+        .get_processing_job(&job_id)
+        .await
+        .api_context(|| format!("Failed to get job {}", job_id))?
+        .ok_or_else(|| ApiError::not_found(format!("Job {} not found", job_id)))?;
+
+    Ok(ws.on_upgrade(move |socket| stream_job_updates(socket, job, updates, storage)))
+}
+
+async fn stream_job_updates(
+    mut socket: WebSocket,
+    job: ProcessingJob,
+    updates: Option<broadcast::Receiver<ProcessingJob>>,
+    storage: Storage,
+) {
+    let job_id = job.id.clone();
+    let finished = job.status.is_finished();
+    if send_job(&mut socket, &job).await.is_err() {
+        return;
+    }
+
+    if let (Some(mut updates), false) = (updates, finished) {
+        loop {
+            tokio::select! {
+                update = updates.recv() => match update {
+                    Ok(job) => {
+                        if send_job(&mut socket, &job).await.is_err() {
+                            return;
+                        }
+                        if job.status.is_finished() {
+                            break;
+                        }
+                    }
+                    // Intermediate progress was skipped; the next update catches up
+                    Err(broadcast::error::RecvError::Lagged(_)) => {}
+                    // The runner is done; its last state is in storage
+                    Err(broadcast::error::RecvError::Closed) => {
+                        if let Ok(Some(job)) = storage.get_processing_job(&job_id).await {
+                            let _ = send_job(&mut socket, &job).await;
+                        }
+                        break;
+                    }
+                },
+                message = socket.recv() => match message {
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+                    // Anything else the client sends is ignored
+                    Some(Ok(_)) => {}
+                },
+            }
+        }
+    }
+
+    let _ = socket.send(Message::Close(None)).await;
+}
+
+async fn send_job(socket: &mut WebSocket, job: &ProcessingJob) -> Result<(), axum::Error> {
+    let text = serde_json::to_string(job).map_err(axum::Error::new)?;
+    socket.send(Message::Text(text)).await
+}
+
+/// How often a followed job log is checked for new lines
+const JOB_LOG_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// Query parameters for a job's log
+#[derive(serde::Deserialize)]
+pub struct JobLogsQuery {
+    /// Keep the response open and stream new lines until the job finishes
+    follow: Option<bool>,
+}
+
+/// A job's log as JSON lines, one per phase change or warning
+///
+/// With `follow=true` the response stays open while the job is pending or
+/// running and ends once its last line has been sent.
+pub async fn get_job_logs(
+    Path(job_id): Path<String>,
+    Query(query): Query<JobLogsQuery>,
+    State(storage): State<Storage>,
+    State(jobs): State<JobQueue>,
+) -> Result<Response, ApiError> {
+    // Subscribe before loading so the job cannot finish unnoticed in between
+    let updates = jobs.subscribe(&job_id);
+    let job = storage
+        .get_processing_job(&job_id)
+        .await
+        .api_context(|| format!("Failed to get job {}", job_id))?
+        .ok_or_else(|| ApiError::not_found(format!("Job {} not found", job_id)))?;
+
+    let path = storage.job_log_path(&job_id);
+    let body = match updates {
+        Some(updates) if query.follow.unwrap_or(false) && !job.status.is_finished() => {
+            axum::body::Body::from_stream(follow_job_log(path, updates))
+        }
+        _ => axum::body::Body::from(
+            read_job_log(&path, 0)
+                .await
+                .api_context(|| format!("Failed to read log of job {}", job_id))?,
+        ),
     };
 
-    Json(job).into_response()
+    Ok(([(header::CONTENT_TYPE, "application/x-ndjson")], body).into_response())
+}
+
+/// Bytes of a job log from `offset` on; a job that has not logged anything has an empty log
+async fn read_job_log(path: &std::path::Path, offset: u64) -> std::io::Result<Vec<u8>> {
+    use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+    let mut file = match tokio::fs::File::open(path).await {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    file.seek(std::io::SeekFrom::Start(offset)).await?;
+    let mut chunk = Vec::new();
+    file.read_to_end(&mut chunk).await?;
+    Ok(chunk)
+}
+
+/// Stream a job log from the start, then each line appended until the job finishes
+fn follow_job_log(
+    path: std::path::PathBuf,
+    updates: broadcast::Receiver<ProcessingJob>,
+) -> impl futures::Stream<Item = std::io::Result<Vec<u8>>> {
+    // (path, bytes sent, updates while the job may still write lines)
+    let state = Some((path, 0u64, Some(updates)));
+    futures::stream::unfold(state, |state| async move {
+        let (path, mut offset, mut updates) = state?;
+        loop {
+            match read_job_log(&path, offset).await {
+                Ok(chunk) if !chunk.is_empty() => {
+                    offset += chunk.len() as u64;
+                    return Some((Ok(chunk), Some((path, offset, updates))));
+                }
+                Ok(_) => {}
+                Err(e) => return Some((Err(e), None)),
+            }
+
+            // The job has finished and every line it wrote has been sent
+            let receiver = updates.as_mut()?;
+            tokio::select! {
+                update = receiver.recv() => match update {
+                    Ok(job) if !job.status.is_finished() => {}
+                    Err(broadcast::error::RecvError::Lagged(_)) => {}
+                    // Lines are written before the final state is saved, so
+                    // one more read picks up the rest of the log
+                    _ => updates = None,
+                },
+                _ = tokio::time::sleep(JOB_LOG_POLL_INTERVAL) => {}
+            }
+        }
+    })
+}
+
+/// Query parameters for GeoJSON responses
+#[derive(serde::Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct GeoJsonQuery {
+    /// Decimal places kept in coordinates, 1 to 15 (default 7)
+    precision: Option<u8>,
+}
+
+/// Check a requested coordinate precision, defaulting to `DEFAULT_GEOJSON_PRECISION`
+fn geojson_precision(precision: Option<u8>) -> Result<u8, ApiError> {
+    let precision = precision.unwrap_or(crate::osm::DEFAULT_GEOJSON_PRECISION);
+    let range = crate::osm::GEOJSON_PRECISION_RANGE;
+    if !range.contains(&precision) {
+        return Err(
+            ApiError::bad_request("Invalid coordinate precision").with_detail(format!(
+                "precision must be between {} and {} decimal places, got {}",
+                range.start(),
+                range.end(),
+                precision
+            )),
+        );
+    }
+    Ok(precision)
 }
 
 /// Get region boundaries as GeoJSON for map display
 pub async fn get_region_boundaries(
     Path(region_id): Path<String>,
+    Query(query): Query<GeoJsonQuery>,
     State(storage): State<Storage>,
-) -> impl IntoResponse {
-    match storage.get_region(&region_id).await {
-        Ok(Some(region_tree)) => {
-            let region = &region_tree.region;
-            let bbox = &region.bounding_box;
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let precision = geojson_precision(query.precision)?;
+    let region_tree = storage
+        .get_region(&region_id)
+        .await
+        .api_context(|| format!("Failed to get region boundaries for {}", region_id))?
+        .ok_or_else(|| region_not_found(&region_id))?;
 
-            // Create a simple GeoJSON polygon from bounding box
-            let geojson = json!({
-                "type": "Feature",
-                "properties": {
-                    "id": region.id,
-                    "name": region.name,
-                    "admin_level": region.admin_level_num(),
-                    "area_km2": region.area_km2,
-                    "population": region.population
-                },
-                "geometry": {
-                    "type": "Polygon",
-                    "coordinates": [[
-                        [bbox.min_lon, bbox.min_lat],
-                        [bbox.max_lon, bbox.min_lat],
-                        [bbox.max_lon, bbox.max_lat],
-                        [bbox.min_lon, bbox.max_lat],
-                        [bbox.min_lon, bbox.min_lat]
-                    ]]
-                }
-            });
+    let region = &region_tree.region;
+    let (geometry, _) = region_geometry(region);
+
+    let mut geojson = json!({
+        "type": "Feature",
+        "properties": {
+            "id": region.id,
+            "name": region.name,
+            "admin_level": region.admin_level_num(),
+            "area_km2": region.area_km2,
+            "population": region.population
+        },
+        "geometry": geometry
+    });
+    crate::osm::utils::round_geojson_coordinates(&mut geojson, precision);
+
+    Ok(Json(geojson))
+}
+
+/// Download a region's stored boundary as a standalone GeoJSON Feature file
+///
+/// Regions without a stored boundary get their bounding box rectangle, flagged
+/// with `"approximate": true` so GIS clients can warn about it.
+#[utoipa::path(
+    get,
+    path = "/api/regions/{region_id}/boundary.geojson",
+    tag = "regions",
+    params(("region_id" = String, Path, description = "Region ID, e.g. `germany`"), GeoJsonQuery),
+    responses(
+        (status = 200, description = "The boundary as a GeoJSON Feature", content_type = "application/geo+json"),
+        (status = 400, description = "Invalid precision", body = super::error::ErrorBody),
+        (status = 404, description = "Region not found", body = super::error::ErrorBody)
+    )
+)]
+pub async fn download_region_boundary(
+    Path(region_id): Path<String>,
+    Query(query): Query<GeoJsonQuery>,
+    State(storage): State<Storage>,
+) -> Result<Response, ApiError> {
+    let precision = geojson_precision(query.precision)?;
+    let region = storage
+        .find_region(&region_id)
+        .await
+        .api_context(|| format!("Failed to get boundary of {}", region_id))?
+        .ok_or_else(|| region_not_found(&region_id))?;
+
+    let (geometry, approximate) = region_geometry(&region);
+    let mut feature = json!({
+        "type": "Feature",
+        "properties": {
+            "id": region.id,
+            "name": region.name,
+            "admin_level": region.admin_level_num(),
+            "parent_id": region.parent_id,
+            "approximate": approximate
+        },
+        "geometry": geometry
+    });
+    crate::osm::utils::round_geojson_coordinates(&mut feature, precision);
+
+    let filename = format!("{}.geojson", region.id.replace('/', "_"));
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/geo+json".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", filename),
+            ),
+        ],
+        feature.to_string(),
+    )
+        .into_response())
+}
+
+/// A region's GeoJSON geometry and whether it only approximates the boundary
+///
+/// Prefers the true boundary; falls back to polygons from the bounding box,
+/// split in two when it crosses the antimeridian.
+fn region_geometry(region: &Region) -> (serde_json::Value, bool) {
+    if let Some(geometry) = &region.geometry {
+        return (geometry.clone(), false);
+    }
 
-            Json(geojson).into_response()
+    let polygons: Vec<_> = region
+        .bounding_box
+        .split_at_antimeridian()
+        .iter()
+        .map(|part| {
+            json!([[
+                [part.min_lon, part.min_lat],
+                [part.max_lon, part.min_lat],
+                [part.max_lon, part.max_lat],
+                [part.min_lon, part.max_lat],
+                [part.min_lon, part.min_lat]
+            ]])
+        })
+        .collect();
+
+    let geometry = match polygons.as_slice() {
+        [polygon] => json!({ "type": "Polygon", "coordinates": polygon }),
+        _ => json!({ "type": "MultiPolygon", "coordinates": polygons }),
+    };
+    (geometry, true)
+}
+
+/// Compare the quality reports of two versions of a region's data
+pub async fn compare_versions(
+    Path(region_id): Path<String>,
+    Query(query): Query<CompareQuery>,
+    State(storage): State<Storage>,
+) -> Result<Json<RegionComparison>, ApiError> {
+    let (from_version, to_version) = (query.from.trim(), query.to.trim());
+    if from_version.is_empty() || to_version.is_empty() {
+        return Err(
+            ApiError::bad_request("Both 'from' and 'to' versions are required")
+                .with_detail(format!("got from='{}', to='{}'", query.from, query.to)),
+        );
+    }
+
+    for version in [from_version, to_version] {
+        let exists = storage
+            .get_file_path(&region_id, version)
+            .await
+            .api_context(|| format!("Failed to get file path for {} {}", region_id, version))?
+            .is_some();
+        if !exists {
+            let files = storage
+                .get_region_files(&region_id)
+                .await
+                .api_context(|| format!("Failed to list files for {}", region_id))?;
+            let available: Vec<String> = files.into_iter().map(|file| file.version).collect();
+            let detail = if available.is_empty() {
+                "no versions have been downloaded".to_string()
+            } else {
+                format!("available versions: {}", available.join(", "))
+            };
+            return Err(ApiError::bad_request(format!(
+                "Version '{}' of region {} does not exist",
+                version, region_id
+            ))
+            .with_detail(detail));
         }
-        Ok(None) => {
-            error!("Region {} not found", region_id);
-            StatusCode::NOT_FOUND.into_response()
+    }
+
+    match storage
+        .compare_versions(&region_id, from_version, to_version)
+        .await
+    {
+        Ok(comparison) => Ok(Json(comparison)),
+        // A version or its report is missing
+        Err(e) if matches!(e.downcast_ref(), Some(crate::RiemapError::Storage(_))) => {
+            Err(ApiError::not_found(e.to_string()))
         }
         Err(e) => {
-            error!("Failed to get region boundaries for {}: {}", region_id, e);
-            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            Err(ApiError::from(e).context(format!("Failed to compare versions of {}", region_id)))
         }
     }
 }
 
-/// Compare two versions of a region's data
-pub async fn compare_versions(
+/// Query parameters for the feature distribution of a version
+#[derive(serde::Deserialize)]
+pub struct FeaturesQuery {
+    version: Option<String>,
+}
+
+/// Feature mix of a version (latest by default), taken from its quality report
+pub async fn get_region_features(
     Path(region_id): Path<String>,
-    Query(params): Query<std::collections::HashMap<String, String>>,
-    State(_storage): State<Storage>,
-) -> impl IntoResponse {
-    let from_version = params.get("from").cloned().unwrap_or_default();
-    let to_version = params.get("to").cloned().unwrap_or_default();
+    Query(query): Query<FeaturesQuery>,
+    State(storage): State<Storage>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let version = query.version.unwrap_or_else(|| "latest".to_string());
 
-    // Mock comparison data
-    let comparison = json!({
+    let report = storage
+        .get_version_report(&region_id, &version)
+        .await
+        .api_context(|| format!("Failed to get quality report for {}", region_id))?
+        .ok_or_else(|| {
+            ApiError::not_found(format!("No quality report for version '{}'", version))
+        })?;
+
+    let metrics = &report.metrics;
+    Ok(Json(json!({
         "region_id": region_id,
-        "from_version": from_version,
-        "to_version": to_version,
-        "comparison_date": chrono::Utc::now().to_rfc3339(),
-        "changes": {
-            "added_features": 1247,
-            "modified_features": 532,
-            "deleted_features": 89,
-            "total_features_from": 15420,
-            "total_features_to": 16578
-        },
-        "change_details": [
-            {
-                "category": "Roads",
-                "added": 423,
-                "modified": 156,
-                "deleted": 23
-            },
-            {
-                "category": "Buildings",
-                "added": 589,
-                "modified": 234,
-                "deleted": 45
-            },
-            {
-                "category": "Points of Interest",
-                "added": 167,
-                "modified": 89,
-                "deleted": 12
-            },
-            {
-                "category": "Natural Features",
-                "added": 68,
-                "modified": 53,
-                "deleted": 9
-            }
-        ],
-        "file_size_change": 2457600,
-        "data_quality_change": 3.2
-    });
+        "version": version,
+        "report_id": report.id,
+        "features": metrics.feature_distribution,
+        "total_features": metrics.feature_distribution.total(),
+        "total_elements": metrics.total_elements(),
+        // Null for reports made before relation types were collected
+        "relation_types": metrics.relation_types()
+    })))
+}
 
-    Json(comparison).into_response()
+/// Query parameters for the tag frequencies of a version
+#[derive(serde::Deserialize)]
+pub struct TagsQuery {
+    version: Option<String>,
+}
+
+/// Most frequent tag keys and key=value pairs of a version (latest by default)
+pub async fn get_region_tags(
+    Path(region_id): Path<String>,
+    Query(query): Query<TagsQuery>,
+    State(storage): State<Storage>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let version = query.version.unwrap_or_else(|| "latest".to_string());
+
+    let report = storage
+        .get_version_report(&region_id, &version)
+        .await
+        .api_context(|| format!("Failed to get quality report for {}", region_id))?
+        .ok_or_else(|| {
+            ApiError::not_found(format!("No quality report for version '{}'", version))
+        })?;
+
+    // Reports from before tag statistics were collected lack the metric
+    let tag_frequency = report
+        .metrics
+        .custom_metrics
+        .get("tag_frequency")
+        .cloned()
+        .ok_or_else(|| {
+            ApiError::not_found(format!(
+                "Quality report for version '{}' has no tag statistics; re-run the analysis",
+                version
+            ))
+        })?;
+
+    Ok(Json(json!({
+        "region_id": region_id,
+        "version": version,
+        "report_id": report.id,
+        "tag_frequency": tag_frequency
+    })))
+}
+
+/// Query parameters for the road lengths of a version
+#[derive(serde::Deserialize)]
+pub struct RoadsQuery {
+    version: Option<String>,
+}
+
+/// Road network length per `highway=*` class of a version (latest by default),
+/// taken from its quality report
+pub async fn get_region_roads(
+    Path(region_id): Path<String>,
+    Query(query): Query<RoadsQuery>,
+    State(storage): State<Storage>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let version = query.version.unwrap_or_else(|| "latest".to_string());
+
+    let report = storage
+        .get_version_report(&region_id, &version)
+        .await
+        .api_context(|| format!("Failed to get quality report for {}", region_id))?
+        .ok_or_else(|| {
+            ApiError::not_found(format!("No quality report for version '{}'", version))
+        })?;
+
+    // Missing from older reports and from analyses run with road lengths off
+    let custom_metrics = &report.metrics.custom_metrics;
+    let road_km = custom_metrics.get("road_km").ok_or_else(|| {
+        ApiError::not_found(format!(
+            "Quality report for version '{}' has no road lengths; re-run the analysis",
+            version
+        ))
+    })?;
+    let total_km: f64 = road_km
+        .as_object()
+        .map(|classes| classes.values().filter_map(|km| km.as_f64()).sum())
+        .unwrap_or(0.0);
+
+    Ok(Json(json!({
+        "region_id": region_id,
+        "version": version,
+        "report_id": report.id,
+        "total_km": total_km,
+        "road_km": road_km,
+        "incomplete_segments": custom_metrics.get("road_incomplete_segments")
+    })))
+}
+
+/// Elements per type the sample endpoint returns by default
+const DEFAULT_SAMPLE_SIZE: usize = 20;
+/// Largest `n` the sample endpoint honours, keeping responses small
+const MAX_SAMPLE_SIZE: usize = 100;
+
+/// Query parameters for element sampling
+#[derive(serde::Deserialize)]
+pub struct SampleQuery {
+    /// Elements per type, capped at `MAX_SAMPLE_SIZE`
+    n: Option<usize>,
+}
+
+/// The first tagged nodes, ways and relations of a region's latest data,
+/// for a quick look at what a file contains
+pub async fn get_region_sample(
+    Path(region_id): Path<String>,
+    Query(query): Query<SampleQuery>,
+    State(storage): State<Storage>,
+    State(processor): State<OsmProcessor>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let n = query
+        .n
+        .unwrap_or(DEFAULT_SAMPLE_SIZE)
+        .clamp(1, MAX_SAMPLE_SIZE);
+
+    let path = storage
+        .get_file_path(&region_id, "latest")
+        .await
+        .api_context(|| format!("Failed to get file path for {}", region_id))?
+        .ok_or_else(|| version_not_found(&region_id, "latest"))?;
+
+    let elements = processor
+        .sample_elements(&path, n)
+        .await
+        .api_context(|| format!("Failed to sample elements of {}", region_id))?;
+
+    Ok(Json(json!({
+        "region_id": region_id,
+        "n": n,
+        "elements": elements
+    })))
+}
+
+/// Default and allowed range of the density grid cell size, in degrees
+const DEFAULT_DENSITY_CELL_DEG: f64 = 0.05;
+const DENSITY_CELL_DEG_RANGE: std::ops::RangeInclusive<f64> = 0.01..=10.0;
+
+/// Most cells a density grid may span over a region's bounding box
+const MAX_DENSITY_CELLS: f64 = 250_000.0;
+
+/// Smallest cell size, rounded up to 0.01°, keeping a grid over `bbox`
+/// within [`MAX_DENSITY_CELLS`]
+fn min_density_cell_deg(bbox: &BoundingBox) -> f64 {
+    let area = (bbox.max_lat - bbox.min_lat).max(0.0) * bbox.lon_span();
+    ((area / MAX_DENSITY_CELLS).sqrt() * 100.0).ceil() / 100.0
+}
+
+/// Query parameters for the node density grid
+#[derive(serde::Deserialize)]
+pub struct DensityQuery {
+    cell: Option<f64>,
+    /// `geojson` for a FeatureCollection of cell polygons, compact arrays otherwise
+    format: Option<String>,
+    /// Decimal places kept in GeoJSON coordinates
+    precision: Option<u8>,
+}
+
+/// Node counts per grid cell of a region's latest data, for heatmaps
+///
+/// The compact form lists `[min_lon, min_lat, count]` per occupied cell;
+/// each cell spans `cell_deg` degrees from that corner. Cells are enlarged
+/// for large regions so the grid stays within [`MAX_DENSITY_CELLS`], and
+/// grids are cached until the region's data changes.
+pub async fn get_region_density(
+    Path(region_id): Path<String>,
+    Query(query): Query<DensityQuery>,
+    State(state): State<AppState>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let mut cell_deg = query.cell.unwrap_or(DEFAULT_DENSITY_CELL_DEG);
+    if !DENSITY_CELL_DEG_RANGE.contains(&cell_deg) {
+        return Err(
+            ApiError::bad_request("Invalid cell size").with_detail(format!(
+                "cell must be between {} and {} degrees, got {}",
+                DENSITY_CELL_DEG_RANGE.start(),
+                DENSITY_CELL_DEG_RANGE.end(),
+                cell_deg
+            )),
+        );
+    }
+    let precision = geojson_precision(query.precision)?;
+
+    let storage = &state.storage;
+    if let Some(region) = storage
+        .find_region(&region_id)
+        .await
+        .api_context(|| "Failed to load regions".to_string())?
+    {
+        cell_deg = cell_deg.max(min_density_cell_deg(&region.bounding_box));
+    }
+
+    let path = storage
+        .get_file_path(&region_id, "latest")
+        .await
+        .api_context(|| format!("Failed to get file path for {}", region_id))?
+        .ok_or_else(|| version_not_found(&region_id, "latest"))?;
+
+    let grid_path = storage
+        .density_grid_path(&region_id, &path, cell_deg)
+        .api_context(|| format!("Failed to resolve density cache path for {:?}", path))?;
+
+    state
+        .cache_builds
+        .get_or_build(&grid_path, || async {
+            let grid = state.processor.node_density_grid(&path, cell_deg).await?;
+            let dir = grid_path.parent().unwrap_or(std::path::Path::new("."));
+            tokio::fs::create_dir_all(dir).await?;
+            let temp_file = TempFile::new(dir, "density.tmp");
+            tokio::fs::write(temp_file.path(), serde_json::to_vec(&grid)?).await?;
+            temp_file.persist(&grid_path)
+        })
+        .await
+        .api_context(|| format!("Failed to compute node density for {}", region_id))?;
+
+    let grid: Vec<(BoundingBox, u64)> = async {
+        let bytes = tokio::fs::read(&grid_path).await?;
+        crate::Result::Ok(serde_json::from_slice(&bytes)?)
+    }
+    .await
+    .api_context(|| format!("Failed to read density grid {:?}", grid_path))?;
+
+    if query.format.as_deref() == Some("geojson") {
+        let features: Vec<_> = grid
+            .iter()
+            .map(|(bbox, count)| {
+                json!({
+                    "type": "Feature",
+                    "properties": { "count": count },
+                    "geometry": {
+                        "type": "Polygon",
+                        "coordinates": [[
+                            [bbox.min_lon, bbox.min_lat],
+                            [bbox.max_lon, bbox.min_lat],
+                            [bbox.max_lon, bbox.max_lat],
+                            [bbox.min_lon, bbox.max_lat],
+                            [bbox.min_lon, bbox.min_lat]
+                        ]]
+                    }
+                })
+            })
+            .collect();
+
+        let mut geojson = json!({
+            "type": "FeatureCollection",
+            "features": features
+        });
+        crate::osm::utils::round_geojson_coordinates(&mut geojson, precision);
+        return Ok(Json(geojson));
+    }
+
+    let max_count = grid.iter().map(|(_, count)| *count).max().unwrap_or(0);
+    let cells: Vec<_> = grid
+        .iter()
+        .map(|(bbox, count)| json!([bbox.min_lon, bbox.min_lat, count]))
+        .collect();
+
+    Ok(Json(json!({
+        "region_id": region_id,
+        "cell_deg": cell_deg,
+        "max_count": max_count,
+        "cells": cells
+    })))
 }
 
 /// Get map tiles endpoint (placeholder for future vector tile support)
-pub async fn get_map_tiles(Path((z, x, y)): Path<(u8, u32, u32)>) -> impl IntoResponse {
-    // This would serve vector tiles in MVT format
+///
+/// Tiles already in the cache are served from disk; rendering, which would
+/// store new tiles with `TileCache::put`, is not implemented yet.
+pub async fn get_map_tiles(
+    Path((z, x, y)): Path<(u8, u32, u32)>,
+    State(storage): State<Storage>,
+) -> Result<Response, ApiError> {
+    if z > 30 || x >> z > 0 || y >> z > 0 {
+        return Err(ApiError::bad_request(format!(
+            "Tile {}/{}/{} is outside the zoom level's grid",
+            z, x, y
+        )));
+    }
+
+    if let Some(tile) = storage
+        .tile_cache()
+        .get((z, x, y))
+        .await
+        .api_context(|| format!("Failed to read cached tile {}/{}/{}", z, x, y))?
+    {
+        return Ok((
+            [(header::CONTENT_TYPE, "application/vnd.mapbox-vector-tile")],
+            tile,
+        )
+            .into_response());
+    }
+
+    // This would render vector tiles in MVT format
     // For now, return a placeholder
-    (
+    Err(ApiError::new(
         StatusCode::NOT_IMPLEMENTED,
-        Json(json!({
-            "error": "Map tiles not implemented yet",
-            "tile": format!("{}/{}/{}", z, x, y)
-        })),
+        "not_implemented",
+        "Map tiles not implemented yet",
     )
-        .into_response()
+    .with_detail(format!("tile {}/{}/{}", z, x, y)))
+}
+
+/// Query parameters for pruning a region's old versions
+#[derive(serde::Deserialize)]
+pub struct PruneQuery {
+    /// Newest versions kept; `latest` is never removed
+    keep: usize,
+}
+
+/// Delete all but the newest `keep` versions of a region's data files, as
+/// the scheduler does, then drop blobs no region links to any more
+pub async fn prune_region_versions(
+    Path(region_id): Path<String>,
+    Query(query): Query<PruneQuery>,
+    State(storage): State<Storage>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    if query.keep == 0 {
+        return Err(ApiError::bad_request("keep must be at least 1"));
+    }
+    if storage
+        .find_region(&region_id)
+        .await
+        .api_context(|| format!("Failed to get region {}", region_id))?
+        .is_none()
+    {
+        return Err(region_not_found(&region_id));
+    }
+
+    let removed = storage
+        .prune_old_versions(&region_id, query.keep)
+        .await
+        .api_context(|| format!("Failed to prune versions of {}", region_id))?;
+    let (blobs, freed_bytes) = storage
+        .remove_unused_blobs()
+        .await
+        .api_context(|| "Failed to remove unused blobs".to_string())?;
+
+    let removed: Vec<_> = removed
+        .iter()
+        .filter_map(|path| path.file_name())
+        .map(|name| name.to_string_lossy().to_string())
+        .collect();
+    Ok(Json(json!({
+        "region_id": region_id,
+        "removed_files": removed,
+        "removed_blobs": blobs,
+        "freed_blob_bytes": freed_bytes
+    })))
+}
+
+/// Delete every cached map tile
+pub async fn clear_tile_cache(
+    State(storage): State<Storage>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let (tiles, bytes) = storage
+        .tile_cache()
+        .clear()
+        .await
+        .api_context(|| "Failed to clear the tile cache".to_string())?;
+    Ok(Json(
+        json!({ "removed_tiles": tiles, "freed_bytes": bytes }),
+    ))
 }
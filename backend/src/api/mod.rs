@@ -1,61 +1,260 @@
+pub mod auth;
+pub mod cache_builds;
+pub mod error;
 pub mod handlers;
+pub mod openapi;
+pub mod rate_limit;
 
 use axum::{
-    routing::{get, post},
+    body::Body,
+    extract::FromRef,
+    http::Request,
+    middleware,
+    routing::{delete, get, post, put},
     Router,
 };
-use tower_http::cors::CorsLayer;
+use tower::ServiceBuilder;
+use tower_http::{
+    compression::{
+        predicate::{NotForContentType, Predicate},
+        CompressionLayer, DefaultPredicate,
+    },
+    cors::CorsLayer,
+    request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer},
+    trace::{DefaultOnResponse, TraceLayer},
+};
+use tracing::Level;
+
+use crate::{config::RateLimitConfig, jobs::JobQueue, osm::OsmProcessor, storage::Storage};
+use auth::ApiKeys;
+use cache_builds::CacheBuilds;
+use rate_limit::RateLimiter;
+
+/// Shared state available to all handlers
+#[derive(Clone)]
+pub struct AppState {
+    pub storage: Storage,
+    pub processor: OsmProcessor,
+    pub jobs: JobQueue,
+    /// Free disk space below which the readiness check fails
+    pub min_free_disk_bytes: u64,
+    pub rate_limit: RateLimitConfig,
+    /// Keys required by the mutating endpoints; empty leaves them open
+    pub api_keys: ApiKeys,
+    /// Redirect downloads of files not held locally to Geofabrik (or the configured mirror)
+    pub redirect_missing_downloads: bool,
+    /// On-demand builds of cached files, one per file and a few at a time
+    pub cache_builds: CacheBuilds,
+}
 
-use crate::storage::Storage;
+impl FromRef<AppState> for Storage {
+    fn from_ref(state: &AppState) -> Self {
+        state.storage.clone()
+    }
+}
+
+impl FromRef<AppState> for JobQueue {
+    fn from_ref(state: &AppState) -> Self {
+        state.jobs.clone()
+    }
+}
+
+impl FromRef<AppState> for OsmProcessor {
+    fn from_ref(state: &AppState) -> Self {
+        state.processor.clone()
+    }
+}
 
 /// API query parameters for version comparison
 #[derive(serde::Deserialize)]
 pub struct CompareQuery {
     pub from: String,
     pub to: String,
+    /// Maximum number of sample IDs returned per change category (diff
+    /// only), capped at 1000
+    pub limit: Option<usize>,
 }
 
 /// Create the API router with all endpoints
-pub fn create_router(storage: Storage) -> Router {
+pub fn create_router(state: AppState) -> Router {
+    // Endpoints that start jobs or change server state need an API key when keys are configured
+    let protected_routes = Router::new()
+        .route(
+            "/regions/:region_id/process",
+            post(handlers::trigger_processing),
+        )
+        .route(
+            "/regions/:region_id/analyze",
+            post(handlers::trigger_analysis),
+        )
+        .route(
+            "/regions/:region_id/analyze-bbox",
+            post(handlers::analyze_bbox),
+        )
+        .route(
+            "/regions/:region_id/files/:version",
+            put(handlers::upload_region_file),
+        )
+        .route(
+            "/regions/:region_id/prune",
+            post(handlers::prune_region_versions),
+        )
+        .route("/jobs/:job_id/cancel", post(handlers::cancel_job))
+        .route("/admin/refresh-tree", post(handlers::refresh_region_tree))
+        .route("/tiles/cache", delete(handlers::clear_tile_cache))
+        .route_layer(middleware::from_fn_with_state(
+            state.api_keys.clone(),
+            auth::require_api_key,
+        ));
+
     let api_routes = Router::new()
         // Health check
         .route("/health", get(handlers::health_check))
+        .route("/health/live", get(handlers::liveness_check))
+        // API description
+        .route("/openapi.json", get(openapi::openapi_json))
+        .route("/docs", get(openapi::swagger_ui))
         // Region endpoints
         .route("/regions", get(handlers::get_regions))
         .route("/regions/search", get(handlers::search_regions))
+        .route("/regions/by-iso/:code", get(handlers::get_regions_by_iso))
+        .route("/regions/stream", get(handlers::stream_regions))
+        .route("/regions/changed", get(handlers::get_changed_regions))
+        .route("/locate", get(handlers::locate_point))
+        .route("/manifest", get(handlers::get_manifest))
+        .route("/regions.csv", get(handlers::export_regions_csv))
         .route("/regions/:region_id", get(handlers::get_region))
+        .route(
+            "/regions/:region_id/children",
+            get(handlers::get_region_children),
+        )
         .route("/regions/:region_id/files", get(handlers::get_region_files))
         .route(
             "/regions/:region_id/boundaries",
             get(handlers::get_region_boundaries),
         )
         .route(
-            "/regions/:region_id/compare",
-            get(handlers::compare_versions),
+            "/regions/:region_id/boundary.geojson",
+            get(handlers::download_region_boundary),
         )
         .route(
-            "/regions/:region_id/process",
-            post(handlers::trigger_processing),
+            "/regions/:region_id/quality",
+            get(handlers::get_region_quality),
+        )
+        .route(
+            "/regions/:region_id/history",
+            get(handlers::get_region_history),
+        )
+        .route(
+            "/regions/:region_id/features",
+            get(handlers::get_region_features),
+        )
+        .route("/regions/:region_id/tags", get(handlers::get_region_tags))
+        .route("/regions/:region_id/roads", get(handlers::get_region_roads))
+        .route(
+            "/regions/:region_id/density",
+            get(handlers::get_region_density),
+        )
+        .route(
+            "/regions/:region_id/sample",
+            get(handlers::get_region_sample),
         )
+        .route(
+            "/regions/:region_id/compare",
+            get(handlers::compare_versions),
+        )
+        .route("/regions/:region_id/diff", get(handlers::diff_versions))
         // Quality reports
         .route("/reports/:report_id", get(handlers::get_quality_report))
         // Processing jobs
+        .route("/jobs", get(handlers::list_jobs))
         .route("/jobs/:job_id", get(handlers::get_processing_status))
+        .route("/jobs/:job_id/ws", get(handlers::job_updates_ws))
+        .route("/jobs/:job_id/logs", get(handlers::get_job_logs))
         // Statistics
         .route("/stats", get(handlers::get_stats))
-        // Map tiles (placeholder)
-        .route("/tiles/:z/:x/:y", get(handlers::get_map_tiles));
+        .route("/stats/by-continent", get(handlers::get_stats_by_continent))
+        // Map tiles (served from the tile cache; rendering is a placeholder)
+        .route("/tiles/:z/:x/:y", get(handlers::get_map_tiles))
+        // Processing triggers and administration
+        .merge(protected_routes)
+        .route_layer(middleware::from_fn_with_state(
+            RateLimiter::new(state.rate_limit.api_requests_per_minute, 0),
+            rate_limit::rate_limit,
+        ));
 
-    Router::new()
-        // Mount API routes under /api prefix
-        .nest("/api", api_routes)
-        // Download endpoints (not under /api prefix to match Next.js config)
+    // Download endpoints (not under /api prefix to match Next.js config)
+    let download_routes = Router::new()
+        .route(
+            "/download/:region_id/bbox",
+            get(handlers::download_bbox_extract),
+        )
+        .route(
+            "/download/:region_id/bundle",
+            get(handlers::download_bundle),
+        )
         .route(
             "/download/:region_id/:version",
             get(handlers::download_file),
         )
+        // Under /api, but serves files, so it shares the download limits
+        .route(
+            "/api/regions/:region_id/download-redirect",
+            get(handlers::download_or_redirect),
+        )
+        // Stricter limits: each download can saturate a lot of bandwidth
+        .route_layer(middleware::from_fn_with_state(
+            RateLimiter::new(
+                state.rate_limit.download_requests_per_minute,
+                state.rate_limit.max_concurrent_downloads,
+            ),
+            rate_limit::rate_limit,
+        ));
+
+    Router::new()
+        // Mount API routes under /api prefix
+        .nest("/api", api_routes)
+        .merge(download_routes)
+        // Compress JSON responses, but not downloads which are already compressed
+        .layer(
+            CompressionLayer::new().compress_when(
+                DefaultPredicate::new()
+                    .and(NotForContentType::const_new("application/octet-stream"))
+                    .and(NotForContentType::const_new("application/zip"))
+                    .and(NotForContentType::const_new(
+                        "application/vnd.openstreetmap.data+pbf",
+                    )),
+            ),
+        )
         // Add CORS middleware
         .layer(CorsLayer::permissive())
-        // Add storage state
-        .with_state(storage)
+        // Tag every request with an x-request-id, log it, and echo the ID back
+        .layer(
+            ServiceBuilder::new()
+                .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
+                .layer(
+                    TraceLayer::new_for_http()
+                        .make_span_with(request_span)
+                        .on_response(DefaultOnResponse::new().level(Level::INFO)),
+                )
+                .layer(PropagateRequestIdLayer::x_request_id()),
+        )
+        // Add shared state
+        .with_state(state)
+}
+
+/// Tracing span for a request; handler logs inherit its `request_id` field
+fn request_span(request: &Request<Body>) -> tracing::Span {
+    let request_id = request
+        .headers()
+        .get("x-request-id")
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("unknown");
+
+    tracing::info_span!(
+        "request",
+        method = %request.method(),
+        uri = %request.uri(),
+        request_id = %request_id,
+    )
 }
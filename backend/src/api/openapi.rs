@@ -0,0 +1,96 @@
+use axum::response::{Html, Json};
+use utoipa::OpenApi;
+
+use super::{error::ErrorBody, handlers};
+use crate::models::*;
+
+/// OpenAPI 3 description of the read-only endpoints, generated from the
+/// handlers' `#[utoipa::path]` annotations and the models' schemas
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Riemap API",
+        description = "Geofabrik-style regions, their OSM extracts and quality reports"
+    ),
+    paths(
+        handlers::health_check,
+        handlers::liveness_check,
+        handlers::get_regions,
+        handlers::search_regions,
+        handlers::get_regions_by_iso,
+        handlers::stream_regions,
+        handlers::get_changed_regions,
+        handlers::locate_point,
+        handlers::export_regions_csv,
+        handlers::get_region,
+        handlers::get_region_children,
+        handlers::get_region_files,
+        handlers::download_region_boundary,
+        handlers::get_region_quality,
+        handlers::get_region_history,
+        handlers::get_stats,
+        handlers::get_stats_by_continent,
+        handlers::download_file,
+        handlers::download_or_redirect,
+        handlers::download_bbox_extract,
+        handlers::download_bundle,
+        handlers::get_manifest,
+    ),
+    components(schemas(
+        Region,
+        AdminLevel,
+        BoundingBox,
+        GeofabrikUrls,
+        RegionTree,
+        DataFile,
+        DataFormat,
+        DownloadStats,
+        QualityReport,
+        QualityMetrics,
+        FeatureDistribution,
+        FreshnessMetrics,
+        AmenityAudit,
+        ElementSample,
+        QualityIssue,
+        IssueSeverity,
+        QualityHistory,
+        QualityHistoryPoint,
+        ContinentStats,
+        ErrorBody,
+    )),
+    tags(
+        (name = "health", description = "Liveness and readiness checks"),
+        (name = "regions", description = "Region hierarchy and search"),
+        (name = "quality", description = "Quality reports of region data"),
+        (name = "stats", description = "Dataset statistics"),
+        (name = "downloads", description = "Data file downloads")
+    )
+)]
+pub struct ApiDoc;
+
+/// The OpenAPI document
+pub async fn openapi_json() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+/// Swagger UI for the OpenAPI document; its assets are loaded from unpkg
+pub async fn swagger_ui() -> Html<&'static str> {
+    Html(SWAGGER_UI_PAGE)
+}
+
+const SWAGGER_UI_PAGE: &str = r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+  <meta charset="utf-8">
+  <title>Riemap API</title>
+  <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css">
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js"></script>
+  <script>
+    window.ui = SwaggerUIBundle({ url: "/api/openapi.json", dom_id: "#swagger-ui" });
+  </script>
+</body>
+</html>
+"##;
@@ -0,0 +1,155 @@
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, Request, State},
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use futures::StreamExt;
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+use super::error::ApiError;
+
+/// Length of the window `requests_per_minute` is counted over
+const WINDOW: Duration = Duration::from_secs(60);
+
+/// Number of tracked clients above which idle entries are dropped
+const PRUNE_THRESHOLD: usize = 10_000;
+
+/// Per-IP request limits; a limit of 0 disables that check
+#[derive(Clone)]
+pub struct RateLimiter {
+    requests_per_minute: u32,
+    max_concurrent: u32,
+    clients: Arc<Mutex<HashMap<IpAddr, ClientState>>>,
+}
+
+/// Requests seen from one client in the current window
+struct ClientState {
+    window_start: Instant,
+    requests: u32,
+    in_flight: u32,
+}
+
+/// Releases a client's concurrency slot when the response body is dropped
+struct InFlightGuard {
+    limiter: RateLimiter,
+    ip: IpAddr,
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        let mut clients = self.limiter.clients.lock().unwrap();
+        if let Some(client) = clients.get_mut(&self.ip) {
+            client.in_flight = client.in_flight.saturating_sub(1);
+        }
+    }
+}
+
+impl RateLimiter {
+    /// Create a limiter allowing `requests_per_minute` requests and
+    /// `max_concurrent` open responses per client IP
+    pub fn new(requests_per_minute: u32, max_concurrent: u32) -> Self {
+        Self {
+            requests_per_minute,
+            max_concurrent,
+            clients: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Admit a request, or return how long the client should wait
+    fn acquire(&self, ip: IpAddr) -> Result<Option<InFlightGuard>, Duration> {
+        let now = Instant::now();
+        let mut clients = self.clients.lock().unwrap();
+
+        if clients.len() > PRUNE_THRESHOLD {
+            clients.retain(|_, client| {
+                client.in_flight > 0 || now.duration_since(client.window_start) < WINDOW
+            });
+        }
+
+        let client = clients.entry(ip).or_insert(ClientState {
+            window_start: now,
+            requests: 0,
+            in_flight: 0,
+        });
+
+        let elapsed = now.duration_since(client.window_start);
+        if elapsed >= WINDOW {
+            client.window_start = now;
+            client.requests = 0;
+        }
+
+        if self.requests_per_minute > 0 && client.requests >= self.requests_per_minute {
+            return Err(WINDOW.saturating_sub(now.duration_since(client.window_start)));
+        }
+        if self.max_concurrent > 0 && client.in_flight >= self.max_concurrent {
+            // No way to know when a download finishes; suggest a short wait
+            return Err(Duration::from_secs(1));
+        }
+
+        client.requests += 1;
+        if self.max_concurrent == 0 {
+            return Ok(None);
+        }
+
+        client.in_flight += 1;
+        Ok(Some(InFlightGuard {
+            limiter: self.clone(),
+            ip,
+        }))
+    }
+}
+
+/// Middleware enforcing a `RateLimiter`, answering `429` with `Retry-After` when exceeded
+///
+/// Clients are identified by peer address, so the server must be started
+/// with connect info; requests without it are not limited.
+pub async fn rate_limit(
+    State(limiter): State<RateLimiter>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(ConnectInfo(addr)) = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .copied()
+    else {
+        return next.run(request).await;
+    };
+
+    let guard = match limiter.acquire(addr.ip()) {
+        Ok(guard) => guard,
+        Err(retry_after) => {
+            let seconds = retry_after.as_secs().max(1);
+            warn!("Rate limit exceeded by {} on {}", addr.ip(), request.uri());
+            return (
+                [(header::RETRY_AFTER, seconds.to_string())],
+                ApiError::new(
+                    StatusCode::TOO_MANY_REQUESTS,
+                    "rate_limited",
+                    "Too many requests",
+                )
+                .with_detail(format!("Retry after {} seconds", seconds)),
+            )
+                .into_response();
+        }
+    };
+
+    let response = next.run(request).await;
+    let Some(guard) = guard else {
+        return response;
+    };
+
+    // Keep the slot until the body has been fully streamed (or the client hangs up)
+    let (parts, body) = response.into_parts();
+    let body = body.into_data_stream().map(move |chunk| {
+        let _ = &guard;
+        chunk
+    });
+    Response::from_parts(parts, Body::from_stream(body))
+}
@@ -1,10 +1,20 @@
-use chrono::Utc;
-use clap::{Parser, Subcommand};
+use chrono::NaiveDate;
+use clap::{Parser, Subcommand, ValueEnum};
+use futures::StreamExt;
 use riemap_backend::{
-    config::Config, osm::OsmProcessor, quality::QualityAnalyzer, storage::Storage,
+    config::Config,
+    jobs, logging,
+    models::{count_text, AdminLevel, IssueSeverity, RegionComparison},
+    node_index::NodeIndexStorage,
+    osm::{self, OsmProcessor, ProcessFilter, ProcessingOptions},
+    quality::{self, QualityAnalyzer},
+    shapefile::ShapeGeometry,
+    storage::Storage,
 };
-use tracing::info;
-use tracing_subscriber;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tracing::{info, warn};
 
 #[derive(Parser)]
 #[command(name = "riemap-processor")]
@@ -20,6 +30,29 @@ enum Commands {
     Download {
         /// Region ID to download
         region: String,
+        /// Skip MD5 verification (for mirrors that don't publish .md5 files)
+        #[arg(long)]
+        no_verify: bool,
+        /// Download the dated historical extract for this day (YYYY-MM-DD) instead of the latest
+        #[arg(long)]
+        date: Option<NaiveDate>,
+        /// Ignore the download size limit (RIEMAP_MAX_FILE_SIZE), e.g. for continent extracts
+        #[arg(long)]
+        no_size_limit: bool,
+    },
+    /// Download the latest data of many regions, e.g. to set up a mirror, without analyzing it
+    DownloadBatch {
+        /// File with one region ID per line; blank lines and `#` comments are skipped
+        file: PathBuf,
+        /// Regions downloaded at the same time
+        #[arg(long, default_value_t = 2)]
+        concurrency: usize,
+        /// Skip MD5 verification (for mirrors that don't publish .md5 files)
+        #[arg(long)]
+        no_verify: bool,
+        /// Ignore the download size limit (RIEMAP_MAX_FILE_SIZE), e.g. for continent extracts
+        #[arg(long)]
+        no_size_limit: bool,
     },
     /// Process OSM data and generate quality reports
     Process {
@@ -28,6 +61,90 @@ enum Commands {
         /// Optional specific version to process
         #[arg(long)]
         version: Option<String>,
+        /// Additional report format written next to the JSON report
+        #[arg(long, value_enum, default_value_t = ReportFormat::Json)]
+        report_format: ReportFormat,
+        /// Skip the coastline check for landlocked countries
+        #[arg(long)]
+        landlocked: bool,
+        /// Only collect single-pass metrics, skipping polygon, member and duplicate checks and road lengths
+        #[arg(long)]
+        aggregates_only: bool,
+        /// Keep node coordinates in a temp file instead of memory (slower, for huge extracts)
+        #[arg(long)]
+        disk_index: bool,
+        /// Worker threads decoding and checking PBF blocks (0: one per core)
+        #[arg(long)]
+        threads: Option<usize>,
+        /// Element types to read, e.g. `ways,relations`; counts of the others are left unset
+        #[arg(long)]
+        elements: Option<ProcessFilter>,
+    },
+    /// Validate an existing file without generating reports
+    Validate {
+        /// Region ID to validate
+        region: String,
+        /// Optional specific version to validate
+        #[arg(long)]
+        version: Option<String>,
+        /// Decode every element instead of stopping after the first blocks
+        #[arg(long)]
+        full: bool,
+    },
+    /// Export a region's latest data to another format
+    Export {
+        /// Region ID to export
+        region: String,
+        /// Output format
+        #[arg(long, value_enum)]
+        format: ExportFormat,
+        /// Output directory (defaults to <data_dir>/exports/<region>)
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Cut a region's latest extract down to the area of an Osmosis .poly file
+    Extract {
+        /// Region ID to extract from
+        region: String,
+        /// Polygon file with the area to keep (outer rings and `!` holes)
+        #[arg(long)]
+        poly: PathBuf,
+        /// Output PBF (defaults to <data_dir>/exports/<region>/<poly name>.osm.pbf)
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Merge several PBF extracts into one, keeping each element once
+    Merge {
+        /// Output PBF
+        #[arg(long)]
+        out: PathBuf,
+        /// Input PBF files; for elements in several files the highest version wins
+        #[arg(required = true, num_args = 2..)]
+        inputs: Vec<PathBuf>,
+    },
+    /// Compare the quality reports of two versions of a region
+    Compare {
+        /// Region ID to compare
+        region: String,
+        /// Older version
+        #[arg(long)]
+        from: String,
+        /// Newer version
+        #[arg(long)]
+        to: String,
+        /// Print the comparison as JSON instead of a table
+        #[arg(long)]
+        json: bool,
+    },
+    /// Apply Geofabrik's daily diffs to a region's latest extract
+    Update {
+        /// Region ID to update
+        region: String,
+    },
+    /// Re-point a region's latest.osm.pbf at its newest downloaded version
+    Repair {
+        /// Region ID to repair
+        region: String,
     },
     /// Initialize the data directory with sample data
     Init,
@@ -35,25 +152,63 @@ enum Commands {
     List,
 }
 
+#[derive(Clone, Copy, ValueEnum)]
+enum ReportFormat {
+    Json,
+    Html,
+    Csv,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ExportFormat {
+    Shapefile,
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Initialize tracing
-    tracing_subscriber::fmt::init();
-
     let cli = Cli::parse();
 
     // Load configuration
     let config = Config::from_env();
+    logging::init(&config.logging);
 
     // Initialize components
-    let storage = Storage::new(&config.storage.data_dir)?;
-    let processor = OsmProcessor::new(&config.storage.data_dir, &config.storage.temp_dir)?;
-    let analyzer = QualityAnalyzer;
+    let storage = Storage::new(&config.storage.data_dir)?
+        .with_geofabrik_config(config.geofabrik.clone())
+        .with_layout(config.storage.layout)
+        .with_tile_cache_limit(config.storage.tile_cache_max_mb * 1_048_576)
+        .with_metadata_recovery(config.storage.recover_corrupt_metadata)
+        .with_metadata_backend(config.storage.metadata_backend)?;
+    let processor = OsmProcessor::new(&config.storage.data_dir, &config.storage.temp_dir)?
+        .with_http_config(&config.geofabrik)?
+        .with_max_download_size(config.storage.max_download_bytes())
+        .with_content_addressed_storage(config.storage.content_addressed)
+        .with_extent_tolerance(config.processing.extent_tolerance_deg)
+        .with_processing_options(config.processing.analysis);
+    let analyzer = QualityAnalyzer::default();
+
+    // Files left behind by runs that crashed
+    if let Some(max_age) = config.storage.temp_max_age() {
+        if let Err(e) = processor.sweep_temp_dir(max_age) {
+            warn!("Failed to sweep the temp directory: {}", e);
+        }
+    }
 
     match cli.command {
-        Commands::Download { region } => {
+        Commands::Download {
+            region,
+            no_verify,
+            date,
+            no_size_limit,
+        } => {
             info!("Downloading data for region: {}", region);
 
+            let downloader = if no_size_limit {
+                processor.clone().with_max_download_size(None)
+            } else {
+                processor.clone()
+            };
+
             // Load region info
             let regions = storage.load_regions().await?;
             let region_info = regions
@@ -62,37 +217,163 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .ok_or_else(|| format!("Region '{}' not found", region))?;
 
             // Download the data
-            let file_path = processor.download_region(&region_info).await?;
+            let region_dir = storage.get_region_path(&region).await?;
+            let file_path = if let Some(date) = date {
+                downloader
+                    .download_region_version(&region_info, &region_dir, date, !no_verify)
+                    .await?
+            } else {
+                let last_reported_mb = AtomicU64::new(0);
+                let file_path = downloader
+                    .download_region_with_progress(
+                        &region_info,
+                        &region_dir,
+                        !no_verify,
+                        |downloaded, total| {
+                            // Only redraw the progress line once per downloaded MB
+                            let downloaded_mb = downloaded / 1_048_576;
+                            let previous_mb =
+                                last_reported_mb.swap(downloaded_mb, Ordering::Relaxed);
+                            if downloaded_mb > previous_mb || Some(downloaded) == total {
+                                print_download_progress(downloaded, total);
+                            }
+                        },
+                    )
+                    .await?;
+                eprintln!();
+                file_path
+            };
             info!("Downloaded data to: {:?}", file_path);
+            storage.touch_region(&region).await?;
 
-            // Process the file
-            let metrics = processor.process_osm_file(&file_path).await?;
+            // Process the file and generate a quality report
+            let report = jobs::analyze_data_file(
+                &storage,
+                &processor,
+                &analyzer,
+                &region,
+                &region_info.admin_level,
+                &file_path,
+                &jobs::JobLog::disabled(),
+            )
+            .await?;
+            let metrics = &report.metrics;
             info!(
                 "Processing complete: {} nodes, {} ways, {} relations",
-                metrics.total_nodes, metrics.total_ways, metrics.total_relations
+                count_text(metrics.total_nodes),
+                count_text(metrics.total_ways),
+                count_text(metrics.total_relations)
+            );
+
+            info!("Quality report saved: {}", report.id);
+            info!("Summary: {}", report.summary);
+        }
+
+        Commands::DownloadBatch {
+            file,
+            concurrency,
+            no_verify,
+            no_size_limit,
+        } => {
+            let region_ids: Vec<String> = std::fs::read_to_string(&file)?
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(String::from)
+                .collect();
+            info!(
+                "Downloading {} regions, {} at a time",
+                region_ids.len(),
+                concurrency.max(1)
             );
 
-            // Generate quality report
-            let issues = processor.validate_file(&file_path).await?;
-            let completeness_issues = analyzer.analyze_completeness(&metrics);
-            let pattern_issues = analyzer.analyze_patterns(&metrics);
+            // Clones share the processor's HTTP client and its connection pool
+            let downloader = if no_size_limit {
+                processor.clone().with_max_download_size(None)
+            } else {
+                processor.clone()
+            };
+            let regions = storage.load_regions().await?;
 
-            let mut all_issues = issues;
-            all_issues.extend(completeness_issues);
-            all_issues.extend(pattern_issues);
+            // A failed region is reported at the end instead of stopping the batch
+            let results: Vec<(String, Result<PathBuf, String>)> = futures::stream::iter(region_ids)
+                .map(|region_id| {
+                    let (storage, downloader, regions) = (&storage, &downloader, &regions);
+                    async move {
+                        let result = async {
+                            let region = regions
+                                .iter()
+                                .find(|r| r.id == region_id)
+                                .ok_or_else(|| format!("Region '{}' not found", region_id))?;
+                            let region_dir = storage
+                                .get_region_path(&region_id)
+                                .await
+                                .map_err(|e| e.to_string())?;
 
-            let data_file_id = format!("{}_{}", region, Utc::now().format("%Y-%m-%d"));
-            let report = analyzer
-                .generate_report(&data_file_id, &region, &metrics, all_issues)
-                .await?;
+                            let last_reported_step = AtomicU64::new(0);
+                            downloader
+                                .download_region_with_progress(
+                                    region,
+                                    &region_dir,
+                                    !no_verify,
+                                    |downloaded, total| {
+                                        // One line per tenth of the file, or per 100 MB
+                                        // when its size is unknown
+                                        let step = match total {
+                                            Some(total) if total > 0 => downloaded * 10 / total,
+                                            _ => downloaded / (100 * 1_048_576),
+                                        };
+                                        if step > last_reported_step.swap(step, Ordering::Relaxed) {
+                                            eprintln!(
+                                                "{}: {}",
+                                                region_id,
+                                                download_progress_text(downloaded, total)
+                                            );
+                                        }
+                                    },
+                                )
+                                .await
+                                .map_err(|e| format!("{:#}", e))
+                        }
+                        .await;
+                        (region_id, result)
+                    }
+                })
+                .buffered(concurrency.max(1))
+                .collect()
+                .await;
 
-            storage.save_quality_report(&report).await?;
+            let failed = results.iter().filter(|(_, result)| result.is_err()).count();
+            for (region_id, result) in &results {
+                match result {
+                    Ok(path) => {
+                        storage.touch_region(region_id).await?;
+                        println!("ok      {}  {}", region_id, path.display())
+                    }
+                    Err(e) => println!("FAILED  {}  {}", region_id, e),
+                }
+            }
+            println!(
+                "{} of {} regions downloaded",
+                results.len() - failed,
+                results.len()
+            );
 
-            info!("Quality report saved: {}", report.id);
-            info!("Summary: {}", report.summary);
+            if failed > 0 {
+                std::process::exit(1);
+            }
         }
 
-        Commands::Process { region, version } => {
+        Commands::Process {
+            region,
+            version,
+            report_format,
+            landlocked,
+            aggregates_only,
+            disk_index,
+            threads,
+            elements,
+        } => {
             info!(
                 "Processing data for region: {} (version: {:?})",
                 region, version
@@ -116,33 +397,243 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     .ok_or_else(|| format!("No latest file found for region '{}'", region))?
             };
 
-            // Process the file
-            let metrics = processor.process_osm_file(&file_path).await?;
-
-            // Generate quality report
-            let issues = processor.validate_file(&file_path).await?;
-            let completeness_issues = analyzer.analyze_completeness(&metrics);
-            let pattern_issues = analyzer.analyze_patterns(&metrics);
+            // Judge completeness by the region's level; unknown regions get the
+            // regional baseline
+            let admin_level = storage
+                .load_regions()
+                .await?
+                .into_iter()
+                .find(|r| r.id == region)
+                .map(|r| r.admin_level)
+                .unwrap_or(AdminLevel::Region);
 
-            let mut all_issues = issues;
-            all_issues.extend(completeness_issues);
-            all_issues.extend(pattern_issues);
+            let mut options = processor.processing_options;
+            if aggregates_only {
+                options = ProcessingOptions {
+                    node_index: options.node_index,
+                    threads: options.threads,
+                    elements: options.elements,
+                    ..ProcessingOptions::aggregates_only()
+                };
+            }
+            if disk_index {
+                options.node_index = NodeIndexStorage::Disk;
+            }
+            if let Some(threads) = threads {
+                options.threads = threads;
+            }
+            if let Some(elements) = elements {
+                options.elements = elements;
+            }
+            let processor = processor.clone().with_processing_options(options);
 
-            let data_file_id = format!("{}_{}", region, Utc::now().format("%Y-%m-%d"));
-            let report = analyzer
-                .generate_report(&data_file_id, &region, &metrics, all_issues)
-                .await?;
+            // Process the file and generate a quality report
+            let analyzer = QualityAnalyzer::default().with_coastline_check(!landlocked);
+            let report = jobs::analyze_data_file(
+                &storage,
+                &processor,
+                &analyzer,
+                &region,
+                &admin_level,
+                &file_path,
+                &jobs::JobLog::disabled(),
+            )
+            .await?;
+            let metrics = &report.metrics;
 
-            storage.save_quality_report(&report).await?;
+            let export = match report_format {
+                ReportFormat::Json => None,
+                ReportFormat::Html => Some(("html", QualityAnalyzer::render_html(&report))),
+                ReportFormat::Csv => Some(("csv", QualityAnalyzer::render_csv(&report))),
+            };
+            if let Some((extension, contents)) = export {
+                let path = storage
+                    .save_quality_report_export(&report, extension, &contents)
+                    .await?;
+                info!("Report exported to: {:?}", path);
+            }
 
             info!("Processing complete!");
             info!(
                 "Quality score: {:.1}",
-                analyzer.calculate_quality_score(&metrics, &report.issues)
+                analyzer.calculate_quality_score(metrics, &report.issues, analyzer.weights())
             );
             info!("Report saved: {}", report.id);
         }
 
+        Commands::Validate {
+            region,
+            version,
+            full,
+        } => {
+            info!(
+                "Validating data for region: {} (version: {:?})",
+                region, version
+            );
+
+            let version = version.unwrap_or_else(|| "latest".to_string());
+            let file_path = storage
+                .get_file_path(&region, &version)
+                .await?
+                .ok_or_else(|| {
+                    format!(
+                        "File not found for region '{}' version '{}'",
+                        region, version
+                    )
+                })?;
+
+            let max_elements = (!full).then_some(osm::DEFAULT_VALIDATION_MAX_ELEMENTS);
+            let issues = processor.validate_file(&file_path, max_elements).await?;
+            info!(
+                "Validation result: {}",
+                quality::utils::generate_issue_summary(&issues)
+            );
+
+            for severity in [
+                IssueSeverity::Critical,
+                IssueSeverity::High,
+                IssueSeverity::Medium,
+                IssueSeverity::Low,
+            ] {
+                let label = quality::utils::severity_label(&severity);
+                for issue in issues
+                    .iter()
+                    .filter(|issue| quality::utils::severity_label(&issue.severity) == label)
+                {
+                    info!("[{}] {}: {}", label, issue.issue_type, issue.description);
+                }
+            }
+
+            // Fail the process so CI pipelines can gate on file integrity
+            if issues
+                .iter()
+                .any(|issue| matches!(issue.severity, IssueSeverity::Critical))
+            {
+                std::process::exit(1);
+            }
+        }
+
+        Commands::Export {
+            region,
+            format,
+            output,
+        } => {
+            info!("Exporting data for region: {}", region);
+
+            let file_path = storage
+                .get_file_path(&region, "latest")
+                .await?
+                .ok_or_else(|| format!("No latest file found for region '{}'", region))?;
+            let out_dir = output.unwrap_or_else(|| {
+                config
+                    .storage
+                    .data_dir
+                    .join("exports")
+                    .join(region.replace('/', "_"))
+            });
+
+            match format {
+                ExportFormat::Shapefile => {
+                    for geometry_type in ShapeGeometry::ALL {
+                        let path = processor
+                            .export_shapefile(&file_path, &out_dir, geometry_type)
+                            .await?;
+                        info!("Wrote {:?}", path);
+                    }
+                }
+            }
+        }
+
+        Commands::Extract {
+            region,
+            poly,
+            output,
+        } => {
+            info!("Extracting {:?} from region: {}", poly, region);
+
+            let file_path = storage
+                .get_file_path(&region, "latest")
+                .await?
+                .ok_or_else(|| format!("No latest file found for region '{}'", region))?;
+            let output = match output {
+                Some(output) => output,
+                None => {
+                    let stem = poly
+                        .file_stem()
+                        .map(|stem| stem.to_string_lossy().into_owned())
+                        .unwrap_or_else(|| "extract".to_string());
+                    let out_dir = config
+                        .storage
+                        .data_dir
+                        .join("exports")
+                        .join(region.replace('/', "_"));
+                    std::fs::create_dir_all(&out_dir)?;
+                    out_dir.join(format!("{}.osm.pbf", stem))
+                }
+            };
+
+            processor
+                .filter_by_polygon(&file_path, &poly, &output)
+                .await?;
+            info!("Wrote {:?}", output);
+        }
+
+        Commands::Repair { region } => match storage.repair_latest(&region).await? {
+            Some(path) => info!("latest.osm.pbf of {} points at {:?}", region, path),
+            None => warn!("No downloaded versions of {} to point latest at", region),
+        },
+
+        Commands::Merge { out, inputs } => {
+            processor.merge_files(&inputs, &out).await?;
+            info!("Wrote {:?}", out);
+        }
+
+        Commands::Compare {
+            region,
+            from,
+            to,
+            json,
+        } => {
+            let comparison = storage.compare_versions(&region, &from, &to).await?;
+
+            if json {
+                println!("{}", serde_json::to_string_pretty(&comparison)?);
+            } else {
+                print_comparison(&comparison);
+            }
+        }
+
+        Commands::Update { region } => {
+            info!("Updating data for region: {}", region);
+
+            let regions = storage.load_regions().await?;
+            let region_info = regions
+                .into_iter()
+                .find(|r| r.id == region)
+                .ok_or_else(|| format!("Region '{}' not found", region))?;
+
+            let base_path = storage
+                .get_file_path(&region, "latest")
+                .await?
+                .ok_or_else(|| format!("No latest file found for region '{}'", region))?;
+            let region_dir = storage.get_region_path(&region).await?;
+
+            match processor
+                .update_region(&region_info, &base_path, &region_dir)
+                .await?
+            {
+                Some((path, summary)) => {
+                    storage.touch_region(&region).await?;
+                    info!("Updated data written to: {:?}", path);
+                    info!(
+                        "{} created, {} modified, {} deleted, {} skipped",
+                        summary.created, summary.modified, summary.deleted, summary.skipped
+                    );
+                }
+                None => info!("Region '{}' is already up to date", region),
+            }
+        }
+
         Commands::Init => {
             info!("Initializing data directory with sample data");
             storage.initialize_with_sample_data().await?;
@@ -151,7 +642,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
         Commands::List => {
             info!("Available regions:");
-            let regions = storage.load_regions().await?;
+            let regions = storage.load_regions_or_recover().await?;
 
             if regions.is_empty() {
                 info!("No regions found. Run 'riemap-processor init' to initialize sample data.");
@@ -173,3 +664,53 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+/// Print a comparison as a table of deltas on stdout
+fn print_comparison(comparison: &RegionComparison) {
+    let diff = &comparison.metrics_diff;
+
+    println!(
+        "{}: {} -> {}",
+        comparison.region_id, comparison.from_version, comparison.to_version
+    );
+    println!("{:<20} {:>12}", "Metric", "Change");
+    println!("{:-<20} {:->12}", "", "");
+    let change = |diff: Option<i64>| {
+        diff.map_or_else(|| "skipped".to_string(), |diff| format!("{:+}", diff))
+    };
+    println!("{:<20} {:>12}", "Nodes", change(diff.nodes_diff));
+    println!("{:<20} {:>12}", "Ways", change(diff.ways_diff));
+    println!("{:<20} {:>12}", "Relations", change(diff.relations_diff));
+    println!("{:<20} {:>+11.1}%", "Completeness", diff.completeness_diff);
+    println!("{:<20} {:>+12}", "Errors", diff.errors_diff);
+
+    let mut features: Vec<_> = diff.feature_changes.iter().collect();
+    features.sort();
+    for (feature, change) in features {
+        println!("{:<20} {:>+12}", feature, change);
+    }
+}
+
+/// Render a single-line textual download progress indicator on stderr
+fn print_download_progress(downloaded: u64, total: Option<u64>) {
+    eprint!(
+        "\rDownloading: {}",
+        download_progress_text(downloaded, total)
+    );
+    let _ = std::io::stderr().flush();
+}
+
+/// Percentage and megabytes downloaded, or only megabytes when the size is unknown
+fn download_progress_text(downloaded: u64, total: Option<u64>) -> String {
+    let downloaded_mb = downloaded as f64 / 1_048_576.0;
+
+    match total {
+        Some(total) if total > 0 => format!(
+            "{:5.1}% ({:.1} / {:.1} MB)",
+            downloaded as f64 / total as f64 * 100.0,
+            downloaded_mb,
+            total as f64 / 1_048_576.0
+        ),
+        _ => format!("{:.1} MB", downloaded_mb),
+    }
+}
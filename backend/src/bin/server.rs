@@ -1,22 +1,42 @@
-use riemap_backend::{api::create_router, config::Config, storage::Storage};
-use tracing::{error, info};
-use tracing_subscriber;
+use riemap_backend::{
+    api::{auth::ApiKeys, cache_builds::CacheBuilds, create_router, AppState},
+    config::Config,
+    jobs::JobQueue,
+    logging,
+    osm::OsmProcessor,
+    scheduler,
+    storage::Storage,
+};
+use std::future::IntoFuture;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Notify;
+use tracing::{error, info, warn};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Initialize tracing
-    tracing_subscriber::fmt::init();
-
-    info!("Starting RieMap backend server");
-
     // Load configuration
     let config = Config::from_env();
+    logging::init(&config.logging);
     config.validate()?;
 
+    info!("Starting RieMap backend server");
+
     info!("Configuration loaded: {:?}", config);
 
     // Initialize storage
-    let storage = Storage::new(&config.storage.data_dir)?;
+    let storage = Storage::new(&config.storage.data_dir)?
+        .with_geofabrik_config(config.geofabrik.clone())
+        .with_layout(config.storage.layout)
+        .with_tile_cache_limit(config.storage.tile_cache_max_mb * 1_048_576)
+        .with_metadata_recovery(config.storage.recover_corrupt_metadata)
+        .with_metadata_backend(config.storage.metadata_backend)?;
+
+    // Unreadable metadata is moved aside here when recovery is enabled
+    if let Err(e) = storage.load_regions_or_recover().await {
+        error!("Failed to load region metadata: {:#}", e);
+    }
 
     // Initialize region data from Geofabrik if not exists
     if let Err(e) = storage.initialize_with_geofabrik_data().await {
@@ -27,8 +47,69 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     }
 
+    let processor = OsmProcessor::new(&config.storage.data_dir, &config.storage.temp_dir)?
+        .with_http_config(&config.geofabrik)?
+        .with_max_download_size(config.storage.max_download_bytes())
+        .with_content_addressed_storage(config.storage.content_addressed)
+        .with_extent_tolerance(config.processing.extent_tolerance_deg)
+        .with_processing_options(config.processing.analysis);
+
+    // Files left behind by runs that crashed
+    if let Some(max_age) = config.storage.temp_max_age() {
+        if let Err(e) = processor.sweep_temp_dir(max_age) {
+            warn!("Failed to sweep the temp directory: {}", e);
+        }
+    }
+
+    let jobs = JobQueue::new(
+        storage.clone(),
+        processor.clone(),
+        config.processing.max_concurrent_jobs,
+    )
+    .with_job_timeout(config.processing.job_timeout());
+
+    if !config.processing.bootstrap_regions.is_empty() {
+        info!(
+            "Bootstrapping {} regions",
+            config.processing.bootstrap_regions.len()
+        );
+        scheduler::bootstrap(
+            storage.clone(),
+            processor.clone(),
+            jobs.clone(),
+            config.processing.bootstrap_regions.clone(),
+        );
+    }
+
+    if config.processing.scheduler_enabled {
+        info!(
+            "Starting scheduler (every {} hours)",
+            config.processing.cleanup_interval_hours
+        );
+        scheduler::spawn(
+            storage.clone(),
+            processor.clone(),
+            jobs.clone(),
+            config.processing.clone(),
+        );
+    }
+
+    let api_keys = ApiKeys::new(config.auth.api_keys.clone());
+    if !api_keys.is_enabled() {
+        warn!("No API keys configured; processing endpoints are open to everyone");
+    }
+
     // Create router
-    let app = create_router(storage);
+    let app = create_router(AppState {
+        storage,
+        processor,
+        jobs: jobs.clone(),
+        min_free_disk_bytes: config.storage.min_free_disk_mb * 1_048_576,
+        rate_limit: config.rate_limit.clone(),
+        api_keys,
+        redirect_missing_downloads: config.geofabrik.redirect_missing_downloads,
+        cache_builds: CacheBuilds::new(config.processing.max_concurrent_jobs),
+    });
 
     // Create listener
     let listener =
@@ -48,8 +129,75 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         config.server.host, config.server.port
     );
 
-    // Start server
-    axum::serve(listener, app).await?;
+    // Start server; on a shutdown signal stop accepting connections and give
+    // in-flight requests (e.g. streaming downloads) a bounded time to finish
+    let stop_accepting = Arc::new(Notify::new());
+    // Connect info gives the rate limiter each client's IP
+    let server = axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown({
+        let stop_accepting = stop_accepting.clone();
+        async move { stop_accepting.notified().await }
+    })
+    .into_future();
+    tokio::pin!(server);
+
+    tokio::select! {
+        result = &mut server => result?,
+        _ = shutdown_signal() => {
+            info!("Shutdown signal received, draining in-flight requests");
+
+            let cancelled = jobs.shutdown().await;
+            if cancelled > 0 {
+                info!("Cancelled {} queued jobs", cancelled);
+            }
+
+            stop_accepting.notify_one();
+            let timeout = Duration::from_secs(config.server.shutdown_timeout_secs);
+            match tokio::time::timeout(timeout, server).await {
+                Ok(result) => result?,
+                Err(_) => warn!(
+                    "In-flight requests did not finish within {:?}, shutting down anyway",
+                    timeout
+                ),
+            }
+        }
+    }
+
+    info!("Server shut down cleanly");
 
     Ok(())
 }
+
+/// Resolve when the process receives Ctrl+C or (on Unix) SIGTERM
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!("Failed to listen for Ctrl+C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                error!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
@@ -1,4 +1,10 @@
+use crate::logging::LogFormat;
+use crate::metadata::MetadataBackend;
+use crate::models::AdminLevel;
+use crate::osm::ProcessingOptions;
+use crate::storage::LayoutStrategy;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::path::PathBuf;
 
 /// Application configuration
@@ -7,6 +13,14 @@ pub struct Config {
     pub server: ServerConfig,
     pub storage: StorageConfig,
     pub processing: ProcessingConfig,
+    #[serde(default)]
+    pub geofabrik: GeofabrikConfig,
+    #[serde(default)]
+    pub rate_limit: RateLimitConfig,
+    #[serde(default)]
+    pub auth: AuthConfig,
+    #[serde(default)]
+    pub logging: LoggingConfig,
 }
 
 /// Server configuration
@@ -15,6 +29,7 @@ pub struct ServerConfig {
     pub host: String,
     pub port: u16,
     pub workers: usize,
+    pub shutdown_timeout_secs: u64, // How long to wait for in-flight requests on shutdown
 }
 
 /// Storage configuration
@@ -22,15 +37,178 @@ pub struct ServerConfig {
 pub struct StorageConfig {
     pub data_dir: PathBuf,
     pub temp_dir: PathBuf,
-    pub max_file_size: u64,
+    pub max_file_size: u64, // Largest download in bytes; 0 disables the limit
+    pub min_free_disk_mb: u64, // Below this the health check reports not ready
+    #[serde(default)]
+    pub metadata_backend: MetadataBackend,
+    #[serde(default)]
+    pub layout: LayoutStrategy,
+    #[serde(default = "default_tile_cache_max_mb")]
+    pub tile_cache_max_mb: u64, // Rendered tiles kept on disk; 0 disables the cache
+    #[serde(default = "default_temp_max_age_hours")]
+    pub temp_max_age_hours: u64, // Temp files older than this are swept on startup; 0 keeps them
+    #[serde(default)]
+    pub recover_corrupt_metadata: bool, // Back up an unparsable metadata.json and start empty
+    #[serde(default)]
+    pub content_addressed: bool, // Keep downloads once under data_dir/blobs/<sha256>, hard-linked into regions
+}
+
+fn default_tile_cache_max_mb() -> u64 {
+    crate::tile_cache::DEFAULT_TILE_CACHE_MAX_BYTES / 1_048_576
+}
+
+fn default_temp_max_age_hours() -> u64 {
+    crate::temp_files::DEFAULT_TEMP_MAX_AGE_HOURS
+}
+
+fn default_extent_tolerance_deg() -> f64 {
+    crate::osm::DEFAULT_EXTENT_TOLERANCE_DEG
 }
 
 /// Processing configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProcessingConfig {
     pub max_concurrent_jobs: usize,
+    #[serde(default)]
+    pub job_timeout_secs: u64, // Jobs running longer fail; 0 disables the limit
     pub cleanup_interval_hours: u64,
     pub keep_versions: usize,
+    /// Versions kept for regions of a level, replacing `keep_versions`
+    #[serde(default)]
+    pub keep_versions_by_level: HashMap<AdminLevel, usize>,
+    pub scheduler_enabled: bool,
+    pub refresh_downloads: bool, // Re-download extracts when Geofabrik publishes newer data
+    #[serde(default)]
+    pub analysis: ProcessingOptions, // Checks run on top of the aggregate metrics
+    #[serde(default = "default_extent_tolerance_deg")]
+    pub extent_tolerance_deg: f64, // Margin around a region's bbox a download's extent must overlap
+    #[serde(default)]
+    pub bootstrap_regions: Vec<String>, // Downloaded on server start unless their data is current
+}
+
+/// Where the Geofabrik index and extracts are fetched from
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GeofabrikConfig {
+    pub mirror_url: Option<String>, // Base URL replacing https://download.geofabrik.de
+    pub index_url: Option<String>,  // Defaults to <mirror or public base>/index-v1.json
+    pub index_path: Option<PathBuf>, // Local index file; skips the HTTP request entirely
+    pub max_attempts: u32,          // Attempts per request on connection errors, 5xx and 429
+    pub connect_timeout_secs: u64,
+    pub read_timeout_secs: u64, // Longest silence allowed before a response or between body chunks
+    pub redirect_missing_downloads: bool, // Redirect requests for files not held locally upstream
+    /// Levels replacing the ones derived from the parent chains, keyed by region ID
+    pub admin_level_overrides: HashMap<String, AdminLevel>,
+}
+
+impl Default for GeofabrikConfig {
+    fn default() -> Self {
+        Self {
+            mirror_url: None,
+            index_url: None,
+            index_path: None,
+            max_attempts: crate::http::DEFAULT_MAX_ATTEMPTS,
+            connect_timeout_secs: crate::http::DEFAULT_CONNECT_TIMEOUT.as_secs(),
+            read_timeout_secs: crate::http::DEFAULT_READ_TIMEOUT.as_secs(),
+            redirect_missing_downloads: false,
+            admin_level_overrides: HashMap::new(),
+        }
+    }
+}
+
+impl GeofabrikConfig {
+    /// Public Geofabrik download server
+    pub const DEFAULT_BASE_URL: &'static str = "https://download.geofabrik.de";
+
+    /// Time allowed to establish a connection
+    pub fn connect_timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.connect_timeout_secs)
+    }
+
+    /// Time a server may stay silent before a request fails
+    pub fn read_timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.read_timeout_secs)
+    }
+
+    /// Base URL downloads are served from
+    pub fn base_url(&self) -> &str {
+        self.mirror_url
+            .as_deref()
+            .unwrap_or(Self::DEFAULT_BASE_URL)
+            .trim_end_matches('/')
+    }
+
+    /// URL of the JSON region index
+    pub fn index_url(&self) -> String {
+        self.index_url
+            .clone()
+            .unwrap_or_else(|| format!("{}/index-v1.json", self.base_url()))
+    }
+
+    /// Point a public Geofabrik URL at the configured mirror
+    pub fn rewrite_url(&self, url: &str) -> String {
+        match (&self.mirror_url, url.strip_prefix(Self::DEFAULT_BASE_URL)) {
+            (Some(_), Some(path)) => format!("{}{}", self.base_url(), path),
+            _ => url.to_string(),
+        }
+    }
+}
+
+/// Per-client-IP request limits; 0 disables a limit
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RateLimitConfig {
+    pub download_requests_per_minute: u32,
+    pub max_concurrent_downloads: u32, // Downloads streaming to one IP at the same time
+    pub api_requests_per_minute: u32,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            download_requests_per_minute: 30,
+            max_concurrent_downloads: 2,
+            api_requests_per_minute: 600,
+        }
+    }
+}
+
+/// API keys required by endpoints that start jobs or change server state
+///
+/// With no keys configured these endpoints stay open.
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AuthConfig {
+    pub api_keys: Vec<String>,
+}
+
+// The configuration is logged at startup, so only the number of keys is shown
+impl std::fmt::Debug for AuthConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AuthConfig")
+            .field(
+                "api_keys",
+                &format_args!("[<{} redacted>]", self.api_keys.len()),
+            )
+            .finish()
+    }
+}
+
+/// Log output shared by the server and the CLI
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LoggingConfig {
+    pub format: LogFormat,
+    pub level: String, // `info` or `target=level,...`; `RUST_LOG` takes precedence when set
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            format: LogFormat::default(),
+            level: crate::logging::DEFAULT_LOG_LEVEL.to_string(),
+        }
+    }
 }
 
 impl Default for Config {
@@ -40,21 +218,71 @@ impl Default for Config {
                 host: "127.0.0.1".to_string(),
                 port: 3001,
                 workers: 4,
+                shutdown_timeout_secs: 30,
             },
             storage: StorageConfig {
                 data_dir: PathBuf::from("./data"),
                 temp_dir: PathBuf::from("./temp"),
                 max_file_size: 1_073_741_824, // 1GB
+                min_free_disk_mb: 1024,
+                metadata_backend: MetadataBackend::default(),
+                layout: LayoutStrategy::default(),
+                tile_cache_max_mb: default_tile_cache_max_mb(),
+                temp_max_age_hours: default_temp_max_age_hours(),
+                recover_corrupt_metadata: false,
+                content_addressed: false,
             },
             processing: ProcessingConfig {
                 max_concurrent_jobs: 2,
+                job_timeout_secs: 0,
                 cleanup_interval_hours: 24,
                 keep_versions: 10,
+                keep_versions_by_level: HashMap::new(),
+                scheduler_enabled: true,
+                refresh_downloads: false,
+                analysis: ProcessingOptions::default(),
+                extent_tolerance_deg: default_extent_tolerance_deg(),
+                bootstrap_regions: Vec::new(),
             },
+            geofabrik: GeofabrikConfig::default(),
+            rate_limit: RateLimitConfig::default(),
+            auth: AuthConfig::default(),
+            logging: LoggingConfig::default(),
         }
     }
 }
 
+impl StorageConfig {
+    /// Download size limit for `OsmProcessor::with_max_download_size`
+    pub fn max_download_bytes(&self) -> Option<u64> {
+        Some(self.max_file_size).filter(|max| *max > 0)
+    }
+
+    /// Age after which leftover temp files are swept; `None` disables the sweep
+    pub fn temp_max_age(&self) -> Option<std::time::Duration> {
+        Some(self.temp_max_age_hours)
+            .filter(|hours| *hours > 0)
+            .map(|hours| std::time::Duration::from_secs(hours * 3600))
+    }
+}
+
+impl ProcessingConfig {
+    /// Time limit for `JobQueue::with_job_timeout`
+    pub fn job_timeout(&self) -> Option<std::time::Duration> {
+        Some(self.job_timeout_secs)
+            .filter(|secs| *secs > 0)
+            .map(std::time::Duration::from_secs)
+    }
+
+    /// Number of versions pruning keeps for a region of `level`
+    pub fn keep_versions_for(&self, level: &AdminLevel) -> usize {
+        self.keep_versions_by_level
+            .get(level)
+            .copied()
+            .unwrap_or(self.keep_versions)
+    }
+}
+
 impl Config {
     /// Load configuration from environment variables and defaults
     pub fn from_env() -> Self {
@@ -70,6 +298,12 @@ impl Config {
             }
         }
 
+        if let Ok(timeout) = std::env::var("RIEMAP_SHUTDOWN_TIMEOUT_SECS") {
+            if let Ok(timeout) = timeout.parse() {
+                config.server.shutdown_timeout_secs = timeout;
+            }
+        }
+
         if let Ok(data_dir) = std::env::var("RIEMAP_DATA_DIR") {
             config.storage.data_dir = PathBuf::from(data_dir);
         }
@@ -78,6 +312,217 @@ impl Config {
             config.storage.temp_dir = PathBuf::from(temp_dir);
         }
 
+        if let Ok(max_size) = std::env::var("RIEMAP_MAX_FILE_SIZE") {
+            if let Ok(max_size) = max_size.parse() {
+                config.storage.max_file_size = max_size;
+            }
+        }
+
+        if let Ok(min_free) = std::env::var("RIEMAP_MIN_FREE_DISK_MB") {
+            if let Ok(min_free) = min_free.parse() {
+                config.storage.min_free_disk_mb = min_free;
+            }
+        }
+
+        if let Ok(backend) = std::env::var("RIEMAP_METADATA_BACKEND") {
+            if let Ok(backend) = backend.parse() {
+                config.storage.metadata_backend = backend;
+            }
+        }
+
+        if let Ok(layout) = std::env::var("RIEMAP_DATA_LAYOUT") {
+            if let Ok(layout) = layout.parse() {
+                config.storage.layout = layout;
+            }
+        }
+
+        if let Ok(max_mb) = std::env::var("RIEMAP_TILE_CACHE_MAX_MB") {
+            if let Ok(max_mb) = max_mb.parse() {
+                config.storage.tile_cache_max_mb = max_mb;
+            }
+        }
+
+        if let Ok(recover) = std::env::var("RIEMAP_RECOVER_CORRUPT_METADATA") {
+            if let Ok(recover) = recover.parse() {
+                config.storage.recover_corrupt_metadata = recover;
+            }
+        }
+
+        if let Ok(enabled) = std::env::var("RIEMAP_CONTENT_ADDRESSED_STORAGE") {
+            if let Ok(enabled) = enabled.parse() {
+                config.storage.content_addressed = enabled;
+            }
+        }
+
+        if let Ok(max_age) = std::env::var("RIEMAP_TEMP_MAX_AGE_HOURS") {
+            if let Ok(max_age) = max_age.parse() {
+                config.storage.temp_max_age_hours = max_age;
+            }
+        }
+
+        if let Ok(mirror_url) = std::env::var("RIEMAP_GEOFABRIK_MIRROR") {
+            config.geofabrik.mirror_url = Some(mirror_url);
+        }
+
+        if let Ok(index_url) = std::env::var("RIEMAP_GEOFABRIK_INDEX_URL") {
+            config.geofabrik.index_url = Some(index_url);
+        }
+
+        if let Ok(index_path) = std::env::var("RIEMAP_GEOFABRIK_INDEX_PATH") {
+            config.geofabrik.index_path = Some(PathBuf::from(index_path));
+        }
+
+        if let Ok(attempts) = std::env::var("RIEMAP_HTTP_MAX_ATTEMPTS") {
+            if let Ok(attempts) = attempts.parse() {
+                config.geofabrik.max_attempts = attempts;
+            }
+        }
+
+        if let Ok(timeout) = std::env::var("RIEMAP_HTTP_CONNECT_TIMEOUT_SECS") {
+            if let Ok(timeout) = timeout.parse() {
+                config.geofabrik.connect_timeout_secs = timeout;
+            }
+        }
+
+        if let Ok(timeout) = std::env::var("RIEMAP_HTTP_READ_TIMEOUT_SECS") {
+            if let Ok(timeout) = timeout.parse() {
+                config.geofabrik.read_timeout_secs = timeout;
+            }
+        }
+
+        if let Ok(redirect) = std::env::var("RIEMAP_REDIRECT_MISSING_DOWNLOADS") {
+            if let Ok(redirect) = redirect.parse() {
+                config.geofabrik.redirect_missing_downloads = redirect;
+            }
+        }
+
+        // Comma-separated `region=level` pairs, e.g. RIEMAP_ADMIN_LEVEL_OVERRIDES=monaco=country
+        if let Ok(overrides) = std::env::var("RIEMAP_ADMIN_LEVEL_OVERRIDES") {
+            config.geofabrik.admin_level_overrides = overrides
+                .split(',')
+                .filter_map(|pair| pair.split_once('='))
+                .filter_map(|(region, level)| {
+                    Some((region.trim().to_string(), level.parse().ok()?))
+                })
+                .collect();
+        }
+
+        if let Ok(limit) = std::env::var("RIEMAP_DOWNLOAD_REQUESTS_PER_MINUTE") {
+            if let Ok(limit) = limit.parse() {
+                config.rate_limit.download_requests_per_minute = limit;
+            }
+        }
+
+        if let Ok(limit) = std::env::var("RIEMAP_MAX_CONCURRENT_DOWNLOADS") {
+            if let Ok(limit) = limit.parse() {
+                config.rate_limit.max_concurrent_downloads = limit;
+            }
+        }
+
+        if let Ok(limit) = std::env::var("RIEMAP_API_REQUESTS_PER_MINUTE") {
+            if let Ok(limit) = limit.parse() {
+                config.rate_limit.api_requests_per_minute = limit;
+            }
+        }
+
+        // Comma-separated, e.g. RIEMAP_API_KEYS=key-one,key-two
+        if let Ok(keys) = std::env::var("RIEMAP_API_KEYS") {
+            config.auth.api_keys = keys
+                .split(',')
+                .map(str::trim)
+                .filter(|key| !key.is_empty())
+                .map(String::from)
+                .collect();
+        }
+
+        if let Ok(format) = std::env::var("RIEMAP_LOG_FORMAT") {
+            if let Ok(format) = format.parse() {
+                config.logging.format = format;
+            }
+        }
+
+        if let Ok(level) = std::env::var("RIEMAP_LOG_LEVEL") {
+            config.logging.level = level;
+        }
+
+        // Comma-separated region IDs, e.g. RIEMAP_BOOTSTRAP_REGIONS=liechtenstein,monaco
+        if let Ok(regions) = std::env::var("RIEMAP_BOOTSTRAP_REGIONS") {
+            config.processing.bootstrap_regions = regions
+                .split(',')
+                .map(str::trim)
+                .filter(|region| !region.is_empty())
+                .map(String::from)
+                .collect();
+        }
+
+        if let Ok(timeout) = std::env::var("RIEMAP_JOB_TIMEOUT_SECS") {
+            if let Ok(timeout) = timeout.parse() {
+                config.processing.job_timeout_secs = timeout;
+            }
+        }
+
+        if let Ok(keep) = std::env::var("RIEMAP_KEEP_VERSIONS") {
+            if let Ok(keep) = keep.parse() {
+                config.processing.keep_versions = keep;
+            }
+        }
+
+        // Comma-separated `level=count` pairs, e.g. RIEMAP_KEEP_VERSIONS_BY_LEVEL=continent=3,subregion=20
+        if let Ok(limits) = std::env::var("RIEMAP_KEEP_VERSIONS_BY_LEVEL") {
+            config.processing.keep_versions_by_level = limits
+                .split(',')
+                .filter_map(|pair| pair.split_once('='))
+                .filter_map(|(level, keep)| Some((level.parse().ok()?, keep.trim().parse().ok()?)))
+                .collect();
+        }
+
+        if let Ok(enabled) = std::env::var("RIEMAP_SCHEDULER_ENABLED") {
+            if let Ok(enabled) = enabled.parse() {
+                config.processing.scheduler_enabled = enabled;
+            }
+        }
+
+        if let Ok(refresh) = std::env::var("RIEMAP_REFRESH_DOWNLOADS") {
+            if let Ok(refresh) = refresh.parse() {
+                config.processing.refresh_downloads = refresh;
+            }
+        }
+
+        if let Ok(aggregates_only) = std::env::var("RIEMAP_AGGREGATES_ONLY") {
+            if let Ok(true) = aggregates_only.parse() {
+                config.processing.analysis = ProcessingOptions {
+                    node_index: config.processing.analysis.node_index,
+                    threads: config.processing.analysis.threads,
+                    elements: config.processing.analysis.elements,
+                    ..ProcessingOptions::aggregates_only()
+                };
+            }
+        }
+
+        if let Ok(node_index) = std::env::var("RIEMAP_NODE_INDEX") {
+            if let Ok(node_index) = node_index.parse() {
+                config.processing.analysis.node_index = node_index;
+            }
+        }
+
+        if let Ok(tolerance) = std::env::var("RIEMAP_EXTENT_TOLERANCE_DEG") {
+            if let Ok(tolerance) = tolerance.parse() {
+                config.processing.extent_tolerance_deg = tolerance;
+            }
+        }
+
+        if let Ok(elements) = std::env::var("RIEMAP_PROCESS_ELEMENTS") {
+            if let Ok(elements) = elements.parse() {
+                config.processing.analysis.elements = elements;
+            }
+        }
+
+        if let Ok(threads) = std::env::var("RIEMAP_PROCESSING_THREADS") {
+            if let Ok(threads) = threads.parse() {
+                config.processing.analysis.threads = threads;
+            }
+        }
+
         config
     }
 
@@ -93,6 +538,42 @@ impl Config {
             );
         }
 
+        if self.geofabrik.max_attempts == 0 {
+            return Err(crate::RiemapError::Config(
+                "HTTP max attempts must be greater than 0".to_string(),
+            )
+            .into());
+        }
+
+        if self.geofabrik.connect_timeout_secs == 0 || self.geofabrik.read_timeout_secs == 0 {
+            return Err(crate::RiemapError::Config(
+                "HTTP timeouts must be greater than 0".to_string(),
+            )
+            .into());
+        }
+
+        if self.processing.scheduler_enabled && self.processing.cleanup_interval_hours == 0 {
+            return Err(crate::RiemapError::Config(
+                "Cleanup interval must be greater than 0 when the scheduler is enabled".to_string(),
+            )
+            .into());
+        }
+
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn api_keys_are_redacted_from_the_logged_config() {
+        let mut config = Config::default();
+        config.auth.api_keys = vec!["secret-one".to_string(), "secret-two".to_string()];
+
+        let logged = format!("{:?}", config);
+        assert!(!logged.contains("secret"));
+        assert!(logged.contains("[<2 redacted>]"));
+    }
+}
@@ -0,0 +1,299 @@
+use crate::Result;
+use bytes::Bytes;
+use reqwest::{header, Client, RequestBuilder, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tracing::warn;
+
+/// Default number of attempts for each outbound request
+pub const DEFAULT_MAX_ATTEMPTS: u32 = 3;
+
+/// Default time allowed to establish a connection
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Default time a server may stay silent, before responding or mid-body
+pub const DEFAULT_READ_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// User-Agent sent with every outbound request
+pub const USER_AGENT: &str = concat!(
+    "RieMap/",
+    env!("CARGO_PKG_VERSION"),
+    " (+https://github.com/tumluliu/riemap)"
+);
+
+/// Build a client with pooled keep-alive connections to be shared by all requests
+///
+/// Only the connect phase is bounded here: a whole-request timeout would cut
+/// off large downloads, so stalls are caught by `read_timeout` in
+/// `send_with_retry` and `next_chunk` instead.
+pub fn build_client(connect_timeout: Duration) -> Result<Client> {
+    Ok(Client::builder()
+        .user_agent(USER_AGENT)
+        .connect_timeout(connect_timeout)
+        .pool_idle_timeout(Duration::from_secs(90))
+        .pool_max_idle_per_host(4)
+        .tcp_keepalive(Duration::from_secs(60))
+        .build()?)
+}
+
+/// Delay before the first retry; doubled for every further attempt
+const BASE_DELAY: Duration = Duration::from_millis(500);
+
+/// Upper bound for any single wait, including server-requested `Retry-After`
+const MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// Send a request, retrying transient failures with exponential backoff and jitter
+///
+/// Connection errors, timeouts, 5xx and 429 responses are retried up to
+/// `max_attempts` times in total, honouring `Retry-After` when the server
+/// sends it. Any other response, including 4xx, is returned as-is so the
+/// caller can report it. An attempt whose response headers take longer
+/// than `read_timeout` counts as a timeout. `request` builds a fresh
+/// request for each attempt.
+pub async fn send_with_retry(
+    max_attempts: u32,
+    read_timeout: Duration,
+    request: impl Fn() -> RequestBuilder,
+) -> Result<Response> {
+    let max_attempts = max_attempts.max(1);
+    let mut attempt = 1;
+
+    loop {
+        let Ok(sent) = tokio::time::timeout(read_timeout, request().send()).await else {
+            if attempt >= max_attempts {
+                return Err(crate::RiemapError::Network(format!(
+                    "No response within {:?} after {} attempts",
+                    read_timeout, attempt
+                ))
+                .into());
+            }
+            warn!(
+                "No response within {:?} (attempt {}/{})",
+                read_timeout, attempt, max_attempts
+            );
+            tokio::time::sleep(backoff(attempt).min(MAX_DELAY)).await;
+            attempt += 1;
+            continue;
+        };
+
+        let delay = match sent {
+            Ok(response) if attempt < max_attempts && is_retryable(response.status()) => {
+                warn!(
+                    "{} returned {} (attempt {}/{})",
+                    response.url(),
+                    response.status(),
+                    attempt,
+                    max_attempts
+                );
+                retry_after(&response).unwrap_or_else(|| backoff(attempt))
+            }
+            Ok(response) => return Ok(response),
+            Err(e) if attempt < max_attempts && (e.is_connect() || e.is_timeout()) => {
+                warn!(
+                    "Request failed (attempt {}/{}): {}",
+                    attempt, max_attempts, e
+                );
+                backoff(attempt)
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        tokio::time::sleep(delay.min(MAX_DELAY)).await;
+        attempt += 1;
+    }
+}
+
+/// `ETag` and `Last-Modified` of a downloaded file, sent back as `If-None-Match`
+/// and `If-Modified-Since` so the server can answer `304` when it is unchanged
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheValidators {
+    /// URL the file came from; the validators are only sent back to it
+    pub url: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+impl CacheValidators {
+    /// Validators of a response, or `None` when the server sent neither header
+    pub fn from_response(url: &str, response: &Response) -> Option<Self> {
+        let value = |name: header::HeaderName| {
+            let value = response.headers().get(name)?.to_str().ok()?;
+            Some(value.to_string())
+        };
+        let (etag, last_modified) = (value(header::ETAG), value(header::LAST_MODIFIED));
+        if etag.is_none() && last_modified.is_none() {
+            return None;
+        }
+
+        Some(Self {
+            url: url.to_string(),
+            etag,
+            last_modified,
+        })
+    }
+
+    /// Make a request conditional on the file having changed
+    pub fn apply(&self, mut request: RequestBuilder) -> RequestBuilder {
+        if let Some(etag) = &self.etag {
+            request = request.header(header::IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &self.last_modified {
+            request = request.header(header::IF_MODIFIED_SINCE, last_modified);
+        }
+        request
+    }
+}
+
+/// Read the next body chunk, failing if the server sends nothing for `read_timeout`
+pub async fn next_chunk(response: &mut Response, read_timeout: Duration) -> Result<Option<Bytes>> {
+    match tokio::time::timeout(read_timeout, response.chunk()).await {
+        Ok(chunk) => Ok(chunk?),
+        Err(_) => Err(crate::RiemapError::Network(format!(
+            "Download from {} stalled: no data for {:?}",
+            response.url(),
+            read_timeout
+        ))
+        .into()),
+    }
+}
+
+/// Whether a response status indicates a transient server-side failure
+fn is_retryable(status: StatusCode) -> bool {
+    status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS
+}
+
+/// Delay requested by a response's `Retry-After` header
+fn retry_after(response: &Response) -> Option<Duration> {
+    parse_retry_after(response.headers().get(header::RETRY_AFTER)?.to_str().ok()?)
+}
+
+/// Parse a `Retry-After` value, either delay-seconds or an HTTP date; a date
+/// already past asks for no delay
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = httpdate::parse_http_date(value).ok()?;
+    Some(
+        date.duration_since(std::time::SystemTime::now())
+            .unwrap_or(Duration::ZERO),
+    )
+}
+
+/// Exponential backoff for the given (1-based) attempt, with up to 50% random jitter
+fn backoff(attempt: u32) -> Duration {
+    let delay = BASE_DELAY * 2u32.saturating_pow(attempt - 1);
+    let jitter = delay.mul_f64(fastrand::f64() * 0.5);
+    delay + jitter
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// Serve `respond(attempt)` (0-based) on a local port, returning its URL
+    /// and the number of requests received
+    async fn mock_server(respond: fn(usize) -> (u16, Duration)) -> (String, Arc<AtomicUsize>) {
+        let hits = Arc::new(AtomicUsize::new(0));
+        let counter = hits.clone();
+        let app = axum::Router::new().route(
+            "/",
+            axum::routing::get(move || {
+                let attempt = counter.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    let (status, delay) = respond(attempt);
+                    tokio::time::sleep(delay).await;
+                    axum::http::StatusCode::from_u16(status).unwrap()
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (url, hits)
+    }
+
+    async fn get(url: &str, max_attempts: u32, read_timeout: Duration) -> Result<Response> {
+        let client = build_client(DEFAULT_CONNECT_TIMEOUT).unwrap();
+        send_with_retry(max_attempts, read_timeout, || client.get(url)).await
+    }
+
+    #[tokio::test]
+    async fn retries_server_errors() {
+        let (url, hits) = mock_server(|attempt| match attempt {
+            0 => (503, Duration::ZERO),
+            _ => (200, Duration::ZERO),
+        })
+        .await;
+
+        let response = get(&url, 3, DEFAULT_READ_TIMEOUT).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn retry_after_accepts_seconds_and_http_dates() {
+        assert_eq!(parse_retry_after(" 7 "), Some(Duration::from_secs(7)));
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"),
+            Some(Duration::ZERO)
+        );
+
+        let later = std::time::SystemTime::now() + Duration::from_secs(120);
+        let delay = parse_retry_after(&httpdate::fmt_http_date(later)).unwrap();
+        assert!(delay > Duration::from_secs(100) && delay <= Duration::from_secs(120));
+
+        assert_eq!(parse_retry_after("soon"), None);
+    }
+
+    #[tokio::test]
+    async fn returns_last_server_error_once_attempts_run_out() {
+        let (url, hits) = mock_server(|_| (500, Duration::ZERO)).await;
+
+        let response = get(&url, 2, DEFAULT_READ_TIMEOUT).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn retries_timeouts() {
+        let (url, hits) = mock_server(|attempt| match attempt {
+            0 => (200, Duration::from_secs(5)),
+            _ => (200, Duration::ZERO),
+        })
+        .await;
+
+        let response = get(&url, 3, Duration::from_millis(200)).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn fails_after_repeated_timeouts() {
+        let (url, hits) = mock_server(|_| (200, Duration::from_secs(5))).await;
+
+        let error = get(&url, 2, Duration::from_millis(100)).await.unwrap_err();
+
+        assert!(matches!(
+            error.downcast_ref::<crate::RiemapError>(),
+            Some(crate::RiemapError::Network(_))
+        ));
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_client_errors() {
+        let (url, hits) = mock_server(|_| (404, Duration::ZERO)).await;
+
+        let response = get(&url, 3, DEFAULT_READ_TIMEOUT).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,769 @@
+use crate::{models::*, osm::OsmProcessor, quality::QualityAnalyzer, storage::Storage, Result};
+use chrono::Utc;
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::{broadcast, watch, Mutex, Semaphore};
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
+
+/// Bounded queue that runs at most `max_concurrent_jobs` jobs at a time
+#[derive(Clone)]
+pub struct JobQueue {
+    storage: Storage,
+    processor: OsmProcessor,
+    max_concurrent_jobs: usize,
+    semaphore: Arc<Semaphore>,
+    /// Region ID -> ID of its pending or running job
+    active: Arc<Mutex<HashMap<String, String>>>,
+    /// Job ID -> token cancelling the pending or running job
+    cancellations: Arc<std::sync::Mutex<HashMap<String, CancellationToken>>>,
+    /// Longest a job may run once it got a slot; `None` for no limit
+    job_timeout: Option<Duration>,
+    updates: JobUpdates,
+}
+
+/// Why a job stopped before its work was done
+#[derive(thiserror::Error, Debug)]
+pub enum JobInterrupted {
+    #[error("Job was cancelled")]
+    Cancelled,
+
+    #[error("Job timed out after {} seconds", .0.as_secs())]
+    TimedOut(Duration),
+}
+
+/// Cancellation and time limit of a running job
+///
+/// Runners check it between phases and race long phases against it, so a
+/// cancelled or timed out download stops at its next chunk. Work on the
+/// blocking thread pool, like reading a PBF, cannot be dropped that way; it
+/// watches `token` instead and stops at its next block.
+#[derive(Clone)]
+pub struct JobControl {
+    cancel: CancellationToken,
+    /// Child of `cancel`, also cancelled when a phase runs out of time
+    stop: CancellationToken,
+    deadline: Option<(Instant, Duration)>,
+}
+
+impl JobControl {
+    /// Control for a job starting now; `timeout` limits how long it may run
+    pub fn new(cancel: CancellationToken, timeout: Option<Duration>) -> Self {
+        Self {
+            stop: cancel.child_token(),
+            cancel,
+            deadline: timeout.map(|timeout| (Instant::now() + timeout, timeout)),
+        }
+    }
+
+    /// Token cancelled once the job is cancelled or a phase runs out of time,
+    /// for blocking work to check
+    pub fn token(&self) -> CancellationToken {
+        self.stop.clone()
+    }
+
+    /// Fail with `JobInterrupted` when the job was cancelled or ran out of time
+    pub fn check(&self) -> Result<()> {
+        if self.cancel.is_cancelled() {
+            return Err(JobInterrupted::Cancelled.into());
+        }
+        match self.deadline {
+            Some((deadline, timeout)) if Instant::now() >= deadline => {
+                Err(JobInterrupted::TimedOut(timeout).into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Run a phase of the job, dropping it as soon as the job is cancelled
+    /// or runs out of time
+    pub async fn run<T>(&self, phase: impl Future<Output = Result<T>>) -> Result<T> {
+        self.check()?;
+        let expired = async {
+            match self.deadline {
+                Some((deadline, timeout)) => {
+                    tokio::time::sleep_until(deadline).await;
+                    timeout
+                }
+                None => std::future::pending().await,
+            }
+        };
+
+        tokio::select! {
+            result = phase => result,
+            _ = self.cancel.cancelled() => Err(JobInterrupted::Cancelled.into()),
+            timeout = expired => {
+                self.stop.cancel();
+                Err(JobInterrupted::TimedOut(timeout).into())
+            }
+        }
+    }
+}
+
+/// Capacity of each job's update channel; slow subscribers skip older updates
+const JOB_UPDATES_CAPACITY: usize = 16;
+
+/// Broadcast channels carrying every saved state of active jobs, keyed by job ID
+#[derive(Clone, Default)]
+pub struct JobUpdates {
+    channels: Arc<std::sync::Mutex<HashMap<String, broadcast::Sender<ProcessingJob>>>>,
+}
+
+impl JobUpdates {
+    fn open(&self, job_id: &str) {
+        let (sender, _) = broadcast::channel(JOB_UPDATES_CAPACITY);
+        self.channels
+            .lock()
+            .unwrap()
+            .insert(job_id.to_string(), sender);
+    }
+
+    /// Dropping the sender closes the channel for every subscriber
+    fn close(&self, job_id: &str) {
+        self.channels.lock().unwrap().remove(job_id);
+    }
+
+    fn publish(&self, job: &ProcessingJob) {
+        if let Some(sender) = self.channels.lock().unwrap().get(&job.id) {
+            // No subscribers is not an error
+            let _ = sender.send(job.clone());
+        }
+    }
+
+    fn subscribe(&self, job_id: &str) -> Option<broadcast::Receiver<ProcessingJob>> {
+        self.channels
+            .lock()
+            .unwrap()
+            .get(job_id)
+            .map(broadcast::Sender::subscribe)
+    }
+}
+
+/// Snapshot of how many jobs are waiting and running
+#[derive(Debug, Clone, Copy, serde::Serialize)]
+pub struct QueueDepth {
+    pub pending: usize,
+    pub running: usize,
+}
+
+/// Append-only log of a job's phases and warnings, one JSON object per line
+///
+/// Lines look like `{"time": ..., "level": "info", "phase": "download", "message": ...}`.
+/// Failing to write a line never fails the job itself.
+#[derive(Debug, Clone)]
+pub struct JobLog {
+    /// `None` for work that runs outside a job, e.g. from the CLI
+    path: Option<PathBuf>,
+}
+
+impl JobLog {
+    /// The log of a job, stored next to its metadata
+    pub fn for_job(storage: &Storage, job_id: &str) -> Self {
+        Self {
+            path: Some(storage.job_log_path(job_id)),
+        }
+    }
+
+    /// A log that discards every line
+    pub fn disabled() -> Self {
+        Self { path: None }
+    }
+
+    pub async fn info(&self, phase: &str, message: impl Into<String>) {
+        self.append("info", phase, message.into()).await;
+    }
+
+    pub async fn warn(&self, phase: &str, message: impl Into<String>) {
+        self.append("warn", phase, message.into()).await;
+    }
+
+    pub async fn error(&self, phase: &str, message: impl Into<String>) {
+        self.append("error", phase, message.into()).await;
+    }
+
+    async fn append(&self, level: &str, phase: &str, message: String) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let mut line = serde_json::json!({
+            "time": Utc::now(),
+            "level": level,
+            "phase": phase,
+            "message": message,
+        })
+        .to_string();
+        line.push('\n');
+
+        let result = async {
+            if let Some(parent) = path.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+            let mut file = tokio::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .await?;
+            file.write_all(line.as_bytes()).await
+        }
+        .await;
+        if let Err(e) = result {
+            warn!("Failed to write job log {:?}: {}", path, e);
+        }
+    }
+}
+
+impl JobQueue {
+    /// Create a queue sharing the given storage and processor
+    pub fn new(storage: Storage, processor: OsmProcessor, max_concurrent_jobs: usize) -> Self {
+        let max_concurrent_jobs = max_concurrent_jobs.max(1);
+
+        Self {
+            storage,
+            processor,
+            max_concurrent_jobs,
+            semaphore: Arc::new(Semaphore::new(max_concurrent_jobs)),
+            active: Arc::new(Mutex::new(HashMap::new())),
+            cancellations: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            job_timeout: None,
+            updates: JobUpdates::default(),
+        }
+    }
+
+    /// Fail jobs still running `timeout` after they started; `None` lets them run
+    pub fn with_job_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.job_timeout = timeout;
+        self
+    }
+
+    /// Queue a download for a region, or return its already active job.
+    /// The flag is `true` when a new job was created.
+    pub async fn submit_download(&self, region: Region) -> Result<(ProcessingJob, bool)> {
+        let storage = self.storage.clone();
+        let processor = self.processor.clone();
+        let updates = self.updates.clone();
+
+        let region_id = region.id.clone();
+        self.submit(&region_id, JobType::Download, move |job, control| {
+            run_download_job(storage, processor, updates, region, job, control)
+        })
+        .await
+    }
+
+    /// Queue a quality analysis of an already downloaded version, or return
+    /// the region's already active job. The flag is `true` when a new job was created.
+    pub async fn submit_analysis(
+        &self,
+        region: Region,
+        version: String,
+    ) -> Result<(ProcessingJob, bool)> {
+        let storage = self.storage.clone();
+        let processor = self.processor.clone();
+        let updates = self.updates.clone();
+
+        let region_id = region.id.clone();
+        self.submit(&region_id, JobType::QualityAnalysis, move |job, control| {
+            run_analysis_job(storage, processor, updates, region, version, job, control)
+        })
+        .await
+    }
+
+    /// Persist a new job and run it once a slot is free; a region has at most one active job
+    async fn submit<F, Fut>(
+        &self,
+        region_id: &str,
+        job_type: JobType,
+        run: F,
+    ) -> Result<(ProcessingJob, bool)>
+    where
+        F: FnOnce(ProcessingJob, JobControl) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send,
+    {
+        let mut active = self.active.lock().await;
+
+        if let Some(job_id) = active.get(region_id) {
+            if let Some(job) = self.storage.get_processing_job(job_id).await? {
+                return Ok((job, false));
+            }
+        }
+
+        // An unfinished job this queue does not track was left behind by an
+        // earlier server process and will never run
+        if let Some(job) = self.storage.latest_job(region_id).await? {
+            if !job.status.is_finished() {
+                fail_job(
+                    &self.storage,
+                    &self.updates,
+                    job,
+                    anyhow::anyhow!("Interrupted by a server restart"),
+                )
+                .await;
+            }
+        }
+
+        let job = new_job(region_id, job_type);
+        self.storage.save_processing_job(&job).await?;
+        JobLog::for_job(&self.storage, &job.id)
+            .info(
+                "queued",
+                format!("{:?} job queued for {}", job.job_type, region_id),
+            )
+            .await;
+        active.insert(region_id.to_string(), job.id.clone());
+        self.updates.open(&job.id);
+        let cancel = CancellationToken::new();
+        self.cancellations
+            .lock()
+            .unwrap()
+            .insert(job.id.clone(), cancel.clone());
+        drop(active);
+
+        let queue = self.clone();
+        let queued_job = job.clone();
+        let region_id = region_id.to_string();
+        tokio::spawn(async move {
+            let job_id = queued_job.id.clone();
+            tokio::select! {
+                permit = queue.semaphore.clone().acquire_owned() => match permit {
+                    Ok(_permit) => {
+                        let control = JobControl::new(cancel, queue.job_timeout);
+                        run(queued_job, control).await
+                    }
+                    // The queue was shut down before this job got a slot
+                    Err(_) => {
+                        cancel_job(&queue.storage, &queue.updates, queued_job, "Cancelled before it started").await
+                    }
+                },
+                _ = cancel.cancelled() => {
+                    cancel_job(&queue.storage, &queue.updates, queued_job, "Cancelled before it started").await
+                }
+            }
+            queue.cancellations.lock().unwrap().remove(&job_id);
+            queue.updates.close(&job_id);
+            queue.active.lock().await.remove(&region_id);
+        });
+
+        Ok((job, true))
+    }
+
+    /// Cancel a pending or running job of this queue
+    ///
+    /// A pending job is cancelled right away; a running one stops at its
+    /// next phase or download chunk and is then marked `Cancelled`. Returns
+    /// `false` when the job is not active in this queue.
+    pub fn cancel(&self, job_id: &str) -> bool {
+        match self.cancellations.lock().unwrap().get(job_id) {
+            Some(cancel) => {
+                cancel.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Stop starting new jobs and mark every job still waiting for a slot as cancelled.
+    /// Returns the number of jobs cancelled.
+    pub async fn shutdown(&self) -> usize {
+        self.semaphore.close();
+
+        let active = self.active.lock().await;
+        let mut cancelled = 0;
+        for job_id in active.values() {
+            match self.storage.get_processing_job(job_id).await {
+                Ok(Some(job)) if matches!(job.status, JobStatus::Pending) => {
+                    cancel_job(
+                        &self.storage,
+                        &self.updates,
+                        job,
+                        "Cancelled before it started",
+                    )
+                    .await;
+                    cancelled += 1;
+                }
+                Ok(_) => {}
+                Err(e) => error!("Failed to load job {} during shutdown: {}", job_id, e),
+            }
+        }
+
+        cancelled
+    }
+
+    /// Receive every state a job is saved in until it finishes
+    ///
+    /// Returns `None` when the job is not pending or running in this queue,
+    /// in which case its stored state is final.
+    pub fn subscribe(&self, job_id: &str) -> Option<broadcast::Receiver<ProcessingJob>> {
+        self.updates.subscribe(job_id)
+    }
+
+    /// Current number of pending and running jobs
+    pub async fn depth(&self) -> QueueDepth {
+        let active = self.active.lock().await.len();
+        let running = self.max_concurrent_jobs - self.semaphore.available_permits();
+
+        QueueDepth {
+            pending: active.saturating_sub(running),
+            running,
+        }
+    }
+}
+
+/// Create a new pending job for a region
+pub fn new_job(region_id: &str, job_type: JobType) -> ProcessingJob {
+    ProcessingJob {
+        id: uuid::Uuid::new_v4().to_string(),
+        region_id: region_id.to_string(),
+        job_type,
+        status: JobStatus::Pending,
+        progress: 0.0,
+        message: Some("Job queued".to_string()),
+        created_at: Utc::now(),
+        started_at: None,
+        completed_at: None,
+        error_message: None,
+    }
+}
+
+/// Run a download job to completion, persisting progress as the file streams in
+pub async fn run_download_job(
+    storage: Storage,
+    processor: OsmProcessor,
+    updates: JobUpdates,
+    region: Region,
+    mut job: ProcessingJob,
+    control: JobControl,
+) {
+    job.status = JobStatus::Running;
+    job.started_at = Some(Utc::now());
+    job.message = Some(format!("Downloading {}", region.name));
+    let log = JobLog::for_job(&storage, &job.id);
+    log.info("download", format!("Downloading {}", region.name))
+        .await;
+    save_job(&storage, &updates, &job).await;
+
+    let region_dir = match control.run(storage.get_region_path(&region.id)).await {
+        Ok(dir) => dir,
+        Err(e) => return fail_job(&storage, &updates, job, e).await,
+    };
+
+    // Progress is published on a watch channel so the (sync) download callback
+    // never blocks on storage; the writer only persists the latest value.
+    let (progress_tx, mut progress_rx) = watch::channel((0u64, None::<u64>));
+    let writer = {
+        let storage = storage.clone();
+        let updates = updates.clone();
+        let log = log.clone();
+        let mut job = job.clone();
+        tokio::spawn(async move {
+            // Only every tenth of the download is logged, not each update
+            let mut logged_tenths = 0;
+            while progress_rx.changed().await.is_ok() {
+                let (downloaded, total) = *progress_rx.borrow_and_update();
+                apply_download_progress(&mut job, downloaded, total);
+                let tenths = (job.progress / 10.0) as u32;
+                if tenths > logged_tenths {
+                    logged_tenths = tenths;
+                    if let Some(message) = &job.message {
+                        log.info("download", message.as_str()).await;
+                    }
+                }
+                save_job(&storage, &updates, &job).await;
+            }
+        })
+    };
+
+    let result = control
+        .run(processor.download_region_with_progress(
+            &region,
+            &region_dir,
+            true,
+            |downloaded, total| {
+                progress_tx.send_replace((downloaded, total));
+            },
+        ))
+        .await;
+
+    drop(progress_tx);
+    let _ = writer.await;
+
+    match result {
+        Ok(path) => {
+            job.status = JobStatus::Completed;
+            job.progress = 100.0;
+            job.completed_at = Some(Utc::now());
+            job.message = Some(format!("Downloaded to {}", path.display()));
+            info!("Job {} completed: {:?}", job.id, path);
+            log.info("completed", format!("Downloaded to {}", path.display()))
+                .await;
+            save_job(&storage, &updates, &job).await;
+
+            // The new file changes the region's listed files and stats
+            if let Err(e) = storage.touch_region(&region.id).await {
+                warn!("Failed to update the timestamp of {}: {}", region.id, e);
+                storage.invalidate_region_tree().await;
+            }
+        }
+        Err(e) => fail_job(&storage, &updates, job, e).await,
+    }
+}
+
+/// Re-run quality analysis on a stored version and save a fresh report
+pub async fn run_analysis_job(
+    storage: Storage,
+    processor: OsmProcessor,
+    updates: JobUpdates,
+    region: Region,
+    version: String,
+    mut job: ProcessingJob,
+    control: JobControl,
+) {
+    job.status = JobStatus::Running;
+    job.started_at = Some(Utc::now());
+    job.message = Some(format!("Analyzing {} version {}", region.name, version));
+    let log = JobLog::for_job(&storage, &job.id);
+    log.info(
+        "analyze",
+        format!("Analyzing {} version {}", region.name, version),
+    )
+    .await;
+    save_job(&storage, &updates, &job).await;
+
+    let file_path = match control
+        .run(storage.get_file_path(&region.id, &version))
+        .await
+    {
+        Ok(Some(path)) => path,
+        Ok(None) => {
+            let err = crate::RiemapError::Storage(format!(
+                "No file found for region '{}' version '{}'",
+                region.id, version
+            ));
+            return fail_job(&storage, &updates, job, err.into()).await;
+        }
+        Err(e) => return fail_job(&storage, &updates, job, e).await,
+    };
+
+    let analyzer = QualityAnalyzer::default();
+    let processor = processor.with_cancellation(control.token());
+    match control
+        .run(analyze_data_file(
+            &storage,
+            &processor,
+            &analyzer,
+            &region.id,
+            &region.admin_level,
+            &file_path,
+            &log,
+        ))
+        .await
+    {
+        Ok(report) => {
+            job.status = JobStatus::Completed;
+            job.progress = 100.0;
+            job.completed_at = Some(Utc::now());
+            job.message = Some(format!("Quality report {} saved", report.id));
+            info!("Job {} completed: report {}", job.id, report.id);
+            log.info("completed", format!("Quality report {} saved", report.id))
+                .await;
+            save_job(&storage, &updates, &job).await;
+
+            // The region's files now link to the new report
+            storage.invalidate_region_tree().await;
+        }
+        Err(e) => fail_job(&storage, &updates, job, e).await,
+    }
+}
+
+/// Process a data file, run every analyzer over it and save the resulting report
+///
+/// The report is linked to the file's `DataFile` entry when the file is one
+/// of the region's stored versions. Each phase is written to `log`.
+pub async fn analyze_data_file(
+    storage: &Storage,
+    processor: &OsmProcessor,
+    analyzer: &QualityAnalyzer,
+    region_id: &str,
+    admin_level: &AdminLevel,
+    file_path: &Path,
+    log: &JobLog,
+) -> Result<QualityReport> {
+    log.info("process", format!("Processing {}", file_path.display()))
+        .await;
+    let (metrics, geometry_issues) = processor.process_osm_file(file_path).await?;
+    log.info(
+        "process",
+        format!(
+            "Read {} nodes, {} ways and {} relations",
+            count_text(metrics.total_nodes),
+            count_text(metrics.total_ways),
+            count_text(metrics.total_relations)
+        ),
+    )
+    .await;
+
+    log.info(
+        "validate",
+        format!(
+            "Validating up to {} elements",
+            crate::osm::DEFAULT_VALIDATION_MAX_ELEMENTS
+        ),
+    )
+    .await;
+    let mut issues = processor
+        .validate_file(file_path, Some(crate::osm::DEFAULT_VALIDATION_MAX_ELEMENTS))
+        .await?;
+    if !issues.is_empty() {
+        log.warn("validate", format!("{} validation issues", issues.len()))
+            .await;
+    }
+    if !geometry_issues.is_empty() {
+        log.warn(
+            "validate",
+            format!("{} geometry issues", geometry_issues.len()),
+        )
+        .await;
+    }
+    issues.extend(geometry_issues);
+    if let Some(region) = storage.find_region(region_id).await? {
+        if let Some(issue) = processor.check_extent(&region.bounding_box, file_path) {
+            log.error("validate", issue.description.as_str()).await;
+            issues.push(issue);
+        }
+    }
+    issues.extend(analyzer.analyze_completeness(&metrics, admin_level));
+    issues.extend(analyzer.analyze_boundaries(&metrics, admin_level));
+    issues.extend(analyzer.analyze_patterns(&metrics));
+    issues.extend(analyzer.audit_amenities(&metrics));
+    issues.extend(analyzer.audit_buildings_3d(&metrics, admin_level));
+
+    let data_file_id = storage
+        .find_data_file(region_id, file_path)
+        .await?
+        .map(|file| file.id)
+        .unwrap_or_else(|| format!("{}_{}", region_id, Utc::now().format("%Y-%m-%d")));
+    let report = analyzer
+        .generate_report(&data_file_id, region_id, &metrics, issues)
+        .await?;
+
+    let severe = report
+        .issues
+        .iter()
+        .filter(|issue| {
+            matches!(
+                issue.severity,
+                IssueSeverity::High | IssueSeverity::Critical
+            )
+        })
+        .count();
+    let summary = format!(
+        "Completeness {:.2} with {} issues, {} high or critical",
+        report.metrics.completeness_score,
+        report.issues.len(),
+        severe
+    );
+    if severe > 0 {
+        log.warn("analyze", summary).await;
+    } else {
+        log.info("analyze", summary).await;
+    }
+
+    storage.save_quality_report(&report).await?;
+    Ok(report)
+}
+
+/// Update a job's progress fields from download byte counts
+fn apply_download_progress(job: &mut ProcessingJob, downloaded: u64, total: Option<u64>) {
+    let downloaded_mb = downloaded as f64 / 1_048_576.0;
+
+    match total {
+        Some(total) if total > 0 => {
+            job.progress = (downloaded as f64 / total as f64 * 100.0).min(100.0);
+            job.message = Some(format!(
+                "Downloaded {:.1} of {:.1} MB",
+                downloaded_mb,
+                total as f64 / 1_048_576.0
+            ));
+        }
+        _ => {
+            job.message = Some(format!("Downloaded {:.1} MB", downloaded_mb));
+        }
+    }
+}
+
+/// Mark a job as failed and persist it; a job stopped by cancellation is
+/// marked cancelled instead
+async fn fail_job(
+    storage: &Storage,
+    updates: &JobUpdates,
+    mut job: ProcessingJob,
+    err: anyhow::Error,
+) {
+    if let Some(JobInterrupted::Cancelled) = err.downcast_ref() {
+        return cancel_job(storage, updates, job, "Cancelled while running").await;
+    }
+
+    error!("Job {} failed: {}", job.id, err);
+    JobLog::for_job(storage, &job.id)
+        .error("failed", format!("{:#}", err))
+        .await;
+    job.status = JobStatus::Failed;
+    job.completed_at = Some(Utc::now());
+    job.error_message = Some(err.to_string());
+    save_job(storage, updates, &job).await;
+}
+
+/// Mark a job as cancelled and persist it
+async fn cancel_job(storage: &Storage, updates: &JobUpdates, mut job: ProcessingJob, reason: &str) {
+    info!("Job {} cancelled", job.id);
+    JobLog::for_job(storage, &job.id)
+        .info("cancelled", reason)
+        .await;
+    job.status = JobStatus::Cancelled;
+    job.completed_at = Some(Utc::now());
+    job.message = Some(reason.to_string());
+    save_job(storage, updates, &job).await;
+}
+
+/// Persist a job and notify its subscribers, logging rather than propagating storage failures
+async fn save_job(storage: &Storage, updates: &JobUpdates, job: &ProcessingJob) {
+    if let Err(e) = storage.save_processing_job(job).await {
+        error!("Failed to save job {}: {}", job.id, e);
+    }
+    updates.publish(job);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn timed_out_phase_stops_blocking_work() {
+        let control = JobControl::new(CancellationToken::new(), Some(Duration::from_millis(50)));
+        let token = control.token();
+
+        let result = control.run(std::future::pending::<Result<()>>()).await;
+        assert!(matches!(
+            result.unwrap_err().downcast_ref(),
+            Some(JobInterrupted::TimedOut(_))
+        ));
+        assert!(token.is_cancelled());
+    }
+
+    #[tokio::test]
+    async fn cancelling_the_job_stops_blocking_work() {
+        let cancel = CancellationToken::new();
+        let control = JobControl::new(cancel.clone(), None);
+        assert!(!control.token().is_cancelled());
+
+        cancel.cancel();
+        assert!(control.token().is_cancelled());
+        assert!(matches!(
+            control.check().unwrap_err().downcast_ref(),
+            Some(JobInterrupted::Cancelled)
+        ));
+    }
+}
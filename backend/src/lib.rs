@@ -1,9 +1,21 @@
 pub mod api;
 pub mod config;
+pub mod http;
+pub mod jobs;
+pub mod logging;
+pub mod metadata;
 pub mod models;
+pub mod node_index;
 pub mod osm;
+pub mod osm_xml;
+pub mod pbf;
+pub mod poly;
 pub mod quality;
+pub mod scheduler;
+pub mod shapefile;
 pub mod storage;
+pub mod temp_files;
+pub mod tile_cache;
 
 pub use models::*;
 
@@ -27,4 +39,7 @@ pub enum RiemapError {
 
     #[error("Configuration error: {0}")]
     Config(String),
+
+    #[error("Invalid bounding box: {0}")]
+    InvalidBoundingBox(String),
 }
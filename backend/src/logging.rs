@@ -0,0 +1,67 @@
+use crate::config::LoggingConfig;
+use serde::{Deserialize, Serialize};
+use tracing_subscriber::filter::{LevelFilter, Targets};
+use tracing_subscriber::prelude::*;
+
+/// Filter used when neither `RUST_LOG` nor a configured level is set
+pub const DEFAULT_LOG_LEVEL: &str = "info";
+
+/// How log lines are written to stdout
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human-readable lines
+    #[default]
+    Text,
+    /// One JSON object per line with `timestamp`, `level`, `target` and
+    /// `fields`, for log pipelines
+    Json,
+}
+
+impl std::str::FromStr for LogFormat {
+    type Err = crate::RiemapError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            other => Err(crate::RiemapError::Config(format!(
+                "Unknown log format '{}', expected 'text' or 'json'",
+                other
+            ))),
+        }
+    }
+}
+
+/// Install the global subscriber for a binary
+///
+/// `RUST_LOG` takes precedence over the configured level. Both use the
+/// `level` or `target=level,...` syntax, e.g. `info,riemap_backend=debug`;
+/// a filter that does not parse falls back to `info` with a warning.
+pub fn init(config: &LoggingConfig) {
+    let directives = std::env::var("RUST_LOG")
+        .ok()
+        .filter(|directives| !directives.trim().is_empty())
+        .unwrap_or_else(|| config.level.clone());
+    let (filter, invalid) = match directives.parse::<Targets>() {
+        Ok(filter) => (filter, None),
+        Err(e) => (Targets::new().with_default(LevelFilter::INFO), Some(e)),
+    };
+
+    let registry = tracing_subscriber::registry().with(filter);
+    match config.format {
+        LogFormat::Text => registry.with(tracing_subscriber::fmt::layer()).init(),
+        LogFormat::Json => registry
+            .with(tracing_subscriber::fmt::layer().json())
+            .init(),
+    }
+
+    if let Some(e) = invalid {
+        tracing::warn!(
+            "Invalid log filter '{}' ({}), logging at {}",
+            directives,
+            e,
+            DEFAULT_LOG_LEVEL
+        );
+    }
+}
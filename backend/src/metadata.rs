@@ -0,0 +1,721 @@
+use crate::{models::*, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::{Mutex, RwLock};
+use tracing::{info, warn};
+
+/// Where region, job and quality report metadata is kept
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MetadataBackend {
+    /// `metadata.json` plus one JSON file per job and report; needs no setup
+    #[default]
+    Json,
+    /// A single `metadata.db` with indexed lookups by region and parent ID
+    Sqlite,
+}
+
+impl std::str::FromStr for MetadataBackend {
+    type Err = crate::RiemapError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "json" => Ok(Self::Json),
+            "sqlite" => Ok(Self::Sqlite),
+            other => Err(crate::RiemapError::Config(format!(
+                "Unknown metadata backend '{}', expected 'json' or 'sqlite'",
+                other
+            ))),
+        }
+    }
+}
+
+/// Persistence for regions, processing jobs and quality reports
+///
+/// Data files are not part of the store: they are discovered from the
+/// region directories on disk.
+#[async_trait]
+pub trait MetadataStore: Send + Sync {
+    /// Where the metadata lives, for log and health check messages
+    fn location(&self) -> &Path;
+
+    /// When the stored regions last changed, if known
+    fn regions_modified(&self) -> Option<SystemTime>;
+
+    /// Load every region
+    async fn load_regions(&self) -> Result<Vec<Region>>;
+
+    /// Replace all regions
+    async fn save_regions(&self, regions: &[Region]) -> Result<()>;
+
+    /// Move unreadable region metadata aside so the store starts out empty,
+    /// returning where it was moved; `None` when the backend cannot do so
+    async fn quarantine_regions(&self) -> Result<Option<PathBuf>> {
+        Ok(None)
+    }
+
+    /// Look up a single region by ID
+    async fn get_region(&self, region_id: &str) -> Result<Option<Region>>;
+
+    /// Direct children of a region with `has_children` set on each
+    async fn get_children(&self, parent_id: &str) -> Result<Vec<Region>>;
+
+    /// Every region below a region in the hierarchy, in no particular order
+    async fn get_descendants(&self, region_id: &str) -> Result<Vec<Region>>;
+
+    /// Insert or replace a processing job
+    async fn save_job(&self, job: &ProcessingJob) -> Result<()>;
+
+    /// Look up a processing job by ID
+    async fn get_job(&self, job_id: &str) -> Result<Option<ProcessingJob>>;
+
+    /// Every stored job, newest first
+    async fn list_jobs(&self) -> Result<Vec<ProcessingJob>>;
+
+    /// The most recently created job of a region, found through an index
+    /// kept up to date by `save_job`
+    async fn latest_job(&self, region_id: &str) -> Result<Option<ProcessingJob>>;
+
+    /// Insert or replace a quality report
+    async fn save_report(&self, report: &QualityReport) -> Result<()>;
+
+    /// Look up a quality report by ID
+    async fn get_report(&self, report_id: &str) -> Result<Option<QualityReport>>;
+
+    /// All quality reports for a region, newest first
+    async fn list_reports(&self, region_id: &str) -> Result<Vec<QualityReport>>;
+}
+
+/// Open the store for the configured backend under `data_dir`
+pub fn open_store(data_dir: &Path, backend: MetadataBackend) -> Result<Arc<dyn MetadataStore>> {
+    Ok(match backend {
+        MetadataBackend::Json => Arc::new(JsonMetadataStore::new(data_dir)),
+        MetadataBackend::Sqlite => {
+            Arc::new(SqliteMetadataStore::open(data_dir.join("metadata.db"))?)
+        }
+    })
+}
+
+/// Mark each region that is the parent of another region in `regions`
+fn set_has_children<'a>(all: &[Region], regions: impl Iterator<Item = &'a mut Region>) {
+    let parents: HashSet<&str> = all
+        .iter()
+        .filter_map(|region| region.parent_id.as_deref())
+        .collect();
+
+    for region in regions {
+        region.has_children = parents.contains(region.id.as_str());
+    }
+}
+
+/// Lazily loaded map of region ID to region
+type RegionCache = RwLock<Option<Arc<HashMap<String, Region>>>>;
+
+/// Entry of `job_index.json`: a region's most recently created job
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct LatestJob {
+    job_id: String,
+    created_at: DateTime<Utc>,
+}
+
+/// Regions in `metadata.json`, jobs and reports as one JSON file each
+pub struct JsonMetadataStore {
+    data_dir: PathBuf,
+    metadata_file: PathBuf,
+    region_cache: RegionCache,
+    /// Serializes metadata.json writers against readers
+    metadata_lock: RwLock<()>,
+    job_index_file: PathBuf,
+    /// Serializes read-modify-write updates of job_index.json
+    job_index_lock: Mutex<()>,
+}
+
+impl JsonMetadataStore {
+    /// Create a store rooted at `data_dir`
+    pub fn new<P: AsRef<Path>>(data_dir: P) -> Self {
+        let data_dir = data_dir.as_ref().to_path_buf();
+
+        Self {
+            metadata_file: data_dir.join("metadata.json"),
+            job_index_file: data_dir.join("job_index.json"),
+            job_index_lock: Mutex::new(()),
+            data_dir,
+            region_cache: RwLock::new(None),
+            metadata_lock: RwLock::new(()),
+        }
+    }
+
+    /// Get all regions keyed by ID, reusing the cached map when available
+    async fn region_map(&self) -> Result<Arc<HashMap<String, Region>>> {
+        if let Some(map) = self.region_cache.read().await.as_ref() {
+            return Ok(map.clone());
+        }
+
+        let regions = self.load_regions().await?;
+        let map: Arc<HashMap<String, Region>> =
+            Arc::new(regions.into_iter().map(|r| (r.id.clone(), r)).collect());
+        *self.region_cache.write().await = Some(map.clone());
+        Ok(map)
+    }
+
+    fn job_file(&self, job_id: &str) -> PathBuf {
+        self.data_dir.join("jobs").join(format!("{}.json", job_id))
+    }
+
+    fn report_file(&self, report_id: &str) -> PathBuf {
+        self.data_dir
+            .join("reports")
+            .join(format!("{}.json", report_id))
+    }
+
+    /// Read a JSON document, or `None` when the file does not exist
+    async fn read_json<T: DeserializeOwned>(path: &Path) -> Result<Option<T>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let contents = tokio::fs::read_to_string(path).await?;
+        Ok(Some(serde_json::from_str(&contents)?))
+    }
+
+    /// Write a JSON document, creating its directory if needed
+    async fn write_json<T: Serialize>(path: &Path, value: &T) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let json = serde_json::to_string_pretty(value)?;
+        tokio::fs::write(path, json).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl MetadataStore for JsonMetadataStore {
+    fn location(&self) -> &Path {
+        &self.metadata_file
+    }
+
+    fn regions_modified(&self) -> Option<SystemTime> {
+        std::fs::metadata(&self.metadata_file)
+            .and_then(|m| m.modified())
+            .ok()
+    }
+
+    async fn load_regions(&self) -> Result<Vec<Region>> {
+        if !self.metadata_file.exists() {
+            return Ok(Vec::new());
+        }
+
+        let contents = {
+            let _guard = self.metadata_lock.read().await;
+            tokio::fs::read_to_string(&self.metadata_file).await?
+        };
+        // The parse error stays in the chain so callers can tell corruption apart
+        let regions: Vec<Region> = serde_json::from_str(&contents).map_err(|e| {
+            let message = format!(
+                "Invalid region metadata in {:?} at line {}, column {}",
+                self.metadata_file,
+                e.line(),
+                e.column()
+            );
+            anyhow::Error::new(e).context(crate::RiemapError::Storage(message))
+        })?;
+        Ok(regions)
+    }
+
+    async fn quarantine_regions(&self) -> Result<Option<PathBuf>> {
+        let backup = self.data_dir.join(format!(
+            "metadata.json.corrupt-{}",
+            Utc::now().format("%Y%m%dT%H%M%SZ")
+        ));
+
+        {
+            let _guard = self.metadata_lock.write().await;
+            tokio::fs::rename(&self.metadata_file, &backup).await?;
+        }
+
+        *self.region_cache.write().await = None;
+        Ok(Some(backup))
+    }
+
+    async fn save_regions(&self, regions: &[Region]) -> Result<()> {
+        let json = serde_json::to_string_pretty(regions)?;
+
+        {
+            let _guard = self.metadata_lock.write().await;
+
+            // Write to a temporary file and rename it over metadata.json so a
+            // crash mid-write never leaves a truncated file behind
+            let tmp_file = self
+                .data_dir
+                .join(format!(".metadata-{}.json.tmp", uuid::Uuid::new_v4()));
+            let mut file = tokio::fs::File::create(&tmp_file).await?;
+            file.write_all(json.as_bytes()).await?;
+            file.sync_all().await?;
+            drop(file);
+
+            if let Err(e) = tokio::fs::rename(&tmp_file, &self.metadata_file).await {
+                let _ = tokio::fs::remove_file(&tmp_file).await;
+                return Err(e.into());
+            }
+        }
+
+        *self.region_cache.write().await = None;
+        Ok(())
+    }
+
+    async fn get_region(&self, region_id: &str) -> Result<Option<Region>> {
+        Ok(self.region_map().await?.get(region_id).cloned())
+    }
+
+    async fn get_children(&self, parent_id: &str) -> Result<Vec<Region>> {
+        let regions = self.region_map().await?;
+        let all: Vec<Region> = regions.values().cloned().collect();
+
+        let mut children: Vec<Region> = all
+            .iter()
+            .filter(|region| region.parent_id.as_deref() == Some(parent_id))
+            .cloned()
+            .collect();
+        set_has_children(&all, children.iter_mut());
+        Ok(children)
+    }
+
+    async fn get_descendants(&self, region_id: &str) -> Result<Vec<Region>> {
+        let regions = self.region_map().await?;
+
+        let mut children_map: HashMap<&str, Vec<&Region>> = HashMap::new();
+        for region in regions.values() {
+            if let Some(ref parent) = region.parent_id {
+                children_map
+                    .entry(parent.as_str())
+                    .or_default()
+                    .push(region);
+            }
+        }
+
+        let mut descendants = Vec::new();
+        let mut seen = HashSet::from([region_id]);
+        let mut queue = vec![region_id];
+        while let Some(id) = queue.pop() {
+            for &child in children_map.get(id).into_iter().flatten() {
+                if seen.insert(child.id.as_str()) {
+                    queue.push(child.id.as_str());
+                    descendants.push(child.clone());
+                }
+            }
+        }
+
+        Ok(descendants)
+    }
+
+    async fn save_job(&self, job: &ProcessingJob) -> Result<()> {
+        Self::write_json(&self.job_file(&job.id), job).await?;
+
+        // Point the region's index entry at this job unless a newer one exists;
+        // progress updates of an already indexed job leave the file alone
+        let _guard = self.job_index_lock.lock().await;
+        let mut index: HashMap<String, LatestJob> = Self::read_json(&self.job_index_file)
+            .await?
+            .unwrap_or_default();
+        let update = match index.get(&job.region_id) {
+            Some(latest) => latest.job_id != job.id && latest.created_at <= job.created_at,
+            None => true,
+        };
+        if update {
+            index.insert(
+                job.region_id.clone(),
+                LatestJob {
+                    job_id: job.id.clone(),
+                    created_at: job.created_at,
+                },
+            );
+            Self::write_json(&self.job_index_file, &index).await?;
+        }
+        Ok(())
+    }
+
+    async fn get_job(&self, job_id: &str) -> Result<Option<ProcessingJob>> {
+        Self::read_json(&self.job_file(job_id)).await
+    }
+
+    async fn list_jobs(&self) -> Result<Vec<ProcessingJob>> {
+        let jobs_dir = self.data_dir.join("jobs");
+
+        if !jobs_dir.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut jobs = Vec::new();
+        let mut entries = tokio::fs::read_dir(&jobs_dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path.extension().and_then(|s| s.to_str()) != Some("json") {
+                continue;
+            }
+
+            let contents = tokio::fs::read_to_string(&path).await?;
+            match serde_json::from_str::<ProcessingJob>(&contents) {
+                Ok(job) => jobs.push(job),
+                Err(e) => warn!("Skipping unreadable job {:?}: {}", path, e),
+            }
+        }
+
+        jobs.sort_by_key(|job| std::cmp::Reverse(job.created_at));
+        Ok(jobs)
+    }
+
+    async fn latest_job(&self, region_id: &str) -> Result<Option<ProcessingJob>> {
+        let index: HashMap<String, LatestJob> = {
+            let _guard = self.job_index_lock.lock().await;
+            Self::read_json(&self.job_index_file)
+                .await?
+                .unwrap_or_default()
+        };
+
+        match index.get(region_id) {
+            Some(latest) => self.get_job(&latest.job_id).await,
+            None => Ok(None),
+        }
+    }
+
+    async fn save_report(&self, report: &QualityReport) -> Result<()> {
+        Self::write_json(&self.report_file(&report.id), report).await
+    }
+
+    async fn get_report(&self, report_id: &str) -> Result<Option<QualityReport>> {
+        Self::read_json(&self.report_file(report_id)).await
+    }
+
+    async fn list_reports(&self, region_id: &str) -> Result<Vec<QualityReport>> {
+        let reports_dir = self.data_dir.join("reports");
+
+        if !reports_dir.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut reports = Vec::new();
+        let mut entries = tokio::fs::read_dir(&reports_dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path.extension().and_then(|s| s.to_str()) != Some("json") {
+                continue;
+            }
+
+            let contents = tokio::fs::read_to_string(&path).await?;
+            match serde_json::from_str::<QualityReport>(&contents) {
+                Ok(report) if report.region_id == region_id => reports.push(report),
+                Ok(_) => {}
+                Err(e) => warn!("Skipping unreadable report {:?}: {}", path, e),
+            }
+        }
+
+        reports.sort_by_key(|report| std::cmp::Reverse(report.created_at));
+        Ok(reports)
+    }
+}
+
+/// Tables and indexes of the SQLite store; each row keeps its record as JSON
+const SQLITE_SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS regions (
+        id TEXT PRIMARY KEY,
+        parent_id TEXT,
+        data TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS regions_parent_id ON regions (parent_id);
+
+    CREATE TABLE IF NOT EXISTS jobs (
+        id TEXT PRIMARY KEY,
+        region_id TEXT NOT NULL,
+        data TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS jobs_region_id ON jobs (region_id);
+
+    CREATE TABLE IF NOT EXISTS latest_jobs (
+        region_id TEXT PRIMARY KEY,
+        job_id TEXT NOT NULL,
+        created_at INTEGER NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS reports (
+        id TEXT PRIMARY KEY,
+        region_id TEXT NOT NULL,
+        created_at INTEGER NOT NULL,
+        data TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS reports_region_id ON reports (region_id, created_at);
+";
+
+/// Metadata in a single SQLite database, queried with indexed lookups
+pub struct SqliteMetadataStore {
+    path: PathBuf,
+    conn: Arc<std::sync::Mutex<Connection>>,
+}
+
+impl SqliteMetadataStore {
+    /// Open (or create) the database at `path` and make sure its tables exist
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let conn = Connection::open(&path)?;
+        conn.execute_batch(SQLITE_SCHEMA)?;
+        info!("Using SQLite metadata store at {:?}", path);
+
+        Ok(Self {
+            path,
+            conn: Arc::new(std::sync::Mutex::new(conn)),
+        })
+    }
+
+    /// Run a query on the blocking thread pool
+    async fn with_conn<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> rusqlite::Result<T> + Send + 'static,
+    {
+        let conn = self.conn.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            let mut conn = conn.lock().unwrap_or_else(|e| e.into_inner());
+            f(&mut conn)
+        })
+        .await?;
+        Ok(result?)
+    }
+
+    /// Look up the JSON record stored under `id` in `table`
+    async fn get_record<T: DeserializeOwned>(
+        &self,
+        table: &'static str,
+        id: &str,
+    ) -> Result<Option<T>> {
+        let id = id.to_string();
+        let data: Option<String> = self
+            .with_conn(move |conn| {
+                conn.query_row(
+                    &format!("SELECT data FROM {} WHERE id = ?1", table),
+                    params![id],
+                    |row| row.get(0),
+                )
+                .optional()
+            })
+            .await?;
+
+        data.map(|data| serde_json::from_str(&data))
+            .transpose()
+            .map_err(Into::into)
+    }
+}
+
+/// Deserialize JSON records read from the database
+fn parse_records<T: DeserializeOwned>(rows: Vec<String>) -> Result<Vec<T>> {
+    rows.iter()
+        .map(|data| serde_json::from_str(data).map_err(Into::into))
+        .collect()
+}
+
+#[async_trait]
+impl MetadataStore for SqliteMetadataStore {
+    fn location(&self) -> &Path {
+        &self.path
+    }
+
+    fn regions_modified(&self) -> Option<SystemTime> {
+        std::fs::metadata(&self.path)
+            .and_then(|m| m.modified())
+            .ok()
+    }
+
+    async fn load_regions(&self) -> Result<Vec<Region>> {
+        let rows = self
+            .with_conn(|conn| {
+                let mut stmt = conn.prepare("SELECT data FROM regions")?;
+                let rows = stmt.query_map([], |row| row.get(0))?;
+                rows.collect()
+            })
+            .await?;
+        parse_records(rows)
+    }
+
+    async fn save_regions(&self, regions: &[Region]) -> Result<()> {
+        let rows = regions
+            .iter()
+            .map(|region| {
+                Ok((
+                    region.id.clone(),
+                    region.parent_id.clone(),
+                    serde_json::to_string(region)?,
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            tx.execute("DELETE FROM regions", [])?;
+            {
+                let mut insert =
+                    tx.prepare("INSERT INTO regions (id, parent_id, data) VALUES (?1, ?2, ?3)")?;
+                for (id, parent_id, data) in &rows {
+                    insert.execute(params![id, parent_id, data])?;
+                }
+            }
+            tx.commit()
+        })
+        .await
+    }
+
+    async fn get_region(&self, region_id: &str) -> Result<Option<Region>> {
+        self.get_record("regions", region_id).await
+    }
+
+    async fn get_children(&self, parent_id: &str) -> Result<Vec<Region>> {
+        let parent_id = parent_id.to_string();
+        let rows: Vec<(String, bool)> = self
+            .with_conn(move |conn| {
+                let mut stmt = conn.prepare(
+                    "SELECT r.data, EXISTS (SELECT 1 FROM regions c WHERE c.parent_id = r.id)
+                     FROM regions r WHERE r.parent_id = ?1",
+                )?;
+                let rows =
+                    stmt.query_map(params![parent_id], |row| Ok((row.get(0)?, row.get(1)?)))?;
+                rows.collect()
+            })
+            .await?;
+
+        rows.into_iter()
+            .map(|(data, has_children)| {
+                let mut region: Region = serde_json::from_str(&data)?;
+                region.has_children = has_children;
+                Ok(region)
+            })
+            .collect()
+    }
+
+    async fn get_descendants(&self, region_id: &str) -> Result<Vec<Region>> {
+        let region_id = region_id.to_string();
+        let rows = self
+            .with_conn(move |conn| {
+                // UNION (not UNION ALL) drops repeated rows, so a cycle in
+                // the hierarchy cannot make the recursion run forever
+                let mut stmt = conn.prepare(
+                    "WITH RECURSIVE subtree (id, data) AS (
+                         SELECT id, data FROM regions WHERE parent_id = ?1
+                         UNION
+                         SELECT r.id, r.data FROM regions r JOIN subtree s ON r.parent_id = s.id
+                     )
+                     SELECT data FROM subtree WHERE id != ?1",
+                )?;
+                let rows = stmt.query_map(params![region_id], |row| row.get(0))?;
+                rows.collect()
+            })
+            .await?;
+        parse_records(rows)
+    }
+
+    async fn save_job(&self, job: &ProcessingJob) -> Result<()> {
+        let (id, region_id, data) = (
+            job.id.clone(),
+            job.region_id.clone(),
+            serde_json::to_string(job)?,
+        );
+
+        let created_at = job.created_at.timestamp_micros();
+
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            tx.execute(
+                "INSERT OR REPLACE INTO jobs (id, region_id, data) VALUES (?1, ?2, ?3)",
+                params![id, region_id, data],
+            )?;
+            tx.execute(
+                "INSERT INTO latest_jobs (region_id, job_id, created_at) VALUES (?1, ?2, ?3)
+                 ON CONFLICT (region_id) DO UPDATE
+                 SET job_id = excluded.job_id, created_at = excluded.created_at
+                 WHERE excluded.created_at >= latest_jobs.created_at",
+                params![region_id, id, created_at],
+            )?;
+            tx.commit()
+        })
+        .await
+    }
+
+    async fn get_job(&self, job_id: &str) -> Result<Option<ProcessingJob>> {
+        self.get_record("jobs", job_id).await
+    }
+
+    async fn list_jobs(&self) -> Result<Vec<ProcessingJob>> {
+        let rows = self
+            .with_conn(|conn| {
+                let mut stmt = conn.prepare("SELECT data FROM jobs")?;
+                let rows = stmt.query_map([], |row| row.get(0))?;
+                rows.collect()
+            })
+            .await?;
+
+        let mut jobs: Vec<ProcessingJob> = parse_records(rows)?;
+        jobs.sort_by_key(|job| std::cmp::Reverse(job.created_at));
+        Ok(jobs)
+    }
+
+    async fn latest_job(&self, region_id: &str) -> Result<Option<ProcessingJob>> {
+        let region_id = region_id.to_string();
+        let data: Option<String> = self
+            .with_conn(move |conn| {
+                conn.query_row(
+                    "SELECT j.data FROM latest_jobs l JOIN jobs j ON j.id = l.job_id
+                     WHERE l.region_id = ?1",
+                    params![region_id],
+                    |row| row.get(0),
+                )
+                .optional()
+            })
+            .await?;
+
+        data.map(|data| serde_json::from_str(&data))
+            .transpose()
+            .map_err(Into::into)
+    }
+
+    async fn save_report(&self, report: &QualityReport) -> Result<()> {
+        let (id, region_id, data) = (
+            report.id.clone(),
+            report.region_id.clone(),
+            serde_json::to_string(report)?,
+        );
+        let created_at = report.created_at.timestamp_micros();
+
+        self.with_conn(move |conn| {
+            conn.execute(
+                "INSERT OR REPLACE INTO reports (id, region_id, created_at, data)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![id, region_id, created_at, data],
+            )
+            .map(|_| ())
+        })
+        .await
+    }
+
+    async fn get_report(&self, report_id: &str) -> Result<Option<QualityReport>> {
+        self.get_record("reports", report_id).await
+    }
+
+    async fn list_reports(&self, region_id: &str) -> Result<Vec<QualityReport>> {
+        let region_id = region_id.to_string();
+        let rows = self
+            .with_conn(move |conn| {
+                let mut stmt = conn.prepare(
+                    "SELECT data FROM reports WHERE region_id = ?1 ORDER BY created_at DESC",
+                )?;
+                let rows = stmt.query_map(params![region_id], |row| row.get(0))?;
+                rows.collect()
+            })
+            .await?;
+        parse_records(rows)
+    }
+}
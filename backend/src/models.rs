@@ -1,9 +1,10 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use utoipa::ToSchema;
 
 /// Represents a geographic region with hierarchical organization matching Geofabrik structure
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct Region {
     pub id: String,
     pub name: String,
@@ -21,10 +22,13 @@ pub struct Region {
     pub iso3166_1: Option<Vec<String>>, // ISO 3166-1 alpha-2 codes
     pub iso3166_2: Option<Vec<String>>, // ISO 3166-2 codes
     pub urls: Option<GeofabrikUrls>,    // Direct URLs from Geofabrik
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    pub geometry: Option<serde_json::Value>, // Original GeoJSON boundary geometry
 }
 
 /// Administrative levels matching Geofabrik hierarchy
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash, ToSchema)]
 pub enum AdminLevel {
     World = 0,
     Continent = 1,
@@ -33,8 +37,27 @@ pub enum AdminLevel {
     Subregion = 4,
 }
 
+impl std::str::FromStr for AdminLevel {
+    type Err = crate::RiemapError;
+
+    /// Parse a level by name (`country`) or number (`2`)
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "world" | "0" => Ok(Self::World),
+            "continent" | "1" => Ok(Self::Continent),
+            "country" | "2" => Ok(Self::Country),
+            "region" | "3" => Ok(Self::Region),
+            "subregion" | "4" => Ok(Self::Subregion),
+            other => Err(crate::RiemapError::Config(format!(
+                "Unknown admin level '{}', expected world, continent, country, region or subregion",
+                other
+            ))),
+        }
+    }
+}
+
 /// Geographic bounding box
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct BoundingBox {
     pub min_lat: f64,
     pub min_lon: f64,
@@ -43,7 +66,7 @@ pub struct BoundingBox {
 }
 
 /// Represents a processed OSM data file
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct DataFile {
     pub id: String,
     pub region_id: String,
@@ -56,10 +79,13 @@ pub struct DataFile {
     pub quality_report_id: Option<String>,
     pub download_url: String,     // Direct download URL
     pub checksum: Option<String>, // MD5/SHA256 checksum
+    /// Extent from the PBF header; more accurate than the region's estimated box
+    #[serde(default)]
+    pub data_bbox: Option<BoundingBox>,
 }
 
 /// Supported data formats
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub enum DataFormat {
     OsmPbf,
     OsmXml,
@@ -68,7 +94,7 @@ pub enum DataFormat {
 }
 
 /// Quality analysis report for a data file
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct QualityReport {
     pub id: String,
     pub data_file_id: String,
@@ -81,24 +107,95 @@ pub struct QualityReport {
 }
 
 /// Quality metrics for OSM data
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct QualityMetrics {
-    pub total_nodes: u64,
-    pub total_ways: u64,
-    pub total_relations: u64,
-    pub tagged_nodes: u64,
-    pub tagged_ways: u64,
-    pub tagged_relations: u64,
+    /// Element counts; `None` for types skipped by the processing options'
+    /// `ProcessFilter`
+    pub total_nodes: Option<u64>,
+    pub total_ways: Option<u64>,
+    pub total_relations: Option<u64>,
+    pub tagged_nodes: Option<u64>,
+    pub tagged_ways: Option<u64>,
+    pub tagged_relations: Option<u64>,
+    /// Percentage of elements carrying tags. Only comparable between reports
+    /// when all element types were processed, as it covers the types read.
     pub completeness_score: f64,
     pub geometry_errors: u64,
     pub tag_errors: u64,
     pub topology_errors: u64,
     pub feature_distribution: FeatureDistribution,
+    #[schema(value_type = HashMap<String, Object>)]
     pub custom_metrics: HashMap<String, serde_json::Value>,
+    /// Percentage of each feature type carrying its expected attribute (e.g. named highways)
+    #[serde(default)]
+    pub completeness_by_feature: HashMap<String, f64>,
+    /// Tagged nodes stacked on top of another tagged node
+    #[serde(default)]
+    pub duplicate_nodes: u64,
+    /// Age of elements' last edits; `None` when the file carries no timestamps
+    #[serde(default)]
+    pub freshness: Option<FreshnessMetrics>,
+    /// Tag completeness of points of interest, keyed by `amenity=*` value
+    #[serde(default)]
+    pub amenity_audit: HashMap<String, AmenityAudit>,
+}
+
+/// How many amenities of one kind lack the tags POI consumers rely on
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct AmenityAudit {
+    pub total: u64,
+    pub missing_name: u64,
+    pub missing_opening_hours: u64,
+    pub missing_address: u64, // No `addr:*` tag at all
+    /// First element found lacking each tag, keyed by `name`, `opening_hours` or `address`
+    #[serde(default)]
+    pub examples: HashMap<String, ElementSample>,
+}
+
+/// How many buildings carry the tags 3D renderers extrude them by
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Building3dCoverage {
+    pub total: u64,
+    pub with_height: u64,
+    pub with_levels: u64, // `building:levels`
+    #[serde(default)]
+    pub with_height_or_levels: u64,
+}
+
+impl Building3dCoverage {
+    /// Share of buildings (0-1) with a height or a level count
+    pub fn coverage(&self) -> f64 {
+        if self.total == 0 {
+            return 0.0;
+        }
+        self.with_height_or_levels as f64 / self.total as f64
+    }
+}
+
+/// An element standing in for an aggregate count
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ElementSample {
+    pub osm_type: String,
+    pub osm_id: i64,
+    #[schema(value_type = Option<Vec<f64>>)]
+    pub location: Option<(f64, f64)>, // lat, lon; ways are placed at their first node
+}
+
+/// How long ago elements were last edited, relative to the extract's own timestamp
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct FreshnessMetrics {
+    /// Replication timestamp of the extract, or its newest edit when the header has none
+    pub reference_time: DateTime<Utc>,
+    /// Elements with an edit timestamp that the ages are computed from
+    pub elements_with_timestamp: u64,
+    pub median_age_days: f64,
+    pub p90_age_days: f64,
+    /// Share of elements (0-1) untouched for over a year
+    pub stale_fraction: f64,
 }
 
 /// Distribution of different feature types
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
 pub struct FeatureDistribution {
     pub highways: u64,
     pub buildings: u64,
@@ -106,14 +203,17 @@ pub struct FeatureDistribution {
     pub amenities: u64,
     pub water_features: u64,
     pub boundaries: u64,
+    #[serde(default)]
+    pub coastlines: u64, // `natural=coastline` ways, not counted as natural features
 }
 
 /// Specific quality issue found in data
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct QualityIssue {
     pub issue_type: String,
     pub severity: IssueSeverity,
     pub description: String,
+    #[schema(value_type = Option<Vec<f64>>)]
     pub location: Option<(f64, f64)>, // lat, lon
     pub osm_id: Option<i64>,
     pub osm_type: Option<String>,
@@ -121,7 +221,7 @@ pub struct QualityIssue {
 }
 
 /// Severity levels for quality issues
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub enum IssueSeverity {
     Low,
     Medium,
@@ -130,7 +230,7 @@ pub enum IssueSeverity {
 }
 
 /// Processing job status
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct ProcessingJob {
     pub id: String,
     pub region_id: String,
@@ -145,7 +245,7 @@ pub struct ProcessingJob {
 }
 
 /// Types of processing jobs
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub enum JobType {
     Download,
     Process,
@@ -155,7 +255,7 @@ pub enum JobType {
 }
 
 /// Job execution status
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
 pub enum JobStatus {
     Pending,
     Running,
@@ -164,8 +264,15 @@ pub enum JobStatus {
     Cancelled,
 }
 
+impl JobStatus {
+    /// Whether the job has stopped and will not change again
+    pub fn is_finished(&self) -> bool {
+        matches!(self, Self::Completed | Self::Failed | Self::Cancelled)
+    }
+}
+
 /// API response for region hierarchy
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct RegionTree {
     pub region: Region,
     pub children: Vec<RegionTree>,
@@ -174,7 +281,7 @@ pub struct RegionTree {
 }
 
 /// Download statistics for a region
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct DownloadStats {
     pub total_downloads: u64,
     pub last_updated: DateTime<Utc>,
@@ -183,7 +290,7 @@ pub struct DownloadStats {
 }
 
 /// API response for region comparison
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct RegionComparison {
     pub region_id: String,
     pub from_version: String,
@@ -192,23 +299,94 @@ pub struct RegionComparison {
     pub metrics_diff: QualityMetricsDiff,
     pub summary: String,
     pub change_details: Vec<ChangeDetail>,
+    /// Counts per feature category in both versions, for charting
+    #[serde(default)]
+    pub feature_breakdown: Vec<FeatureChange>,
+}
+
+/// Change of one feature category between two versions
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct FeatureChange {
+    pub category: String,
+    pub from_count: u64,
+    pub to_count: u64,
+    pub abs_change: i64,
+    /// Change relative to `from_count` in percent; `None` when that is 0
+    pub pct_change: Option<f64>,
+}
+
+/// One quality report of a region, as a point in its quality history
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct QualityHistoryPoint {
+    pub report_id: String,
+    pub version: String,
+    pub created_at: DateTime<Utc>,
+    pub completeness_score: f64,
+    pub total_elements: u64,
+    pub geometry_errors: u64,
+    pub topology_errors: u64,
+    pub tag_errors: u64,
+    pub issue_count: usize,
+}
+
+impl QualityHistoryPoint {
+    /// Summarize a report; the version is taken from its `<region>_<version>` data file ID
+    pub fn from_report(report: &QualityReport) -> Self {
+        let metrics = &report.metrics;
+        let version = report
+            .data_file_id
+            .strip_prefix(&format!("{}_", report.region_id))
+            .unwrap_or(&report.data_file_id)
+            .to_string();
+
+        Self {
+            report_id: report.id.clone(),
+            version,
+            created_at: report.created_at,
+            completeness_score: metrics.completeness_score,
+            total_elements: metrics.total_elements(),
+            geometry_errors: metrics.geometry_errors,
+            topology_errors: metrics.topology_errors,
+            tag_errors: metrics.tag_errors,
+            issue_count: report.issues.len(),
+        }
+    }
+}
+
+/// API response for a region's quality history, oldest report first
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct QualityHistory {
+    pub region_id: String,
+    pub points: Vec<QualityHistoryPoint>,
+}
+
+/// Totals of the regions under one continent, keyed by the continent's ID
+/// in the by-continent statistics
+#[derive(Debug, Default, Serialize, Deserialize, ToSchema)]
+pub struct ContinentStats {
+    pub name: String,
+    pub region_count: usize,
+    pub total_area_km2: f64,
+    pub downloadable_count: usize, // Regions with their own extract
 }
 
 /// Specific change between versions
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct ChangeDetail {
     pub change_type: String,
     pub description: String,
     pub impact: String,
+    #[schema(value_type = Option<Vec<f64>>)]
     pub location: Option<(f64, f64)>,
 }
 
 /// Difference between quality metrics
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct QualityMetricsDiff {
-    pub nodes_diff: i64,
-    pub ways_diff: i64,
-    pub relations_diff: i64,
+    /// `None` when either report skipped the element type
+    pub nodes_diff: Option<i64>,
+    pub ways_diff: Option<i64>,
+    pub relations_diff: Option<i64>,
     pub completeness_diff: f64,
     pub errors_diff: i64,
     pub feature_changes: HashMap<String, i64>,
@@ -240,9 +418,10 @@ pub struct GeofabrikProperties {
     #[serde(rename = "iso3166-2")]
     pub iso3166_2: Option<Vec<String>>,
     pub urls: Option<GeofabrikUrls>,
+    pub population: Option<u64>, // Not part of the official index, but honored if present
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct GeofabrikUrls {
     pub pbf: Option<String>,
     pub bz2: Option<String>,
@@ -254,6 +433,29 @@ pub struct GeofabrikUrls {
     pub taginfo: Option<String>,
 }
 
+/// Outcome of a single readiness check
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct HealthCheck {
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+impl HealthCheck {
+    /// A passing check with an optional note
+    pub fn pass(detail: Option<String>) -> Self {
+        Self { ok: true, detail }
+    }
+
+    /// A failing check with the reason
+    pub fn fail(detail: impl Into<String>) -> Self {
+        Self {
+            ok: false,
+            detail: Some(detail.into()),
+        }
+    }
+}
+
 impl BoundingBox {
     /// Create a new bounding box
     pub fn new(min_lat: f64, min_lon: f64, max_lat: f64, max_lon: f64) -> Self {
@@ -265,29 +467,133 @@ impl BoundingBox {
         }
     }
 
+    /// Create a bounding box from untrusted input, rejecting swapped or out-of-range coordinates.
+    /// `min_lon > max_lon` is accepted as a box crossing the antimeridian.
+    pub fn try_new(min_lat: f64, min_lon: f64, max_lat: f64, max_lon: f64) -> crate::Result<Self> {
+        let valid_lat = |lat: f64| (-90.0..=90.0).contains(&lat);
+        let valid_lon = |lon: f64| (-180.0..=180.0).contains(&lon);
+
+        if !valid_lat(min_lat) || !valid_lat(max_lat) {
+            return Err(crate::RiemapError::InvalidBoundingBox(format!(
+                "latitude out of range: {}..{}",
+                min_lat, max_lat
+            ))
+            .into());
+        }
+        if !valid_lon(min_lon) || !valid_lon(max_lon) {
+            return Err(crate::RiemapError::InvalidBoundingBox(format!(
+                "longitude out of range: {}..{}",
+                min_lon, max_lon
+            ))
+            .into());
+        }
+        if min_lat > max_lat {
+            return Err(crate::RiemapError::InvalidBoundingBox(format!(
+                "min latitude exceeds max: {} > {}",
+                min_lat, max_lat
+            ))
+            .into());
+        }
+
+        Ok(Self::new(min_lat, min_lon, max_lat, max_lon))
+    }
+
+    /// Whether the box crosses the 180° meridian (stored as `min_lon > max_lon`)
+    pub fn wraps_antimeridian(&self) -> bool {
+        self.min_lon > self.max_lon
+    }
+
+    /// Split a box crossing the antimeridian into its eastern and western halves
+    pub fn split_at_antimeridian(&self) -> Vec<BoundingBox> {
+        if self.wraps_antimeridian() {
+            vec![
+                BoundingBox::new(self.min_lat, self.min_lon, self.max_lat, 180.0),
+                BoundingBox::new(self.min_lat, -180.0, self.max_lat, self.max_lon),
+            ]
+        } else {
+            vec![self.clone()]
+        }
+    }
+
+    /// Longitude extent in degrees, summing both halves when wrapping
+    pub fn lon_span(&self) -> f64 {
+        self.split_at_antimeridian()
+            .iter()
+            .map(|part| part.max_lon - part.min_lon)
+            .sum()
+    }
+
     /// Calculate area in square kilometers (approximate)
     pub fn area_km2(&self) -> f64 {
         let lat_diff = self.max_lat - self.min_lat;
-        let lon_diff = self.max_lon - self.min_lon;
 
-        // Rough approximation: 1 degree ≈ 111 km
+        // Rough approximation: 1 degree ≈ 111 km, scaled by the mean latitude
         let lat_km = lat_diff * 111.0;
-        let lon_km = lon_diff * 111.0 * (self.min_lat + self.max_lat).to_radians().cos() / 2.0;
+        let mean_lat = (self.min_lat + self.max_lat) / 2.0;
+        let lon_km = self.lon_span() * 111.0 * mean_lat.to_radians().cos();
 
         lat_km * lon_km
     }
 
     /// Get center point of bounding box
     pub fn center(&self) -> (f64, f64) {
-        (
-            (self.min_lat + self.max_lat) / 2.0,
-            (self.min_lon + self.max_lon) / 2.0,
-        )
+        let mut center_lon = self.min_lon + self.lon_span() / 2.0;
+        if center_lon > 180.0 {
+            center_lon -= 360.0;
+        }
+
+        ((self.min_lat + self.max_lat) / 2.0, center_lon)
     }
 
     /// Check if this bounding box contains a point
     pub fn contains(&self, lat: f64, lon: f64) -> bool {
-        lat >= self.min_lat && lat <= self.max_lat && lon >= self.min_lon && lon <= self.max_lon
+        let lon_inside = if self.wraps_antimeridian() {
+            lon >= self.min_lon || lon <= self.max_lon
+        } else {
+            lon >= self.min_lon && lon <= self.max_lon
+        };
+
+        lat >= self.min_lat && lat <= self.max_lat && lon_inside
+    }
+
+    /// Grow the box by `margin` degrees on every side, clamped to valid
+    /// latitudes and wrapping across the antimeridian
+    pub fn expanded(&self, margin: f64) -> BoundingBox {
+        let min_lat = (self.min_lat - margin).max(-90.0);
+        let max_lat = (self.max_lat + margin).min(90.0);
+        if self.lon_span() + 2.0 * margin >= 360.0 {
+            return BoundingBox::new(min_lat, -180.0, max_lat, 180.0);
+        }
+
+        let wrap = |lon: f64| {
+            if lon < -180.0 {
+                lon + 360.0
+            } else if lon > 180.0 {
+                lon - 360.0
+            } else {
+                lon
+            }
+        };
+        BoundingBox::new(
+            min_lat,
+            wrap(self.min_lon - margin),
+            max_lat,
+            wrap(self.max_lon + margin),
+        )
+    }
+
+    /// Check if this bounding box overlaps another (edges touching counts as overlap)
+    pub fn intersects(&self, other: &BoundingBox) -> bool {
+        let other_parts = other.split_at_antimeridian();
+
+        self.split_at_antimeridian().iter().any(|part| {
+            other_parts.iter().any(|other| {
+                part.min_lat <= other.max_lat
+                    && part.max_lat >= other.min_lat
+                    && part.min_lon <= other.max_lon
+                    && part.max_lon >= other.min_lon
+            })
+        })
     }
 }
 
@@ -300,6 +606,8 @@ impl Region {
         bounding_box: BoundingBox,
     ) -> Self {
         let now = Utc::now();
+        // Replaced by the boundary's area when a geometry is attached
+        let area_km2 = Some(bounding_box.area_km2());
         let provides_data_services = matches!(
             admin_level,
             AdminLevel::Country | AdminLevel::Region | AdminLevel::Subregion
@@ -311,7 +619,7 @@ impl Region {
             admin_level,
             parent_id: None,
             bounding_box,
-            area_km2: None,
+            area_km2,
             population: None,
             country_code: None,
             geofabrik_url: None,
@@ -322,6 +630,7 @@ impl Region {
             iso3166_1: None,
             iso3166_2: None,
             urls: None,
+            geometry: None,
         }
     }
 
@@ -335,21 +644,129 @@ impl Region {
             AdminLevel::Subregion => 4,
         }
     }
-}
 
-impl Default for FeatureDistribution {
-    fn default() -> Self {
-        Self {
-            highways: 0,
-            buildings: 0,
-            natural_features: 0,
-            amenities: 0,
-            water_features: 0,
-            boundaries: 0,
+    /// Whether an ISO 3166-1 alpha-2 or ISO 3166-2 code (any case) belongs to this region
+    pub fn has_iso_code(&self, code: &str) -> bool {
+        self.iso3166_1
+            .iter()
+            .chain(&self.iso3166_2)
+            .flatten()
+            .chain(&self.country_code)
+            .any(|region_code| region_code.eq_ignore_ascii_case(code.trim()))
+    }
+
+    /// Whether a point lies inside the region: inside its boundary polygon
+    /// when one is stored, otherwise inside its bounding box
+    pub fn contains_point(&self, lat: f64, lon: f64) -> bool {
+        use geo::Intersects;
+
+        if !self.bounding_box.contains(lat, lon) {
+            return false;
+        }
+
+        match self.boundary() {
+            Some(boundary) => boundary.intersects(&geo::Point::new(lon, lat)),
+            None => true,
+        }
+    }
+
+    /// Area in km²: the spherical area of the boundary polygon when one is
+    /// stored, otherwise the bounding box estimate, which overstates regions
+    /// that do not fill their box
+    pub fn compute_area_km2(&self) -> f64 {
+        use geo::ChamberlainDuquetteArea;
+
+        // Rings are measured separately so holes are subtracted whatever their
+        // winding; geo's polygon area adds holes wound opposite to the shell,
+        // which is how GeoJSON (RFC 7946) winds them
+        let ring_area = |ring: &geo::LineString<f64>| {
+            geo::Polygon::new(ring.clone(), vec![]).chamberlain_duquette_unsigned_area()
+        };
+        let polygon_area = |polygon: &geo::Polygon<f64>| {
+            ring_area(polygon.exterior()) - polygon.interiors().iter().map(ring_area).sum::<f64>()
+        };
+
+        match self.boundary() {
+            Some(geo::Geometry::Polygon(polygon)) => polygon_area(&polygon) / 1_000_000.0,
+            Some(geo::Geometry::MultiPolygon(polygons)) => {
+                polygons.iter().map(polygon_area).sum::<f64>() / 1_000_000.0
+            }
+            _ => self.bounding_box.area_km2(),
+        }
+    }
+
+    /// The stored GeoJSON geometry, if it is a polygon or multipolygon
+    fn boundary(&self) -> Option<geo::Geometry<f64>> {
+        let geometry = geojson::Geometry::from_json_value(self.geometry.clone()?).ok()?;
+        match geo::Geometry::<f64>::try_from(&geometry.value).ok()? {
+            boundary @ (geo::Geometry::Polygon(_) | geo::Geometry::MultiPolygon(_)) => {
+                Some(boundary)
+            }
+            _ => None,
         }
     }
 }
 
+impl QualityMetrics {
+    /// Elements counted, over the types that were processed
+    pub fn total_elements(&self) -> u64 {
+        [self.total_nodes, self.total_ways, self.total_relations]
+            .into_iter()
+            .flatten()
+            .sum()
+    }
+
+    /// Tagged elements counted, over the types that were processed
+    pub fn tagged_elements(&self) -> u64 {
+        [self.tagged_nodes, self.tagged_ways, self.tagged_relations]
+            .into_iter()
+            .flatten()
+            .sum()
+    }
+
+    /// Building height coverage, absent from reports made before it was collected
+    pub fn building_3d(&self) -> Option<Building3dCoverage> {
+        serde_json::from_value(self.custom_metrics.get("building_3d")?.clone()).ok()
+    }
+
+    /// Relation counts per `type=*` value, with relations lacking one under
+    /// `untyped`; absent from reports made before they were collected
+    pub fn relation_types(&self) -> Option<std::collections::BTreeMap<String, u64>> {
+        serde_json::from_value(self.custom_metrics.get("relation_types")?.clone()).ok()
+    }
+}
+
+/// A count for display, or `skipped` when its element type was not processed
+pub fn count_text(count: Option<u64>) -> String {
+    count.map_or_else(|| "skipped".to_string(), |count| count.to_string())
+}
+
+impl FeatureDistribution {
+    /// Count of each feature class, keyed by its field name
+    pub fn categories(&self) -> [(&'static str, u64); 7] {
+        [
+            ("highways", self.highways),
+            ("buildings", self.buildings),
+            ("natural_features", self.natural_features),
+            ("amenities", self.amenities),
+            ("water_features", self.water_features),
+            ("boundaries", self.boundaries),
+            ("coastlines", self.coastlines),
+        ]
+    }
+
+    /// Sum over all feature classes; an element in several classes is counted in each
+    pub fn total(&self) -> u64 {
+        self.highways
+            + self.buildings
+            + self.natural_features
+            + self.amenities
+            + self.water_features
+            + self.boundaries
+            + self.coastlines
+    }
+}
+
 impl Default for DownloadStats {
     fn default() -> Self {
         Self {
@@ -360,3 +777,151 @@ impl Default for DownloadStats {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 170°E to 170°W, 20° of longitude across the antimeridian
+    fn wrapping_box() -> BoundingBox {
+        BoundingBox::new(-20.0, 170.0, -10.0, -170.0)
+    }
+
+    #[test]
+    fn wrapping_box_area_matches_unwrapped_box_of_same_span() {
+        let wrapping = wrapping_box();
+        let unwrapped = BoundingBox::new(-20.0, 0.0, -10.0, 20.0);
+
+        assert!(wrapping.wraps_antimeridian());
+        assert_eq!(wrapping.lon_span(), 20.0);
+        assert!((wrapping.area_km2() - unwrapped.area_km2()).abs() < 1e-6);
+        assert!(wrapping.area_km2() > 0.0);
+    }
+
+    #[test]
+    fn wrapping_box_contains_points_on_both_sides_of_antimeridian() {
+        let region = Region::new(
+            "fiji".to_string(),
+            "Fiji".to_string(),
+            AdminLevel::Country,
+            wrapping_box(),
+        );
+
+        assert!(region.contains_point(-15.0, 175.0));
+        assert!(region.contains_point(-15.0, -175.0));
+        assert!(region.contains_point(-15.0, 180.0));
+        assert!(region.contains_point(-15.0, -180.0));
+        assert!(!region.contains_point(-15.0, 0.0));
+        assert!(!region.contains_point(-15.0, 160.0));
+        assert!(!region.contains_point(-25.0, 175.0));
+    }
+
+    #[test]
+    fn wrapping_box_intersects_across_antimeridian() {
+        let wrapping = wrapping_box();
+
+        // Only the western half overlaps
+        assert!(wrapping.intersects(&BoundingBox::new(-18.0, -175.0, -12.0, -160.0)));
+        // Only the eastern half overlaps, in either argument order
+        let east = BoundingBox::new(-18.0, 160.0, -12.0, 175.0);
+        assert!(wrapping.intersects(&east));
+        assert!(east.intersects(&wrapping));
+        // Two wrapping boxes always share the meridian when latitudes overlap
+        assert!(wrapping.intersects(&BoundingBox::new(-15.0, 179.0, -5.0, -179.0)));
+
+        assert!(!wrapping.intersects(&BoundingBox::new(-18.0, 0.0, -12.0, 10.0)));
+        assert!(!wrapping.intersects(&BoundingBox::new(-18.0, -160.0, -12.0, 160.0)));
+        assert!(!wrapping.intersects(&BoundingBox::new(0.0, 175.0, 10.0, -175.0)));
+    }
+
+    fn region_with_geometry(geometry: serde_json::Value) -> Region {
+        let mut region = Region::new(
+            "cell".to_string(),
+            "Cell".to_string(),
+            AdminLevel::Region,
+            BoundingBox::new(-1.0, -1.0, 3.0, 3.0),
+        );
+        region.geometry = Some(geometry);
+        region
+    }
+
+    /// Spherical area of a lat/lon cell on the sphere the area formula uses
+    fn spherical_cell_km2(south: f64, west: f64, north: f64, east: f64) -> f64 {
+        let radius_km: f64 = 6378.137;
+        radius_km.powi(2)
+            * (east - west).to_radians()
+            * (north.to_radians().sin() - south.to_radians().sin())
+    }
+
+    #[test]
+    fn one_degree_cell_at_equator_matches_published_area() {
+        let region = region_with_geometry(serde_json::json!({
+            "type": "Polygon",
+            "coordinates": [[[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0], [0.0, 0.0]]]
+        }));
+
+        let area = region.compute_area_km2();
+        // 12,308 km² on the WGS84 ellipsoid; the spherical formula runs ~0.7% over
+        assert!((area - 12_308.0).abs() / 12_308.0 < 0.01, "area {}", area);
+        assert!((area - spherical_cell_km2(0.0, 0.0, 1.0, 1.0)).abs() < 1.0);
+        assert!(area < region.bounding_box.area_km2());
+    }
+
+    #[test]
+    fn simplified_egypt_boundary_matches_published_area() {
+        // Egypt traced through 19 points: the Libyan border along 25°E, the
+        // Sudanese border along 22°N (with the Halaib triangle), the Red Sea
+        // coast, both gulfs around Sinai and the Mediterranean coast
+        let region = region_with_geometry(serde_json::json!({
+            "type": "Polygon",
+            "coordinates": [[
+                [25.15, 31.57], [25.0, 31.4], [25.0, 22.0], [36.9, 22.0], [35.6, 23.9],
+                [34.3, 26.1], [33.8, 27.2], [32.55, 29.95], [33.3, 28.8], [34.25, 27.73],
+                [34.45, 28.3], [34.9, 29.5], [34.25, 31.32], [32.3, 31.26], [31.8, 31.5],
+                [30.4, 31.46], [29.9, 31.2], [28.95, 30.83], [27.25, 31.35], [25.15, 31.57]
+            ]]
+        }));
+
+        let area = region.compute_area_km2();
+        // 1,001,450 km² as published in the CIA World Factbook
+        assert!(
+            (area - 1_001_450.0).abs() / 1_001_450.0 < 0.02,
+            "area {}",
+            area
+        );
+    }
+
+    #[test]
+    fn polygon_hole_is_subtracted_from_area() {
+        let shell = serde_json::json!([[0.0, 0.0], [2.0, 0.0], [2.0, 2.0], [0.0, 2.0], [0.0, 0.0]]);
+        // Clockwise as RFC 7946 requires, and wound like the shell
+        let holes = [
+            serde_json::json!([[0.5, 0.5], [0.5, 1.5], [1.5, 1.5], [1.5, 0.5], [0.5, 0.5]]),
+            serde_json::json!([[0.5, 0.5], [1.5, 0.5], [1.5, 1.5], [0.5, 1.5], [0.5, 0.5]]),
+        ];
+        let expected =
+            spherical_cell_km2(0.0, 0.0, 2.0, 2.0) - spherical_cell_km2(0.5, 0.5, 1.5, 1.5);
+
+        for hole in holes {
+            let region = region_with_geometry(serde_json::json!({
+                "type": "Polygon",
+                "coordinates": [shell.clone(), hole]
+            }));
+
+            let area = region.compute_area_km2();
+            assert!((area - expected).abs() / expected < 1e-3, "area {}", area);
+        }
+    }
+
+    #[test]
+    fn area_falls_back_to_bounding_box_without_boundary() {
+        let mut region = region_with_geometry(serde_json::json!({
+            "type": "Point",
+            "coordinates": [1.0, 1.0]
+        }));
+        assert_eq!(region.compute_area_km2(), region.bounding_box.area_km2());
+
+        region.geometry = None;
+        assert_eq!(region.compute_area_km2(), region.bounding_box.area_km2());
+    }
+}
@@ -0,0 +1,217 @@
+use crate::Result;
+use memmap2::MmapMut;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use tracing::warn;
+
+/// Where node coordinates are kept while checks that need them run
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NodeIndexStorage {
+    /// A hash map; fastest, but needs roughly 40 bytes of RAM per node
+    #[default]
+    Memory,
+    /// A sorted 16-byte record per node in an unlinked temp file, looked up
+    /// by binary search through a memory map. Resident memory is left to the
+    /// page cache, at the cost of disk space and slower lookups.
+    Disk,
+}
+
+impl std::str::FromStr for NodeIndexStorage {
+    type Err = crate::RiemapError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "memory" => Ok(Self::Memory),
+            "disk" => Ok(Self::Disk),
+            other => Err(crate::RiemapError::Config(format!(
+                "Unknown node index storage '{}', expected 'memory' or 'disk'",
+                other
+            ))),
+        }
+    }
+}
+
+/// Bytes per on-disk record: ID, then latitude and longitude in 1e-7 degrees
+const RECORD_SIZE: usize = 16;
+
+/// Coordinates are stored with the precision OSM itself uses
+const COORDINATE_SCALE: f64 = 1e7;
+
+/// Node ID to (lat, lon) lookup, filled while reading and queried afterwards
+pub enum NodeIndex {
+    Memory(HashMap<i64, (f64, f64)>),
+    Disk(DiskNodeIndex),
+}
+
+impl NodeIndex {
+    /// Create an empty index; the disk variant puts its file in `temp_dir`
+    pub fn new(storage: NodeIndexStorage, temp_dir: &Path) -> Result<Self> {
+        Ok(match storage {
+            NodeIndexStorage::Memory => Self::Memory(HashMap::new()),
+            NodeIndexStorage::Disk => Self::Disk(DiskNodeIndex::new(temp_dir)?),
+        })
+    }
+
+    pub fn insert(&mut self, id: i64, lat: f64, lon: f64) {
+        match self {
+            Self::Memory(nodes) => {
+                nodes.insert(id, (lat, lon));
+            }
+            Self::Disk(index) => index.insert(id, lat, lon),
+        }
+    }
+
+    pub fn get(&mut self, id: i64) -> Option<(f64, f64)> {
+        self.seal();
+        self.lookup(id)
+    }
+
+    /// Finish inserting so `lookup` can be shared between threads; the disk
+    /// variant drops nodes inserted after this, as after the first `get`
+    pub fn seal(&mut self) {
+        if let Self::Disk(index) = self {
+            if index.records.is_none() && index.writer.is_some() {
+                if let Err(e) = index.map() {
+                    index.error.get_or_insert(e);
+                }
+            }
+        }
+    }
+
+    /// Look up a node without sealing; the disk variant finds nothing until sealed
+    pub fn lookup(&self, id: i64) -> Option<(f64, f64)> {
+        match self {
+            Self::Memory(nodes) => nodes.get(&id).copied(),
+            Self::Disk(index) => index.lookup(id),
+        }
+    }
+
+    pub fn contains(&mut self, id: i64) -> bool {
+        self.get(id).is_some()
+    }
+
+    /// The first write or mapping error, if any; lookups after an error find nothing
+    pub fn take_error(&mut self) -> Option<std::io::Error> {
+        match self {
+            Self::Memory(_) => None,
+            Self::Disk(index) => index.error.take(),
+        }
+    }
+}
+
+/// Node records appended to a temp file, then memory-mapped for lookups
+///
+/// The file is written while nodes are read and mapped when the index is
+/// sealed (at the latest by the first `get`); nodes inserted after that are
+/// dropped. Input sorted by ID (as Geofabrik
+/// extracts are) is searched as written, anything else is sorted in place
+/// once when mapped.
+pub struct DiskNodeIndex {
+    writer: Option<BufWriter<File>>,
+    records: Option<MmapMut>,
+    last_id: Option<i64>,
+    sorted: bool,
+    dropped: u64,
+    error: Option<std::io::Error>,
+}
+
+impl DiskNodeIndex {
+    fn new(temp_dir: &Path) -> Result<Self> {
+        // Unlinked on creation, so the file is removed however processing ends
+        let file = tempfile::tempfile_in(temp_dir)?;
+
+        Ok(Self {
+            writer: Some(BufWriter::new(file)),
+            records: None,
+            last_id: None,
+            sorted: true,
+            dropped: 0,
+            error: None,
+        })
+    }
+
+    fn insert(&mut self, id: i64, lat: f64, lon: f64) {
+        let Some(writer) = self.writer.as_mut() else {
+            self.dropped += 1;
+            if self.dropped == 1 && self.error.is_none() {
+                warn!("Node {} read after node lookups started; not indexed", id);
+            }
+            return;
+        };
+
+        let mut record = [0u8; RECORD_SIZE];
+        record[..8].copy_from_slice(&id.to_le_bytes());
+        record[8..12].copy_from_slice(&((lat * COORDINATE_SCALE).round() as i32).to_le_bytes());
+        record[12..].copy_from_slice(&((lon * COORDINATE_SCALE).round() as i32).to_le_bytes());
+
+        if let Err(e) = writer.write_all(&record) {
+            self.error.get_or_insert(e);
+            self.writer = None;
+            return;
+        }
+
+        self.sorted &= !matches!(self.last_id, Some(last) if last >= id);
+        self.last_id = Some(id);
+    }
+
+    fn lookup(&self, id: i64) -> Option<(f64, f64)> {
+        let records = self.records.as_ref()?;
+
+        let (mut low, mut high) = (0, records.len() / RECORD_SIZE);
+        while low < high {
+            let mid = (low + high) / 2;
+            let record = &records[mid * RECORD_SIZE..(mid + 1) * RECORD_SIZE];
+            let record_id = i64::from_le_bytes(record[..8].try_into().ok()?);
+
+            match record_id.cmp(&id) {
+                std::cmp::Ordering::Less => low = mid + 1,
+                std::cmp::Ordering::Greater => high = mid,
+                std::cmp::Ordering::Equal => {
+                    let lat = i32::from_le_bytes(record[8..12].try_into().ok()?);
+                    let lon = i32::from_le_bytes(record[12..].try_into().ok()?);
+                    return Some((lat as f64 / COORDINATE_SCALE, lon as f64 / COORDINATE_SCALE));
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Finish writing and map the file, sorting the records if they arrived out of order
+    fn map(&mut self) -> std::io::Result<()> {
+        let Some(writer) = self.writer.take() else {
+            return Ok(());
+        };
+        let file = writer.into_inner().map_err(|e| e.into_error())?;
+
+        // An empty file cannot be mapped, and has nothing to find anyway
+        if file.metadata()?.len() == 0 {
+            return Ok(());
+        }
+
+        // Safety: the file is private to this index (unlinked and never
+        // handed out), so nothing else can modify or truncate it while mapped.
+        let mut records = unsafe { MmapMut::map_mut(&file)? };
+
+        if !self.sorted {
+            // Safety: `[u8; RECORD_SIZE]` has alignment 1 and the map length is
+            // a multiple of the record size, as only whole records are written.
+            let records = unsafe {
+                std::slice::from_raw_parts_mut(
+                    records.as_mut_ptr() as *mut [u8; RECORD_SIZE],
+                    records.len() / RECORD_SIZE,
+                )
+            };
+            records.sort_unstable_by_key(|record| {
+                i64::from_le_bytes(record[..8].try_into().unwrap_or_default())
+            });
+        }
+
+        self.records = Some(records);
+        Ok(())
+    }
+}
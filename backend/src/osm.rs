@@ -1,17 +1,286 @@
-use crate::{models::*, Result};
-use chrono::Utc;
-use osmpbf::{Element, ElementReader};
+use crate::node_index::{NodeIndex, NodeIndexStorage};
+use crate::osm_xml::ChangeAction;
+use crate::pbf::{MemberType, OsmData, OsmElement};
+use crate::shapefile::{ShapeFeature, ShapeGeometry};
+use crate::temp_files::TempFile;
+use crate::{config::GeofabrikConfig, jobs::JobInterrupted, models::*, Result};
+use chrono::{DateTime, NaiveDate, Utc};
+use osmpbf::{BlobDecode, BlobReader, Element, ElementReader};
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
 use reqwest;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio_util::sync::CancellationToken;
 
-use tracing::{info, warn};
+use tracing::info;
+
+/// Counts of changes applied from OsmChange files
+#[derive(Debug, Clone, Default)]
+pub struct ChangeSummary {
+    pub created: u64,
+    pub modified: u64,
+    pub deleted: u64,
+    pub skipped: u64,
+}
+
+/// Reference to a single OSM element by type and ID
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ElementRef {
+    #[serde(rename = "type")]
+    pub element_type: MemberType,
+    pub id: i64,
+}
+
+/// Element-level differences between two OSM datasets
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ElementDiff {
+    pub added: DiffCategory,
+    pub modified: DiffCategory,
+    pub deleted: DiffCategory,
+}
+
+/// Number of elements in one kind of change, with the first few of them
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DiffCategory {
+    pub count: u64,
+    pub sample: Vec<ElementRef>,
+}
+
+impl DiffCategory {
+    fn push(&mut self, element: &OsmElement, sample_limit: usize) {
+        self.count += 1;
+        if self.sample.len() < sample_limit {
+            self.sample.push(ElementRef {
+                element_type: element.member_type(),
+                id: element.id(),
+            });
+        }
+    }
+}
+
+/// A tagged element picked by `sample_elements`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SampledElement {
+    #[serde(rename = "type")]
+    pub element_type: MemberType,
+    pub id: i64,
+    pub tags: BTreeMap<String, String>,
+    /// (lat, lon) of a node, or of a way's nodes in order (nodes missing
+    /// from the file are left out); empty for relations
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub coordinates: Vec<(f64, f64)>,
+}
+
+/// Replication position as published in an updates directory's `state.txt`
+#[derive(Debug, Clone)]
+pub struct ReplicationState {
+    pub sequence_number: i64,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Default distance within which two tagged nodes count as duplicates
+pub const DEFAULT_DUPLICATE_NODE_TOLERANCE_M: f64 = 0.5;
+
+/// Default margin, in degrees, a region's bounding box is grown by before a
+/// download's extent must overlap it; extracts often reach past admin borders
+pub const DEFAULT_EXTENT_TOLERANCE_DEG: f64 = 0.5;
+
+/// Which checks `process_osm_file` runs beyond the single-pass aggregate metrics
+///
+/// Element counts, feature distribution, completeness and tag frequencies
+/// are always collected in one pass with bounded memory. Each check below
+/// adds memory or time that grows with the size of the extract.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProcessingOptions {
+    /// Validate closed building/landuse ways as polygons. Needs the
+    /// coordinates of every node (see `node_index`) and, for PBF input, a
+    /// second pass over the file.
+    pub polygon_checks: bool,
+    /// Flag relations whose members are missing from the file. Needs the ID
+    /// of every node (see `node_index`), and keeps every way and relation ID
+    /// and relation member in memory.
+    pub member_checks: bool,
+    /// Flag tagged nodes stacked within the duplicate tolerance. Memory grows
+    /// with the number of tagged nodes.
+    pub duplicate_node_checks: bool,
+    /// Sum the length of `highway=*` ways per class. Needs the coordinates of
+    /// every node (see `node_index`) and, for PBF input, a second pass over
+    /// the file.
+    pub road_lengths: bool,
+    /// Where node coordinates live for polygon checks, member checks and
+    /// road lengths. `Disk`
+    /// trades lookup speed and temp space for flat memory on huge extracts.
+    pub node_index: NodeIndexStorage,
+    /// Worker threads decoding and checking the blocks of PBF input; 0 uses
+    /// one per available core. Results are merged in file order, so they
+    /// match a single-threaded pass.
+    pub threads: usize,
+    /// Element types read at all; the counts of skipped types are left unset
+    /// and the checks needing them are turned off
+    pub elements: ProcessFilter,
+}
+
+impl Default for ProcessingOptions {
+    fn default() -> Self {
+        Self {
+            polygon_checks: true,
+            member_checks: true,
+            duplicate_node_checks: true,
+            road_lengths: true,
+            node_index: NodeIndexStorage::Memory,
+            threads: 0,
+            elements: ProcessFilter::default(),
+        }
+    }
+}
+
+impl ProcessingOptions {
+    /// Only the single-pass aggregate metrics, in near-constant memory
+    pub fn aggregates_only() -> Self {
+        Self {
+            polygon_checks: false,
+            member_checks: false,
+            duplicate_node_checks: false,
+            road_lengths: false,
+            node_index: NodeIndexStorage::Memory,
+            threads: 0,
+            elements: ProcessFilter::default(),
+        }
+    }
+
+    /// Turn off the checks whose input `elements` skips: polygon checks and
+    /// road lengths need nodes and ways, duplicate node checks need nodes,
+    /// and member checks need all three to tell missing members apart
+    fn restricted_to_elements(mut self) -> Self {
+        let ProcessFilter {
+            nodes,
+            ways,
+            relations,
+        } = self.elements;
+        self.polygon_checks &= nodes && ways;
+        self.road_lengths &= nodes && ways;
+        self.duplicate_node_checks &= nodes;
+        self.member_checks &= nodes && ways && relations;
+        self
+    }
+
+    fn needs_node_index(&self) -> bool {
+        self.polygon_checks || self.member_checks || self.road_lengths
+    }
+
+    /// Threads to process PBF blocks with, resolving 0 to the available cores
+    fn worker_threads(&self) -> usize {
+        match self.threads {
+            0 => std::thread::available_parallelism().map_or(1, |cores| cores.get()),
+            threads => threads,
+        }
+    }
+}
+
+/// Element types `process_osm_file` reads
+///
+/// Skipping a type saves the time spent on its tags, but its counts in
+/// `QualityMetrics` are `None` and `completeness_score` then only covers the
+/// types that were read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProcessFilter {
+    pub nodes: bool,
+    pub ways: bool,
+    pub relations: bool,
+}
+
+impl Default for ProcessFilter {
+    fn default() -> Self {
+        Self {
+            nodes: true,
+            ways: true,
+            relations: true,
+        }
+    }
+}
+
+impl ProcessFilter {
+    pub fn is_all(&self) -> bool {
+        self.nodes && self.ways && self.relations
+    }
+}
+
+impl std::str::FromStr for ProcessFilter {
+    type Err = crate::RiemapError;
+
+    /// Parse a comma-separated list such as `ways,relations`
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let mut filter = Self {
+            nodes: false,
+            ways: false,
+            relations: false,
+        };
+        for element in s.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            match element.to_ascii_lowercase().as_str() {
+                "nodes" => filter.nodes = true,
+                "ways" => filter.ways = true,
+                "relations" => filter.relations = true,
+                other => {
+                    return Err(crate::RiemapError::Config(format!(
+                        "Unknown element type '{}', expected 'nodes', 'ways' or 'relations'",
+                        other
+                    )))
+                }
+            }
+        }
+        if !(filter.nodes || filter.ways || filter.relations) {
+            return Err(crate::RiemapError::Config(
+                "At least one element type must be processed".to_string(),
+            ));
+        }
+        Ok(filter)
+    }
+}
+
+/// Default number of elements `validate_file` reads before stopping
+pub const DEFAULT_VALIDATION_MAX_ELEMENTS: usize = 1000;
+
+/// Data blocks (of up to 8000 elements) each pass of `sample_elements` reads
+pub const MAX_SAMPLE_SCAN_BLOCKS: usize = 512;
+
+/// Default decimal places kept in exported GeoJSON coordinates (about 1 cm)
+pub const DEFAULT_GEOJSON_PRECISION: u8 = 7;
+
+/// Allowed decimal places for GeoJSON coordinates; an f64 holds no more
+pub const GEOJSON_PRECISION_RANGE: std::ops::RangeInclusive<u8> = 1..=15;
 
 /// OSM data processor for downloading and filtering regional data
 #[derive(Clone)]
 pub struct OsmProcessor {
     pub data_dir: PathBuf,
     pub temp_dir: PathBuf,
+    /// Tagged nodes closer than this (in meters) are reported as duplicates
+    pub duplicate_node_tolerance_m: f64,
+    /// Degrees a region's bounding box is grown by when checking a file's extent
+    pub extent_tolerance_deg: f64,
+    /// Checks run by `process_osm_file` on top of the aggregate metrics
+    pub processing_options: ProcessingOptions,
+    /// Attempts per outbound request before giving up on transient failures
+    pub http_max_attempts: u32,
+    /// How long a server may stay silent before a request or download fails
+    pub http_read_timeout: Duration,
+    /// Largest extract a download may write; `None` for no limit
+    pub max_download_bytes: Option<u64>,
+    /// Store downloads once under `data_dir/blobs/<sha256>`, hard-linked
+    /// into the region directories
+    pub content_addressed: bool,
+    /// Shared by every request so connections are pooled and kept alive
+    client: reqwest::Client,
+    /// Stops `process_osm_file` at its next block once cancelled; never
+    /// cancelled unless set with `with_cancellation`
+    cancel: CancellationToken,
 }
 
 impl OsmProcessor {
@@ -24,26 +293,150 @@ impl OsmProcessor {
         fs::create_dir_all(&data_dir)?;
         fs::create_dir_all(&temp_dir)?;
 
-        Ok(Self { data_dir, temp_dir })
+        Ok(Self {
+            data_dir,
+            temp_dir,
+            duplicate_node_tolerance_m: DEFAULT_DUPLICATE_NODE_TOLERANCE_M,
+            extent_tolerance_deg: DEFAULT_EXTENT_TOLERANCE_DEG,
+            processing_options: ProcessingOptions::default(),
+            http_max_attempts: crate::http::DEFAULT_MAX_ATTEMPTS,
+            http_read_timeout: crate::http::DEFAULT_READ_TIMEOUT,
+            max_download_bytes: None,
+            content_addressed: false,
+            client: crate::http::build_client(crate::http::DEFAULT_CONNECT_TIMEOUT)?,
+            cancel: CancellationToken::new(),
+        })
+    }
+
+    /// Delete temp files older than `max_age` that crashed runs left behind
+    pub fn sweep_temp_dir(&self, max_age: Duration) -> Result<(usize, u64)> {
+        crate::temp_files::sweep_stale(&self.temp_dir, max_age)
+    }
+
+    /// Apply the retry and timeout settings for outbound requests
+    pub fn with_http_config(mut self, config: &GeofabrikConfig) -> Result<Self> {
+        self.http_max_attempts = config.max_attempts;
+        self.http_read_timeout = config.read_timeout();
+        self.client = crate::http::build_client(config.connect_timeout())?;
+        Ok(self)
+    }
+
+    /// Stop processing with `JobInterrupted::Cancelled` once `cancel` is cancelled
+    pub fn with_cancellation(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = cancel;
+        self
     }
 
-    /// Download OSM data for a specific region
-    pub async fn download_region(&self, region: &Region) -> Result<PathBuf> {
+    /// Fail with `JobInterrupted::Cancelled` once the processor's token is cancelled
+    fn check_cancelled(&self) -> Result<()> {
+        if self.cancel.is_cancelled() {
+            return Err(JobInterrupted::Cancelled.into());
+        }
+        Ok(())
+    }
+
+    /// GET a URL, retrying connection errors, timeouts, 5xx and 429 responses
+    async fn http_get(&self, url: &str) -> Result<reqwest::Response> {
+        crate::http::send_with_retry(self.http_max_attempts, self.http_read_timeout, || {
+            self.client.get(url)
+        })
+        .await
+    }
+
+    /// Set the distance within which tagged nodes are reported as duplicates
+    ///
+    /// A tolerance of zero or less disables the check.
+    pub fn with_duplicate_node_tolerance(mut self, meters: f64) -> Self {
+        self.duplicate_node_tolerance_m = meters;
+        self
+    }
+
+    /// Set the margin in degrees by which a download's extent may miss its
+    /// region's bounding box before it is rejected
+    pub fn with_extent_tolerance(mut self, degrees: f64) -> Self {
+        self.extent_tolerance_deg = degrees;
+        self
+    }
+
+    /// Abort downloads larger than `max_bytes`, e.g. a planet file behind a
+    /// misconfigured region URL; `None` lifts the limit for trusted downloads
+    pub fn with_max_download_size(mut self, max_bytes: Option<u64>) -> Self {
+        self.max_download_bytes = max_bytes;
+        self
+    }
+
+    /// Deduplicate downloads with identical content, e.g. from region aliases
+    /// pointing at the same extract
+    ///
+    /// Each download is kept once as `data_dir/blobs/<sha256>` and its region
+    /// path becomes a hard link to that blob, so readers see a regular file.
+    pub fn with_content_addressed_storage(mut self, enabled: bool) -> Self {
+        self.content_addressed = enabled;
+        self
+    }
+
+    /// Choose which checks `process_osm_file` runs and where it keeps node coordinates
+    pub fn with_processing_options(mut self, options: ProcessingOptions) -> Self {
+        self.processing_options = options;
+        self
+    }
+
+    /// Download OSM data for a specific region into its storage directory
+    ///
+    /// When `verify` is set, the download is checked against the `.md5` file
+    /// Geofabrik publishes next to each extract and the verified hash is kept
+    /// in a `<file>.md5` sidecar. Mirrors without md5 files should pass `false`.
+    pub async fn download_region(
+        &self,
+        region: &Region,
+        region_dir: &Path,
+        verify: bool,
+    ) -> Result<PathBuf> {
+        self.download_region_with_progress(region, region_dir, verify, |_, _| {})
+            .await
+    }
+
+    /// Download OSM data for a region, reporting progress as it streams in
+    ///
+    /// `progress` is called with the bytes downloaded so far and the total size
+    /// from `Content-Length`, which is `None` when the server omits it. The
+    /// request is conditional on the `ETag` and `Last-Modified` recorded for
+    /// the file `latest` points at; when the server answers `304 Not Modified`
+    /// nothing is written and that file's path is returned.
+    pub async fn download_region_with_progress(
+        &self,
+        region: &Region,
+        region_dir: &Path,
+        verify: bool,
+        progress: impl Fn(u64, Option<u64>) + Send + Sync,
+    ) -> Result<PathBuf> {
         info!("Downloading OSM data for region: {}", region.name);
 
-        // For Liechtenstein, we'll use a specific URL
-        let url = match region.id.as_str() {
-            "liechtenstein" => "https://download.geofabrik.de/europe/liechtenstein-latest.osm.pbf",
-            _ => {
-                return Err(crate::RiemapError::OsmProcessing(format!(
-                    "No download URL configured for region: {}",
-                    region.id
-                ))
-                .into())
-            }
-        };
+        // The URL comes from the Geofabrik index, already rewritten for any mirror
+        let url = utils::download_url(region)?;
+
+        let previous = std::fs::canonicalize(region_dir.join("latest.osm.pbf"))
+            .ok()
+            .and_then(|path| {
+                let validators = utils::read_validators(&path).filter(|v| v.url == url)?;
+                Some((path, validators))
+            });
+        let response =
+            crate::http::send_with_retry(self.http_max_attempts, self.http_read_timeout, || {
+                let request = self.client.get(url);
+                match &previous {
+                    Some((_, validators)) => validators.apply(request),
+                    None => request,
+                }
+            })
+            .await?;
 
-        let response = reqwest::get(url).await?;
+        if response.status() == reqwest::StatusCode::NOT_MODIFIED {
+            if let Some((path, _)) = previous {
+                info!("{} has not changed, keeping {:?}", url, path);
+                return Ok(path);
+            }
+        }
         if !response.status().is_success() {
             return Err(crate::RiemapError::Network(format!(
                 "Failed to download OSM data: {}",
@@ -53,244 +446,3927 @@ impl OsmProcessor {
         }
 
         // Create region directory
-        let region_dir = self.data_dir.join("europe").join(&region.id);
-        fs::create_dir_all(&region_dir)?;
+        fs::create_dir_all(region_dir)?;
 
         // Save to timestamped file
         let timestamp = Utc::now().format("%Y-%m-%d").to_string();
         let filename = format!("{}.osm.pbf", timestamp);
         let file_path = region_dir.join(&filename);
 
-        let bytes = response.bytes().await?;
-        tokio::fs::write(&file_path, bytes).await?;
+        let validators = crate::http::CacheValidators::from_response(url, &response);
+        self.save_download(
+            response,
+            url,
+            &region.bounding_box,
+            &file_path,
+            verify,
+            progress,
+        )
+        .await?;
+
+        // A file downloaded earlier the same day may have left validators behind
+        let validators_path = utils::validators_sidecar_path(&file_path);
+        match validators {
+            Some(validators) => {
+                tokio::fs::write(&validators_path, serde_json::to_vec(&validators)?).await?
+            }
+            None if validators_path.exists() => tokio::fs::remove_file(&validators_path).await?,
+            None => {}
+        }
 
         // Also create a "latest" symlink
-        let latest_path = region_dir.join("latest.osm.pbf");
-        if latest_path.exists() {
-            fs::remove_file(&latest_path)?;
+        utils::point_latest_to(region_dir, &file_path)?;
+
+        info!("Downloaded OSM data to: {:?}", file_path);
+        Ok(file_path)
+    }
+
+    /// Download a dated historical extract for a region
+    ///
+    /// Geofabrik keeps older extracts next to the latest one, e.g.
+    /// `liechtenstein-210101.osm.pbf`. The URL is derived from the region's
+    /// `geofabrik_url` and the file is stored as `<YYYY-MM-DD>.osm.pbf`. The
+    /// region's `latest` link is left untouched.
+    pub async fn download_region_version(
+        &self,
+        region: &Region,
+        region_dir: &Path,
+        date: NaiveDate,
+        verify: bool,
+    ) -> Result<PathBuf> {
+        let latest_url = utils::download_url(region)?;
+        let url = utils::historical_extract_url(latest_url, date).ok_or_else(|| {
+            crate::RiemapError::OsmProcessing(format!(
+                "Cannot derive a dated URL from {}",
+                latest_url
+            ))
+        })?;
+
+        info!(
+            "Downloading OSM data for region {} as of {}",
+            region.name, date
+        );
+
+        let response = self.http_get(&url).await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Err(crate::RiemapError::Network(format!(
+                "No extract of {} is available for {} ({})",
+                region.id, date, url
+            ))
+            .into());
+        }
+        if !response.status().is_success() {
+            return Err(crate::RiemapError::Network(format!(
+                "Failed to download OSM data: {}",
+                response.status()
+            ))
+            .into());
         }
 
-        #[cfg(unix)]
-        std::os::unix::fs::symlink(&filename, &latest_path)?;
+        fs::create_dir_all(region_dir)?;
+        let file_path = region_dir.join(format!("{}.osm.pbf", date.format("%Y-%m-%d")));
 
-        #[cfg(windows)]
-        fs::copy(&file_path, &latest_path)?;
+        self.save_download(
+            response,
+            &url,
+            &region.bounding_box,
+            &file_path,
+            verify,
+            |_, _| {},
+        )
+        .await?;
 
         info!("Downloaded OSM data to: {:?}", file_path);
         Ok(file_path)
     }
 
-    /// Process OSM data and extract basic statistics
-    pub async fn process_osm_file(&self, file_path: &Path) -> Result<QualityMetrics> {
-        info!("Processing OSM file: {:?}", file_path);
-
-        let mut metrics = QualityMetrics {
-            total_nodes: 0,
-            total_ways: 0,
-            total_relations: 0,
-            tagged_nodes: 0,
-            tagged_ways: 0,
-            tagged_relations: 0,
-            completeness_score: 0.0,
-            geometry_errors: 0,
-            tag_errors: 0,
-            topology_errors: 0,
-            feature_distribution: FeatureDistribution::default(),
-            custom_metrics: std::collections::HashMap::new(),
+    /// Stream a download response to `file_path`, optionally verifying its MD5
+    ///
+    /// The data is streamed to a temp file that only replaces `file_path`
+    /// once complete, so a failed, cancelled or aborted download leaves
+    /// nothing behind. Downloads over `max_download_bytes` are refused by
+    /// their `Content-Length`, or stopped once that many bytes arrived when
+    /// the header is missing or wrong. A file whose header extent lies
+    /// entirely outside `expected_extent`, e.g. another country fetched
+    /// through a misconfigured URL, is rejected as well.
+    async fn save_download(
+        &self,
+        mut response: reqwest::Response,
+        url: &str,
+        expected_extent: &BoundingBox,
+        file_path: &Path,
+        verify: bool,
+        progress: impl Fn(u64, Option<u64>) + Send + Sync,
+    ) -> Result<()> {
+        let total = response.content_length();
+        let too_large = |size: u64, max: u64| {
+            crate::RiemapError::Storage(format!(
+                "Download of {} exceeds the size limit: {} bytes, at most {} allowed",
+                url, size, max
+            ))
         };
+        if let (Some(total), Some(max)) = (total, self.max_download_bytes) {
+            if total > max {
+                return Err(too_large(total, max).into());
+            }
+        }
 
-        let reader = ElementReader::from_path(file_path)?;
+        let mut hasher = md5::Context::new();
+        let mut sha256 = Sha256::new();
 
-        reader.for_each(|element| {
-            match element {
-                Element::Node(node) => {
-                    metrics.total_nodes += 1;
-                    if node.tags().count() > 0 {
-                        metrics.tagged_nodes += 1;
-                    }
-                    // Basic validation
-                    if node.lat().abs() > 90.0 || node.lon().abs() > 180.0 {
-                        metrics.geometry_errors += 1;
-                    }
-                }
-                Element::Way(way) => {
-                    metrics.total_ways += 1;
-                    if way.tags().count() > 0 {
-                        metrics.tagged_ways += 1;
-                    }
-                    // Check for valid way structure
-                    if way.refs().count() < 2 {
-                        metrics.topology_errors += 1;
-                    }
-                }
-                Element::Relation(relation) => {
-                    metrics.total_relations += 1;
-                    if relation.tags().count() > 0 {
-                        metrics.tagged_relations += 1;
-                    }
-                }
-                Element::DenseNode(node) => {
-                    metrics.total_nodes += 1;
-                    if node.tags().count() > 0 {
-                        metrics.tagged_nodes += 1;
-                    }
-                    // Basic validation
-                    if node.lat().abs() > 90.0 || node.lon().abs() > 180.0 {
-                        metrics.geometry_errors += 1;
-                    }
-                }
+        let filename = file_path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("download.osm.pbf");
+        let temp_file = TempFile::new(&self.temp_dir, filename);
+        let mut file = tokio::fs::File::create(temp_file.path()).await?;
+        let mut downloaded = 0u64;
+
+        while let Some(chunk) =
+            crate::http::next_chunk(&mut response, self.http_read_timeout).await?
+        {
+            file.write_all(&chunk).await?;
+            hasher.consume(&chunk);
+            sha256.update(&chunk);
+            downloaded += chunk.len() as u64;
+            if let Some(max) = self.max_download_bytes.filter(|max| downloaded > *max) {
+                return Err(too_large(downloaded, max).into());
             }
-        })?;
+            progress(downloaded, total);
+        }
 
-        // Calculate completeness score (simplified)
-        let total_elements = metrics.total_nodes + metrics.total_ways + metrics.total_relations;
-        let tagged_elements = metrics.tagged_nodes + metrics.tagged_ways + metrics.tagged_relations;
+        file.flush().await?;
+        drop(file);
 
-        if total_elements > 0 {
-            metrics.completeness_score = (tagged_elements as f64 / total_elements as f64) * 100.0;
+        let md5 = format!("{:x}", hasher.compute());
+        if verify {
+            let expected = self.fetch_md5(url).await?;
+            if md5 != expected {
+                return Err(crate::RiemapError::Network(format!(
+                    "MD5 mismatch for {}: expected {}, got {}",
+                    url, expected, md5
+                ))
+                .into());
+            }
+            info!("Verified MD5 checksum: {}", md5);
         }
 
-        info!(
-            "Processing complete. Nodes: {}, Ways: {}, Relations: {}",
-            metrics.total_nodes, metrics.total_ways, metrics.total_relations
-        );
+        if let Some(issue) = self.check_extent(expected_extent, temp_file.path()) {
+            return Err(crate::RiemapError::Quality(format!(
+                "Download of {} rejected: {}",
+                url, issue.description
+            ))
+            .into());
+        }
 
-        Ok(metrics)
+        let sha256 = format!("{:x}", sha256.finalize());
+        self.install_data_file(temp_file, file_path, &sha256)
+            .await?;
+
+        // An unverified download must not keep the MD5 of an earlier file
+        let md5_sidecar = utils::checksum_sidecar_path(file_path);
+        if verify {
+            tokio::fs::write(&md5_sidecar, &md5).await?;
+        } else if md5_sidecar.exists() {
+            tokio::fs::remove_file(&md5_sidecar).await?;
+        }
+
+        Ok(())
     }
 
-    /// Filter OSM data by bounding box (placeholder for more advanced filtering)
-    pub async fn filter_by_bounds(
+    /// Store an uploaded data file whose SHA-256 the caller has verified
+    ///
+    /// The Geofabrik MD5 and HTTP validators of an earlier file at the same
+    /// path are removed, as they no longer describe its content.
+    pub async fn save_upload(
         &self,
-        input_path: &Path,
-        bounds: &BoundingBox,
-        output_path: &Path,
+        temp_file: TempFile,
+        file_path: &Path,
+        sha256: &str,
     ) -> Result<()> {
-        info!("Filtering OSM data by bounds: {:?}", bounds);
+        self.install_data_file(temp_file, file_path, sha256).await?;
 
-        // For now, we'll just copy the file as filtering requires more complex logic
-        // In a production system, you'd implement proper spatial filtering here
-        fs::copy(input_path, output_path)?;
+        for sidecar in [
+            utils::checksum_sidecar_path(file_path),
+            utils::validators_sidecar_path(file_path),
+        ] {
+            if sidecar.exists() {
+                tokio::fs::remove_file(&sidecar).await?;
+            }
+        }
 
-        warn!("Spatial filtering not yet implemented - copied file as-is");
         Ok(())
     }
 
-    /// Validate OSM data file integrity
-    pub async fn validate_file(&self, file_path: &Path) -> Result<Vec<QualityIssue>> {
-        info!("Validating OSM file: {:?}", file_path);
+    /// Move a complete temp file to `file_path` and record its SHA-256
+    async fn install_data_file(
+        &self,
+        temp_file: TempFile,
+        file_path: &Path,
+        sha256: &str,
+    ) -> Result<()> {
+        // Replace rather than overwrite an earlier file; it may be a link to
+        // a blob other regions share
+        if file_path.symlink_metadata().is_ok() {
+            tokio::fs::remove_file(file_path).await?;
+        }
+        temp_file.persist(file_path)?;
 
-        let mut issues = Vec::new();
+        // Kept so downloads from this server can be verified end-to-end
+        tokio::fs::write(utils::sha256_sidecar_path(file_path), sha256).await?;
 
-        // Check if file exists and is readable
-        if !file_path.exists() {
-            issues.push(QualityIssue {
-                issue_type: "file_missing".to_string(),
-                severity: IssueSeverity::Critical,
-                description: "OSM data file does not exist".to_string(),
-                location: None,
-                osm_id: None,
-                osm_type: None,
-                fix_suggestion: Some(
-                    "Ensure the OSM data file has been downloaded properly".to_string(),
-                ),
-            });
-            return Ok(issues);
+        if self.content_addressed {
+            utils::link_to_blob(&self.data_dir, file_path, sha256)?;
         }
 
-        // Check file size
-        let metadata = fs::metadata(file_path)?;
-        if metadata.len() == 0 {
-            issues.push(QualityIssue {
-                issue_type: "empty_file".to_string(),
-                severity: IssueSeverity::Critical,
-                description: "OSM data file is empty".to_string(),
-                location: None,
-                osm_id: None,
-                osm_type: None,
-                fix_suggestion: Some("Re-download the OSM data file".to_string()),
-            });
-        }
+        Ok(())
+    }
 
-        // Try to read the PBF header
-        match ElementReader::from_path(file_path) {
-            Ok(reader) => {
-                let mut element_count = 0;
-                let result = reader.for_each(|_| {
-                    element_count += 1;
-                    if element_count > 1000 {
-                        // Stop after checking first 1000 elements for validation
-                        return;
-                    }
-                });
+    /// Apply an OsmChange (`.osc` / `.osc.gz`) file to a base extract, writing a new PBF
+    pub async fn apply_changes(
+        &self,
+        base_pbf: &Path,
+        osc_path: &Path,
+        output_pbf: &Path,
+    ) -> Result<ChangeSummary> {
+        self.apply_change_files(base_pbf, &[osc_path.to_path_buf()], output_pbf, None)
+            .await
+    }
 
-                if let Err(e) = result {
-                    issues.push(QualityIssue {
-                        issue_type: "parsing_error".to_string(),
-                        severity: IssueSeverity::High,
-                        description: format!("Error parsing OSM data: {}", e),
-                        location: None,
-                        osm_id: None,
-                        osm_type: None,
-                        fix_suggestion: Some(
-                            "Check if the OSM file is corrupted and re-download if necessary"
-                                .to_string(),
-                        ),
-                    });
+    /// Apply a sequence of OsmChange files, in order, to a base extract
+    ///
+    /// The whole extract is held in memory while changes are applied. Actions
+    /// are applied in document order and files in the order given, so diffs
+    /// must be passed oldest first. Edge cases are resolved as follows:
+    ///
+    /// - `create` for an ID that already exists replaces it, unless the stored
+    ///   element has a newer version (diffs overlapping the extract timestamp
+    ///   can re-announce elements the base already contains)
+    /// - `modify` replaces by type and ID, inserting the element if it is
+    ///   missing (it moved into the extract area); stale versions are skipped
+    /// - `delete` for a missing ID is ignored, and is skipped when the stored
+    ///   element is newer than the deleted version
+    /// - deletions are not cascaded: ways or relations still referencing a
+    ///   deleted element keep the dangling reference
+    ///
+    /// When `replication` is given it replaces the base header's replication
+    /// state so later updates continue from the last applied diff.
+    pub async fn apply_change_files(
+        &self,
+        base_pbf: &Path,
+        osc_paths: &[PathBuf],
+        output_pbf: &Path,
+        replication: Option<ReplicationState>,
+    ) -> Result<ChangeSummary> {
+        info!(
+            "Applying {} change file(s) to {:?}",
+            osc_paths.len(),
+            base_pbf
+        );
+
+        let mut header = crate::pbf::read_header(base_pbf)?;
+        let mut data = OsmData::read_pbf(base_pbf)?;
+        let mut summary = ChangeSummary::default();
+
+        for osc_path in osc_paths {
+            for (action, element) in crate::osm_xml::parse_change_file(osc_path)? {
+                let existing = data.existing_version(&element);
+                let is_stale = existing.is_some_and(|v| v > element.version());
+
+                if is_stale {
+                    summary.skipped += 1;
+                    continue;
+                }
+
+                match action {
+                    ChangeAction::Create | ChangeAction::Modify => {
+                        data.insert(element);
+                        if action == ChangeAction::Create && existing.is_none() {
+                            summary.created += 1;
+                        } else {
+                            summary.modified += 1;
+                        }
+                    }
+                    ChangeAction::Delete => {
+                        if data.remove(&element).is_some() {
+                            summary.deleted += 1;
+                        } else {
+                            summary.skipped += 1;
+                        }
+                    }
                 }
-            }
-            Err(e) => {
-                issues.push(QualityIssue {
-                    issue_type: "invalid_format".to_string(),
-                    severity: IssueSeverity::Critical,
-                    description: format!("Cannot read OSM PBF file: {}", e),
-                    location: None,
-                    osm_id: None,
-                    osm_type: None,
-                    fix_suggestion: Some(
-                        "Ensure the file is a valid OSM PBF format and re-download if corrupted"
-                            .to_string(),
-                    ),
-                });
             }
         }
 
-        Ok(issues)
-    }
-}
+        if let Some(state) = replication {
+            header.replication_sequence_number = Some(state.sequence_number);
+            header.replication_timestamp = Some(state.timestamp.timestamp());
+        }
 
-/// Utility functions for OSM data
-pub mod utils {
-    use super::*;
+        // Write to a temp file first so the output may safely replace the base
+        let temp_file = TempFile::new(&self.temp_dir, "updated.osm.pbf");
+        crate::pbf::write_pbf(temp_file.path(), &data, &header)?;
+        temp_file.persist(output_pbf)?;
 
-    /// Get file size in MB
-    pub fn get_file_size_mb(path: &Path) -> Result<f64> {
-        let metadata = std::fs::metadata(path)?;
-        Ok(metadata.len() as f64 / 1_048_576.0) // Convert bytes to MB
-    }
+        info!(
+            "Applied changes: {} created, {} modified, {} deleted, {} skipped",
+            summary.created, summary.modified, summary.deleted, summary.skipped
+        );
 
-    /// Extract timestamp from filename
-    pub fn extract_timestamp_from_filename(filename: &str) -> Option<String> {
-        // Extract YYYY-MM-DD pattern from filename
-        let re = regex::Regex::new(r"\d{4}-\d{2}-\d{2}").ok()?;
-        re.find(filename).map(|m| m.as_str().to_string())
+        Ok(summary)
     }
 
-    /// Generate quality report summary
-    pub fn generate_summary(metrics: &QualityMetrics, issues: &[QualityIssue]) -> String {
-        let total_elements = metrics.total_nodes + metrics.total_ways + metrics.total_relations;
-        let critical_issues = issues
-            .iter()
-            .filter(|i| matches!(i.severity, IssueSeverity::Critical))
-            .count();
+    /// Bring a region's latest extract up to date with Geofabrik's daily diffs
+    ///
+    /// Reads the replication sequence number from the base extract's header,
+    /// downloads every newer diff from the region's `updates` URL and applies
+    /// them in sequence order. Returns `None` when already up to date.
+    pub async fn update_region(
+        &self,
+        region: &Region,
+        base_pbf: &Path,
+        region_dir: &Path,
+    ) -> Result<Option<(PathBuf, ChangeSummary)>> {
+        let updates_url = region
+            .urls
+            .as_ref()
+            .and_then(|urls| urls.updates.clone())
+            .ok_or_else(|| {
+                crate::RiemapError::OsmProcessing(format!(
+                    "No updates URL available for region: {}",
+                    region.id
+                ))
+            })?;
+
+        let base_sequence = crate::pbf::read_header(base_pbf)?
+            .replication_sequence_number
+            .ok_or_else(|| {
+                crate::RiemapError::OsmProcessing(format!(
+                    "Base extract {:?} has no replication sequence number",
+                    base_pbf
+                ))
+            })?;
+
+        let latest = self.fetch_replication_state(&updates_url).await?;
+        if latest.sequence_number <= base_sequence {
+            info!("Region {} is already up to date", region.id);
+            return Ok(None);
+        }
+
+        info!(
+            "Updating {} from sequence {} to {}",
+            region.id, base_sequence, latest.sequence_number
+        );
+
+        // Removed again when this returns, whether or not the update succeeded
+        let mut diff_files = Vec::new();
+        for sequence in (base_sequence + 1)..=latest.sequence_number {
+            let url = utils::replication_diff_url(&updates_url, sequence);
+            let response = self.http_get(&url).await?;
+            if !response.status().is_success() {
+                return Err(crate::RiemapError::Network(format!(
+                    "Failed to download diff {}: {}",
+                    url,
+                    response.status()
+                ))
+                .into());
+            }
+
+            let diff_file = TempFile::new(
+                &self.temp_dir,
+                &format!("{}-{}.osc.gz", region.id.replace('/', "_"), sequence),
+            );
+            tokio::fs::write(diff_file.path(), response.bytes().await?).await?;
+            diff_files.push(diff_file);
+        }
+
+        let diff_paths: Vec<PathBuf> = diff_files
+            .iter()
+            .map(|diff_file| diff_file.path().to_path_buf())
+            .collect();
+        let filename = format!("{}.osm.pbf", latest.timestamp.format("%Y-%m-%d"));
+        let output_path = region_dir.join(&filename);
+        let summary = self
+            .apply_change_files(base_pbf, &diff_paths, &output_path, Some(latest))
+            .await?;
+        utils::point_latest_to(region_dir, &output_path)?;
+
+        Ok(Some((output_path, summary)))
+    }
+
+    /// Compare two PBF files element by element, keeping the first
+    /// `sample_limit` IDs of each kind of change
+    ///
+    /// Elements present in both files count as modified when their version,
+    /// tags or geometry (coordinates, node refs, members) differ. Both files
+    /// must list elements by type and ID, as extracts do; they are merged a
+    /// block at a time on the blocking thread pool instead of being loaded.
+    pub async fn diff_files(
+        &self,
+        old_pbf: &Path,
+        new_pbf: &Path,
+        sample_limit: usize,
+    ) -> Result<ElementDiff> {
+        info!("Diffing {:?} against {:?}", old_pbf, new_pbf);
+
+        let (old_pbf, new_pbf) = (old_pbf.to_path_buf(), new_pbf.to_path_buf());
+        let diff = tokio::task::spawn_blocking(move || {
+            utils::diff_sorted(
+                utils::SortedElements::open(&old_pbf)?,
+                utils::SortedElements::open(&new_pbf)?,
+                sample_limit,
+            )
+        })
+        .await??;
+
+        info!(
+            "Diff complete: {} added, {} modified, {} deleted",
+            diff.added.count, diff.modified.count, diff.deleted.count
+        );
+
+        Ok(diff)
+    }
+
+    /// The first `n` tagged nodes, ways and relations of a file, in that order
+    ///
+    /// Reading stops as soon as every type has `n` elements. Way coordinates
+    /// are resolved with a second read over the nodes, which ends once all
+    /// of them are found (or the ways begin, in a sorted file). Each read
+    /// covers at most [`MAX_SAMPLE_SCAN_BLOCKS`] data blocks, so types that
+    /// only appear further into a large file may come back short.
+    pub async fn sample_elements(&self, path: &Path, n: usize) -> Result<Vec<SampledElement>> {
+        let path = path.to_path_buf();
+        tokio::task::spawn_blocking(move || Self::sample_elements_blocking(&path, n)).await?
+    }
+
+    fn sample_elements_blocking(path: &Path, n: usize) -> Result<Vec<SampledElement>> {
+        info!("Sampling {} elements of each type from {:?}", n, path);
+
+        let sample = |element_type, id, tags: Vec<(String, String)>, coordinates| SampledElement {
+            element_type,
+            id,
+            tags: tags.into_iter().collect(),
+            coordinates,
+        };
+
+        let (mut nodes, mut ways, mut relations) = (Vec::new(), Vec::new(), Vec::new());
+        let mut way_refs: Vec<Vec<i64>> = Vec::new();
+        Self::scan_elements(path, |element| {
+            match element {
+                OsmElement::Node(node) if !node.tags.is_empty() && nodes.len() < n => {
+                    let coordinates = vec![(node.lat, node.lon)];
+                    nodes.push(sample(MemberType::Node, node.id, node.tags, coordinates));
+                }
+                OsmElement::Way(way) if !way.tags.is_empty() && ways.len() < n => {
+                    ways.push(sample(MemberType::Way, way.id, way.tags, Vec::new()));
+                    way_refs.push(way.refs);
+                }
+                OsmElement::Relation(relation)
+                    if !relation.tags.is_empty() && relations.len() < n =>
+                {
+                    relations.push(sample(
+                        MemberType::Relation,
+                        relation.id,
+                        relation.tags,
+                        Vec::new(),
+                    ));
+                }
+                _ => {}
+            }
+            nodes.len() < n || ways.len() < n || relations.len() < n
+        })?;
+
+        let mut wanted: HashMap<i64, Option<(f64, f64)>> =
+            way_refs.iter().flatten().map(|id| (*id, None)).collect();
+        if !wanted.is_empty() {
+            let mut missing = wanted.len();
+            Self::scan_elements(path, |element| {
+                let OsmElement::Node(node) = element else {
+                    return false;
+                };
+                if let Some(location @ None) = wanted.get_mut(&node.id) {
+                    *location = Some((node.lat, node.lon));
+                    missing -= 1;
+                }
+                missing > 0
+            })?;
+        }
+
+        for (way, refs) in ways.iter_mut().zip(way_refs) {
+            way.coordinates = refs
+                .iter()
+                .filter_map(|id| wanted.get(id).copied().flatten())
+                .collect();
+        }
+
+        nodes.extend(ways);
+        nodes.extend(relations);
+        Ok(nodes)
+    }
+
+    /// Read a PBF or OSM XML file element by element until `visit` returns
+    /// false or [`MAX_SAMPLE_SCAN_BLOCKS`] blocks (or as many elements of XML)
+    /// have been read
+    fn scan_elements(path: &Path, mut visit: impl FnMut(OsmElement) -> bool) -> Result<()> {
+        match utils::detect_format(path) {
+            Some(DataFormat::OsmXml) => {
+                // The XML reader has no early exit, so reading is stopped
+                // by failing the underlying reader
+                let stop = CancellationToken::new();
+                let mut remaining = MAX_SAMPLE_SCAN_BLOCKS * crate::pbf::BLOCK_SIZE;
+                let input = std::io::BufReader::new(utils::CancellableRead {
+                    inner: crate::osm_xml::open_maybe_compressed(path)?,
+                    cancel: &stop,
+                });
+                let result = crate::osm_xml::for_each_element(input, |_, element| {
+                    if stop.is_cancelled() {
+                        return;
+                    }
+                    remaining -= 1;
+                    if !visit(element) || remaining == 0 {
+                        stop.cancel();
+                    }
+                });
+                if !stop.is_cancelled() {
+                    result?;
+                }
+            }
+            _ => {
+                let mut blocks = 0;
+                for blob in BlobReader::from_path(path)? {
+                    if let BlobDecode::OsmData(block) = blob?.decode()? {
+                        for element in block.elements() {
+                            if !visit(crate::pbf::owned_element(element)) {
+                                return Ok(());
+                            }
+                        }
+                        blocks += 1;
+                        if blocks == MAX_SAMPLE_SCAN_BLOCKS {
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Count nodes per cell of a lat/lon grid with `cell_deg` sized cells
+    ///
+    /// Cells are aligned to (-180, -90) so grids of different regions line
+    /// up. Only cells with at least one node are returned, ordered south to
+    /// north and west to east.
+    pub async fn node_density_grid(
+        &self,
+        pbf: &Path,
+        cell_deg: f64,
+    ) -> Result<Vec<(BoundingBox, u64)>> {
+        if !cell_deg.is_finite() || cell_deg <= 0.0 {
+            return Err(crate::RiemapError::OsmProcessing(format!(
+                "Grid cell size must be positive, got {}",
+                cell_deg
+            ))
+            .into());
+        }
+
+        let pbf = pbf.to_path_buf();
+        tokio::task::spawn_blocking(move || Self::node_density_grid_blocking(&pbf, cell_deg))
+            .await?
+    }
+
+    fn node_density_grid_blocking(pbf: &Path, cell_deg: f64) -> Result<Vec<(BoundingBox, u64)>> {
+        info!("Binning nodes of {:?} into {}° cells", pbf, cell_deg);
+
+        // Counts are keyed on (row, column), so memory grows with the number
+        // of occupied cells rather than nodes
+        let mut counts: HashMap<(i64, i64), u64> = HashMap::new();
+        let mut add = |lat: f64, lon: f64| {
+            let row = ((lat + 90.0) / cell_deg).floor() as i64;
+            let col = ((lon + 180.0) / cell_deg).floor() as i64;
+            *counts.entry((row, col)).or_insert(0) += 1;
+        };
+
+        match utils::detect_format(pbf) {
+            Some(DataFormat::OsmXml) => {
+                let input = crate::osm_xml::open_maybe_compressed(pbf)?;
+                crate::osm_xml::for_each_element(input, |_, element| {
+                    if let OsmElement::Node(node) = element {
+                        add(node.lat, node.lon);
+                    }
+                })?;
+            }
+            _ => {
+                let reader = ElementReader::from_path(pbf)?;
+                reader.for_each(|element| match element {
+                    Element::Node(node) => add(node.lat(), node.lon()),
+                    Element::DenseNode(node) => add(node.lat(), node.lon()),
+                    _ => {}
+                })?;
+            }
+        }
+
+        let mut cells: Vec<_> = counts.into_iter().collect();
+        cells.sort_unstable_by_key(|(cell, _)| *cell);
+
+        let grid: Vec<(BoundingBox, u64)> = cells
+            .into_iter()
+            .map(|((row, col), count)| {
+                let min_lat = row as f64 * cell_deg - 90.0;
+                let min_lon = col as f64 * cell_deg - 180.0;
+                let bbox = BoundingBox::new(
+                    min_lat,
+                    min_lon,
+                    (min_lat + cell_deg).min(90.0),
+                    (min_lon + cell_deg).min(180.0),
+                );
+                (bbox, count)
+            })
+            .collect();
+
+        info!("Density grid has {} occupied cells", grid.len());
+        Ok(grid)
+    }
+
+    /// Export tagged features of one geometry type from a PBF as an ESRI shapefile
+    ///
+    /// Points are tagged nodes, lines are open (or non-area closed) tagged
+    /// ways and polygons are closed area ways; multipolygon relations are not
+    /// exported. Returns the path of the written `.shp` file.
+    pub async fn export_shapefile(
+        &self,
+        input_pbf: &Path,
+        out_dir: &Path,
+        geometry_type: ShapeGeometry,
+    ) -> Result<PathBuf> {
+        info!(
+            "Exporting {} from {:?} to {:?}",
+            geometry_type.layer_name(),
+            input_pbf,
+            out_dir
+        );
+
+        let data = OsmData::read_pbf(input_pbf)?;
+        let attributes = |id: i64, tags: &[(String, String)]| {
+            let mut values = vec![id.to_string()];
+            values.extend(
+                SHAPEFILE_TAGS
+                    .iter()
+                    .map(|key| utils::tag_value(tags, key).unwrap_or_default().to_string()),
+            );
+            values
+        };
+
+        let features: Vec<ShapeFeature> = match geometry_type {
+            ShapeGeometry::Point => data
+                .nodes
+                .values()
+                .filter(|node| !node.tags.is_empty())
+                .map(|node| ShapeFeature {
+                    parts: vec![vec![(node.lon, node.lat)]],
+                    attributes: attributes(node.id, &node.tags),
+                })
+                .collect(),
+            ShapeGeometry::Line | ShapeGeometry::Polygon => data
+                .ways
+                .values()
+                .filter(|way| !way.tags.is_empty())
+                .filter(|way| utils::is_area_way(way) == (geometry_type == ShapeGeometry::Polygon))
+                .filter_map(|way| {
+                    // Skip ways whose nodes are not all part of this extract
+                    let coords: Option<Vec<(f64, f64)>> = way
+                        .refs
+                        .iter()
+                        .map(|id| data.nodes.get(id).map(|node| (node.lon, node.lat)))
+                        .collect();
+                    Some(ShapeFeature {
+                        parts: vec![coords?],
+                        attributes: attributes(way.id, &way.tags),
+                    })
+                })
+                .collect(),
+        };
+
+        fs::create_dir_all(out_dir)?;
+        let base = out_dir.join(geometry_type.layer_name());
+
+        let mut fields = vec!["osm_id"];
+        fields.extend_from_slice(SHAPEFILE_TAGS);
+        crate::shapefile::write_shapefile(&base, geometry_type, &fields, &features)?;
+
+        info!("Exported {} features to {:?}", features.len(), base);
+        Ok(base.with_extension("shp"))
+    }
+
+    /// Fetch the current replication state from an updates directory's `state.txt`
+    pub async fn fetch_replication_state(&self, updates_url: &str) -> Result<ReplicationState> {
+        let state_url = format!("{}/state.txt", updates_url.trim_end_matches('/'));
+        let response = self.http_get(&state_url).await?;
+        if !response.status().is_success() {
+            return Err(crate::RiemapError::Network(format!(
+                "Failed to fetch replication state {}: {}",
+                state_url,
+                response.status()
+            ))
+            .into());
+        }
+
+        utils::parse_replication_state(&response.text().await?).ok_or_else(|| {
+            crate::RiemapError::Network(format!("Invalid replication state: {}", state_url)).into()
+        })
+    }
+
+    /// Read the OSM data timestamp (`osmosis_replication_timestamp`) from a PBF header
+    pub fn read_replication_timestamp(path: &Path) -> Option<DateTime<Utc>> {
+        let header = crate::pbf::read_header(path).ok()?;
+        DateTime::from_timestamp(header.replication_timestamp?, 0)
+    }
+
+    /// Read the extent declared in a PBF header, if the file has one
+    pub fn read_header_bbox(path: &Path) -> Option<BoundingBox> {
+        crate::pbf::read_header(path).ok()?.bbox
+    }
+
+    /// Report a critical issue when a PBF's header extent lies entirely
+    /// outside `expected`, grown by `extent_tolerance_deg`
+    ///
+    /// Files without a header bounding box cannot be checked and pass.
+    pub fn check_extent(&self, expected: &BoundingBox, path: &Path) -> Option<QualityIssue> {
+        let actual = Self::read_header_bbox(path)?;
+        let allowed = expected.expanded(self.extent_tolerance_deg.max(0.0));
+        if allowed.intersects(&actual) {
+            return None;
+        }
+
+        Some(QualityIssue {
+            issue_type: "extent_mismatch".to_string(),
+            severity: IssueSeverity::Critical,
+            description: format!(
+                "Data extent {},{},{},{} lies outside the region's bounding box {},{},{},{}",
+                actual.min_lon,
+                actual.min_lat,
+                actual.max_lon,
+                actual.max_lat,
+                expected.min_lon,
+                expected.min_lat,
+                expected.max_lon,
+                expected.max_lat
+            ),
+            location: Some(actual.center()),
+            osm_id: None,
+            osm_type: None,
+            fix_suggestion: Some(
+                "Check that the region's download URL points at the right extract".to_string(),
+            ),
+        })
+    }
+
+    /// Fetch the published MD5 hash for a download URL from `<url>.md5`
+    async fn fetch_md5(&self, url: &str) -> Result<String> {
+        let md5_url = format!("{}.md5", url);
+        let response = self.http_get(&md5_url).await?;
+        if !response.status().is_success() {
+            return Err(crate::RiemapError::Network(format!(
+                "Failed to fetch checksum {}: {}",
+                md5_url,
+                response.status()
+            ))
+            .into());
+        }
+
+        // Format is "<hash>  <filename>", as produced by md5sum
+        let body = response.text().await?;
+        body.split_whitespace()
+            .next()
+            .map(|hash| hash.to_lowercase())
+            .ok_or_else(|| {
+                crate::RiemapError::Network(format!("Empty checksum file: {}", md5_url)).into()
+            })
+    }
+
+    /// Process OSM data and extract basic statistics
+    ///
+    /// Returns the aggregate metrics together with element-level issues found
+    /// by the checks enabled in `processing_options`. Element types left out
+    /// of `processing_options.elements` are skipped without reading their tags.
+    /// The file is read on the blocking thread pool, which stops at the next
+    /// block (or XML buffer) once the processor's cancellation token fires.
+    pub async fn process_osm_file(
+        &self,
+        file_path: &Path,
+    ) -> Result<(QualityMetrics, Vec<QualityIssue>)> {
+        let processor = self.clone();
+        let file_path = file_path.to_path_buf();
+        tokio::task::spawn_blocking(move || processor.process_osm_file_blocking(&file_path)).await?
+    }
+
+    fn process_osm_file_blocking(
+        &self,
+        file_path: &Path,
+    ) -> Result<(QualityMetrics, Vec<QualityIssue>)> {
+        info!("Processing OSM file: {:?}", file_path);
+
+        let options = self.processing_options.restricted_to_elements();
+        let elements = options.elements;
+        let mut collector =
+            MetricsCollector::new(self.duplicate_node_tolerance_m, options, &self.temp_dir)?;
+
+        match utils::detect_format(file_path) {
+            Some(DataFormat::OsmXml) => {
+                // OSM XML lists all nodes before ways, so polygons can be
+                // checked as soon as each way is read.
+                let input = std::io::BufReader::new(utils::CancellableRead {
+                    inner: crate::osm_xml::open_maybe_compressed(file_path)?,
+                    cancel: &self.cancel,
+                });
+                let result = crate::osm_xml::for_each_element(input, |_, element| match element {
+                    OsmElement::Node(node) if elements.nodes => {
+                        collector.edited(node.timestamp);
+                        collector.node(node.id, node.lat, node.lon, !node.tags.is_empty());
+                        collector.feature_tags(
+                            TaggedElement::Node {
+                                id: node.id,
+                                lat: node.lat,
+                                lon: node.lon,
+                            },
+                            node.tags
+                                .iter()
+                                .map(|(key, value)| (key.as_str(), value.as_str())),
+                        );
+                    }
+                    OsmElement::Way(way) if elements.ways => {
+                        collector.edited(way.timestamp);
+                        collector.way(way.id, !way.tags.is_empty(), way.refs.len());
+                        collector.feature_tags(
+                            TaggedElement::Way {
+                                id: way.id,
+                                first_node: way.refs.first().copied(),
+                            },
+                            way.tags
+                                .iter()
+                                .map(|(key, value)| (key.as_str(), value.as_str())),
+                        );
+                        let is_area = way
+                            .tags
+                            .iter()
+                            .any(|(key, _)| key == "building" || key == "landuse");
+                        if options.polygon_checks {
+                            collector.check_polygon(way.id, is_area, &way.refs);
+                        }
+                        if options.road_lengths {
+                            if let Some(highway) = utils::tag_value(&way.tags, "highway") {
+                                collector.road_length(highway, &way.refs);
+                            }
+                        }
+                    }
+                    OsmElement::Relation(relation) if elements.relations => {
+                        collector.edited(relation.timestamp);
+                        collector.relation(
+                            relation.id,
+                            &relation.tags,
+                            relation.members.iter().map(|member| {
+                                (member.member_type, member.id, member.role.as_str())
+                            }),
+                        )
+                    }
+                    _ => {}
+                });
+                // A cancelled read surfaces as an XML error
+                self.check_cancelled()?;
+                result?;
+            }
+            _ => {
+                let pool = Self::worker_pool(options.worker_threads())?;
+                self.collect_metrics_parallel(file_path, &pool, &mut collector)?;
+
+                // Second pass: validate closed building/landuse ways as
+                // polygons and measure roads
+                if options.polygon_checks || options.road_lengths {
+                    self.check_ways_parallel(file_path, &pool, &mut collector)?;
+                }
+            }
+        }
+
+        // Ages are measured from the extract's replication time when its header has one
+        let reference_time = match utils::detect_format(file_path) {
+            Some(DataFormat::OsmPbf) => Self::read_replication_timestamp(file_path),
+            _ => None,
+        };
+        let (metrics, issues) = collector.finish(reference_time)?;
+
+        info!(
+            "Processing complete. Nodes: {}, Ways: {}, Relations: {}",
+            count_text(metrics.total_nodes),
+            count_text(metrics.total_ways),
+            count_text(metrics.total_relations)
+        );
+
+        Ok((metrics, issues))
+    }
+
+    /// A pool of `threads` workers for processing PBF blocks
+    pub(crate) fn worker_pool(threads: usize) -> Result<rayon::ThreadPool> {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .map_err(|e| {
+                crate::RiemapError::OsmProcessing(format!("Failed to start worker threads: {}", e))
+                    .into()
+            })
+    }
+
+    /// Collect the metrics of a PBF file into `collector`, decoding and
+    /// counting blocks on `pool`
+    ///
+    /// Each block is counted into its own partial collector; the partials are
+    /// merged in file order, which is also when their nodes are indexed and
+    /// checked for duplicates, so the result matches a sequential pass. Only
+    /// tag frequencies that had to be trimmed (already approximate) may differ.
+    fn collect_metrics_parallel(
+        &self,
+        file_path: &Path,
+        pool: &rayon::ThreadPool,
+        collector: &mut MetricsCollector,
+    ) -> Result<()> {
+        let (duplicate_tolerance_m, options) = (collector.duplicate_tolerance_m, collector.options);
+
+        utils::for_each_block_parallel(
+            file_path,
+            pool,
+            &self.cancel,
+            |block| {
+                let mut partial = MetricsCollector::partial(duplicate_tolerance_m, options);
+                block
+                    .elements()
+                    .for_each(|element| partial.pbf_element(element));
+                partial
+            },
+            |partial| collector.merge(partial),
+        )
+    }
+
+    /// Run the polygon checks and road lengths over the ways of a PBF file on
+    /// `pool`, looking nodes up in the index filled by the first pass
+    fn check_ways_parallel(
+        &self,
+        file_path: &Path,
+        pool: &rayon::ThreadPool,
+        collector: &mut MetricsCollector,
+    ) -> Result<()> {
+        let options = collector.options;
+        let Some(node_coords) = collector.node_coords.as_mut() else {
+            return Ok(());
+        };
+        node_coords.seal();
+        let node_coords = &*node_coords;
+        let way_checks = &mut collector.way_checks;
+
+        utils::for_each_block_parallel(
+            file_path,
+            pool,
+            &self.cancel,
+            |block| {
+                let mut checks = WayChecks::default();
+                for way in block.groups().flat_map(|group| group.ways()) {
+                    let is_area = options.polygon_checks
+                        && way
+                            .tags()
+                            .any(|(key, _)| key == "building" || key == "landuse");
+                    let highway = way
+                        .tags()
+                        .find(|(key, _)| *key == "highway")
+                        .map(|(_, value)| value)
+                        .filter(|_| options.road_lengths);
+                    if !is_area && highway.is_none() {
+                        continue;
+                    }
+
+                    let refs: Vec<i64> = way.refs().collect();
+                    checks.check_polygon(node_coords, way.id(), is_area, &refs);
+                    if let Some(highway) = highway {
+                        checks.road_length(node_coords, highway, &refs);
+                    }
+                }
+                checks
+            },
+            |checks| way_checks.merge(checks),
+        )
+    }
+
+    /// Filter OSM data by bounding box, writing the result as a new PBF
+    ///
+    /// Keeps every node inside the box, every way with at least one such node
+    /// (complete, including its nodes outside the box) and every relation
+    /// with a kept node or way as a member. The replication metadata of the
+    /// input is carried over and the header extent is set to `bounds`.
+    /// When the input header declares an extent that `bounds` misses, an
+    /// empty extract is written without reading the data.
+    pub async fn filter_by_bounds(
+        &self,
+        input_path: &Path,
+        bounds: &BoundingBox,
+        output_path: &Path,
+    ) -> Result<()> {
+        info!("Filtering OSM data by bounds: {:?}", bounds);
+
+        let mut header = crate::pbf::read_header(input_path).unwrap_or_default();
+        if let Some(data_bbox) = header.bbox.replace(bounds.clone()) {
+            if !bounds.intersects(&data_bbox) {
+                info!(
+                    "Bounds miss the data extent {:?}; writing empty extract",
+                    data_bbox
+                );
+                return crate::pbf::write_pbf(output_path, &OsmData::default(), &header);
+            }
+        }
+
+        let bounds = bounds.clone();
+        Self::filter_pbf(input_path, header, output_path, move |lat, lon| {
+            bounds.contains(lat, lon)
+        })
+        .await
+    }
+
+    /// Filter OSM data by an Osmosis `.poly` polygon, writing the result as a new PBF
+    ///
+    /// Selects elements like `filter_by_bounds`, with nodes tested against the
+    /// polygon (outer rings minus holes) instead of a box. The header extent
+    /// is set to the polygon's bounding box.
+    pub async fn filter_by_polygon(
+        &self,
+        input_path: &Path,
+        poly_path: &Path,
+        output_path: &Path,
+    ) -> Result<()> {
+        let polygon = crate::poly::Polygon::read(poly_path)?;
+        info!(
+            "Filtering OSM data by polygon '{}' ({} rings)",
+            polygon.name,
+            polygon.rings.len()
+        );
+
+        let bounds = polygon.bounding_box();
+        let mut header = crate::pbf::read_header(input_path).unwrap_or_default();
+        if let Some(data_bbox) = header.bbox.replace(bounds.clone()) {
+            if !bounds.intersects(&data_bbox) {
+                info!(
+                    "Polygon misses the data extent {:?}; writing empty extract",
+                    data_bbox
+                );
+                return crate::pbf::write_pbf(output_path, &OsmData::default(), &header);
+            }
+        }
+
+        Self::filter_pbf(input_path, header, output_path, move |lat, lon| {
+            polygon.contains(lat, lon)
+        })
+        .await
+    }
+
+    /// Keep nodes passing `keep_node`, the ways using them and relations with a
+    /// kept member, and write them as a PBF with `header`
+    ///
+    /// The input is streamed twice on the blocking thread pool: once to pick
+    /// the IDs to keep, relying on the usual nodes-ways-relations order, and
+    /// once to copy those elements. Only the extract is held in memory.
+    async fn filter_pbf(
+        input_path: &Path,
+        header: crate::pbf::PbfHeader,
+        output_path: &Path,
+        keep_node: impl Fn(f64, f64) -> bool + Send + 'static,
+    ) -> Result<()> {
+        let (input_path, output_path) = (input_path.to_path_buf(), output_path.to_path_buf());
+        tokio::task::spawn_blocking(move || {
+            Self::filter_pbf_blocking(&input_path, &header, &output_path, keep_node)
+        })
+        .await?
+    }
+
+    fn filter_pbf_blocking(
+        input_path: &Path,
+        header: &crate::pbf::PbfHeader,
+        output_path: &Path,
+        keep_node: impl Fn(f64, f64) -> bool,
+    ) -> Result<()> {
+        let mut in_bounds = HashSet::new();
+        let mut way_nodes = HashSet::new();
+        let mut ways = HashSet::new();
+        let mut relations = HashSet::new();
+
+        ElementReader::from_path(input_path)?.for_each(|element| match element {
+            Element::Node(node) => {
+                if keep_node(node.lat(), node.lon()) {
+                    in_bounds.insert(node.id());
+                }
+            }
+            Element::DenseNode(node) => {
+                if keep_node(node.lat(), node.lon()) {
+                    in_bounds.insert(node.id());
+                }
+            }
+            Element::Way(way) => {
+                if way.refs().any(|id| in_bounds.contains(&id)) {
+                    ways.insert(way.id());
+                    way_nodes.extend(way.refs());
+                }
+            }
+            Element::Relation(relation) => {
+                let has_kept_member = relation.members().any(|member| {
+                    let id = member.member_id;
+                    match MemberType::from(member.member_type.clone()) {
+                        MemberType::Node => in_bounds.contains(&id) || way_nodes.contains(&id),
+                        MemberType::Way => ways.contains(&id),
+                        MemberType::Relation => false,
+                    }
+                });
+                if has_kept_member {
+                    relations.insert(relation.id());
+                }
+            }
+        })?;
+
+        let mut filtered = OsmData::default();
+        ElementReader::from_path(input_path)?.for_each(|element| {
+            let keep = match &element {
+                Element::Node(node) => {
+                    in_bounds.contains(&node.id()) || way_nodes.contains(&node.id())
+                }
+                Element::DenseNode(node) => {
+                    in_bounds.contains(&node.id()) || way_nodes.contains(&node.id())
+                }
+                Element::Way(way) => ways.contains(&way.id()),
+                Element::Relation(relation) => relations.contains(&relation.id()),
+            };
+            if keep {
+                filtered.insert(crate::pbf::owned_element(element));
+            }
+        })?;
+
+        crate::pbf::write_pbf(output_path, &filtered, header)?;
+
+        info!(
+            "Filtered to {} nodes, {} ways, {} relations",
+            filtered.nodes.len(),
+            filtered.ways.len(),
+            filtered.relations.len()
+        );
+        Ok(())
+    }
+
+    /// Merge several PBF extracts (e.g. adjacent subregions) into one PBF
+    ///
+    /// Elements are deduplicated by type and ID, so nodes and ways along
+    /// shared borders appear once. When inputs hold different versions of
+    /// the same element the highest version wins; equal versions keep the
+    /// copy from the earliest input. The header extent is the union of the
+    /// inputs' extents when all of them declare one (and none crosses the
+    /// antimeridian). The replication timestamp is the oldest of the inputs,
+    /// as the merged data is only as current as its oldest part; sequence
+    /// number and base URL are dropped, since no single replication feed can
+    /// update the result.
+    pub async fn merge_files(&self, inputs: &[PathBuf], output_path: &Path) -> Result<()> {
+        if inputs.is_empty() {
+            return Err(
+                crate::RiemapError::OsmProcessing("No input files to merge".to_string()).into(),
+            );
+        }
+        info!("Merging {} files into {:?}", inputs.len(), output_path);
+
+        let mut merged = OsmData::default();
+        let mut header = crate::pbf::PbfHeader::default();
+        let mut extents = Vec::new();
+
+        for input in inputs {
+            let input_header = crate::pbf::read_header(input).unwrap_or_default();
+            extents.push(input_header.bbox);
+            header.replication_timestamp = match (
+                header.replication_timestamp,
+                input_header.replication_timestamp,
+            ) {
+                (Some(oldest), Some(timestamp)) => Some(oldest.min(timestamp)),
+                (oldest, timestamp) => oldest.or(timestamp),
+            };
+
+            let (mut read, mut replaced) = (0u64, 0u64);
+            ElementReader::from_path(input)?.for_each(|element| {
+                let element = crate::pbf::owned_element(element);
+                read += 1;
+                match merged.existing_version(&element) {
+                    Some(version) if version >= element.version() => {}
+                    existing => {
+                        replaced += existing.is_some() as u64;
+                        merged.insert(element);
+                    }
+                }
+            })?;
+            info!(
+                "Read {} elements from {:?} ({} replaced an older version)",
+                read, input, replaced
+            );
+        }
+
+        let extents: Option<Vec<BoundingBox>> = extents.into_iter().collect();
+        header.bbox = extents
+            .filter(|extents| extents.iter().all(|bbox| !bbox.wraps_antimeridian()))
+            .and_then(|extents| {
+                extents.into_iter().reduce(|a, b| {
+                    BoundingBox::new(
+                        a.min_lat.min(b.min_lat),
+                        a.min_lon.min(b.min_lon),
+                        a.max_lat.max(b.max_lat),
+                        a.max_lon.max(b.max_lon),
+                    )
+                })
+            });
+
+        crate::pbf::write_pbf(output_path, &merged, &header)?;
+
+        info!(
+            "Merged into {} nodes, {} ways, {} relations",
+            merged.nodes.len(),
+            merged.ways.len(),
+            merged.relations.len()
+        );
+        Ok(())
+    }
+
+    /// Validate OSM data file integrity
+    ///
+    /// Reading stops at the first data block that brings the element count to
+    /// `max_elements`; `None` reads and decodes the whole file.
+    pub async fn validate_file(
+        &self,
+        file_path: &Path,
+        max_elements: Option<usize>,
+    ) -> Result<Vec<QualityIssue>> {
+        info!("Validating OSM file: {:?}", file_path);
+
+        let mut issues = Vec::new();
+
+        // Check if file exists and is readable
+        if !file_path.exists() {
+            issues.push(QualityIssue {
+                issue_type: "file_missing".to_string(),
+                severity: IssueSeverity::Critical,
+                description: "OSM data file does not exist".to_string(),
+                location: None,
+                osm_id: None,
+                osm_type: None,
+                fix_suggestion: Some(
+                    "Ensure the OSM data file has been downloaded properly".to_string(),
+                ),
+            });
+            return Ok(issues);
+        }
+
+        // Check file size
+        let metadata = fs::metadata(file_path)?;
+        if metadata.len() == 0 {
+            issues.push(QualityIssue {
+                issue_type: "empty_file".to_string(),
+                severity: IssueSeverity::Critical,
+                description: "OSM data file is empty".to_string(),
+                location: None,
+                osm_id: None,
+                osm_type: None,
+                fix_suggestion: Some("Re-download the OSM data file".to_string()),
+            });
+        }
+
+        // Read up to the element limit with the reader matching the format
+        let counted = match utils::detect_format(file_path) {
+            Some(DataFormat::OsmXml) => utils::count_xml_elements(file_path, max_elements),
+            _ => match BlobReader::from_path(file_path) {
+                Ok(reader) => utils::count_elements(reader, max_elements).map_err(Into::into),
+                Err(e) => {
+                    issues.push(QualityIssue {
+                        issue_type: "invalid_format".to_string(),
+                        severity: IssueSeverity::Critical,
+                        description: format!("Cannot read OSM PBF file: {}", e),
+                        location: None,
+                        osm_id: None,
+                        osm_type: None,
+                        fix_suggestion: Some(
+                            "Ensure the file is a valid OSM PBF format and re-download if corrupted"
+                                .to_string(),
+                        ),
+                    });
+                    return Ok(issues);
+                }
+            },
+        };
+
+        match counted {
+            Ok((element_count, true)) => issues.push(QualityIssue {
+                issue_type: "validation_truncated".to_string(),
+                severity: IssueSeverity::Low,
+                description: format!(
+                    "Validation stopped after {} elements; the rest of the file was not checked",
+                    element_count
+                ),
+                location: None,
+                osm_id: None,
+                osm_type: None,
+                fix_suggestion: Some(
+                    "Validate without an element limit to check the whole file".to_string(),
+                ),
+            }),
+            Ok(_) => {}
+            Err(e) => issues.push(QualityIssue {
+                issue_type: "parsing_error".to_string(),
+                severity: IssueSeverity::High,
+                description: format!("Error parsing OSM data: {}", e),
+                location: None,
+                osm_id: None,
+                osm_type: None,
+                fix_suggestion: Some(
+                    "Check if the OSM file is corrupted and re-download if necessary".to_string(),
+                ),
+            }),
+        }
+
+        Ok(issues)
+    }
+}
+
+/// Tags exported as shapefile attribute columns (after `osm_id`)
+const SHAPEFILE_TAGS: &[&str] = &["name", "highway", "building", "amenity"];
+
+/// Per-feature completeness checks: (metric name, feature key, expected attribute key)
+const FEATURE_COMPLETENESS_RULES: &[(&str, &str, &str)] = &[
+    ("highway_name", "highway", "name"),
+    ("building_housenumber", "building", "addr:housenumber"),
+    ("amenity_opening_hours", "amenity", "opening_hours"),
+];
+
+/// Amenity values audited for missing name, opening hours and address tags,
+/// with whether opening hours are expected for that kind of amenity
+pub const AUDITED_AMENITIES: &[(&str, bool)] = &[
+    ("restaurant", true),
+    ("cafe", true),
+    ("fast_food", true),
+    ("bar", true),
+    ("pub", true),
+    ("ice_cream", true),
+    ("pharmacy", true),
+    ("bank", true),
+    ("post_office", true),
+    ("fuel", true),
+    ("library", true),
+    ("doctors", true),
+    ("dentist", true),
+    ("clinic", true),
+    ("veterinary", true),
+    ("cinema", true),
+    ("theatre", true),
+    ("marketplace", true),
+    ("car_rental", true),
+    ("hospital", false),
+    ("school", false),
+    ("kindergarten", false),
+    ("university", false),
+    ("police", false),
+    ("townhall", false),
+    ("place_of_worship", false),
+];
+
+const MS_PER_DAY: i64 = 86_400_000;
+
+/// Rings with more nodes than this are reported rather than checked for
+/// self-intersections; it is the OSM API's limit on the nodes of a way
+const MAX_SELF_INTERSECTION_VERTICES: usize = 2_000;
+
+/// Elements last edited longer ago than this count as stale
+const STALE_AFTER_DAYS: i64 = 365;
+
+/// Number of most frequent keys and key=value pairs kept in a report
+const TAG_FREQUENCY_TOP_N: usize = 50;
+
+/// Distinct keys or pairs tracked before the least frequent half is dropped
+const TAG_FREQUENCY_CAPACITY: usize = 10_000;
+
+/// Keys (or key namespaces, before the first `:`) with free-form values, whose values are not counted
+const FREE_TEXT_KEYS: &[&str] = &[
+    "name",
+    "addr",
+    "ref",
+    "note",
+    "description",
+    "fixme",
+    "source",
+    "website",
+    "contact",
+    "phone",
+    "email",
+    "wikidata",
+    "wikipedia",
+    "opening_hours",
+    "check_date",
+];
+
+/// Bounded counts of tag keys and key=value pairs
+///
+/// When a table grows past twice the capacity it is trimmed back to the
+/// most frequent entries, so counts of rare entries are approximate on huge
+/// extracts while frequent ones stay exact.
+#[derive(Default)]
+struct TagFrequency {
+    keys: HashMap<String, u64>,
+    /// Keyed on `key=value`
+    pairs: HashMap<String, u64>,
+    trimmed: bool,
+    /// Reused buffer for building `key=value` lookups
+    pair: String,
+}
+
+impl TagFrequency {
+    fn add(&mut self, tags: &[(&str, &str)]) {
+        for (key, value) in tags {
+            Self::count(&mut self.keys, key, &mut self.trimmed);
+
+            let namespace = key.split(':').next().unwrap_or(key);
+            if FREE_TEXT_KEYS.contains(&namespace) {
+                continue;
+            }
+
+            self.pair.clear();
+            self.pair.push_str(key);
+            self.pair.push('=');
+            self.pair.push_str(value);
+            Self::count(&mut self.pairs, &self.pair, &mut self.trimmed);
+        }
+    }
+
+    /// Add the counts of another table, trimming the result as `add` would
+    fn merge(&mut self, other: TagFrequency) {
+        self.trimmed |= other.trimmed;
+        for (table, theirs) in [(&mut self.keys, other.keys), (&mut self.pairs, other.pairs)] {
+            for (entry, count) in theirs {
+                *table.entry(entry).or_insert(0) += count;
+            }
+            if table.len() > TAG_FREQUENCY_CAPACITY * 2 {
+                Self::trim(table, TAG_FREQUENCY_CAPACITY);
+                self.trimmed = true;
+            }
+        }
+    }
+
+    fn count(table: &mut HashMap<String, u64>, entry: &str, trimmed: &mut bool) {
+        match table.get_mut(entry) {
+            Some(count) => *count += 1,
+            None => {
+                if table.len() >= TAG_FREQUENCY_CAPACITY * 2 {
+                    Self::trim(table, TAG_FREQUENCY_CAPACITY);
+                    *trimmed = true;
+                }
+                table.insert(entry.to_string(), 1);
+            }
+        }
+    }
+
+    /// Keep only the `n` most frequent entries
+    fn trim(table: &mut HashMap<String, u64>, n: usize) {
+        let mut counts: Vec<u64> = table.values().copied().collect();
+        if counts.len() <= n {
+            return;
+        }
+        counts.select_nth_unstable_by(n - 1, |a, b| b.cmp(a));
+        let threshold = counts[n - 1];
+        table.retain(|_, count| *count > threshold);
+    }
+
+    fn top(table: &HashMap<String, u64>, n: usize) -> Vec<(&str, u64)> {
+        let mut entries: Vec<(&str, u64)> = table
+            .iter()
+            .map(|(entry, count)| (entry.as_str(), *count))
+            .collect();
+        entries.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        entries.truncate(n);
+        entries
+    }
+
+    /// Summary stored under the `tag_frequency` custom metric
+    fn summary(&self) -> serde_json::Value {
+        let keys: Vec<_> = Self::top(&self.keys, TAG_FREQUENCY_TOP_N)
+            .into_iter()
+            .map(|(key, count)| serde_json::json!({ "key": key, "count": count }))
+            .collect();
+        let tags: Vec<_> = Self::top(&self.pairs, TAG_FREQUENCY_TOP_N)
+            .into_iter()
+            .map(|(pair, count)| {
+                let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+                serde_json::json!({ "key": key, "value": value, "count": count })
+            })
+            .collect();
+
+        serde_json::json!({
+            "keys": keys,
+            "tags": tags,
+            "approximate": self.trimmed
+        })
+    }
+}
+
+/// Tagged nodes (ID, lat, lon) bucketed into square cells as wide as the duplicate tolerance
+type NodeGrid = HashMap<(i64, i64), Vec<(i64, f64, f64)>>;
+
+/// A tagged node or way as seen by the reading pass
+#[derive(Debug, Clone, Copy)]
+enum TaggedElement {
+    Node {
+        id: i64,
+        lat: f64,
+        lon: f64,
+    },
+    /// Placed at its first node once the node index can be queried
+    Way {
+        id: i64,
+        first_node: Option<i64>,
+    },
+}
+
+/// An element count starting at zero for types being read, unset otherwise
+fn counted(read: bool) -> Option<u64> {
+    read.then_some(0)
+}
+
+/// Add to an element count, leaving it unset for skipped types
+fn add_count(count: &mut Option<u64>, other: Option<u64>) {
+    if let (Some(count), Some(other)) = (count, other) {
+        *count += other;
+    }
+}
+
+fn increment(count: &mut Option<u64>) {
+    add_count(count, Some(1));
+}
+
+/// Accumulates quality metrics and polygon checks independently of the input format
+struct MetricsCollector {
+    metrics: QualityMetrics,
+    issues: Vec<QualityIssue>,
+    /// Node coordinates (lat, lon) so closed ways can be resolved to polygons
+    /// and node members checked; only kept when a check needs them
+    node_coords: Option<NodeIndex>,
+    way_ids: HashSet<i64>,
+    relation_ids: HashSet<i64>,
+    /// (relation ID, member type, member ID), resolved once all elements are read
+    member_refs: Vec<(i64, MemberType, i64)>,
+    /// (features seen, features with the expected attribute) per completeness rule
+    feature_counts: [(u64, u64); FEATURE_COMPLETENESS_RULES.len()],
+    tagged_node_grid: NodeGrid,
+    duplicate_tolerance_m: f64,
+    tag_frequency: TagFrequency,
+    /// Element edit counts per day since the Unix epoch, for freshness percentiles
+    edits_by_day: BTreeMap<i64, u64>,
+    newest_edit_ms: Option<i64>,
+    /// (amenity value, missing tag, first node) of way examples still to be located
+    amenity_way_examples: Vec<(String, &'static str, i64)>,
+    building_3d: Building3dCoverage,
+    /// Relations per `type=*` value
+    relation_types: BTreeMap<String, u64>,
+    /// Polygon issues and road lengths, kept apart until `finish`
+    way_checks: WayChecks,
+    /// (ID, lat, lon, tagged) of nodes a partial collector leaves for the
+    /// node index and duplicate check, applied when it is merged
+    deferred_nodes: Option<Vec<(i64, f64, f64, bool)>>,
+    options: ProcessingOptions,
+}
+
+/// Polygon issues and road lengths of a run of ways
+///
+/// The checks only read the node index, so blocks of ways can be checked in
+/// parallel and their results merged.
+#[derive(Default)]
+struct WayChecks {
+    issues: Vec<QualityIssue>,
+    geometry_errors: u64,
+    /// Meters of road per `highway=*` value
+    road_m: BTreeMap<String, f64>,
+    /// Road segments skipped because a node is missing from the extract
+    incomplete_road_segments: u64,
+}
+
+impl MetricsCollector {
+    fn new(
+        duplicate_tolerance_m: f64,
+        options: ProcessingOptions,
+        temp_dir: &Path,
+    ) -> Result<Self> {
+        let node_coords = if options.needs_node_index() {
+            Some(NodeIndex::new(options.node_index, temp_dir)?)
+        } else {
+            None
+        };
+
+        Ok(Self::with_node_index(
+            duplicate_tolerance_m,
+            options,
+            node_coords,
+        ))
+    }
+
+    fn with_node_index(
+        duplicate_tolerance_m: f64,
+        options: ProcessingOptions,
+        node_coords: Option<NodeIndex>,
+    ) -> Self {
+        Self {
+            metrics: QualityMetrics {
+                total_nodes: counted(options.elements.nodes),
+                total_ways: counted(options.elements.ways),
+                total_relations: counted(options.elements.relations),
+                tagged_nodes: counted(options.elements.nodes),
+                tagged_ways: counted(options.elements.ways),
+                tagged_relations: counted(options.elements.relations),
+                completeness_score: 0.0,
+                geometry_errors: 0,
+                tag_errors: 0,
+                topology_errors: 0,
+                feature_distribution: FeatureDistribution::default(),
+                custom_metrics: std::collections::HashMap::new(),
+                completeness_by_feature: HashMap::new(),
+                duplicate_nodes: 0,
+                freshness: None,
+                amenity_audit: HashMap::new(),
+            },
+            issues: Vec::new(),
+            node_coords,
+            way_ids: HashSet::new(),
+            relation_ids: HashSet::new(),
+            member_refs: Vec::new(),
+            feature_counts: [(0, 0); FEATURE_COMPLETENESS_RULES.len()],
+            tagged_node_grid: HashMap::new(),
+            duplicate_tolerance_m,
+            tag_frequency: TagFrequency::default(),
+            edits_by_day: BTreeMap::new(),
+            newest_edit_ms: None,
+            amenity_way_examples: Vec::new(),
+            building_3d: Building3dCoverage::default(),
+            relation_types: BTreeMap::new(),
+            way_checks: WayChecks::default(),
+            deferred_nodes: None,
+            options,
+        }
+    }
+
+    /// An empty collector for one block of a parallel pass, leaving the node
+    /// index and duplicate check to the collector it is merged into
+    fn partial(duplicate_tolerance_m: f64, options: ProcessingOptions) -> Self {
+        let mut partial = Self::with_node_index(duplicate_tolerance_m, options, None);
+        if options.needs_node_index() || options.duplicate_node_checks {
+            partial.deferred_nodes = Some(Vec::new());
+        }
+        partial
+    }
+
+    /// Count one element read from a PBF file
+    fn pbf_element(&mut self, element: Element) {
+        let elements = self.options.elements;
+        let wanted = match &element {
+            Element::Node(_) | Element::DenseNode(_) => elements.nodes,
+            Element::Way(_) => elements.ways,
+            Element::Relation(_) => elements.relations,
+        };
+        if !wanted {
+            return;
+        }
+
+        match element {
+            Element::Node(node) => {
+                self.edited(node.info().milli_timestamp().unwrap_or(0));
+                self.node(node.id(), node.lat(), node.lon(), node.tags().count() > 0);
+                self.feature_tags(
+                    TaggedElement::Node {
+                        id: node.id(),
+                        lat: node.lat(),
+                        lon: node.lon(),
+                    },
+                    node.tags(),
+                );
+            }
+            Element::DenseNode(node) => {
+                self.edited(node.info().map_or(0, |info| info.milli_timestamp()));
+                self.node(node.id(), node.lat(), node.lon(), node.tags().count() > 0);
+                self.feature_tags(
+                    TaggedElement::Node {
+                        id: node.id(),
+                        lat: node.lat(),
+                        lon: node.lon(),
+                    },
+                    node.tags(),
+                );
+            }
+            Element::Way(way) => {
+                self.edited(way.info().milli_timestamp().unwrap_or(0));
+                self.way(way.id(), way.tags().count() > 0, way.refs().count());
+                self.feature_tags(
+                    TaggedElement::Way {
+                        id: way.id(),
+                        first_node: way.refs().next(),
+                    },
+                    way.tags(),
+                );
+            }
+            Element::Relation(relation) => {
+                self.edited(relation.info().milli_timestamp().unwrap_or(0));
+                let tags: Vec<(String, String)> = relation
+                    .tags()
+                    .map(|(key, value)| (key.to_string(), value.to_string()))
+                    .collect();
+                self.relation(
+                    relation.id(),
+                    &tags,
+                    relation.members().map(|member| {
+                        (
+                            member.member_type.clone().into(),
+                            member.member_id,
+                            member.role().unwrap_or_default(),
+                        )
+                    }),
+                )
+            }
+        }
+    }
+
+    /// Add the counts of a collector that read the next part of the same file
+    ///
+    /// The nodes `other` deferred are indexed and checked for duplicates
+    /// here, so collectors must be merged in file order.
+    fn merge(&mut self, mut other: MetricsCollector) {
+        for (id, lat, lon, tagged) in other.deferred_nodes.take().into_iter().flatten() {
+            self.index_node(id, lat, lon, tagged);
+        }
+
+        let (metrics, theirs) = (&mut self.metrics, other.metrics);
+        add_count(&mut metrics.total_nodes, theirs.total_nodes);
+        add_count(&mut metrics.total_ways, theirs.total_ways);
+        add_count(&mut metrics.total_relations, theirs.total_relations);
+        add_count(&mut metrics.tagged_nodes, theirs.tagged_nodes);
+        add_count(&mut metrics.tagged_ways, theirs.tagged_ways);
+        add_count(&mut metrics.tagged_relations, theirs.tagged_relations);
+        metrics.geometry_errors += theirs.geometry_errors;
+        metrics.tag_errors += theirs.tag_errors;
+        metrics.topology_errors += theirs.topology_errors;
+        metrics.duplicate_nodes += theirs.duplicate_nodes;
+
+        let (distribution, other_distribution) = (
+            &mut metrics.feature_distribution,
+            theirs.feature_distribution,
+        );
+        distribution.highways += other_distribution.highways;
+        distribution.buildings += other_distribution.buildings;
+        distribution.natural_features += other_distribution.natural_features;
+        distribution.amenities += other_distribution.amenities;
+        distribution.water_features += other_distribution.water_features;
+        distribution.boundaries += other_distribution.boundaries;
+        distribution.coastlines += other_distribution.coastlines;
+
+        for (category, audit) in theirs.amenity_audit {
+            let ours = metrics.amenity_audit.entry(category).or_default();
+            ours.total += audit.total;
+            ours.missing_name += audit.missing_name;
+            ours.missing_opening_hours += audit.missing_opening_hours;
+            ours.missing_address += audit.missing_address;
+
+            // Keep the example a sequential pass would have met first: nodes
+            // before ways, lowest ID first
+            for (tag, example) in audit.examples {
+                match ours.examples.entry(tag) {
+                    std::collections::hash_map::Entry::Vacant(entry) => {
+                        entry.insert(example);
+                    }
+                    std::collections::hash_map::Entry::Occupied(mut entry) => {
+                        let current = entry.get();
+                        if (example.osm_type.as_str(), example.osm_id)
+                            < (current.osm_type.as_str(), current.osm_id)
+                        {
+                            entry.insert(example);
+                        }
+                    }
+                }
+            }
+        }
+
+        for (counts, (total, complete)) in self.feature_counts.iter_mut().zip(other.feature_counts)
+        {
+            counts.0 += total;
+            counts.1 += complete;
+        }
+
+        for (day, count) in other.edits_by_day {
+            *self.edits_by_day.entry(day).or_insert(0) += count;
+        }
+        self.newest_edit_ms = self.newest_edit_ms.max(other.newest_edit_ms);
+
+        let (building_3d, theirs) = (&mut self.building_3d, other.building_3d);
+        building_3d.total += theirs.total;
+        building_3d.with_height += theirs.with_height;
+        building_3d.with_levels += theirs.with_levels;
+        building_3d.with_height_or_levels += theirs.with_height_or_levels;
+
+        for (relation_type, count) in other.relation_types {
+            *self.relation_types.entry(relation_type).or_insert(0) += count;
+        }
+
+        self.tag_frequency.merge(other.tag_frequency);
+        self.issues.extend(other.issues);
+        self.amenity_way_examples.extend(other.amenity_way_examples);
+        self.way_ids.extend(other.way_ids);
+        self.relation_ids.extend(other.relation_ids);
+        self.member_refs.extend(other.member_refs);
+        self.way_checks.merge(other.way_checks);
+    }
+
+    /// Record an element's last edit time; 0 (no metadata in the file) is skipped
+    fn edited(&mut self, timestamp_ms: i64) {
+        if timestamp_ms <= 0 {
+            return;
+        }
+        *self
+            .edits_by_day
+            .entry(timestamp_ms.div_euclid(MS_PER_DAY))
+            .or_insert(0) += 1;
+        self.newest_edit_ms = self.newest_edit_ms.max(Some(timestamp_ms));
+    }
+
+    /// Edit age percentiles relative to `reference_time`, or to the newest edit
+    fn freshness(&self, reference_time: Option<DateTime<Utc>>) -> Option<FreshnessMetrics> {
+        let reference_ms = reference_time
+            .map(|time| time.timestamp_millis())
+            .or(self.newest_edit_ms)?;
+        let reference_time = DateTime::from_timestamp_millis(reference_ms)?;
+        let reference_day = reference_ms.div_euclid(MS_PER_DAY);
+
+        let total: u64 = self.edits_by_day.values().sum();
+        let age_at = |share: f64| {
+            // Walk from the newest edits back until `share` of elements are covered
+            let target = (total as f64 * share).ceil().max(1.0) as u64;
+            let mut seen = 0;
+            for (day, count) in self.edits_by_day.iter().rev() {
+                seen += count;
+                if seen >= target {
+                    return (reference_day - day).max(0) as f64;
+                }
+            }
+            0.0
+        };
+
+        let stale: u64 = self
+            .edits_by_day
+            .range(..reference_day - STALE_AFTER_DAYS)
+            .map(|(_, count)| count)
+            .sum();
+
+        Some(FreshnessMetrics {
+            reference_time,
+            elements_with_timestamp: total,
+            median_age_days: age_at(0.5),
+            p90_age_days: age_at(0.9),
+            stale_fraction: stale as f64 / total as f64,
+        })
+    }
+
+    fn node(&mut self, id: i64, lat: f64, lon: f64, tagged: bool) {
+        increment(&mut self.metrics.total_nodes);
+        if tagged {
+            increment(&mut self.metrics.tagged_nodes);
+        }
+        match &mut self.deferred_nodes {
+            Some(deferred) => {
+                if tagged || self.options.needs_node_index() {
+                    deferred.push((id, lat, lon, tagged));
+                }
+            }
+            None => self.index_node(id, lat, lon, tagged),
+        }
+        // Basic validation
+        if lat.abs() > 90.0 || lon.abs() > 180.0 {
+            self.metrics.geometry_errors += 1;
+        }
+    }
+
+    /// Add a node to the node index and check it for duplicates, as the
+    /// enabled checks need
+    fn index_node(&mut self, id: i64, lat: f64, lon: f64, tagged: bool) {
+        if let Some(node_coords) = &mut self.node_coords {
+            node_coords.insert(id, lat, lon);
+        }
+        if tagged && self.options.duplicate_node_checks {
+            self.check_duplicate_node(id, lat, lon);
+        }
+    }
+
+    /// Count a node's or way's feature classes and tags, and features with
+    /// their expected attributes for per-feature completeness
+    fn feature_tags<'a>(
+        &mut self,
+        element: TaggedElement,
+        tags: impl Iterator<Item = (&'a str, &'a str)>,
+    ) {
+        let tags: Vec<(&str, &str)> = tags.collect();
+        if tags.is_empty() {
+            return;
+        }
+
+        self.classify_feature(&tags);
+        self.audit_amenity(element, &tags);
+        self.tag_frequency.add(&tags);
+        let keys: Vec<&str> = tags.iter().map(|(key, _)| *key).collect();
+
+        for (counts, (_, feature_key, attribute_key)) in self
+            .feature_counts
+            .iter_mut()
+            .zip(FEATURE_COMPLETENESS_RULES)
+        {
+            if keys.contains(feature_key) {
+                counts.0 += 1;
+                if keys.contains(attribute_key) {
+                    counts.1 += 1;
+                }
+            }
+        }
+    }
+
+    /// Count audited amenities lacking a name, opening hours or any address tag
+    fn audit_amenity(&mut self, element: TaggedElement, tags: &[(&str, &str)]) {
+        let Some(category) = tags
+            .iter()
+            .find(|(key, _)| *key == "amenity")
+            .map(|(_, value)| *value)
+            .filter(|value| {
+                AUDITED_AMENITIES
+                    .iter()
+                    .any(|(audited, _)| audited == value)
+            })
+        else {
+            return;
+        };
+
+        let has_key = |key: &str| tags.iter().any(|(k, _)| *k == key);
+        let missing = [
+            ("name", !has_key("name")),
+            ("opening_hours", !has_key("opening_hours")),
+            (
+                "address",
+                !tags.iter().any(|(key, _)| key.starts_with("addr:")),
+            ),
+        ];
+
+        let audit = self
+            .metrics
+            .amenity_audit
+            .entry(category.to_string())
+            .or_default();
+        audit.total += 1;
+
+        for (tag, is_missing) in missing {
+            if !is_missing {
+                continue;
+            }
+            match tag {
+                "name" => audit.missing_name += 1,
+                "opening_hours" => audit.missing_opening_hours += 1,
+                _ => audit.missing_address += 1,
+            }
+
+            if audit.examples.contains_key(tag) {
+                continue;
+            }
+            let example = match element {
+                TaggedElement::Node { id, lat, lon } => ElementSample {
+                    osm_type: "node".to_string(),
+                    osm_id: id,
+                    location: Some((lat, lon)),
+                },
+                TaggedElement::Way { id, first_node } => {
+                    if let Some(first_node) = first_node {
+                        self.amenity_way_examples
+                            .push((category.to_string(), tag, first_node));
+                    }
+                    ElementSample {
+                        osm_type: "way".to_string(),
+                        osm_id: id,
+                        location: None,
+                    }
+                }
+            };
+            audit.examples.insert(tag.to_string(), example);
+        }
+    }
+
+    /// Add a tagged element to every feature class of the distribution it belongs to
+    fn classify_feature(&mut self, tags: &[(&str, &str)]) {
+        let distribution = &mut self.metrics.feature_distribution;
+        let value = |key: &str| tags.iter().find(|(k, _)| *k == key).map(|(_, v)| *v);
+
+        if value("highway").is_some() {
+            distribution.highways += 1;
+        }
+        if value("building").is_some_and(|building| building != "no") {
+            distribution.buildings += 1;
+
+            let (height, levels) = (
+                value("height").is_some(),
+                value("building:levels").is_some(),
+            );
+            let building_3d = &mut self.building_3d;
+            building_3d.total += 1;
+            building_3d.with_height += height as u64;
+            building_3d.with_levels += levels as u64;
+            building_3d.with_height_or_levels += (height || levels) as u64;
+        }
+        if value("amenity").is_some() {
+            distribution.amenities += 1;
+        }
+        if value("boundary").is_some() {
+            distribution.boundaries += 1;
+        }
+
+        let natural = value("natural");
+        if value("waterway").is_some() || value("water").is_some() || natural == Some("water") {
+            distribution.water_features += 1;
+        } else if natural == Some("coastline") {
+            distribution.coastlines += 1;
+        } else if natural.is_some() {
+            distribution.natural_features += 1;
+        }
+    }
+
+    fn way(&mut self, id: i64, tagged: bool, ref_count: usize) {
+        increment(&mut self.metrics.total_ways);
+        if self.options.member_checks {
+            self.way_ids.insert(id);
+        }
+        if tagged {
+            increment(&mut self.metrics.tagged_ways);
+        }
+        // Check for valid way structure
+        if ref_count < 2 {
+            self.metrics.topology_errors += 1;
+        }
+    }
+
+    fn relation<'a>(
+        &mut self,
+        id: i64,
+        tags: &[(String, String)],
+        members: impl Iterator<Item = (MemberType, i64, &'a str)>,
+    ) {
+        increment(&mut self.metrics.total_relations);
+        if self.options.member_checks {
+            self.relation_ids.insert(id);
+        }
+        let relation_type = tags
+            .iter()
+            .find(|(key, value)| key == "type" && !value.is_empty())
+            .map_or("untyped", |(_, value)| value.as_str());
+        *self
+            .relation_types
+            .entry(relation_type.to_string())
+            .or_insert(0) += 1;
+        if !tags.is_empty() {
+            increment(&mut self.metrics.tagged_relations);
+            let tags: Vec<(&str, &str)> = tags
+                .iter()
+                .map(|(key, value)| (key.as_str(), value.as_str()))
+                .collect();
+            self.classify_feature(&tags);
+            self.tag_frequency.add(&tags);
+        }
+
+        let mut has_outer = false;
+        for (member_type, member_id, role) in members {
+            has_outer |= role == "outer";
+            if self.options.member_checks {
+                self.member_refs.push((id, member_type, member_id));
+            }
+        }
+
+        let is_multipolygon = tags
+            .iter()
+            .any(|(key, value)| key == "type" && value == "multipolygon");
+        if is_multipolygon && !has_outer {
+            self.metrics.topology_errors += 1;
+            self.issues.push(QualityIssue {
+                issue_type: "multipolygon_without_outer".to_string(),
+                severity: IssueSeverity::Medium,
+                description: format!("Multipolygon relation {} has no outer members", id),
+                location: None,
+                osm_id: Some(id),
+                osm_type: Some("relation".to_string()),
+                fix_suggestion: Some(
+                    "Add the enclosing ways as members with the `outer` role".to_string(),
+                ),
+            });
+        }
+    }
+
+    /// Flag relations whose members are missing from the file
+    fn resolve_members(&mut self) {
+        let Some(node_coords) = &mut self.node_coords else {
+            return;
+        };
+
+        let mut missing: BTreeMap<i64, usize> = BTreeMap::new();
+        for (relation_id, member_type, member_id) in &self.member_refs {
+            let exists = match member_type {
+                MemberType::Node => node_coords.contains(*member_id),
+                MemberType::Way => self.way_ids.contains(member_id),
+                MemberType::Relation => self.relation_ids.contains(member_id),
+            };
+            if !exists {
+                *missing.entry(*relation_id).or_insert(0) += 1;
+            }
+        }
+
+        for (relation_id, count) in missing {
+            self.metrics.topology_errors += 1;
+            self.issues.push(QualityIssue {
+                issue_type: "broken_relation".to_string(),
+                severity: IssueSeverity::Medium,
+                description: format!(
+                    "Relation {} references {} missing member(s)",
+                    relation_id, count
+                ),
+                location: None,
+                osm_id: Some(relation_id),
+                osm_type: Some("relation".to_string()),
+                fix_suggestion: Some(
+                    "Remove members that no longer exist or restore the referenced elements"
+                        .to_string(),
+                ),
+            });
+        }
+    }
+
+    /// Flag a tagged node lying within the tolerance of an earlier tagged node
+    ///
+    /// Nodes are hashed into square cells as wide as the tolerance, so only
+    /// the surrounding 3x3 cells need to be searched.
+    fn check_duplicate_node(&mut self, id: i64, lat: f64, lon: f64) {
+        let tolerance = self.duplicate_tolerance_m;
+        if tolerance <= 0.0 {
+            return;
+        }
+
+        let (x, y) = utils::project_meters(lat, lon);
+        let cell = (
+            (x / tolerance).floor() as i64,
+            (y / tolerance).floor() as i64,
+        );
+
+        let duplicate = (-1..=1)
+            .flat_map(|dx| (-1..=1).map(move |dy| (cell.0 + dx, cell.1 + dy)))
+            .filter_map(|neighbour| self.tagged_node_grid.get(&neighbour))
+            .flatten()
+            .map(|&(other_id, other_lat, other_lon)| {
+                (
+                    other_id,
+                    utils::distance_m((lat, lon), (other_lat, other_lon)),
+                )
+            })
+            .find(|&(_, distance)| distance <= tolerance);
+
+        if let Some((other_id, distance)) = duplicate {
+            self.metrics.duplicate_nodes += 1;
+            self.issues.push(QualityIssue {
+                issue_type: "duplicate_node".to_string(),
+                severity: IssueSeverity::Low,
+                description: format!(
+                    "Tagged nodes {} and {} are {:.2} m apart",
+                    other_id, id, distance
+                ),
+                location: Some((lat, lon)),
+                osm_id: Some(id),
+                osm_type: Some("node".to_string()),
+                fix_suggestion: Some(
+                    "Merge the stacked nodes into one, combining their tags".to_string(),
+                ),
+            });
+        }
+
+        self.tagged_node_grid
+            .entry(cell)
+            .or_default()
+            .push((id, lat, lon));
+    }
+
+    /// Validate a closed building/landuse way as a polygon
+    fn check_polygon(&mut self, way_id: i64, is_area: bool, refs: &[i64]) {
+        if let Some(node_coords) = &mut self.node_coords {
+            node_coords.seal();
+            self.way_checks
+                .check_polygon(node_coords, way_id, is_area, refs);
+        }
+    }
+
+    /// Add the great-circle length of a highway way to its class
+    fn road_length(&mut self, highway: &str, refs: &[i64]) {
+        if let Some(node_coords) = &mut self.node_coords {
+            node_coords.seal();
+            self.way_checks.road_length(node_coords, highway, refs);
+        }
+    }
+
+    fn finish(
+        mut self,
+        reference_time: Option<DateTime<Utc>>,
+    ) -> Result<(QualityMetrics, Vec<QualityIssue>)> {
+        // Reported after the first pass's issues, as a PBF second pass finds them
+        let way_checks = std::mem::take(&mut self.way_checks);
+        self.metrics.geometry_errors += way_checks.geometry_errors;
+        self.issues.extend(way_checks.issues);
+
+        if self.options.member_checks {
+            self.resolve_members();
+        }
+
+        // Lookups in a failed disk index find nothing, so the checks would be wrong
+        if let Some(e) = self.node_coords.as_mut().and_then(NodeIndex::take_error) {
+            return Err(
+                crate::RiemapError::OsmProcessing(format!("Node index failed: {}", e)).into(),
+            );
+        }
+
+        self.metrics.freshness = self.freshness(reference_time);
+
+        // Way examples can only be placed when node coordinates were kept
+        if let Some(node_coords) = &mut self.node_coords {
+            for (category, tag, first_node) in self.amenity_way_examples.drain(..) {
+                let example = self
+                    .metrics
+                    .amenity_audit
+                    .get_mut(&category)
+                    .and_then(|audit| audit.examples.get_mut(tag));
+                if let Some(example) = example {
+                    example.location = node_coords.get(first_node);
+                }
+            }
+        }
+
+        // Calculate completeness score (simplified), over the element types read
+        let metrics = &mut self.metrics;
+        let total_elements = metrics.total_elements();
+        let tagged_elements = metrics.tagged_elements();
+
+        if total_elements > 0 {
+            metrics.completeness_score = (tagged_elements as f64 / total_elements as f64) * 100.0;
+        }
+
+        // Only report rules for feature types that actually occur
+        for ((name, _, _), (total, complete)) in
+            FEATURE_COMPLETENESS_RULES.iter().zip(self.feature_counts)
+        {
+            if total > 0 {
+                metrics
+                    .completeness_by_feature
+                    .insert(name.to_string(), complete as f64 / total as f64 * 100.0);
+            }
+        }
+
+        metrics
+            .custom_metrics
+            .insert("tag_frequency".to_string(), self.tag_frequency.summary());
+        metrics.custom_metrics.insert(
+            "building_3d".to_string(),
+            serde_json::json!(self.building_3d),
+        );
+        metrics.custom_metrics.insert(
+            "relation_types".to_string(),
+            serde_json::json!(self.relation_types),
+        );
+
+        if self.options.road_lengths {
+            // Rounded to whole meters
+            let road_km: BTreeMap<&str, f64> = way_checks
+                .road_m
+                .iter()
+                .map(|(class, meters)| (class.as_str(), meters.round() / 1000.0))
+                .collect();
+            metrics
+                .custom_metrics
+                .insert("road_km".to_string(), serde_json::json!(road_km));
+            metrics.custom_metrics.insert(
+                "road_incomplete_segments".to_string(),
+                serde_json::json!(way_checks.incomplete_road_segments),
+            );
+        }
+
+        Ok((self.metrics, self.issues))
+    }
+}
+
+impl WayChecks {
+    /// Validate a closed building/landuse way as a polygon
+    fn check_polygon(&mut self, node_coords: &NodeIndex, way_id: i64, is_area: bool, refs: &[i64]) {
+        if !is_area || refs.len() < 4 || refs.first() != refs.last() {
+            return;
+        }
+
+        // Skip ways whose nodes are not all part of this extract
+        let ring: Option<Vec<(f64, f64)>> = refs.iter().map(|id| node_coords.lookup(*id)).collect();
+        let Some(ring) = ring else {
+            return;
+        };
+
+        let (issue_type, description) = if utils::ring_area(&ring).abs() < 1e-14 {
+            (
+                "zero_area_polygon",
+                format!("Closed way {} encloses zero area", way_id),
+            )
+        } else if ring.len() > MAX_SELF_INTERSECTION_VERTICES {
+            self.issues.push(QualityIssue {
+                issue_type: "self_intersection_not_checked".to_string(),
+                severity: IssueSeverity::Low,
+                description: format!(
+                    "Closed way {} has {} nodes and was not checked for self-intersections",
+                    way_id,
+                    ring.len()
+                ),
+                location: Some(ring[0]),
+                osm_id: Some(way_id),
+                osm_type: Some("way".to_string()),
+                fix_suggestion: Some(format!(
+                    "Split the way into polygons of at most {} nodes",
+                    MAX_SELF_INTERSECTION_VERTICES
+                )),
+            });
+            return;
+        } else if utils::ring_self_intersects(&ring) {
+            (
+                "self_intersecting_polygon",
+                format!("Closed way {} self-intersects", way_id),
+            )
+        } else {
+            return;
+        };
+
+        self.geometry_errors += 1;
+        self.issues.push(QualityIssue {
+            issue_type: issue_type.to_string(),
+            severity: IssueSeverity::Medium,
+            description,
+            location: Some(ring[0]),
+            osm_id: Some(way_id),
+            osm_type: Some("way".to_string()),
+            fix_suggestion: Some(
+                "Redraw the polygon so it encloses an area without crossing itself".to_string(),
+            ),
+        });
+    }
+
+    /// Add the great-circle length of a highway way to its class
+    ///
+    /// Segments with a node outside the extract are skipped and counted as
+    /// incomplete.
+    fn road_length(&mut self, node_coords: &NodeIndex, highway: &str, refs: &[i64]) {
+        let coords: Vec<Option<(f64, f64)>> =
+            refs.iter().map(|id| node_coords.lookup(*id)).collect();
+        let mut length_m = 0.0;
+        for segment in coords.windows(2) {
+            match (segment[0], segment[1]) {
+                (Some(a), Some(b)) => length_m += utils::great_circle_m(a, b),
+                _ => self.incomplete_road_segments += 1,
+            }
+        }
+
+        *self.road_m.entry(highway.to_string()).or_insert(0.0) += length_m;
+    }
+
+    /// Append the results for the ways after these
+    fn merge(&mut self, other: WayChecks) {
+        self.issues.extend(other.issues);
+        self.geometry_errors += other.geometry_errors;
+        for (highway, length_m) in other.road_m {
+            *self.road_m.entry(highway).or_insert(0.0) += length_m;
+        }
+        self.incomplete_road_segments += other.incomplete_road_segments;
+    }
+}
+
+/// Utility functions for OSM data
+pub mod utils {
+    use super::*;
+
+    /// Owned elements of a PBF file in file order, decoded a block at a time
+    ///
+    /// Fails when an element does not come after the previous one by type
+    /// (nodes, ways, relations) and ID.
+    pub struct SortedElements {
+        path: PathBuf,
+        blobs: BlobReader<std::io::BufReader<fs::File>>,
+        block: std::vec::IntoIter<OsmElement>,
+        last: Option<(MemberType, i64)>,
+    }
+
+    impl SortedElements {
+        pub fn open(path: &Path) -> crate::Result<Self> {
+            Ok(Self {
+                path: path.to_path_buf(),
+                blobs: BlobReader::from_path(path)?,
+                block: Vec::new().into_iter(),
+                last: None,
+            })
+        }
+
+        fn next_element(&mut self) -> crate::Result<Option<OsmElement>> {
+            let element = loop {
+                if let Some(element) = self.block.next() {
+                    break element;
+                }
+                let Some(blob) = self.blobs.next() else {
+                    return Ok(None);
+                };
+                if let BlobDecode::OsmData(block) = blob?.decode()? {
+                    self.block = block
+                        .elements()
+                        .map(crate::pbf::owned_element)
+                        .collect::<Vec<_>>()
+                        .into_iter();
+                }
+            };
+
+            let key = (element.member_type(), element.id());
+            if self.last.is_some_and(|last| last >= key) {
+                return Err(crate::RiemapError::OsmProcessing(format!(
+                    "{:?} is not sorted by type and ID at {:?} {}",
+                    self.path, key.0, key.1
+                ))
+                .into());
+            }
+            self.last = Some(key);
+            Ok(Some(element))
+        }
+    }
+
+    impl Iterator for SortedElements {
+        type Item = crate::Result<OsmElement>;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            self.next_element().transpose()
+        }
+    }
+
+    /// Classify elements as added, modified or deleted by merging two streams
+    /// sorted by type and ID, keeping the first `sample_limit` of each kind
+    pub fn diff_sorted(
+        old: impl Iterator<Item = crate::Result<OsmElement>>,
+        new: impl Iterator<Item = crate::Result<OsmElement>>,
+        sample_limit: usize,
+    ) -> crate::Result<ElementDiff> {
+        let (mut old, mut new) = (old.peekable(), new.peekable());
+        let mut diff = ElementDiff::default();
+        let key = |element: &OsmElement| (element.member_type(), element.id());
+
+        loop {
+            let order = match (old.peek(), new.peek()) {
+                (None, None) => return Ok(diff),
+                (Some(Err(_)), _) => return Err(old.next().and_then(Result::err).unwrap()),
+                (_, Some(Err(_))) => return Err(new.next().and_then(Result::err).unwrap()),
+                (Some(Ok(old_element)), Some(Ok(new_element))) => {
+                    key(old_element).cmp(&key(new_element))
+                }
+                (Some(_), None) => std::cmp::Ordering::Less,
+                (None, Some(_)) => std::cmp::Ordering::Greater,
+            };
+
+            match order {
+                std::cmp::Ordering::Less => {
+                    diff.deleted.push(&old.next().unwrap()?, sample_limit);
+                }
+                std::cmp::Ordering::Greater => {
+                    diff.added.push(&new.next().unwrap()?, sample_limit);
+                }
+                std::cmp::Ordering::Equal => {
+                    let (old_element, new_element) = (old.next().unwrap()?, new.next().unwrap()?);
+                    if old_element != new_element {
+                        diff.modified.push(&new_element, sample_limit);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Latest extract URL of a region, failing for regions Geofabrik only
+    /// lists as containers for their children
+    pub fn download_url(region: &Region) -> Result<&str> {
+        if !region.provides_data_services {
+            return Err(crate::RiemapError::OsmProcessing(format!(
+                "Region {} has no extract of its own; download one of its child regions instead",
+                region.id
+            ))
+            .into());
+        }
+
+        Ok(region.geofabrik_url.as_deref().ok_or_else(|| {
+            crate::RiemapError::OsmProcessing(format!(
+                "No download URL configured for region: {}",
+                region.id
+            ))
+        })?)
+    }
+
+    /// Reader failing with `JobInterrupted::Cancelled` once `cancel` is cancelled
+    pub(crate) struct CancellableRead<'a, R> {
+        pub inner: R,
+        pub cancel: &'a CancellationToken,
+    }
+
+    impl<R: std::io::Read> std::io::Read for CancellableRead<'_, R> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.cancel.is_cancelled() {
+                return Err(std::io::Error::other(JobInterrupted::Cancelled));
+            }
+            self.inner.read(buf)
+        }
+    }
+
+    /// Data blocks each worker thread decodes per batch
+    const BLOCKS_PER_THREAD: usize = 4;
+
+    /// Decode the data blocks of a PBF file and run `process_block` on them on
+    /// `pool`, a batch at a time, handing the results to `merge` in file order
+    ///
+    /// Fails with `JobInterrupted::Cancelled` before the next block once
+    /// `cancel` is cancelled.
+    pub(crate) fn for_each_block_parallel<T: Send>(
+        file_path: &Path,
+        pool: &rayon::ThreadPool,
+        cancel: &CancellationToken,
+        process_block: impl Fn(&osmpbf::PrimitiveBlock) -> T + Sync,
+        mut merge: impl FnMut(T),
+    ) -> crate::Result<()> {
+        let mut reader = BlobReader::from_path(file_path)?;
+        let batch_size = pool.current_num_threads() * BLOCKS_PER_THREAD;
+        let check_cancelled = || -> crate::Result<()> {
+            if cancel.is_cancelled() {
+                return Err(JobInterrupted::Cancelled.into());
+            }
+            Ok(())
+        };
+
+        loop {
+            check_cancelled()?;
+            let batch = reader
+                .by_ref()
+                .take(batch_size)
+                .collect::<osmpbf::Result<Vec<_>>>()?;
+            if batch.is_empty() {
+                return Ok(());
+            }
+
+            let results = pool.install(|| {
+                batch
+                    .into_par_iter()
+                    .map(|blob| -> crate::Result<Option<T>> {
+                        check_cancelled()?;
+                        Ok(match blob.decode()? {
+                            BlobDecode::OsmData(block) => Some(process_block(&block)),
+                            _ => None,
+                        })
+                    })
+                    .collect::<crate::Result<Vec<_>>>()
+            })?;
+            results.into_iter().flatten().for_each(&mut merge);
+        }
+    }
+
+    /// Decode data blocks until `max_elements` elements have been seen (or the
+    /// file ends), returning the number of elements read and whether blocks
+    /// were left unread
+    pub fn count_elements<R: std::io::Read + Send>(
+        mut reader: BlobReader<R>,
+        max_elements: Option<usize>,
+    ) -> osmpbf::Result<(usize, bool)> {
+        let mut element_count = 0;
+
+        while let Some(blob) = reader.next() {
+            if let BlobDecode::OsmData(block) = blob?.decode()? {
+                element_count += block
+                    .groups()
+                    .map(|group| {
+                        group.nodes().count()
+                            + group.dense_nodes().count()
+                            + group.ways().count()
+                            + group.relations().count()
+                    })
+                    .sum::<usize>();
+
+                if max_elements.is_some_and(|max| element_count >= max) {
+                    return Ok((element_count, reader.next().is_some()));
+                }
+            }
+        }
+
+        Ok((element_count, false))
+    }
+
+    /// OSM XML counterpart of [`count_elements`], parsing elements until one
+    /// past `max_elements` shows that the file continues
+    pub fn count_xml_elements(
+        path: &Path,
+        max_elements: Option<usize>,
+    ) -> crate::Result<(usize, bool)> {
+        // The XML reader has no early exit, so reading is stopped by failing
+        // the underlying reader
+        let stop = CancellationToken::new();
+        let (mut element_count, mut truncated) = (0, false);
+        let input = std::io::BufReader::new(CancellableRead {
+            inner: crate::osm_xml::open_maybe_compressed(path)?,
+            cancel: &stop,
+        });
+        let result = crate::osm_xml::for_each_element(input, |_, _| {
+            if stop.is_cancelled() {
+                return;
+            }
+            if max_elements.is_some_and(|max| element_count >= max) {
+                truncated = true;
+                stop.cancel();
+            } else {
+                element_count += 1;
+            }
+        });
+        if !truncated {
+            result?;
+        }
+
+        Ok((element_count, truncated))
+    }
+
+    /// Value of a tag in an owned tag list
+    pub fn tag_value<'a>(tags: &'a [(String, String)], key: &str) -> Option<&'a str> {
+        tags.iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Whether a way represents an area rather than a line
+    pub fn is_area_way(way: &crate::pbf::OsmWay) -> bool {
+        let closed = way.refs.len() >= 4 && way.refs.first() == way.refs.last();
+        if !closed {
+            return false;
+        }
+
+        match tag_value(&way.tags, "area") {
+            Some("yes") => true,
+            Some("no") => false,
+            _ => {
+                ["building", "landuse", "leisure", "amenity"]
+                    .iter()
+                    .any(|key| tag_value(&way.tags, key).is_some())
+                    || tag_value(&way.tags, "natural").is_some_and(|value| value != "coastline")
+            }
+        }
+    }
+
+    /// Detect the data format of an OSM file from its extension
+    pub fn detect_format(path: &Path) -> Option<DataFormat> {
+        let filename = path.file_name()?.to_str()?;
+
+        if filename.ends_with(".pbf") {
+            Some(DataFormat::OsmPbf)
+        } else if [".osm", ".osm.bz2", ".osm.gz"]
+            .iter()
+            .any(|ext| filename.ends_with(ext))
+        {
+            Some(DataFormat::OsmXml)
+        } else {
+            None
+        }
+    }
+
+    /// Get file size in MB
+    pub fn get_file_size_mb(path: &Path) -> Result<f64> {
+        let metadata = std::fs::metadata(path)?;
+        Ok(metadata.len() as f64 / 1_048_576.0) // Convert bytes to MB
+    }
+
+    /// Path of the checksum sidecar stored next to a data file
+    pub fn checksum_sidecar_path(path: &Path) -> PathBuf {
+        let mut sidecar = path.as_os_str().to_owned();
+        sidecar.push(".md5");
+        PathBuf::from(sidecar)
+    }
+
+    /// Path of the `<file>.sha256` sidecar holding a data file's SHA-256 (hex)
+    pub fn sha256_sidecar_path(path: &Path) -> PathBuf {
+        let mut sidecar = path.as_os_str().to_owned();
+        sidecar.push(".sha256");
+        PathBuf::from(sidecar)
+    }
+
+    /// Path of the `<file>.http.json` sidecar holding the `ETag` and
+    /// `Last-Modified` a data file was served with
+    pub fn validators_sidecar_path(path: &Path) -> PathBuf {
+        let mut sidecar = path.as_os_str().to_owned();
+        sidecar.push(".http.json");
+        PathBuf::from(sidecar)
+    }
+
+    /// Read the cache validators recorded for a data file, if any
+    pub fn read_validators(path: &Path) -> Option<crate::http::CacheValidators> {
+        let contents = std::fs::read(validators_sidecar_path(path)).ok()?;
+        serde_json::from_slice(&contents).ok()
+    }
+
+    /// Read the stored SHA-256 (hex) for a data file, if one was recorded
+    pub fn read_sha256(path: &Path) -> Option<String> {
+        let contents = std::fs::read_to_string(sha256_sidecar_path(path)).ok()?;
+        contents.split_whitespace().next().map(str::to_string)
+    }
+
+    /// Read the stored checksum for a data file, if one was recorded
+    pub fn read_checksum(path: &Path) -> Option<String> {
+        let contents = std::fs::read_to_string(checksum_sidecar_path(path)).ok()?;
+        contents.split_whitespace().next().map(str::to_string)
+    }
+
+    /// Path of the content-addressed copy of data with the given SHA-256
+    pub fn blob_path(data_dir: &Path, sha256: &str) -> PathBuf {
+        data_dir.join("blobs").join(sha256)
+    }
+
+    /// Make `file_path` a hard link to the blob holding its content
+    ///
+    /// The first file with some content becomes the blob; later files with
+    /// the same SHA-256 are replaced by links to it. When the file system has
+    /// no hard links the file is kept as it is.
+    pub fn link_to_blob(data_dir: &Path, file_path: &Path, sha256: &str) -> Result<()> {
+        let blob = blob_path(data_dir, sha256);
+        if !blob.exists() {
+            std::fs::create_dir_all(blob.parent().unwrap_or(data_dir))?;
+            if let Err(e) = std::fs::hard_link(file_path, &blob) {
+                tracing::warn!(
+                    "Hard link for blob {:?} failed ({}), not deduplicating",
+                    blob,
+                    e
+                );
+            }
+            return Ok(());
+        }
+        if same_data_file(file_path, &blob) {
+            return Ok(());
+        }
+
+        // Link next to the file first, so a failure leaves the download in place
+        let mut link = file_path.as_os_str().to_owned();
+        link.push(".link");
+        let link = PathBuf::from(link);
+        if let Err(e) = std::fs::hard_link(&blob, &link) {
+            tracing::warn!(
+                "Hard link to blob {:?} failed ({}), not deduplicating",
+                blob,
+                e
+            );
+            return Ok(());
+        }
+        std::fs::rename(&link, file_path)?;
+        tracing::info!("Deduplicated {:?} against blob {}", file_path, sha256);
+        Ok(())
+    }
+
+    /// Point a region directory's `latest.osm.pbf` at the given data file
+    ///
+    /// Unix gets a relative symlink. Windows symlinks need extra privileges
+    /// and junctions only link directories, so there `latest` is a hard link
+    /// to the file, falling back to a full copy when the file system has no
+    /// hard links.
+    pub fn point_latest_to(region_dir: &Path, file_path: &Path) -> Result<()> {
+        let latest_path = region_dir.join("latest.osm.pbf");
+        if latest_path.symlink_metadata().is_ok() {
+            std::fs::remove_file(&latest_path)?;
+        }
+
+        #[cfg(unix)]
+        {
+            let filename = file_path.file_name().unwrap_or(file_path.as_os_str());
+            std::os::unix::fs::symlink(filename, &latest_path)?;
+        }
+
+        #[cfg(windows)]
+        if let Err(e) = std::fs::hard_link(file_path, &latest_path) {
+            tracing::warn!(
+                "Hard link for {:?} failed ({}), copying instead",
+                latest_path,
+                e
+            );
+            std::fs::copy(file_path, &latest_path)?;
+        }
+
+        Ok(())
+    }
+
+    /// Whether two paths lead to the same data, whichever way `latest` was created
+    ///
+    /// Symlinks are followed; a hard link or copy is recognized by sharing
+    /// the target's size and modification time, which both keep.
+    pub fn same_data_file(a: &Path, b: &Path) -> bool {
+        let (Ok(a), Ok(b)) = (std::fs::metadata(a), std::fs::metadata(b)) else {
+            return false;
+        };
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            a.dev() == b.dev() && a.ino() == b.ino()
+        }
+
+        #[cfg(not(unix))]
+        {
+            a.len() == b.len() && a.modified().ok() == b.modified().ok()
+        }
+    }
 
+    /// URL of a replication diff, e.g. `<updates>/004/213/120.osc.gz` for sequence 4213120
+    pub fn replication_diff_url(updates_url: &str, sequence: i64) -> String {
         format!(
+            "{}/{:03}/{:03}/{:03}.osc.gz",
+            updates_url.trim_end_matches('/'),
+            sequence / 1_000_000,
+            (sequence / 1000) % 1000,
+            sequence % 1000
+        )
+    }
+
+    /// Parse an Osmosis `state.txt` (`sequenceNumber=` and escaped `timestamp=` lines)
+    pub fn parse_replication_state(contents: &str) -> Option<ReplicationState> {
+        let mut sequence_number = None;
+        let mut timestamp = None;
+
+        for line in contents.lines() {
+            if let Some(value) = line.strip_prefix("sequenceNumber=") {
+                sequence_number = value.trim().parse().ok();
+            } else if let Some(value) = line.strip_prefix("timestamp=") {
+                timestamp = DateTime::parse_from_rfc3339(&value.trim().replace('\\', ""))
+                    .ok()
+                    .map(|ts| ts.with_timezone(&Utc));
+            }
+        }
+
+        Some(ReplicationState {
+            sequence_number: sequence_number?,
+            timestamp: timestamp?,
+        })
+    }
+
+    /// Build the URL of a dated extract from a `*-latest.osm.pbf` URL
+    ///
+    /// Geofabrik names dated extracts with a `YYMMDD` suffix, e.g.
+    /// `liechtenstein-latest.osm.pbf` becomes `liechtenstein-210101.osm.pbf`.
+    pub fn historical_extract_url(latest_url: &str, date: NaiveDate) -> Option<String> {
+        let (base, extension) = latest_url.rsplit_once("-latest.")?;
+        Some(format!("{}-{}.{}", base, date.format("%y%m%d"), extension))
+    }
+
+    /// Extract timestamp from filename
+    pub fn extract_timestamp_from_filename(filename: &str) -> Option<String> {
+        // Extract YYYY-MM-DD pattern from filename
+        let re = regex::Regex::new(r"\d{4}-\d{2}-\d{2}").ok()?;
+        re.find(filename).map(|m| m.as_str().to_string())
+    }
+
+    /// Project (lat, lon) to approximate planar meters, accurate at small distances
+    pub fn project_meters(lat: f64, lon: f64) -> (f64, f64) {
+        let x = lon * METERS_PER_DEGREE * lat.to_radians().cos();
+        (x, lat * METERS_PER_DEGREE)
+    }
+
+    /// Equirectangular distance in meters between two (lat, lon) points
+    pub fn distance_m(a: (f64, f64), b: (f64, f64)) -> f64 {
+        let mean_lat = ((a.0 + b.0) / 2.0).to_radians();
+        let dx = (b.1 - a.1) * METERS_PER_DEGREE * mean_lat.cos();
+        let dy = (b.0 - a.0) * METERS_PER_DEGREE;
+        (dx * dx + dy * dy).sqrt()
+    }
+
+    /// Length of one degree of latitude in meters
+    const METERS_PER_DEGREE: f64 = 111_320.0;
+
+    /// Mean Earth radius in meters
+    const EARTH_RADIUS_M: f64 = 6_371_008.8;
+
+    /// Great-circle (haversine) distance in meters between two (lat, lon) points
+    pub fn great_circle_m(a: (f64, f64), b: (f64, f64)) -> f64 {
+        let (lat_a, lat_b) = (a.0.to_radians(), b.0.to_radians());
+        let half_dlat = (lat_b - lat_a) / 2.0;
+        let half_dlon = (b.1 - a.1).to_radians() / 2.0;
+        let h = half_dlat.sin().powi(2) + lat_a.cos() * lat_b.cos() * half_dlon.sin().powi(2);
+        2.0 * EARTH_RADIUS_M * h.sqrt().min(1.0).asin()
+    }
+
+    /// Signed area of a closed ring of (lat, lon) points in square degrees (shoelace formula)
+    pub fn ring_area(ring: &[(f64, f64)]) -> f64 {
+        ring.windows(2)
+            .map(|pair| {
+                let (lat1, lon1) = pair[0];
+                let (lat2, lon2) = pair[1];
+                lon1 * lat2 - lon2 * lat1
+            })
+            .sum::<f64>()
+            / 2.0
+    }
+
+    /// Check whether any two non-adjacent edges of a closed ring intersect
+    ///
+    /// Edges are swept south to north, and each is only tested against the
+    /// edges whose latitude range still overlaps it.
+    pub fn ring_self_intersects(ring: &[(f64, f64)]) -> bool {
+        let edges = ring.len().saturating_sub(1);
+        let south = |edge: usize| ring[edge].0.min(ring[edge + 1].0);
+        let north = |edge: usize| ring[edge].0.max(ring[edge + 1].0);
+
+        let mut order: Vec<usize> = (0..edges).collect();
+        order.sort_unstable_by(|a, b| south(*a).total_cmp(&south(*b)));
+
+        let mut active: Vec<usize> = Vec::new();
+        for edge in order {
+            active.retain(|other| north(*other) >= south(edge));
+            for other in &active {
+                let (i, j) = (edge.min(*other), edge.max(*other));
+                // Neighbouring edges share a node, as do the first and last
+                if j == i + 1 || (i == 0 && j == edges - 1) {
+                    continue;
+                }
+
+                if segments_intersect(ring[i], ring[i + 1], ring[j], ring[j + 1]) {
+                    return true;
+                }
+            }
+            active.push(edge);
+        }
+
+        false
+    }
+
+    /// Check whether segments p1-p2 and q1-q2 touch or cross
+    fn segments_intersect(p1: (f64, f64), p2: (f64, f64), q1: (f64, f64), q2: (f64, f64)) -> bool {
+        let orientation = |a: (f64, f64), b: (f64, f64), c: (f64, f64)| {
+            let cross = (b.0 - a.0) * (c.1 - a.1) - (b.1 - a.1) * (c.0 - a.0);
+            if cross > 0.0 {
+                1
+            } else if cross < 0.0 {
+                -1
+            } else {
+                0
+            }
+        };
+        let on_segment = |a: (f64, f64), b: (f64, f64), c: (f64, f64)| {
+            c.0 >= a.0.min(b.0) && c.0 <= a.0.max(b.0) && c.1 >= a.1.min(b.1) && c.1 <= a.1.max(b.1)
+        };
+
+        let o1 = orientation(p1, p2, q1);
+        let o2 = orientation(p1, p2, q2);
+        let o3 = orientation(q1, q2, p1);
+        let o4 = orientation(q1, q2, p2);
+
+        if o1 != o2 && o3 != o4 {
+            return true;
+        }
+
+        (o1 == 0 && on_segment(p1, p2, q1))
+            || (o2 == 0 && on_segment(p1, p2, q2))
+            || (o3 == 0 && on_segment(q1, q2, p1))
+            || (o4 == 0 && on_segment(q1, q2, p2))
+    }
+
+    /// Round the positions of a GeoJSON geometry, feature or feature
+    /// collection to `precision` decimal places
+    ///
+    /// Rounded longitudes stay within ±180 and latitudes within ±90, so a
+    /// coordinate near the edge never becomes invalid.
+    pub fn round_geojson_coordinates(value: &mut serde_json::Value, precision: u8) {
+        let serde_json::Value::Object(object) = value else {
+            return;
+        };
+        if let Some(coordinates) = object.get_mut("coordinates") {
+            round_positions(coordinates, 10f64.powi(precision as i32));
+        }
+        for key in ["geometry", "geometries", "features"] {
+            match object.get_mut(key) {
+                Some(serde_json::Value::Array(members)) => members
+                    .iter_mut()
+                    .for_each(|member| round_geojson_coordinates(member, precision)),
+                Some(member) => round_geojson_coordinates(member, precision),
+                None => {}
+            }
+        }
+    }
+
+    /// Round nested coordinate arrays down to their `[lon, lat, ...]` positions
+    fn round_positions(coordinates: &mut serde_json::Value, scale: f64) {
+        let serde_json::Value::Array(items) = coordinates else {
+            return;
+        };
+        if !items.first().is_some_and(serde_json::Value::is_number) {
+            items
+                .iter_mut()
+                .for_each(|item| round_positions(item, scale));
+            return;
+        }
+
+        for (axis, item) in items.iter_mut().enumerate() {
+            let Some(value) = item.as_f64() else {
+                continue;
+            };
+            let rounded = (value * scale).round() / scale;
+            let rounded = match axis {
+                0 => rounded.clamp(-180.0, 180.0),
+                1 => rounded.clamp(-90.0, 90.0),
+                _ => rounded,
+            };
+            if let Some(number) = serde_json::Number::from_f64(rounded) {
+                *item = serde_json::Value::Number(number);
+            }
+        }
+    }
+
+    /// Generate quality report summary
+    pub fn generate_summary(metrics: &QualityMetrics, issues: &[QualityIssue]) -> String {
+        let total_elements = metrics.total_elements();
+        let critical_issues = issues
+            .iter()
+            .filter(|i| matches!(i.severity, IssueSeverity::Critical))
+            .count();
+
+        let mut summary = format!(
             "Data contains {} elements ({} nodes, {} ways, {} relations) with {:.1}% completeness. {} critical issues found.",
             total_elements,
-            metrics.total_nodes,
-            metrics.total_ways,
-            metrics.total_relations,
+            count_text(metrics.total_nodes),
+            count_text(metrics.total_ways),
+            count_text(metrics.total_relations),
             metrics.completeness_score,
             critical_issues
+        );
+
+        if !metrics.completeness_by_feature.is_empty() {
+            let mut features: Vec<_> = metrics.completeness_by_feature.iter().collect();
+            features.sort_by(|a, b| a.0.cmp(b.0));
+            let parts: Vec<String> = features
+                .iter()
+                .map(|(name, ratio)| format!("{} {:.1}%", name, ratio))
+                .collect();
+            summary.push_str(&format!(" Feature completeness: {}.", parts.join(", ")));
+        }
+
+        if metrics.duplicate_nodes > 0 {
+            summary.push_str(&format!(
+                " {} duplicate tagged nodes.",
+                metrics.duplicate_nodes
+            ));
+        }
+
+        if let Some(building_3d) = metrics.building_3d().filter(|coverage| coverage.total > 0) {
+            let share = |count: u64| count as f64 / building_3d.total as f64 * 100.0;
+            summary.push_str(&format!(
+                " 3D: {:.1}% of {} buildings have a height, {:.1}% a level count.",
+                share(building_3d.with_height),
+                building_3d.total,
+                share(building_3d.with_levels)
+            ));
+        }
+
+        summary
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pbf::{OsmNode, OsmWay, PbfHeader};
+    use axum::response::IntoResponse;
+    use futures::StreamExt;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    fn tags(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    fn node(id: i64, pairs: &[(&str, &str)]) -> OsmNode {
+        OsmNode {
+            id,
+            version: 1,
+            timestamp: 0,
+            lat: 47.1 + id as f64 * 1e-3,
+            lon: 9.5 + id as f64 * 1e-3,
+            tags: tags(pairs),
+        }
+    }
+
+    fn way(id: i64, refs: &[i64], pairs: &[(&str, &str)]) -> OsmWay {
+        OsmWay {
+            id,
+            version: 1,
+            timestamp: 0,
+            refs: refs.to_vec(),
+            tags: tags(pairs),
+        }
+    }
+
+    /// Write `data` as a PBF in a fresh directory, returning both
+    fn write_fixture(data: &OsmData) -> (tempfile::TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("fixture.osm.pbf");
+        crate::pbf::write_pbf(&path, data, &PbfHeader::default()).unwrap();
+        (dir, path)
+    }
+
+    fn processor(dir: &Path) -> OsmProcessor {
+        OsmProcessor::new(dir.join("data"), dir.join("tmp")).unwrap()
+    }
+
+    #[tokio::test]
+    async fn completeness_by_feature_counts_expected_attributes() {
+        let mut data = OsmData::default();
+        for id in 1..=6 {
+            data.nodes.insert(id, node(id, &[]));
+        }
+        // One of four buildings has a house number
+        data.nodes.insert(
+            7,
+            node(7, &[("building", "yes"), ("addr:housenumber", "1")]),
+        );
+        for id in 8..=10 {
+            data.nodes.insert(id, node(id, &[("building", "yes")]));
+        }
+        // Amenity with opening hours
+        data.nodes.insert(
+            11,
+            node(
+                11,
+                &[("amenity", "cafe"), ("opening_hours", "Mo-Fr 08:00-18:00")],
+            ),
+        );
+        // One of two highways is named
+        data.ways.insert(
+            20,
+            way(20, &[1, 2], &[("highway", "primary"), ("name", "Main")]),
+        );
+        data.ways
+            .insert(21, way(21, &[3, 4], &[("highway", "service")]));
+        data.ways.insert(22, way(22, &[5, 6], &[]));
+
+        let (dir, path) = write_fixture(&data);
+        let (metrics, _) = processor(dir.path()).process_osm_file(&path).await.unwrap();
+
+        let completeness = &metrics.completeness_by_feature;
+        assert_eq!(completeness.get("highway_name"), Some(&50.0));
+        assert_eq!(completeness.get("building_housenumber"), Some(&25.0));
+        assert_eq!(completeness.get("amenity_opening_hours"), Some(&100.0));
+        assert_eq!(completeness.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn completeness_by_feature_omits_absent_feature_types() {
+        let mut data = OsmData::default();
+        data.nodes.insert(1, node(1, &[]));
+        data.nodes.insert(2, node(2, &[]));
+        data.ways
+            .insert(10, way(10, &[1, 2], &[("highway", "residential")]));
+
+        let (dir, path) = write_fixture(&data);
+        let (metrics, _) = processor(dir.path()).process_osm_file(&path).await.unwrap();
+
+        assert_eq!(
+            metrics.completeness_by_feature,
+            HashMap::from([("highway_name".to_string(), 0.0)])
+        );
+    }
+
+    #[tokio::test]
+    async fn validation_stops_at_the_element_limit_and_reports_truncation() {
+        let mut data = OsmData::default();
+        // Three PBF data blocks of nodes
+        for id in 1..=20_000 {
+            data.nodes.insert(id, node(id, &[]));
+        }
+        let (dir, path) = write_fixture(&data);
+        let processor = processor(dir.path());
+
+        let reader = BlobReader::from_path(&path).unwrap();
+        assert_eq!(
+            utils::count_elements(reader, Some(DEFAULT_VALIDATION_MAX_ELEMENTS)).unwrap(),
+            (8000, true)
+        );
+
+        let issues = processor
+            .validate_file(&path, Some(DEFAULT_VALIDATION_MAX_ELEMENTS))
+            .await
+            .unwrap();
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].issue_type, "validation_truncated");
+        assert!(issues[0].description.contains("8000 elements"));
+
+        assert!(processor
+            .validate_file(&path, None)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn validation_reads_osm_xml_with_the_xml_parser() {
+        let dir = tempfile::tempdir().unwrap();
+        let processor = processor(dir.path());
+        let path = dir.path().join("fixture.osm");
+        fs::write(
+            &path,
+            r#"<osm version="0.6">
+                <node id="1" lat="47.1" lon="9.5"/>
+                <node id="2" lat="47.2" lon="9.6"/>
+                <way id="10"><nd ref="1"/><nd ref="2"/></way>
+            </osm>"#,
+        )
+        .unwrap();
+
+        assert!(processor
+            .validate_file(&path, None)
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(processor
+            .validate_file(&path, Some(3))
+            .await
+            .unwrap()
+            .is_empty());
+        let issues = processor.validate_file(&path, Some(2)).await.unwrap();
+        assert_eq!(issues.len(), 1);
+        assert!(issues[0].description.contains("2 elements"));
+
+        fs::write(&path, r#"<osm version="0.6"><node id="1" lat="#).unwrap();
+        let issues = processor.validate_file(&path, None).await.unwrap();
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].issue_type, "parsing_error");
+    }
+
+    #[tokio::test]
+    async fn validation_within_the_limit_is_not_truncated() {
+        let mut data = OsmData::default();
+        for id in 1..=10 {
+            data.nodes.insert(id, node(id, &[]));
+        }
+        let (dir, path) = write_fixture(&data);
+
+        let reader = BlobReader::from_path(&path).unwrap();
+        assert_eq!(
+            utils::count_elements(reader, Some(10)).unwrap(),
+            (10, false)
+        );
+
+        let issues = processor(dir.path())
+            .validate_file(&path, Some(DEFAULT_VALIDATION_MAX_ELEMENTS))
+            .await
+            .unwrap();
+        assert!(issues.is_empty());
+    }
+
+    /// Several blocks of nodes with stacked tagged nodes on a block boundary,
+    /// buildings (one self-intersecting), roads and relations with problems
+    fn checks_fixture() -> OsmData {
+        let mut data = OsmData::default();
+        for id in 1..=20_000 {
+            data.nodes.insert(id, node(id, &[]));
+        }
+        for id in [7_999, 8_001] {
+            data.nodes.insert(
+                id,
+                OsmNode {
+                    lat: 47.5,
+                    lon: 9.2,
+                    ..node(id, &[("amenity", "bench")])
+                },
+            );
+        }
+        // A lopsided bowtie: 100 -> 102 crosses 101 -> 103
+        let bowtie = [
+            (100, 47.0, 9.0),
+            (101, 47.0, 9.001),
+            (102, 47.002, 9.001),
+            (103, 47.001, 9.0),
+        ];
+        for (id, lat, lon) in bowtie {
+            data.nodes.insert(
+                id,
+                OsmNode {
+                    lat,
+                    lon,
+                    ..node(id, &[])
+                },
+            );
+        }
+        data.ways.insert(
+            1,
+            way(1, &[100, 102, 101, 103, 100], &[("building", "yes")]),
+        );
+        for id in 2..=3_000 {
+            // Off the line the generated nodes lie on
+            let first = id * 6;
+            data.ways.insert(
+                id,
+                way(id, &[first, first + 1, 100, first], &[("building", "yes")]),
+            );
+        }
+        for id in 3_001..=6_000 {
+            data.ways.insert(
+                id,
+                way(id, &[id, id + 1, 30_000], &[("highway", "residential")]),
+            );
+        }
+        data.relations.insert(
+            1,
+            crate::pbf::OsmRelation {
+                id: 1,
+                version: 1,
+                timestamp: 0,
+                members: vec![crate::pbf::OsmMember {
+                    member_type: MemberType::Way,
+                    id: 99_999,
+                    role: "outer".to_string(),
+                }],
+                tags: tags(&[("type", "multipolygon")]),
+            },
+        );
+        data.relations.insert(
+            2,
+            crate::pbf::OsmRelation {
+                id: 2,
+                version: 1,
+                timestamp: 0,
+                members: vec![crate::pbf::OsmMember {
+                    member_type: MemberType::Way,
+                    id: 2,
+                    role: "inner".to_string(),
+                }],
+                tags: tags(&[("type", "multipolygon")]),
+            },
+        );
+        data
+    }
+
+    #[tokio::test]
+    async fn worker_pool_matches_single_threaded_checks() {
+        let (dir, path) = write_fixture(&checks_fixture());
+        let run = |threads: usize, node_index: NodeIndexStorage| {
+            let processor = processor(dir.path()).with_processing_options(ProcessingOptions {
+                threads,
+                node_index,
+                ..ProcessingOptions::default()
+            });
+            let path = path.clone();
+            async move {
+                let (metrics, issues) = processor.process_osm_file(&path).await.unwrap();
+                (
+                    serde_json::to_value(metrics).unwrap(),
+                    serde_json::to_value(issues).unwrap(),
+                )
+            }
+        };
+
+        let (metrics, issues) = run(1, NodeIndexStorage::Memory).await;
+        let issue_types: Vec<&str> = issues
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|issue| issue["issue_type"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            issue_types,
+            [
+                "duplicate_node",
+                "multipolygon_without_outer",
+                "self_intersecting_polygon",
+                "broken_relation"
+            ]
+        );
+        assert_eq!(metrics["duplicate_nodes"], 1);
+        assert_eq!(metrics["custom_metrics"]["road_incomplete_segments"], 3_000);
+
+        for (threads, node_index) in [(4, NodeIndexStorage::Memory), (3, NodeIndexStorage::Disk)] {
+            assert_eq!(
+                run(threads, node_index).await,
+                (metrics.clone(), issues.clone())
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn processing_stops_once_cancelled() {
+        let (dir, pbf_path) = write_fixture(&checks_fixture());
+        let xml_path = dir.path().join("fixture.osm");
+        fs::write(
+            &xml_path,
+            r#"<osm version="0.6"><node id="1" lat="47.1" lon="9.5"/></osm>"#,
+        )
+        .unwrap();
+
+        let cancel = CancellationToken::new();
+        let processor = processor(dir.path()).with_cancellation(cancel.clone());
+        for path in [&pbf_path, &xml_path] {
+            assert!(processor.process_osm_file(path).await.is_ok());
+        }
+
+        cancel.cancel();
+        for path in [&pbf_path, &xml_path] {
+            let err = processor.process_osm_file(path).await.unwrap_err();
+            assert!(
+                matches!(err.downcast_ref(), Some(JobInterrupted::Cancelled)),
+                "{:?}: {}",
+                path,
+                err
+            );
+        }
+    }
+
+    #[test]
+    fn ring_self_intersections_are_found_by_the_sweep() {
+        // A closed ring of `n` points around a circle, optionally with the
+        // point at `dent` pulled across the opposite side
+        let circle = |n: usize, dent: Option<usize>| {
+            let mut ring: Vec<(f64, f64)> = (0..n)
+                .map(|i| {
+                    let angle = i as f64 / n as f64 * std::f64::consts::TAU;
+                    (47.0 + angle.sin(), 9.0 + angle.cos())
+                })
+                .collect();
+            if let Some(dent) = dent {
+                let (lat, lon) = ring[dent];
+                ring[dent] = (47.0 - (lat - 47.0) * 1.5, 9.0 - (lon - 9.0) * 1.5);
+            }
+            ring.push(ring[0]);
+            ring
+        };
+
+        assert!(!utils::ring_self_intersects(&circle(4, None)));
+        assert!(!utils::ring_self_intersects(&circle(1_999, None)));
+        assert!(utils::ring_self_intersects(&circle(1_999, Some(500))));
+        assert!(utils::ring_self_intersects(&[
+            (47.0, 9.0),
+            (47.002, 9.001),
+            (47.0, 9.001),
+            (47.001, 9.0),
+            (47.0, 9.0)
+        ]));
+    }
+
+    #[test]
+    fn rings_over_the_vertex_limit_are_reported_unchecked() {
+        let refs: Vec<i64> = (1..=MAX_SELF_INTERSECTION_VERTICES as i64 + 1)
+            .chain([1])
+            .collect();
+        let node_coords = NodeIndex::Memory(
+            refs.iter()
+                .map(|id| {
+                    let angle = *id as f64 / refs.len() as f64 * std::f64::consts::TAU;
+                    (*id, (47.0 + angle.sin(), 9.0 + angle.cos()))
+                })
+                .collect(),
+        );
+
+        let mut checks = WayChecks::default();
+        checks.check_polygon(&node_coords, 1, true, &refs);
+        assert_eq!(checks.geometry_errors, 0);
+        assert_eq!(checks.issues.len(), 1);
+        assert_eq!(checks.issues[0].issue_type, "self_intersection_not_checked");
+
+        // A ring of exactly the limit is checked
+        let at_limit: Vec<i64> = refs[..MAX_SELF_INTERSECTION_VERTICES - 1]
+            .iter()
+            .copied()
+            .chain([1])
+            .collect();
+        let mut checks = WayChecks::default();
+        checks.check_polygon(&node_coords, 1, true, &at_limit);
+        assert!(checks.issues.is_empty());
+    }
+
+    #[test]
+    fn pbf_headers_are_read_from_the_first_bytes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("upload.osm.pbf");
+        let header = PbfHeader {
+            replication_timestamp: Some(1_700_000_000),
+            ..PbfHeader::default()
+        };
+        crate::pbf::write_pbf(&path, &checks_fixture(), &header).unwrap();
+        let bytes = fs::read(&path).unwrap();
+
+        let read = crate::pbf::read_header_from(&bytes[..]).unwrap();
+        assert_eq!(read.replication_timestamp, Some(1_700_000_000));
+
+        let err = crate::pbf::read_header_from(&bytes[..20]).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<std::io::Error>().map(|e| e.kind()),
+            Some(std::io::ErrorKind::UnexpectedEof)
+        );
+
+        // XML would claim a blob header of about a gigabyte
+        let err = crate::pbf::read_header_from(&br#"<?xml version="1.0"?>"#[..]).unwrap_err();
+        assert!(err.to_string().contains("exceeds the PBF limit"), "{}", err);
+    }
+
+    #[test]
+    fn header_probe_parses_the_block_once_it_has_arrived() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("upload.osm.pbf");
+        let header = PbfHeader {
+            replication_timestamp: Some(1_700_000_000),
+            ..PbfHeader::default()
+        };
+        crate::pbf::write_pbf(&path, &checks_fixture(), &header).unwrap();
+        let bytes = fs::read(&path).unwrap();
+
+        let mut probe = crate::pbf::HeaderProbe::default();
+        let mut chunks = bytes.chunks(7);
+        let read = loop {
+            if let Some(read) = probe.push(chunks.next().unwrap()).unwrap() {
+                break read;
+            }
+        };
+        assert_eq!(read.replication_timestamp, Some(1_700_000_000));
+
+        // A body ending inside the header block
+        let mut probe = crate::pbf::HeaderProbe::default();
+        assert!(probe.push(&bytes[..20]).unwrap().is_none());
+        assert!(probe.finish().is_err());
+
+        // Other data is rejected with its first chunk
+        let mut probe = crate::pbf::HeaderProbe::default();
+        assert!(probe.push(br#"<?xml version="1.0"?>"#).is_err());
+    }
+
+    /// Serve `app` on a free local port
+    async fn serve(app: axum::Router) -> std::net::SocketAddr {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        addr
+    }
+
+    /// A region whose extracts are downloaded from `addr`
+    fn served_region(addr: std::net::SocketAddr) -> Region {
+        let mut region = Region::new(
+            "liechtenstein".to_string(),
+            "Liechtenstein".to_string(),
+            AdminLevel::Country,
+            BoundingBox::new(47.0, 9.4, 47.3, 9.6),
+        );
+        region.geofabrik_url = Some(format!("http://{}/liechtenstein-latest.osm.pbf", addr));
+        region
+    }
+
+    /// A processor whose HTTP client makes one attempt per request and waits
+    /// at most a second for data
+    fn impatient_processor(dir: &Path) -> OsmProcessor {
+        let config = crate::config::GeofabrikConfig {
+            max_attempts: 1,
+            connect_timeout_secs: 4,
+            read_timeout_secs: 1,
+            ..Default::default()
+        };
+        let processor = processor(dir).with_http_config(&config).unwrap();
+        assert_eq!(processor.http_max_attempts, 1);
+        assert_eq!(processor.http_read_timeout, Duration::from_secs(1));
+        processor
+    }
+
+    #[tokio::test]
+    async fn downloads_give_up_on_a_server_slower_than_the_read_timeout() {
+        let dir = tempfile::tempdir().unwrap();
+        let hits = Arc::new(AtomicUsize::new(0));
+        let counter = hits.clone();
+        let app = axum::Router::new().fallback(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+            async {
+                tokio::time::sleep(Duration::from_secs(10)).await;
+                "too late"
+            }
+        });
+        let region = served_region(serve(app).await);
+
+        let started = std::time::Instant::now();
+        let result = impatient_processor(dir.path())
+            .download_region_version(
+                &region,
+                &dir.path().join("data"),
+                NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+                false,
+            )
+            .await;
+
+        assert!(result.is_err());
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn downloads_stalling_mid_body_time_out() {
+        let dir = tempfile::tempdir().unwrap();
+        // The first bytes arrive at once, then the server goes quiet
+        let app = axum::Router::new().fallback(|| async {
+            let chunk = futures::stream::once(async {
+                Ok::<_, std::io::Error>(axum::body::Bytes::from_static(b"partial extract"))
+            });
+            axum::body::Body::from_stream(chunk.chain(futures::stream::pending()))
+        });
+        let region = served_region(serve(app).await);
+        let region_dir = dir.path().join("data");
+
+        let started = std::time::Instant::now();
+        let err = impatient_processor(dir.path())
+            .download_region_version(
+                &region,
+                &region_dir,
+                NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+                false,
+            )
+            .await
+            .unwrap_err();
+
+        assert!(err.to_string().contains("stalled"), "{}", err);
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(!region_dir.join("2024-01-01.osm.pbf").exists());
+    }
+
+    #[tokio::test]
+    async fn downloads_complete_after_transient_server_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let (_fixture_dir, fixture) = write_fixture(&checks_fixture());
+        let body = fs::read(fixture).unwrap();
+
+        // Two 503s asking for an immediate retry, then the extract
+        let hits = Arc::new(AtomicUsize::new(0));
+        let counter = hits.clone();
+        let app = axum::Router::new().fallback(move || {
+            let attempt = counter.fetch_add(1, Ordering::SeqCst);
+            let body = body.clone();
+            async move {
+                if attempt < 2 {
+                    (
+                        axum::http::StatusCode::SERVICE_UNAVAILABLE,
+                        [(axum::http::header::RETRY_AFTER, "0")],
+                    )
+                        .into_response()
+                } else {
+                    body.into_response()
+                }
+            }
+        });
+        let region = served_region(serve(app).await);
+
+        let path = processor(dir.path())
+            .download_region_version(
+                &region,
+                &dir.path().join("data"),
+                NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+                false,
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+        let (metrics, _) = processor(dir.path()).process_osm_file(&path).await.unwrap();
+        assert_eq!(metrics.total_ways, Some(checks_fixture().ways.len() as u64));
+    }
+
+    #[tokio::test]
+    async fn unverified_redownload_drops_the_earlier_md5() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut data = OsmData::default();
+        data.nodes.insert(1, node(1, &[]));
+        let (_fixture_dir, first) = write_fixture(&data);
+        data.nodes.insert(2, node(2, &[]));
+        let (_fixture_dir2, second) = write_fixture(&data);
+
+        let body = Arc::new(std::sync::Mutex::new(fs::read(first).unwrap()));
+        let served = body.clone();
+        let app = axum::Router::new().fallback(move |uri: axum::http::Uri| {
+            let bytes = served.lock().unwrap().clone();
+            async move {
+                if uri.path().ends_with(".md5") {
+                    format!("{:x}  extract.osm.pbf", md5::compute(&bytes)).into_bytes()
+                } else {
+                    bytes
+                }
+            }
+        });
+        let region = served_region(serve(app).await);
+
+        let processor = processor(dir.path());
+        let region_dir = dir.path().join("data");
+        let date = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let path = processor
+            .download_region_version(&region, &region_dir, date, true)
+            .await
+            .unwrap();
+        assert!(utils::checksum_sidecar_path(&path).exists());
+
+        *body.lock().unwrap() = fs::read(second).unwrap();
+        processor
+            .download_region_version(&region, &region_dir, date, false)
+            .await
+            .unwrap();
+        assert!(!utils::checksum_sidecar_path(&path).exists());
+        assert!(utils::read_sha256(&path).is_some());
+    }
+
+    #[test]
+    fn malformed_pbf_headers_fail_without_panicking() {
+        // A PBF stream of one blob header followed by `blob`
+        let stream = |blob_header: &[u8], blob: &[u8]| {
+            let mut bytes = (blob_header.len() as u32).to_be_bytes().to_vec();
+            bytes.extend_from_slice(blob_header);
+            bytes.extend_from_slice(blob);
+            bytes
+        };
+
+        // Field 1 declaring a length of u64::MAX bytes
+        let overflowing = [
+            0x0a, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01,
+        ];
+        let err = crate::pbf::read_header_from(&stream(&overflowing, &[])[..]).unwrap_err();
+        assert!(err.to_string().contains("Truncated"), "{}", err);
+
+        // A header blob claiming 16 bytes that inflates to a megabyte
+        let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::best());
+        std::io::Write::write_all(&mut encoder, &vec![0u8; 1 << 20]).unwrap();
+        let zlib = encoder.finish().unwrap();
+        let varint = |bytes: &mut Vec<u8>, mut value: usize| {
+            while value >= 0x80 {
+                bytes.push((value as u8) | 0x80);
+                value >>= 7;
+            }
+            bytes.push(value as u8);
+        };
+        let mut blob = vec![0x10, 16, 0x1a];
+        varint(&mut blob, zlib.len());
+        blob.extend_from_slice(&zlib);
+        let mut blob_header = vec![0x0a, 9];
+        blob_header.extend_from_slice(b"OSMHeader");
+        blob_header.push(0x18);
+        varint(&mut blob_header, blob.len());
+
+        let err = crate::pbf::read_header_from(&stream(&blob_header, &blob)[..]).unwrap_err();
+        assert!(
+            err.to_string().contains("inflates to more than 16"),
+            "{}",
+            err
+        );
+    }
+
+    #[tokio::test]
+    async fn sampling_stops_reading_once_every_type_is_found() {
+        let dir = tempfile::tempdir().unwrap();
+        // Everything after the first way is malformed and must not be parsed
+        let xml_path = dir.path().join("sample.osm");
+        fs::write(
+            &xml_path,
+            r#"<osm version="0.6">
+                <node id="1" lat="47.1" lon="9.5"><tag k="amenity" v="cafe"/></node>
+                <node id="2" lat="47.2" lon="9.6"/>
+                <way id="10"><nd ref="1"/><nd ref="2"/><tag k="highway" v="path"/></way>
+                <relation id="20"><member type="way" ref="10" role=""/><tag k="type" v="route"/></relation>
+                <node id="3" lat="#,
         )
+        .unwrap();
+
+        let processor = processor(dir.path());
+        let sample = processor.sample_elements(&xml_path, 1).await.unwrap();
+        let found: Vec<_> = sample.iter().map(|e| (e.element_type, e.id)).collect();
+        assert_eq!(
+            found,
+            [
+                (MemberType::Node, 1),
+                (MemberType::Way, 10),
+                (MemberType::Relation, 20)
+            ]
+        );
+        assert_eq!(sample[1].coordinates, [(47.1, 9.5), (47.2, 9.6)]);
+    }
+
+    #[tokio::test]
+    async fn filter_by_bounds_keeps_complete_ways_and_touching_relations() {
+        let mut data = OsmData::default();
+        // Nodes 1-10 run from (47.101, 9.501) to (47.110, 9.510)
+        for id in 1..=10 {
+            data.nodes.insert(id, node(id, &[]));
+        }
+        data.ways
+            .insert(20, way(20, &[4, 5, 6], &[("highway", "path")]));
+        data.ways
+            .insert(21, way(21, &[8, 9, 10], &[("highway", "path")]));
+        let relation = |id, member_type, member_id| crate::pbf::OsmRelation {
+            id,
+            version: 1,
+            timestamp: 0,
+            members: vec![crate::pbf::OsmMember {
+                member_type,
+                id: member_id,
+                role: String::new(),
+            }],
+            tags: tags(&[("type", "route")]),
+        };
+        data.relations.insert(30, relation(30, MemberType::Way, 20));
+        data.relations.insert(31, relation(31, MemberType::Node, 6));
+        data.relations.insert(32, relation(32, MemberType::Way, 21));
+
+        let (dir, path) = write_fixture(&data);
+        let output = dir.path().join("extract.osm.pbf");
+        // Covers nodes 1-4 only
+        let bounds = BoundingBox::new(47.1, 9.5, 47.1045, 9.5045);
+        processor(dir.path())
+            .filter_by_bounds(&path, &bounds, &output)
+            .await
+            .unwrap();
+
+        let extract = OsmData::read_pbf(&output).unwrap();
+        assert_eq!(
+            extract.nodes.keys().copied().collect::<Vec<_>>(),
+            [1, 2, 3, 4, 5, 6]
+        );
+        assert_eq!(extract.ways.keys().copied().collect::<Vec<_>>(), [20]);
+        assert_eq!(
+            extract.relations.keys().copied().collect::<Vec<_>>(),
+            [30, 31]
+        );
+        let extent = crate::pbf::read_header(&output).unwrap().bbox.unwrap();
+        assert_eq!(
+            (extent.min_lat, extent.max_lat),
+            (bounds.min_lat, bounds.max_lat)
+        );
+    }
+
+    #[tokio::test]
+    async fn diff_files_merges_sorted_files() {
+        let mut old = OsmData::default();
+        for id in 1..=5 {
+            old.nodes.insert(id, node(id, &[]));
+        }
+        old.ways
+            .insert(10, way(10, &[1, 2], &[("highway", "path")]));
+        old.ways.insert(11, way(11, &[2, 3], &[]));
+
+        let mut new = old.clone();
+        new.nodes.remove(&1);
+        new.nodes.insert(2, node(2, &[("amenity", "bench")]));
+        new.nodes.insert(6, node(6, &[]));
+        new.nodes.insert(7, node(7, &[]));
+        new.ways.remove(&11);
+        new.ways.insert(12, way(12, &[6, 7], &[]));
+
+        let (dir, old_path) = write_fixture(&old);
+        let new_path = dir.path().join("new.osm.pbf");
+        crate::pbf::write_pbf(&new_path, &new, &PbfHeader::default()).unwrap();
+
+        let diff = processor(dir.path())
+            .diff_files(&old_path, &new_path, 2)
+            .await
+            .unwrap();
+        let ids = |category: &DiffCategory| {
+            category
+                .sample
+                .iter()
+                .map(|element| (element.element_type, element.id))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(diff.added.count, 3);
+        assert_eq!(
+            ids(&diff.added),
+            [(MemberType::Node, 6), (MemberType::Node, 7)]
+        );
+        assert_eq!(diff.modified.count, 1);
+        assert_eq!(ids(&diff.modified), [(MemberType::Node, 2)]);
+        assert_eq!(diff.deleted.count, 2);
+        assert_eq!(
+            ids(&diff.deleted),
+            [(MemberType::Node, 1), (MemberType::Way, 11)]
+        );
+    }
+
+    #[tokio::test]
+    async fn diff_rejects_unsorted_input() {
+        let mut high = OsmData::default();
+        high.nodes.insert(5, node(5, &[]));
+        let mut low = OsmData::default();
+        low.nodes.insert(1, node(1, &[]));
+        let (dir, sorted_path) = write_fixture(&high);
+        let low_path = dir.path().join("low.osm.pbf");
+        crate::pbf::write_pbf(&low_path, &low, &PbfHeader::default()).unwrap();
+
+        // Blocks of node 5, then of node 1
+        let unsorted_path = dir.path().join("unsorted.osm.pbf");
+        let mut bytes = fs::read(&sorted_path).unwrap();
+        bytes.extend(fs::read(&low_path).unwrap());
+        fs::write(&unsorted_path, bytes).unwrap();
+
+        let err = processor(dir.path())
+            .diff_files(&sorted_path, &unsorted_path, 10)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("not sorted"), "{}", err);
     }
 }
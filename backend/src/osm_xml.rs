@@ -0,0 +1,205 @@
+use crate::pbf::{MemberType, OsmElement, OsmMember, OsmNode, OsmRelation, OsmWay};
+use crate::Result;
+use bzip2::read::MultiBzDecoder;
+use flate2::read::GzDecoder;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+
+/// Section of an OsmChange document an element appeared in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeAction {
+    Create,
+    Modify,
+    Delete,
+}
+
+/// Open a possibly gzip- or bzip2-compressed file for buffered reading
+pub fn open_maybe_compressed(path: &Path) -> Result<Box<dyn BufRead>> {
+    let file = File::open(path)?;
+    let reader: Box<dyn Read> = match path.extension().and_then(|s| s.to_str()) {
+        Some("gz") => Box::new(GzDecoder::new(file)),
+        Some("bz2") => Box::new(MultiBzDecoder::new(file)),
+        _ => Box::new(file),
+    };
+    Ok(Box::new(BufReader::new(reader)))
+}
+
+/// Parse an OsmChange (`.osc` or `.osc.gz`) file into actions in document order
+pub fn parse_change_file(path: &Path) -> Result<Vec<(ChangeAction, OsmElement)>> {
+    let mut changes = Vec::new();
+
+    for_each_element(
+        open_maybe_compressed(path)?,
+        |action, element| match action {
+            Some(action) => changes.push((action, element)),
+            None => tracing::warn!(
+                "Ignoring element {} outside of a create/modify/delete section",
+                element.id()
+            ),
+        },
+    )?;
+
+    Ok(changes)
+}
+
+/// Stream OSM XML elements (`<node>`, `<way>`, `<relation>`) to a callback
+///
+/// Works for both plain `.osm` documents and OsmChange documents; for the
+/// latter the enclosing create/modify/delete section is passed along.
+pub fn for_each_element<R: BufRead>(
+    input: R,
+    mut callback: impl FnMut(Option<ChangeAction>, OsmElement),
+) -> Result<()> {
+    let mut reader = Reader::from_reader(input);
+    reader.config_mut().trim_text(true);
+
+    let mut buf = Vec::new();
+    let mut action = None;
+    let mut current: Option<OsmElement> = None;
+
+    loop {
+        let event = reader.read_event_into(&mut buf)?;
+        let is_empty = matches!(event, Event::Empty(_));
+
+        match event {
+            Event::Start(ref e) | Event::Empty(ref e) => match e.name().as_ref() {
+                b"create" => action = Some(ChangeAction::Create),
+                b"modify" => action = Some(ChangeAction::Modify),
+                b"delete" => action = Some(ChangeAction::Delete),
+                b"node" | b"way" | b"relation" => {
+                    let element = parse_element(e)?;
+                    if is_empty {
+                        callback(action, element);
+                    } else {
+                        current = Some(element);
+                    }
+                }
+                b"tag" => {
+                    if let Some(ref mut element) = current {
+                        let (k, v) = (attribute(e, b"k")?, attribute(e, b"v")?);
+                        if let (Some(k), Some(v)) = (k, v) {
+                            element_tags(element).push((k, v));
+                        }
+                    }
+                }
+                b"nd" => {
+                    if let Some(OsmElement::Way(ref mut way)) = current {
+                        if let Some(node_ref) = parse_attribute(e, b"ref")? {
+                            way.refs.push(node_ref);
+                        }
+                    }
+                }
+                b"member" => {
+                    if let Some(OsmElement::Relation(ref mut relation)) = current {
+                        let member_type = match attribute(e, b"type")?.as_deref() {
+                            Some("node") => Some(MemberType::Node),
+                            Some("way") => Some(MemberType::Way),
+                            Some("relation") => Some(MemberType::Relation),
+                            _ => None,
+                        };
+                        if let (Some(member_type), Some(id)) =
+                            (member_type, parse_attribute(e, b"ref")?)
+                        {
+                            relation.members.push(OsmMember {
+                                member_type,
+                                id,
+                                role: attribute(e, b"role")?.unwrap_or_default(),
+                            });
+                        }
+                    }
+                }
+                _ => {}
+            },
+            Event::End(ref e) => match e.name().as_ref() {
+                b"create" | b"modify" | b"delete" => action = None,
+                b"node" | b"way" | b"relation" => {
+                    if let Some(element) = current.take() {
+                        callback(action, element);
+                    }
+                }
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+
+        buf.clear();
+    }
+
+    Ok(())
+}
+
+/// Build an element (without tags/children) from its start tag attributes
+fn parse_element(e: &BytesStart) -> Result<OsmElement> {
+    let id = parse_attribute(e, b"id")?.ok_or_else(|| {
+        crate::RiemapError::OsmProcessing("OSM XML element without id".to_string())
+    })?;
+    let version = parse_attribute(e, b"version")?.unwrap_or(0);
+    let timestamp = attribute(e, b"timestamp")?
+        .and_then(|ts| chrono::DateTime::parse_from_rfc3339(&ts).ok())
+        .map(|ts| ts.timestamp_millis())
+        .unwrap_or(0);
+
+    Ok(match e.name().as_ref() {
+        b"node" => OsmElement::Node(OsmNode {
+            id,
+            version,
+            timestamp,
+            // Deleted nodes may omit their coordinates
+            lat: parse_attribute(e, b"lat")?.unwrap_or(0.0),
+            lon: parse_attribute(e, b"lon")?.unwrap_or(0.0),
+            tags: Vec::new(),
+        }),
+        b"way" => OsmElement::Way(OsmWay {
+            id,
+            version,
+            timestamp,
+            refs: Vec::new(),
+            tags: Vec::new(),
+        }),
+        _ => OsmElement::Relation(OsmRelation {
+            id,
+            version,
+            timestamp,
+            members: Vec::new(),
+            tags: Vec::new(),
+        }),
+    })
+}
+
+fn element_tags(element: &mut OsmElement) -> &mut Vec<(String, String)> {
+    match element {
+        OsmElement::Node(node) => &mut node.tags,
+        OsmElement::Way(way) => &mut way.tags,
+        OsmElement::Relation(relation) => &mut relation.tags,
+    }
+}
+
+/// Read an unescaped attribute value
+fn attribute(e: &BytesStart, name: &[u8]) -> Result<Option<String>> {
+    for attr in e.attributes() {
+        let attr = attr?;
+        if attr.key.as_ref() == name {
+            return Ok(Some(attr.unescape_value()?.to_string()));
+        }
+    }
+    Ok(None)
+}
+
+/// Read and parse an attribute value
+fn parse_attribute<T: std::str::FromStr>(e: &BytesStart, name: &[u8]) -> Result<Option<T>> {
+    match attribute(e, name)? {
+        Some(value) => value.parse().map(Some).map_err(|_| {
+            crate::RiemapError::OsmProcessing(format!(
+                "Invalid value {:?} for attribute {}",
+                value,
+                String::from_utf8_lossy(name)
+            ))
+            .into()
+        }),
+        None => Ok(None),
+    }
+}
@@ -0,0 +1,816 @@
+use crate::{models::BoundingBox, Result};
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+use osmpbf::{Element, ElementReader, RelMemberType};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
+use std::path::Path;
+
+/// Maximum number of elements written per primitive block
+pub(crate) const BLOCK_SIZE: usize = 8000;
+
+/// Coordinate resolution used when writing (100 nanodegrees, the PBF default)
+const GRANULARITY: f64 = 1e7;
+
+/// Owned OSM node
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OsmNode {
+    pub id: i64,
+    pub version: i32,
+    pub timestamp: i64, // milliseconds since epoch, 0 when unknown
+    pub lat: f64,
+    pub lon: f64,
+    pub tags: Vec<(String, String)>,
+}
+
+/// Owned OSM way
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OsmWay {
+    pub id: i64,
+    pub version: i32,
+    pub timestamp: i64,
+    pub refs: Vec<i64>,
+    pub tags: Vec<(String, String)>,
+}
+
+/// Type of a relation member, ordered as element types appear in a PBF file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum MemberType {
+    Node,
+    Way,
+    Relation,
+}
+
+impl From<RelMemberType> for MemberType {
+    fn from(member_type: RelMemberType) -> Self {
+        match member_type {
+            RelMemberType::Node => MemberType::Node,
+            RelMemberType::Way => MemberType::Way,
+            RelMemberType::Relation => MemberType::Relation,
+        }
+    }
+}
+
+/// Owned relation member
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OsmMember {
+    pub member_type: MemberType,
+    pub id: i64,
+    pub role: String,
+}
+
+/// Owned OSM relation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OsmRelation {
+    pub id: i64,
+    pub version: i32,
+    pub timestamp: i64,
+    pub members: Vec<OsmMember>,
+    pub tags: Vec<(String, String)>,
+}
+
+/// Any owned OSM element
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum OsmElement {
+    Node(OsmNode),
+    Way(OsmWay),
+    Relation(OsmRelation),
+}
+
+impl OsmElement {
+    /// OSM ID of the element
+    pub fn id(&self) -> i64 {
+        match self {
+            OsmElement::Node(node) => node.id,
+            OsmElement::Way(way) => way.id,
+            OsmElement::Relation(relation) => relation.id,
+        }
+    }
+
+    /// Type of the element
+    pub fn member_type(&self) -> MemberType {
+        match self {
+            OsmElement::Node(_) => MemberType::Node,
+            OsmElement::Way(_) => MemberType::Way,
+            OsmElement::Relation(_) => MemberType::Relation,
+        }
+    }
+
+    /// Version of the element (0 when unknown)
+    pub fn version(&self) -> i32 {
+        match self {
+            OsmElement::Node(node) => node.version,
+            OsmElement::Way(way) => way.version,
+            OsmElement::Relation(relation) => relation.version,
+        }
+    }
+
+    /// Tags of the element
+    pub fn tags(&self) -> &[(String, String)] {
+        match self {
+            OsmElement::Node(node) => &node.tags,
+            OsmElement::Way(way) => &way.tags,
+            OsmElement::Relation(relation) => &relation.tags,
+        }
+    }
+}
+
+/// Replication metadata and extent stored in a PBF `HeaderBlock`
+#[derive(Debug, Clone, Default)]
+pub struct PbfHeader {
+    pub bbox: Option<BoundingBox>,
+    pub replication_timestamp: Option<i64>, // seconds since epoch
+    pub replication_sequence_number: Option<i64>,
+    pub replication_base_url: Option<String>,
+}
+
+/// Full in-memory OSM dataset, ordered by ID within each element type
+#[derive(Debug, Clone, Default)]
+pub struct OsmData {
+    pub nodes: BTreeMap<i64, OsmNode>,
+    pub ways: BTreeMap<i64, OsmWay>,
+    pub relations: BTreeMap<i64, OsmRelation>,
+}
+
+impl OsmData {
+    /// Read every element of a PBF file into memory
+    pub fn read_pbf(path: &Path) -> Result<Self> {
+        let mut data = OsmData::default();
+
+        ElementReader::from_path(path)?.for_each(|element| {
+            data.insert(owned_element(element));
+        })?;
+
+        Ok(data)
+    }
+
+    /// Insert an element, replacing any existing element of the same type and ID
+    pub fn insert(&mut self, element: OsmElement) {
+        match element {
+            OsmElement::Node(node) => {
+                self.nodes.insert(node.id, node);
+            }
+            OsmElement::Way(way) => {
+                self.ways.insert(way.id, way);
+            }
+            OsmElement::Relation(relation) => {
+                self.relations.insert(relation.id, relation);
+            }
+        }
+    }
+
+    /// Remove an element matching the type and ID of `element`
+    pub fn remove(&mut self, element: &OsmElement) -> Option<OsmElement> {
+        match element {
+            OsmElement::Node(node) => self.nodes.remove(&node.id).map(OsmElement::Node),
+            OsmElement::Way(way) => self.ways.remove(&way.id).map(OsmElement::Way),
+            OsmElement::Relation(relation) => self
+                .relations
+                .remove(&relation.id)
+                .map(OsmElement::Relation),
+        }
+    }
+
+    /// Version of the stored element with the same type and ID, if present
+    pub fn existing_version(&self, element: &OsmElement) -> Option<i32> {
+        match element {
+            OsmElement::Node(node) => self.nodes.get(&node.id).map(|n| n.version),
+            OsmElement::Way(way) => self.ways.get(&way.id).map(|w| w.version),
+            OsmElement::Relation(relation) => self.relations.get(&relation.id).map(|r| r.version),
+        }
+    }
+}
+
+/// Convert a borrowed `osmpbf` element into an owned element
+pub fn owned_element(element: Element) -> OsmElement {
+    let tags = |iter: &mut dyn Iterator<Item = (&str, &str)>| {
+        iter.map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect::<Vec<_>>()
+    };
+
+    match element {
+        Element::Node(node) => {
+            let info = node.info();
+            OsmElement::Node(OsmNode {
+                id: node.id(),
+                version: info.version().unwrap_or(0),
+                timestamp: info.milli_timestamp().unwrap_or(0),
+                lat: node.lat(),
+                lon: node.lon(),
+                tags: tags(&mut node.tags()),
+            })
+        }
+        Element::DenseNode(node) => {
+            let (version, timestamp) = node
+                .info()
+                .map(|info| (info.version(), info.milli_timestamp()))
+                .unwrap_or((0, 0));
+            OsmElement::Node(OsmNode {
+                id: node.id(),
+                version,
+                timestamp,
+                lat: node.lat(),
+                lon: node.lon(),
+                tags: tags(&mut node.tags()),
+            })
+        }
+        Element::Way(way) => {
+            let info = way.info();
+            OsmElement::Way(OsmWay {
+                id: way.id(),
+                version: info.version().unwrap_or(0),
+                timestamp: info.milli_timestamp().unwrap_or(0),
+                refs: way.refs().collect(),
+                tags: tags(&mut way.tags()),
+            })
+        }
+        Element::Relation(relation) => {
+            let info = relation.info();
+            let members = relation
+                .members()
+                .map(|member| OsmMember {
+                    member_type: member.member_type.clone().into(),
+                    id: member.member_id,
+                    role: member.role().unwrap_or_default().to_string(),
+                })
+                .collect();
+            OsmElement::Relation(OsmRelation {
+                id: relation.id(),
+                version: info.version().unwrap_or(0),
+                timestamp: info.milli_timestamp().unwrap_or(0),
+                members,
+                tags: tags(&mut relation.tags()),
+            })
+        }
+    }
+}
+
+/// Largest `BlobHeader` and (uncompressed) `Blob` the PBF format allows
+const MAX_BLOB_HEADER_SIZE: usize = 64 * 1024;
+const MAX_BLOB_SIZE: usize = 32 * 1024 * 1024;
+
+/// Read the `HeaderBlock` at the start of a PBF file
+pub fn read_header(path: &Path) -> Result<PbfHeader> {
+    read_header_from(File::open(path)?)
+}
+
+/// Read the `HeaderBlock` at the start of a PBF stream
+///
+/// Sizes beyond the format's limits are rejected before anything is
+/// allocated for them, so other data fails after its first bytes. A stream
+/// ending inside the header block fails with an `UnexpectedEof` I/O error.
+pub fn read_header_from(mut file: impl Read) -> Result<PbfHeader> {
+    let mut len_bytes = [0u8; 4];
+    file.read_exact(&mut len_bytes)?;
+    let mut blob_header = vec![0u8; blob_header_size(len_bytes)?];
+    file.read_exact(&mut blob_header)?;
+    let data_size = header_blob_size(&blob_header)?;
+
+    let mut blob = vec![0u8; data_size];
+    file.read_exact(&mut blob)?;
+    let block = decode_blob(&blob)?;
+
+    let mut header = PbfHeader::default();
+    for field in ProtoReader::new(&block) {
+        match field? {
+            (1, ProtoValue::Bytes(bbox)) => header.bbox = decode_header_bbox(bbox)?,
+            (32, ProtoValue::Varint(ts)) => header.replication_timestamp = Some(ts as i64),
+            (33, ProtoValue::Varint(seq)) => header.replication_sequence_number = Some(seq as i64),
+            (34, ProtoValue::Bytes(url)) => {
+                header.replication_base_url = Some(String::from_utf8_lossy(url).to_string())
+            }
+            _ => {}
+        }
+    }
+
+    Ok(header)
+}
+
+/// Size of the `BlobHeader` announced by the 4-byte length prefix
+fn blob_header_size(len_bytes: [u8; 4]) -> Result<usize> {
+    let size = u32::from_be_bytes(len_bytes) as usize;
+    if size > MAX_BLOB_HEADER_SIZE {
+        return Err(crate::RiemapError::OsmProcessing(format!(
+            "Blob header of {} bytes exceeds the PBF limit of {}",
+            size, MAX_BLOB_HEADER_SIZE
+        ))
+        .into());
+    }
+    Ok(size)
+}
+
+/// Size of the `Blob` following a `BlobHeader`, which must announce the
+/// `OSMHeader` block
+fn header_blob_size(blob_header: &[u8]) -> Result<usize> {
+    let mut blob_type = String::new();
+    let mut data_size = 0usize;
+    for field in ProtoReader::new(blob_header) {
+        match field? {
+            (1, ProtoValue::Bytes(bytes)) => blob_type = String::from_utf8_lossy(bytes).to_string(),
+            (3, ProtoValue::Varint(size)) => data_size = size as usize,
+            _ => {}
+        }
+    }
+
+    if blob_type != "OSMHeader" {
+        return Err(crate::RiemapError::OsmProcessing(format!(
+            "Expected OSMHeader blob, found {:?}",
+            blob_type
+        ))
+        .into());
+    }
+    if data_size > MAX_BLOB_SIZE {
+        return Err(crate::RiemapError::OsmProcessing(format!(
+            "Header blob of {} bytes exceeds the PBF limit of {}",
+            data_size, MAX_BLOB_SIZE
+        ))
+        .into());
+    }
+    Ok(data_size)
+}
+
+/// The start of a PBF stream arriving in chunks, kept until its header
+/// block is complete
+///
+/// The block's length is taken from its first bytes, so the block itself
+/// is only parsed once all of it has arrived.
+#[derive(Default)]
+pub struct HeaderProbe {
+    bytes: Vec<u8>,
+    block_len: Option<usize>,
+}
+
+impl HeaderProbe {
+    /// Add the next chunk, returning the header once its block is complete
+    ///
+    /// Fails as soon as the bytes so far cannot start a PBF file.
+    pub fn push(&mut self, chunk: &[u8]) -> Result<Option<PbfHeader>> {
+        self.bytes.extend_from_slice(chunk);
+
+        if self.block_len.is_none() {
+            let Some(len_bytes) = self.bytes.first_chunk::<4>() else {
+                return Ok(None);
+            };
+            let header_end = 4 + blob_header_size(*len_bytes)?;
+            let Some(blob_header) = self.bytes.get(4..header_end) else {
+                return Ok(None);
+            };
+            self.block_len = Some(header_end + header_blob_size(blob_header)?);
+        }
+
+        match self.block_len {
+            Some(len) if self.bytes.len() >= len => read_header_from(&self.bytes[..len]).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    /// The header of a stream that has ended; fails with an `UnexpectedEof`
+    /// I/O error when it ended inside the header block
+    pub fn finish(self) -> Result<PbfHeader> {
+        read_header_from(&self.bytes[..])
+    }
+}
+
+/// Decompress the payload of a `Blob` message
+///
+/// Compressed data is inflated to at most its declared `raw_size`, and never
+/// beyond the format's 32 MiB limit, so a small blob cannot expand without
+/// bound.
+fn decode_blob(blob: &[u8]) -> Result<Vec<u8>> {
+    let (mut raw_size, mut zlib_data) = (None, None);
+    for field in ProtoReader::new(blob) {
+        match field? {
+            (1, ProtoValue::Bytes(raw)) => return Ok(raw.to_vec()),
+            (2, ProtoValue::Varint(size)) => raw_size = Some(size as usize),
+            (3, ProtoValue::Bytes(zlib)) => zlib_data = Some(zlib),
+            _ => {}
+        }
+    }
+
+    let Some(zlib) = zlib_data else {
+        return Err(crate::RiemapError::OsmProcessing(
+            "Unsupported PBF blob compression".to_string(),
+        )
+        .into());
+    };
+    let limit = raw_size.map_or(MAX_BLOB_SIZE, |size| size.min(MAX_BLOB_SIZE));
+    let mut data = Vec::new();
+    ZlibDecoder::new(zlib)
+        .take(limit as u64 + 1)
+        .read_to_end(&mut data)?;
+    if data.len() > limit {
+        return Err(crate::RiemapError::OsmProcessing(format!(
+            "PBF blob inflates to more than {} bytes",
+            limit
+        ))
+        .into());
+    }
+    Ok(data)
+}
+
+/// Decode a `HeaderBBox` message (nanodegree sint64 fields)
+fn decode_header_bbox(bytes: &[u8]) -> Result<Option<BoundingBox>> {
+    let (mut left, mut right, mut top, mut bottom) = (None, None, None, None);
+    for field in ProtoReader::new(bytes) {
+        if let (number, ProtoValue::Varint(value)) = field? {
+            let degrees = Some(zigzag_decode(value) as f64 / 1e9);
+            match number {
+                1 => left = degrees,
+                2 => right = degrees,
+                3 => top = degrees,
+                4 => bottom = degrees,
+                _ => {}
+            }
+        }
+    }
+
+    Ok(match (left, right, top, bottom) {
+        (Some(left), Some(right), Some(top), Some(bottom)) => {
+            Some(BoundingBox::new(bottom, left, top, right))
+        }
+        _ => None,
+    })
+}
+
+/// Write a dataset as an OSM PBF file (DenseNodes, zlib-compressed blocks)
+pub fn write_pbf(path: &Path, data: &OsmData, header: &PbfHeader) -> Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+
+    write_blob(&mut out, "OSMHeader", &encode_header(header))?;
+
+    let nodes: Vec<&OsmNode> = data.nodes.values().collect();
+    for chunk in nodes.chunks(BLOCK_SIZE) {
+        write_blob(&mut out, "OSMData", &encode_node_block(chunk))?;
+    }
+
+    let ways: Vec<&OsmWay> = data.ways.values().collect();
+    for chunk in ways.chunks(BLOCK_SIZE) {
+        write_blob(&mut out, "OSMData", &encode_way_block(chunk))?;
+    }
+
+    let relations: Vec<&OsmRelation> = data.relations.values().collect();
+    for chunk in relations.chunks(BLOCK_SIZE) {
+        write_blob(&mut out, "OSMData", &encode_relation_block(chunk))?;
+    }
+
+    out.flush()?;
+    Ok(())
+}
+
+/// Wrap a block in a zlib-compressed `Blob` preceded by its `BlobHeader`
+fn write_blob(out: &mut impl Write, blob_type: &str, data: &[u8]) -> Result<()> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data)?;
+    let compressed = encoder.finish()?;
+
+    let mut blob = ProtoWriter::default();
+    blob.varint(2, data.len() as u64);
+    blob.bytes(3, &compressed);
+
+    let mut blob_header = ProtoWriter::default();
+    blob_header.bytes(1, blob_type.as_bytes());
+    blob_header.varint(3, blob.buf.len() as u64);
+
+    out.write_all(&(blob_header.buf.len() as u32).to_be_bytes())?;
+    out.write_all(&blob_header.buf)?;
+    out.write_all(&blob.buf)?;
+    Ok(())
+}
+
+fn encode_header(header: &PbfHeader) -> Vec<u8> {
+    let mut block = ProtoWriter::default();
+
+    if let Some(ref bbox) = header.bbox {
+        let mut proto_bbox = ProtoWriter::default();
+        proto_bbox.sint(1, (bbox.min_lon * 1e9) as i64);
+        proto_bbox.sint(2, (bbox.max_lon * 1e9) as i64);
+        proto_bbox.sint(3, (bbox.max_lat * 1e9) as i64);
+        proto_bbox.sint(4, (bbox.min_lat * 1e9) as i64);
+        block.bytes(1, &proto_bbox.buf);
+    }
+
+    block.bytes(4, b"OsmSchema-V0.6");
+    block.bytes(4, b"DenseNodes");
+    block.bytes(16, b"riemap");
+
+    if let Some(ts) = header.replication_timestamp {
+        block.varint(32, ts as u64);
+    }
+    if let Some(seq) = header.replication_sequence_number {
+        block.varint(33, seq as u64);
+    }
+    if let Some(ref url) = header.replication_base_url {
+        block.bytes(34, url.as_bytes());
+    }
+
+    block.buf
+}
+
+fn encode_node_block(nodes: &[&OsmNode]) -> Vec<u8> {
+    let mut strings = StringTable::default();
+    let (mut ids, mut lats, mut lons, mut keys_vals) =
+        (Vec::new(), Vec::new(), Vec::new(), Vec::new());
+    let (mut versions, mut timestamps) = (Vec::new(), Vec::new());
+
+    for node in nodes {
+        ids.push(node.id);
+        lats.push((node.lat * GRANULARITY).round() as i64);
+        lons.push((node.lon * GRANULARITY).round() as i64);
+        versions.push(node.version as u64);
+        timestamps.push(node.timestamp / 1000);
+        for (k, v) in &node.tags {
+            keys_vals.push(strings.index(k) as u64);
+            keys_vals.push(strings.index(v) as u64);
+        }
+        keys_vals.push(0);
+    }
+
+    let zeros = vec![0i64; nodes.len()];
+    let mut dense_info = ProtoWriter::default();
+    dense_info.packed_varint(1, &versions);
+    dense_info.packed_sint_delta(2, &timestamps);
+    dense_info.packed_sint_delta(3, &zeros); // changeset
+    dense_info.packed_sint_delta(4, &zeros); // uid
+    dense_info.packed_sint_delta(5, &zeros); // user_sid
+
+    let mut dense = ProtoWriter::default();
+    dense.packed_sint_delta(1, &ids);
+    dense.bytes(5, &dense_info.buf);
+    dense.packed_sint_delta(8, &lats);
+    dense.packed_sint_delta(9, &lons);
+    dense.packed_varint(10, &keys_vals);
+
+    let mut group = ProtoWriter::default();
+    group.bytes(2, &dense.buf);
+
+    primitive_block(strings, group)
+}
+
+fn encode_way_block(ways: &[&OsmWay]) -> Vec<u8> {
+    let mut strings = StringTable::default();
+    let mut group = ProtoWriter::default();
+
+    for way in ways {
+        let mut proto_way = ProtoWriter::default();
+        proto_way.varint(1, way.id as u64);
+        encode_tags(&mut proto_way, &mut strings, &way.tags);
+        proto_way.bytes(4, &encode_info(way.version, way.timestamp));
+        proto_way.packed_sint_delta(8, &way.refs);
+        group.bytes(3, &proto_way.buf);
+    }
+
+    primitive_block(strings, group)
+}
+
+fn encode_relation_block(relations: &[&OsmRelation]) -> Vec<u8> {
+    let mut strings = StringTable::default();
+    let mut group = ProtoWriter::default();
+
+    for relation in relations {
+        let mut proto_relation = ProtoWriter::default();
+        proto_relation.varint(1, relation.id as u64);
+        encode_tags(&mut proto_relation, &mut strings, &relation.tags);
+        proto_relation.bytes(4, &encode_info(relation.version, relation.timestamp));
+
+        let roles: Vec<u64> = relation
+            .members
+            .iter()
+            .map(|m| strings.index(&m.role) as u64)
+            .collect();
+        let ids: Vec<i64> = relation.members.iter().map(|m| m.id).collect();
+        let types: Vec<u64> = relation
+            .members
+            .iter()
+            .map(|m| match m.member_type {
+                MemberType::Node => 0,
+                MemberType::Way => 1,
+                MemberType::Relation => 2,
+            })
+            .collect();
+
+        proto_relation.packed_varint(8, &roles);
+        proto_relation.packed_sint_delta(9, &ids);
+        proto_relation.packed_varint(10, &types);
+        group.bytes(4, &proto_relation.buf);
+    }
+
+    primitive_block(strings, group)
+}
+
+fn encode_tags(message: &mut ProtoWriter, strings: &mut StringTable, tags: &[(String, String)]) {
+    let keys: Vec<u64> = tags.iter().map(|(k, _)| strings.index(k) as u64).collect();
+    let vals: Vec<u64> = tags.iter().map(|(_, v)| strings.index(v) as u64).collect();
+    message.packed_varint(2, &keys);
+    message.packed_varint(3, &vals);
+}
+
+fn encode_info(version: i32, timestamp_ms: i64) -> Vec<u8> {
+    let mut info = ProtoWriter::default();
+    info.varint(1, version as u64);
+    info.varint(2, (timestamp_ms / 1000) as u64);
+    info.buf
+}
+
+fn primitive_block(strings: StringTable, group: ProtoWriter) -> Vec<u8> {
+    let mut table = ProtoWriter::default();
+    for s in &strings.strings {
+        table.bytes(1, s.as_bytes());
+    }
+
+    let mut block = ProtoWriter::default();
+    block.bytes(1, &table.buf);
+    block.bytes(2, &group.buf);
+    block.buf
+}
+
+/// Per-block string table; index 0 is reserved for the empty string
+struct StringTable {
+    strings: Vec<String>,
+    indices: HashMap<String, u32>,
+}
+
+impl Default for StringTable {
+    fn default() -> Self {
+        Self {
+            strings: vec![String::new()],
+            indices: HashMap::new(),
+        }
+    }
+}
+
+impl StringTable {
+    fn index(&mut self, s: &str) -> u32 {
+        if let Some(&index) = self.indices.get(s) {
+            return index;
+        }
+
+        let index = self.strings.len() as u32;
+        self.strings.push(s.to_string());
+        self.indices.insert(s.to_string(), index);
+        index
+    }
+}
+
+fn zigzag_encode(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+fn zigzag_decode(value: u64) -> i64 {
+    ((value >> 1) as i64) ^ -((value & 1) as i64)
+}
+
+/// Minimal protobuf encoder for the messages used by the PBF format
+#[derive(Default)]
+struct ProtoWriter {
+    buf: Vec<u8>,
+}
+
+impl ProtoWriter {
+    fn raw_varint(buf: &mut Vec<u8>, mut value: u64) {
+        while value >= 0x80 {
+            buf.push((value as u8) | 0x80);
+            value >>= 7;
+        }
+        buf.push(value as u8);
+    }
+
+    fn key(&mut self, field: u32, wire_type: u8) {
+        Self::raw_varint(&mut self.buf, ((field as u64) << 3) | wire_type as u64);
+    }
+
+    fn varint(&mut self, field: u32, value: u64) {
+        self.key(field, 0);
+        Self::raw_varint(&mut self.buf, value);
+    }
+
+    fn sint(&mut self, field: u32, value: i64) {
+        self.varint(field, zigzag_encode(value));
+    }
+
+    fn bytes(&mut self, field: u32, bytes: &[u8]) {
+        self.key(field, 2);
+        Self::raw_varint(&mut self.buf, bytes.len() as u64);
+        self.buf.extend_from_slice(bytes);
+    }
+
+    fn packed_varint(&mut self, field: u32, values: &[u64]) {
+        if values.is_empty() {
+            return;
+        }
+        let mut packed = Vec::new();
+        for &value in values {
+            Self::raw_varint(&mut packed, value);
+        }
+        self.bytes(field, &packed);
+    }
+
+    fn packed_sint_delta(&mut self, field: u32, values: &[i64]) {
+        let mut previous = 0i64;
+        let deltas: Vec<u64> = values
+            .iter()
+            .map(|&value| {
+                let delta = zigzag_encode(value.wrapping_sub(previous));
+                previous = value;
+                delta
+            })
+            .collect();
+        self.packed_varint(field, &deltas);
+    }
+}
+
+/// Value of a decoded protobuf field
+enum ProtoValue<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+    Fixed,
+}
+
+/// Minimal protobuf field iterator for decoding PBF headers
+struct ProtoReader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ProtoReader<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        Self { buf, pos: 0 }
+    }
+
+    fn read_varint(&mut self) -> Result<u64> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = *self.buf.get(self.pos).ok_or_else(truncated)?;
+            self.pos += 1;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(truncated())
+    }
+
+    /// The next `len` bytes; lengths come from the data, so they are checked
+    /// against the buffer rather than trusted
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|&end| end <= self.buf.len())
+            .ok_or_else(truncated)?;
+        let bytes = &self.buf[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn read_field(&mut self) -> Result<(u32, ProtoValue<'a>)> {
+        let key = self.read_varint()?;
+        let field = (key >> 3) as u32;
+
+        let value = match key & 0x7 {
+            0 => ProtoValue::Varint(self.read_varint()?),
+            1 => {
+                self.take(8)?;
+                ProtoValue::Fixed
+            }
+            2 => {
+                let len = usize::try_from(self.read_varint()?).map_err(|_| truncated())?;
+                ProtoValue::Bytes(self.take(len)?)
+            }
+            5 => {
+                self.take(4)?;
+                ProtoValue::Fixed
+            }
+            wire_type => {
+                return Err(crate::RiemapError::OsmProcessing(format!(
+                    "Unsupported protobuf wire type {}",
+                    wire_type
+                ))
+                .into())
+            }
+        };
+
+        Ok((field, value))
+    }
+}
+
+impl<'a> Iterator for ProtoReader<'a> {
+    type Item = Result<(u32, ProtoValue<'a>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.pos >= self.buf.len() {
+            return None;
+        }
+
+        let field = self.read_field();
+        if field.is_err() {
+            // Stop after the first malformed field
+            self.pos = self.buf.len();
+        }
+        Some(field)
+    }
+}
+
+fn truncated() -> anyhow::Error {
+    crate::RiemapError::OsmProcessing("Truncated protobuf message".to_string()).into()
+}
@@ -0,0 +1,163 @@
+use crate::{models::BoundingBox, Result};
+use std::path::Path;
+
+/// A closed ring of (lon, lat) points
+#[derive(Debug, Clone)]
+pub struct PolyRing {
+    pub name: String,
+    pub points: Vec<(f64, f64)>,
+    pub hole: bool, // Section name started with `!`: subtracted from the area
+}
+
+/// An area read from an Osmosis `.poly` file
+///
+/// A point is inside when it lies in at least one outer ring and in none of
+/// the holes, which is how Osmosis and osmium interpret these files.
+#[derive(Debug, Clone)]
+pub struct Polygon {
+    pub name: String,
+    pub rings: Vec<PolyRing>,
+    bbox: BoundingBox,
+}
+
+impl Polygon {
+    /// Read and parse a `.poly` file
+    pub fn read(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path).map_err(|e| {
+            crate::RiemapError::OsmProcessing(format!(
+                "Failed to read polygon file {:?}: {}",
+                path, e
+            ))
+        })?;
+        Self::parse(&text)
+    }
+
+    /// Parse the `.poly` format: a name line, then sections of `lon lat`
+    /// lines each closed by `END`, and a final `END`
+    pub fn parse(text: &str) -> Result<Self> {
+        let invalid = |line: usize, message: &str| {
+            crate::RiemapError::OsmProcessing(format!(
+                "Invalid polygon file (line {}): {}",
+                line, message
+            ))
+        };
+
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty());
+
+        let name = lines
+            .next()
+            .map(|(_, line)| line.to_string())
+            .ok_or_else(|| invalid(1, "file is empty"))?;
+
+        let mut rings = Vec::new();
+        let mut finished = false;
+        while let Some((number, line)) = lines.next() {
+            if line == "END" {
+                finished = true;
+                break;
+            }
+
+            let (ring_name, hole) = match line.strip_prefix('!') {
+                Some(ring_name) => (ring_name.to_string(), true),
+                None => (line.to_string(), false),
+            };
+
+            let mut points = Vec::new();
+            let mut closed = false;
+            for (number, line) in lines.by_ref() {
+                if line == "END" {
+                    closed = true;
+                    break;
+                }
+                let mut fields = line.split_whitespace().map(str::parse::<f64>);
+                match (fields.next(), fields.next(), fields.next()) {
+                    (Some(Ok(lon)), Some(Ok(lat)), None) => points.push((lon, lat)),
+                    _ => return Err(invalid(number, "expected `longitude latitude`").into()),
+                }
+            }
+
+            if !closed {
+                return Err(invalid(number, "section is missing its END").into());
+            }
+            if points.len() < 3 {
+                return Err(invalid(number, "a ring needs at least three points").into());
+            }
+            rings.push(PolyRing {
+                name: ring_name,
+                points,
+                hole,
+            });
+        }
+
+        if !finished {
+            return Err(invalid(text.lines().count(), "missing final END").into());
+        }
+
+        let outer: Vec<&(f64, f64)> = rings
+            .iter()
+            .filter(|ring| !ring.hole)
+            .flat_map(|ring| &ring.points)
+            .collect();
+        if outer.is_empty() {
+            return Err(invalid(1, "no outer ring").into());
+        }
+
+        let bbox = outer.iter().fold(
+            BoundingBox::new(f64::MAX, f64::MAX, f64::MIN, f64::MIN),
+            |bbox, &&(lon, lat)| {
+                BoundingBox::new(
+                    bbox.min_lat.min(lat),
+                    bbox.min_lon.min(lon),
+                    bbox.max_lat.max(lat),
+                    bbox.max_lon.max(lon),
+                )
+            },
+        );
+
+        Ok(Self { name, rings, bbox })
+    }
+
+    /// Extent of the outer rings
+    pub fn bounding_box(&self) -> &BoundingBox {
+        &self.bbox
+    }
+
+    pub fn contains(&self, lat: f64, lon: f64) -> bool {
+        if lat < self.bbox.min_lat
+            || lat > self.bbox.max_lat
+            || lon < self.bbox.min_lon
+            || lon > self.bbox.max_lon
+        {
+            return false;
+        }
+
+        let in_rings = |hole: bool| {
+            self.rings
+                .iter()
+                .filter(|ring| ring.hole == hole)
+                .any(|ring| ring_contains(&ring.points, lat, lon))
+        };
+        in_rings(false) && !in_rings(true)
+    }
+}
+
+/// Even-odd ray casting; the closing edge back to the first point is implied
+fn ring_contains(points: &[(f64, f64)], lat: f64, lon: f64) -> bool {
+    let mut inside = false;
+    let mut previous = points[points.len() - 1];
+
+    for &point in points {
+        let ((lon1, lat1), (lon2, lat2)) = (previous, point);
+        if (lat1 > lat) != (lat2 > lat) && lon < lon1 + (lat - lat1) / (lat2 - lat1) * (lon2 - lon1)
+        {
+            inside = !inside;
+        }
+        previous = point;
+    }
+
+    inside
+}
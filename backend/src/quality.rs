@@ -1,13 +1,161 @@
 use crate::{models::*, Result};
 use chrono::Utc;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use tracing::info;
 use uuid::Uuid;
 
+/// Weights applied when turning metrics and issues into a 0-100 quality score
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScoringWeights {
+    /// Points deducted per geometry error
+    pub geometry_error: f64,
+    /// Points deducted per topology error
+    pub topology_error: f64,
+    /// Points deducted per tag error
+    pub tag_error: f64,
+    /// Points deducted per critical issue
+    pub critical_issue: f64,
+    /// Points deducted per high severity issue
+    pub high_issue: f64,
+    /// Points deducted per medium severity issue
+    pub medium_issue: f64,
+    /// Points deducted per low severity issue
+    pub low_issue: f64,
+    /// Points added per percent of completeness
+    pub completeness_bonus: f64,
+    /// Points added per percent of elements edited within the year before the extract
+    #[serde(default = "default_freshness_bonus")]
+    pub freshness_bonus: f64,
+}
+
+fn default_freshness_bonus() -> f64 {
+    0.05
+}
+
+impl Default for ScoringWeights {
+    fn default() -> Self {
+        Self {
+            geometry_error: 2.0,
+            topology_error: 1.5,
+            tag_error: 1.0,
+            critical_issue: 20.0,
+            high_issue: 10.0,
+            medium_issue: 5.0,
+            low_issue: 1.0,
+            completeness_bonus: 0.3,
+            freshness_bonus: default_freshness_bonus(),
+        }
+    }
+}
+
+/// Completeness expectations for extracts of one administrative level
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompletenessBaseline {
+    /// Minimum share of tagged elements (0-1)
+    pub min_tagging_rate: f64,
+    /// Minimum number of ways per 100 nodes
+    pub min_ways_per_100_nodes: f64,
+}
+
+/// Default baselines: large extracts are mostly untagged geometry nodes, so
+/// smaller areas are held to a stricter bar
+pub fn default_completeness_baselines() -> HashMap<AdminLevel, CompletenessBaseline> {
+    [
+        (AdminLevel::World, 0.05, 0.5),
+        (AdminLevel::Continent, 0.05, 0.5),
+        (AdminLevel::Country, 0.08, 0.8),
+        (AdminLevel::Region, 0.10, 1.0),
+        (AdminLevel::Subregion, 0.15, 1.0),
+    ]
+    .into_iter()
+    .map(|(level, min_tagging_rate, min_ways_per_100_nodes)| {
+        (
+            level,
+            CompletenessBaseline {
+                min_tagging_rate,
+                min_ways_per_100_nodes,
+            },
+        )
+    })
+    .collect()
+}
+
+/// Amenity kinds with fewer elements than this are too small to judge
+const AMENITY_AUDIT_MIN_COUNT: u64 = 5;
+
+/// Share of an amenity kind lacking a tag before an issue is raised
+const AMENITY_AUDIT_MISSING_SHARE: f64 = 0.5;
+
+/// Default share of buildings with a height or level count below which city
+/// extracts get an issue
+pub const DEFAULT_MIN_BUILDING_3D_COVERAGE: f64 = 0.25;
+
+/// Extracts with fewer buildings than this are too small to judge 3D coverage
+const BUILDING_3D_MIN_COUNT: u64 = 50;
+
 /// Quality analyzer for OSM data
-pub struct QualityAnalyzer;
+pub struct QualityAnalyzer {
+    weights: ScoringWeights,
+    baselines: HashMap<AdminLevel, CompletenessBaseline>,
+    /// Whether country extracts are expected to contain coastline
+    expect_coastline: bool,
+    /// Share of buildings (0-1) expected to have a height or level count
+    min_building_3d_coverage: f64,
+}
+
+impl Default for QualityAnalyzer {
+    fn default() -> Self {
+        Self {
+            weights: ScoringWeights::default(),
+            baselines: default_completeness_baselines(),
+            expect_coastline: true,
+            min_building_3d_coverage: DEFAULT_MIN_BUILDING_3D_COVERAGE,
+        }
+    }
+}
 
 impl QualityAnalyzer {
+    /// Create an analyzer with custom scoring weights
+    pub fn with_weights(weights: ScoringWeights) -> Self {
+        Self {
+            weights,
+            ..Self::default()
+        }
+    }
+
+    /// Override the completeness baseline for an administrative level
+    pub fn with_baseline(mut self, level: AdminLevel, baseline: CompletenessBaseline) -> Self {
+        self.baselines.insert(level, baseline);
+        self
+    }
+
+    /// Enable or skip the coastline check, e.g. for landlocked countries
+    pub fn with_coastline_check(mut self, enabled: bool) -> Self {
+        self.expect_coastline = enabled;
+        self
+    }
+
+    /// Set the share of buildings (0-1) expected to carry a height or level
+    /// count in city extracts; 0 disables the check
+    pub fn with_building_3d_coverage(mut self, min_coverage: f64) -> Self {
+        self.min_building_3d_coverage = min_coverage;
+        self
+    }
+
+    /// Completeness baseline applied to extracts of the given level
+    pub fn baseline(&self, level: &AdminLevel) -> CompletenessBaseline {
+        self.baselines
+            .get(level)
+            .cloned()
+            .unwrap_or_else(|| default_completeness_baselines()[level].clone())
+    }
+
+    /// Scoring weights this analyzer was configured with
+    pub fn weights(&self) -> &ScoringWeights {
+        &self.weights
+    }
+
     /// Generate a comprehensive quality report for metrics
     pub async fn generate_report(
         &self,
@@ -36,22 +184,31 @@ impl QualityAnalyzer {
         Ok(report)
     }
 
-    /// Analyze completeness of OSM data
-    pub fn analyze_completeness(&self, metrics: &QualityMetrics) -> Vec<QualityIssue> {
+    /// Analyze completeness of OSM data against the baseline for the region's level
+    pub fn analyze_completeness(
+        &self,
+        metrics: &QualityMetrics,
+        admin_level: &AdminLevel,
+    ) -> Vec<QualityIssue> {
         let mut issues = Vec::new();
+        let baseline = self.baseline(admin_level);
+        let level = format!("{:?}", admin_level).to_lowercase();
 
         // Check for very low tagging rates
-        let total_elements = metrics.total_nodes + metrics.total_ways + metrics.total_relations;
+        let total_elements = metrics.total_elements();
         if total_elements > 0 {
-            let tagging_rate =
-                (metrics.tagged_nodes + metrics.tagged_ways + metrics.tagged_relations) as f64
-                    / total_elements as f64;
+            let tagging_rate = metrics.tagged_elements() as f64 / total_elements as f64;
 
-            if tagging_rate < 0.1 {
+            if tagging_rate < baseline.min_tagging_rate {
                 issues.push(QualityIssue {
                     issue_type: "low_tagging_rate".to_string(),
                     severity: IssueSeverity::High,
-                    description: format!("Very low tagging rate: {:.1}%", tagging_rate * 100.0),
+                    description: format!(
+                        "Very low tagging rate: {:.1}% (below the {:.1}% baseline for {} extracts)",
+                        tagging_rate * 100.0,
+                        baseline.min_tagging_rate * 100.0,
+                        level
+                    ),
                     location: None,
                     osm_id: None,
                     osm_type: None,
@@ -63,12 +220,19 @@ impl QualityAnalyzer {
             }
         }
 
-        // Check for missing key feature types
-        if metrics.total_ways < metrics.total_nodes / 100 {
-            issues.push(QualityIssue {
+        // Check for missing key feature types, when both were counted
+        if let (Some(nodes), Some(ways)) = (metrics.total_nodes, metrics.total_ways) {
+            let min_ways = nodes as f64 * baseline.min_ways_per_100_nodes / 100.0;
+            if (ways as f64) < min_ways {
+                issues.push(QualityIssue {
                 issue_type: "low_way_density".to_string(),
                 severity: IssueSeverity::Medium,
-                description: "Unusually low number of ways compared to nodes".to_string(),
+                description: format!(
+                    "Unusually low number of ways compared to nodes: {:.2} per 100 nodes (below the {:.2} baseline for {} extracts)",
+                    ways as f64 * 100.0 / nodes as f64,
+                    baseline.min_ways_per_100_nodes,
+                    level
+                ),
                 location: None,
                 osm_id: None,
                 osm_type: None,
@@ -76,6 +240,69 @@ impl QualityAnalyzer {
                     "Verify that linear features (roads, paths) are properly mapped".to_string(),
                 ),
             });
+            }
+        }
+
+        issues
+    }
+
+    /// Check that country and region extracts contain boundary features and,
+    /// for countries unless disabled, coastline
+    pub fn analyze_boundaries(
+        &self,
+        metrics: &QualityMetrics,
+        admin_level: &AdminLevel,
+    ) -> Vec<QualityIssue> {
+        let mut issues = Vec::new();
+        if !matches!(admin_level, AdminLevel::Country | AdminLevel::Region) {
+            return issues;
+        }
+
+        // Boundaries are mostly relations and coastlines are ways, so neither
+        // can be judged missing when their element type was skipped
+        let features = &metrics.feature_distribution;
+        if features.boundaries == 0 && metrics.total_relations.is_some() {
+            let level = format!("{:?}", admin_level).to_lowercase();
+            let description = if metrics.total_relations == Some(0) {
+                format!(
+                    "No boundary features and no relations in this {} extract",
+                    level
+                )
+            } else {
+                format!("No boundary features in this {} extract", level)
+            };
+
+            issues.push(QualityIssue {
+                issue_type: "missing_boundaries".to_string(),
+                severity: IssueSeverity::Medium,
+                description,
+                location: None,
+                osm_id: None,
+                osm_type: None,
+                fix_suggestion: Some(
+                    "Check that the extract includes its boundary=administrative relations"
+                        .to_string(),
+                ),
+            });
+        }
+
+        if self.expect_coastline
+            && matches!(admin_level, AdminLevel::Country)
+            && features.coastlines == 0
+            && metrics.total_ways.is_some()
+        {
+            issues.push(QualityIssue {
+                issue_type: "missing_coastline".to_string(),
+                severity: IssueSeverity::Low,
+                description: "No natural=coastline ways in this country extract".to_string(),
+                location: None,
+                osm_id: None,
+                osm_type: None,
+                fix_suggestion: Some(
+                    "Verify the coastline was not clipped; skip this check for landlocked countries"
+                        .to_string(),
+                ),
+            });
         }
 
         issues
@@ -86,7 +313,11 @@ impl QualityAnalyzer {
         let mut issues = Vec::new();
 
         // Check for suspicious ratios
-        if metrics.total_relations > metrics.total_ways {
+        if metrics
+            .total_relations
+            .zip(metrics.total_ways)
+            .is_some_and(|(relations, ways)| relations > ways)
+        {
             issues.push(QualityIssue {
                 issue_type: "high_relation_ratio".to_string(),
                 severity: IssueSeverity::Medium,
@@ -132,35 +363,331 @@ impl QualityAnalyzer {
         issues
     }
 
+    /// Turn the per-kind amenity tag counts collected while reading into issues
+    ///
+    /// Raises a `Medium` issue when most amenities of a kind are unnamed and
+    /// `Low` issues for missing addresses and, where they are expected,
+    /// opening hours. Each issue points at one affected element.
+    pub fn audit_amenities(&self, metrics: &QualityMetrics) -> Vec<QualityIssue> {
+        let mut issues = Vec::new();
+
+        let mut categories: Vec<(&String, &AmenityAudit)> = metrics
+            .amenity_audit
+            .iter()
+            .filter(|(_, audit)| audit.total >= AMENITY_AUDIT_MIN_COUNT)
+            .collect();
+        categories.sort_by(|a, b| b.1.total.cmp(&a.1.total).then(a.0.cmp(b.0)));
+
+        for (category, audit) in categories {
+            let expects_opening_hours = crate::osm::AUDITED_AMENITIES
+                .iter()
+                .any(|(amenity, expected)| amenity == category && *expected);
+
+            let checks = [
+                (
+                    "name",
+                    audit.missing_name,
+                    IssueSeverity::Medium,
+                    "have no name",
+                    "Add name tags so the places can be found and labelled",
+                ),
+                (
+                    "opening_hours",
+                    if expects_opening_hours {
+                        audit.missing_opening_hours
+                    } else {
+                        0
+                    },
+                    IssueSeverity::Low,
+                    "have no opening hours",
+                    "Add opening_hours in the standard syntax, e.g. \"Mo-Fr 09:00-18:00\"",
+                ),
+                (
+                    "address",
+                    audit.missing_address,
+                    IssueSeverity::Low,
+                    "have no address tags",
+                    "Add addr:street and addr:housenumber, or check the building carries them",
+                ),
+            ];
+
+            for (tag, missing, severity, problem, fix) in checks {
+                let share = missing as f64 / audit.total as f64;
+                if share < AMENITY_AUDIT_MISSING_SHARE {
+                    continue;
+                }
+
+                let example = audit.examples.get(tag);
+                issues.push(QualityIssue {
+                    issue_type: format!("amenity_missing_{}", tag),
+                    severity,
+                    description: format!(
+                        "{} of {} amenity={} ({:.0}%) {}",
+                        missing,
+                        audit.total,
+                        category,
+                        share * 100.0,
+                        problem
+                    ),
+                    location: example.and_then(|example| example.location),
+                    osm_id: example.map(|example| example.osm_id),
+                    osm_type: example.map(|example| example.osm_type.clone()),
+                    fix_suggestion: Some(fix.to_string()),
+                });
+            }
+        }
+
+        issues
+    }
+
+    /// Flag city-sized extracts (regions and subregions) where few buildings
+    /// carry a `height` or `building:levels`, so 3D models would be flat
+    pub fn audit_buildings_3d(
+        &self,
+        metrics: &QualityMetrics,
+        admin_level: &AdminLevel,
+    ) -> Vec<QualityIssue> {
+        if !matches!(admin_level, AdminLevel::Region | AdminLevel::Subregion) {
+            return Vec::new();
+        }
+        let Some(building_3d) = metrics.building_3d() else {
+            return Vec::new();
+        };
+        if building_3d.total < BUILDING_3D_MIN_COUNT
+            || building_3d.coverage() >= self.min_building_3d_coverage
+        {
+            return Vec::new();
+        }
+
+        vec![QualityIssue {
+            issue_type: "low_building_3d_coverage".to_string(),
+            severity: IssueSeverity::Low,
+            description: format!(
+                "Only {} of {} buildings ({:.1}%) have a height or level count, below the expected {:.0}%",
+                building_3d.with_height_or_levels,
+                building_3d.total,
+                building_3d.coverage() * 100.0,
+                self.min_building_3d_coverage * 100.0
+            ),
+            location: None,
+            osm_id: None,
+            osm_type: None,
+            fix_suggestion: Some(
+                "Add building:levels (or height in metres) to buildings for 3D rendering"
+                    .to_string(),
+            ),
+        }]
+    }
+
     /// Generate quality score (0-100)
     pub fn calculate_quality_score(
         &self,
         metrics: &QualityMetrics,
         issues: &[QualityIssue],
+        weights: &ScoringWeights,
     ) -> f64 {
         let mut score = 100.0;
 
         // Deduct points for errors
-        score -= metrics.geometry_errors as f64 * 2.0;
-        score -= metrics.topology_errors as f64 * 1.5;
-        score -= metrics.tag_errors as f64 * 1.0;
+        score -= metrics.geometry_errors as f64 * weights.geometry_error;
+        score -= metrics.topology_errors as f64 * weights.topology_error;
+        score -= metrics.tag_errors as f64 * weights.tag_error;
 
         // Deduct points for issues
         for issue in issues {
             let deduction = match issue.severity {
-                IssueSeverity::Critical => 20.0,
-                IssueSeverity::High => 10.0,
-                IssueSeverity::Medium => 5.0,
-                IssueSeverity::Low => 1.0,
+                IssueSeverity::Critical => weights.critical_issue,
+                IssueSeverity::High => weights.high_issue,
+                IssueSeverity::Medium => weights.medium_issue,
+                IssueSeverity::Low => weights.low_issue,
             };
             score -= deduction;
         }
 
         // Add points for completeness
-        score += metrics.completeness_score * 0.3;
+        score += metrics.completeness_score * weights.completeness_bonus;
+
+        // Add points for recently edited data
+        if let Some(freshness) = &metrics.freshness {
+            score += (1.0 - freshness.stale_fraction) * 100.0 * weights.freshness_bonus;
+        }
 
         // Ensure score is between 0 and 100
-        score.max(0.0).min(100.0)
+        score.clamp(0.0, 100.0)
+    }
+
+    /// Render a report as a self-contained HTML page
+    pub fn render_html(report: &QualityReport) -> String {
+        let metrics = &report.metrics;
+        let features = &metrics.feature_distribution;
+        let mut html = String::new();
+
+        html.push_str("<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n");
+        html.push_str(&format!(
+            "<title>Quality report: {}</title>\n",
+            utils::escape_html(&report.region_id)
+        ));
+        html.push_str(
+            "<style>\n\
+             body { font-family: sans-serif; margin: 2em; color: #222; }\n\
+             table { border-collapse: collapse; margin-bottom: 1.5em; }\n\
+             th, td { border: 1px solid #ccc; padding: 4px 10px; text-align: left; }\n\
+             th { background: #f0f0f0; }\n\
+             .critical { color: #b00020; } .high { color: #d35400; }\n\
+             .medium { color: #b7950b; } .low { color: #555; }\n\
+             </style>\n</head>\n<body>\n",
+        );
+
+        html.push_str(&format!(
+            "<h1>Quality report: {}</h1>\n<p>Generated {} for file {}</p>\n<p>{}</p>\n",
+            utils::escape_html(&report.region_id),
+            report.created_at.format("%Y-%m-%d %H:%M UTC"),
+            utils::escape_html(&report.data_file_id),
+            utils::escape_html(&report.summary)
+        ));
+
+        let mut rows = vec![
+            ("Total nodes", count_text(metrics.total_nodes)),
+            ("Total ways", count_text(metrics.total_ways)),
+            ("Total relations", count_text(metrics.total_relations)),
+            ("Tagged nodes", count_text(metrics.tagged_nodes)),
+            ("Tagged ways", count_text(metrics.tagged_ways)),
+            ("Tagged relations", count_text(metrics.tagged_relations)),
+            (
+                "Completeness",
+                format!("{:.1}%", metrics.completeness_score),
+            ),
+            ("Geometry errors", metrics.geometry_errors.to_string()),
+            ("Tag errors", metrics.tag_errors.to_string()),
+            ("Topology errors", metrics.topology_errors.to_string()),
+            ("Highways", features.highways.to_string()),
+            ("Buildings", features.buildings.to_string()),
+            ("Natural features", features.natural_features.to_string()),
+            ("Amenities", features.amenities.to_string()),
+            ("Water features", features.water_features.to_string()),
+            ("Boundaries", features.boundaries.to_string()),
+            ("Coastlines", features.coastlines.to_string()),
+        ];
+        if let Some(freshness) = &metrics.freshness {
+            rows.extend([
+                (
+                    "Median edit age",
+                    format!("{:.0} days", freshness.median_age_days),
+                ),
+                (
+                    "90th percentile edit age",
+                    format!("{:.0} days", freshness.p90_age_days),
+                ),
+                (
+                    "Untouched for over a year",
+                    format!("{:.1}%", freshness.stale_fraction * 100.0),
+                ),
+            ]);
+        }
+
+        html.push_str("<h2>Metrics</h2>\n<table>\n<tr><th>Metric</th><th>Value</th></tr>\n");
+        for (name, value) in rows {
+            html.push_str(&format!("<tr><td>{}</td><td>{}</td></tr>\n", name, value));
+        }
+        html.push_str("</table>\n");
+
+        if !metrics.amenity_audit.is_empty() {
+            let mut categories: Vec<_> = metrics.amenity_audit.iter().collect();
+            categories.sort_by(|a, b| b.1.total.cmp(&a.1.total).then(a.0.cmp(b.0)));
+
+            html.push_str(
+                "<h2>Amenity audit</h2>\n<table>\n<tr><th>Amenity</th><th>Total</th>\
+                 <th>No name</th><th>No opening hours</th><th>No address</th></tr>\n",
+            );
+            for (category, audit) in categories {
+                html.push_str(&format!(
+                    "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                    utils::escape_html(category),
+                    audit.total,
+                    audit.missing_name,
+                    audit.missing_opening_hours,
+                    audit.missing_address
+                ));
+            }
+            html.push_str("</table>\n");
+        }
+
+        html.push_str(&format!(
+            "<h2>Issues</h2>\n<p>{}</p>\n",
+            utils::escape_html(&utils::generate_issue_summary(&report.issues))
+        ));
+        for severity in [
+            IssueSeverity::Critical,
+            IssueSeverity::High,
+            IssueSeverity::Medium,
+            IssueSeverity::Low,
+        ] {
+            let label = utils::severity_label(&severity);
+            let group: Vec<&QualityIssue> = report
+                .issues
+                .iter()
+                .filter(|issue| utils::severity_label(&issue.severity) == label)
+                .collect();
+            if group.is_empty() {
+                continue;
+            }
+
+            html.push_str(&format!(
+                "<h3 class=\"{}\">{} ({})</h3>\n<ul>\n",
+                label,
+                label,
+                group.len()
+            ));
+            for issue in group {
+                let osm_ref = match (&issue.osm_type, issue.osm_id) {
+                    (Some(osm_type), Some(id)) => format!(" ({} {})", osm_type, id),
+                    (None, Some(id)) => format!(" ({})", id),
+                    _ => String::new(),
+                };
+                html.push_str(&format!(
+                    "<li><strong>{}</strong>: {}{}</li>\n",
+                    utils::escape_html(&issue.issue_type),
+                    utils::escape_html(&issue.description),
+                    utils::escape_html(&osm_ref)
+                ));
+            }
+            html.push_str("</ul>\n");
+        }
+
+        html.push_str("<h2>Recommendations</h2>\n<ul>\n");
+        for recommendation in &report.recommendations {
+            html.push_str(&format!(
+                "<li>{}</li>\n",
+                utils::escape_html(recommendation)
+            ));
+        }
+        html.push_str("</ul>\n</body>\n</html>\n");
+
+        html
+    }
+
+    /// Render a report's issues as CSV rows (type, severity, description, osm_id, lat, lon)
+    pub fn render_csv(report: &QualityReport) -> String {
+        let mut csv = String::from("type,severity,description,osm_id,lat,lon\n");
+
+        for issue in &report.issues {
+            let (lat, lon) = issue
+                .location
+                .map(|(lat, lon)| (lat.to_string(), lon.to_string()))
+                .unwrap_or_default();
+
+            csv.push_str(&format!(
+                "{},{},{},{},{},{}\n",
+                utils::escape_csv(&issue.issue_type),
+                utils::severity_label(&issue.severity),
+                utils::escape_csv(&issue.description),
+                issue.osm_id.map(|id| id.to_string()).unwrap_or_default(),
+                lat,
+                lon
+            ));
+        }
+
+        csv
     }
 
     /// Generate recommendations based on quality analysis
@@ -221,40 +748,16 @@ pub mod utils {
 
     /// Compare quality metrics between two versions
     pub fn compare_metrics(old: &QualityMetrics, new: &QualityMetrics) -> QualityMetricsDiff {
-        let mut feature_changes = std::collections::HashMap::new();
-
         // Calculate feature distribution changes
-        feature_changes.insert(
-            "highways".to_string(),
-            new.feature_distribution.highways as i64 - old.feature_distribution.highways as i64,
-        );
-        feature_changes.insert(
-            "buildings".to_string(),
-            new.feature_distribution.buildings as i64 - old.feature_distribution.buildings as i64,
-        );
-        feature_changes.insert(
-            "natural_features".to_string(),
-            new.feature_distribution.natural_features as i64
-                - old.feature_distribution.natural_features as i64,
-        );
-        feature_changes.insert(
-            "amenities".to_string(),
-            new.feature_distribution.amenities as i64 - old.feature_distribution.amenities as i64,
-        );
-        feature_changes.insert(
-            "water_features".to_string(),
-            new.feature_distribution.water_features as i64
-                - old.feature_distribution.water_features as i64,
-        );
-        feature_changes.insert(
-            "boundaries".to_string(),
-            new.feature_distribution.boundaries as i64 - old.feature_distribution.boundaries as i64,
-        );
+        let feature_changes = feature_breakdown(old, new)
+            .into_iter()
+            .map(|change| (change.category, change.abs_change))
+            .collect();
 
         QualityMetricsDiff {
-            nodes_diff: new.total_nodes as i64 - old.total_nodes as i64,
-            ways_diff: new.total_ways as i64 - old.total_ways as i64,
-            relations_diff: new.total_relations as i64 - old.total_relations as i64,
+            nodes_diff: count_diff(old.total_nodes, new.total_nodes),
+            ways_diff: count_diff(old.total_ways, new.total_ways),
+            relations_diff: count_diff(old.total_relations, new.total_relations),
             completeness_diff: new.completeness_score - old.completeness_score,
             errors_diff: (new.geometry_errors + new.topology_errors + new.tag_errors) as i64
                 - (old.geometry_errors + old.topology_errors + old.tag_errors) as i64,
@@ -262,17 +765,66 @@ pub mod utils {
         }
     }
 
+    /// Change in an element count; `None` when either version skipped the type
+    fn count_diff(old: Option<u64>, new: Option<u64>) -> Option<i64> {
+        Some(new? as i64 - old? as i64)
+    }
+
+    /// Counts of each feature category in both versions, with the change
+    /// relative to the old count (`None` when the category was absent)
+    pub fn feature_breakdown(old: &QualityMetrics, new: &QualityMetrics) -> Vec<FeatureChange> {
+        old.feature_distribution
+            .categories()
+            .into_iter()
+            .zip(new.feature_distribution.categories())
+            .map(|((category, from_count), (_, to_count))| {
+                let abs_change = to_count as i64 - from_count as i64;
+                FeatureChange {
+                    category: category.to_string(),
+                    from_count,
+                    to_count,
+                    abs_change,
+                    pct_change: (from_count > 0)
+                        .then(|| abs_change as f64 / from_count as f64 * 100.0),
+                }
+            })
+            .collect()
+    }
+
+    /// Lowercase label for a severity level
+    pub fn severity_label(severity: &IssueSeverity) -> &'static str {
+        match severity {
+            IssueSeverity::Critical => "critical",
+            IssueSeverity::High => "high",
+            IssueSeverity::Medium => "medium",
+            IssueSeverity::Low => "low",
+        }
+    }
+
+    /// Escape text for inclusion in HTML
+    pub fn escape_html(text: &str) -> String {
+        text.replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+            .replace('"', "&quot;")
+            .replace('\'', "&#39;")
+    }
+
+    /// Quote a CSV field when it contains separators, quotes or newlines
+    pub fn escape_csv(field: &str) -> String {
+        if field.contains([',', '"', '\n', '\r']) {
+            format!("\"{}\"", field.replace('"', "\"\""))
+        } else {
+            field.to_string()
+        }
+    }
+
     /// Categorize issues by severity
     pub fn categorize_issues(issues: &[QualityIssue]) -> HashMap<String, usize> {
         let mut categories = HashMap::new();
 
         for issue in issues {
-            let severity_str = match issue.severity {
-                IssueSeverity::Critical => "critical",
-                IssueSeverity::High => "high",
-                IssueSeverity::Medium => "medium",
-                IssueSeverity::Low => "low",
-            };
+            let severity_str = severity_label(&issue.severity);
 
             *categories.entry(severity_str.to_string()).or_insert(0) += 1;
         }
@@ -0,0 +1,146 @@
+use crate::{
+    config::ProcessingConfig, jobs::JobQueue, models::*, osm::OsmProcessor, storage::Storage,
+    Result,
+};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tokio::time::MissedTickBehavior;
+use tracing::{error, info, warn};
+
+/// Spawn the background task that prunes and refreshes region data every
+/// `cleanup_interval_hours`
+pub fn spawn(
+    storage: Storage,
+    processor: OsmProcessor,
+    jobs: JobQueue,
+    config: ProcessingConfig,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let period = Duration::from_secs(config.cleanup_interval_hours.max(1) * 3600);
+        let mut interval = tokio::time::interval(period);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        // The first tick completes immediately; don't slow down server startup
+        interval.tick().await;
+
+        loop {
+            interval.tick().await;
+            if let Err(e) = run_once(&storage, &processor, &jobs, &config).await {
+                error!("Scheduled maintenance failed: {}", e);
+            }
+        }
+    })
+}
+
+/// Spawn a task queueing downloads of the regions a fresh deploy should serve
+///
+/// Regions whose latest data is as new as Geofabrik's are skipped, and the
+/// job queue bounds how many download at once. Failures are logged per region.
+pub fn bootstrap(
+    storage: Storage,
+    processor: OsmProcessor,
+    jobs: JobQueue,
+    region_ids: Vec<String>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        for region_id in region_ids {
+            if let Err(e) = bootstrap_region(&storage, &processor, &jobs, &region_id).await {
+                warn!("Failed to bootstrap region {}: {}", region_id, e);
+            }
+        }
+    })
+}
+
+/// Queue a download of one bootstrap region unless its data is current
+async fn bootstrap_region(
+    storage: &Storage,
+    processor: &OsmProcessor,
+    jobs: &JobQueue,
+    region_id: &str,
+) -> Result<()> {
+    let Some(region) = storage.find_region(region_id).await? else {
+        warn!("Bootstrap region {} is not in the region index", region_id);
+        return Ok(());
+    };
+
+    let files = storage.scan_region_files(region_id).await?;
+    if let Some(latest) = files.iter().find(|file| file.is_latest) {
+        if !has_newer_extract(processor, &region, latest).await? {
+            info!("Bootstrap region {} already has current data", region_id);
+            return Ok(());
+        }
+    }
+
+    let (job, created) = jobs.submit_download(region).await?;
+    if created {
+        info!(
+            "Queued bootstrap download of {} (job {})",
+            region_id, job.id
+        );
+    }
+    Ok(())
+}
+
+/// Run a single maintenance pass over all regions with local data
+pub async fn run_once(
+    storage: &Storage,
+    processor: &OsmProcessor,
+    jobs: &JobQueue,
+    config: &ProcessingConfig,
+) -> Result<()> {
+    info!("Running scheduled maintenance");
+
+    for region in storage.load_regions().await? {
+        let files = storage.get_region_files(&region.id).await?;
+        let Some(latest) = files.iter().find(|file| file.is_latest) else {
+            continue;
+        };
+
+        if config.refresh_downloads {
+            match has_newer_extract(processor, &region, latest).await {
+                Ok(true) => {
+                    info!(
+                        "Newer extract available for {}, queueing download",
+                        region.id
+                    );
+                    jobs.submit_download(region.clone()).await?;
+                }
+                Ok(false) => {}
+                Err(e) => warn!("Failed to check freshness of {}: {}", region.id, e),
+            }
+        }
+
+        storage
+            .prune_old_versions(&region.id, config.keep_versions_for(&region.admin_level))
+            .await?;
+    }
+
+    // Content pruned from every region that shared it
+    storage.remove_unused_blobs().await?;
+
+    Ok(())
+}
+
+/// Compare the local extract's replication timestamp with Geofabrik's
+async fn has_newer_extract(
+    processor: &OsmProcessor,
+    region: &Region,
+    latest: &DataFile,
+) -> Result<bool> {
+    let Some(updates_url) = region
+        .urls
+        .as_ref()
+        .and_then(|urls| urls.updates.as_deref())
+    else {
+        return Ok(false);
+    };
+
+    let Some(local) =
+        OsmProcessor::read_replication_timestamp(std::path::Path::new(&latest.file_path))
+    else {
+        return Ok(false);
+    };
+
+    let remote = processor.fetch_replication_state(updates_url).await?;
+    Ok(remote.timestamp > local)
+}
@@ -0,0 +1,281 @@
+use crate::Result;
+use chrono::{Datelike, Utc};
+use std::path::Path;
+
+/// ESRI WKT for WGS84 geographic coordinates, written as the `.prj` file
+pub const WGS84_PRJ: &str = "GEOGCS[\"GCS_WGS_1984\",DATUM[\"D_WGS_1984\",SPHEROID[\"WGS_1984\",6378137.0,298.257223563]],PRIMEM[\"Greenwich\",0.0],UNIT[\"Degree\",0.0174532925199433]]";
+
+/// Maximum length of a DBF field name
+const DBF_FIELD_NAME_LEN: usize = 10;
+
+/// Maximum width of a DBF character field
+const DBF_MAX_FIELD_LEN: usize = 254;
+
+/// Geometry type of a shapefile layer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShapeGeometry {
+    Point,
+    Line,
+    Polygon,
+}
+
+impl ShapeGeometry {
+    /// All layer types, in export order
+    pub const ALL: [ShapeGeometry; 3] = [
+        ShapeGeometry::Point,
+        ShapeGeometry::Line,
+        ShapeGeometry::Polygon,
+    ];
+
+    /// Base file name used for the layer
+    pub fn layer_name(&self) -> &'static str {
+        match self {
+            ShapeGeometry::Point => "points",
+            ShapeGeometry::Line => "lines",
+            ShapeGeometry::Polygon => "polygons",
+        }
+    }
+
+    fn shape_type(&self) -> i32 {
+        match self {
+            ShapeGeometry::Point => 1,
+            ShapeGeometry::Line => 3,
+            ShapeGeometry::Polygon => 5,
+        }
+    }
+}
+
+/// A single feature: coordinate parts as (lon, lat) plus one value per attribute field
+#[derive(Debug, Clone)]
+pub struct ShapeFeature {
+    pub parts: Vec<Vec<(f64, f64)>>,
+    pub attributes: Vec<String>,
+}
+
+/// Write a shapefile layer as `<base>.shp`, `.shx`, `.dbf`, `.prj` and `.cpg`
+///
+/// Attribute values are stored as UTF-8 (declared in the `.cpg` file) in
+/// character fields. DBF limits field names to 10 characters, so longer
+/// names are truncated, e.g. `addr:housenumber` becomes `addr:house`.
+/// Values longer than 254 bytes are truncated as well.
+pub fn write_shapefile(
+    base: &Path,
+    geometry: ShapeGeometry,
+    fields: &[&str],
+    features: &[ShapeFeature],
+) -> Result<()> {
+    let mut records = Vec::with_capacity(features.len());
+    let mut extent: Option<[f64; 4]> = None;
+
+    for feature in features {
+        let content = encode_shape(geometry, &feature.parts);
+        if let Some(bbox) = parts_bbox(&feature.parts) {
+            extent = Some(match extent {
+                Some(e) => [
+                    e[0].min(bbox[0]),
+                    e[1].min(bbox[1]),
+                    e[2].max(bbox[2]),
+                    e[3].max(bbox[3]),
+                ],
+                None => bbox,
+            });
+        }
+        records.push(content);
+    }
+
+    let extent = extent.unwrap_or([0.0; 4]);
+    let shape_type = geometry.shape_type();
+
+    // .shp: header followed by (record header, content) pairs
+    let shp_len: usize = 100 + records.iter().map(|r| 8 + r.len()).sum::<usize>();
+    let mut shp = file_header(shape_type, shp_len, &extent);
+    // .shx: header followed by (offset, content length) pairs
+    let mut shx = file_header(shape_type, 100 + 8 * records.len(), &extent);
+
+    let mut offset = 100;
+    for (index, content) in records.iter().enumerate() {
+        shp.extend_from_slice(&(index as i32 + 1).to_be_bytes());
+        shp.extend_from_slice(&((content.len() / 2) as i32).to_be_bytes());
+        shp.extend_from_slice(content);
+
+        shx.extend_from_slice(&((offset / 2) as i32).to_be_bytes());
+        shx.extend_from_slice(&((content.len() / 2) as i32).to_be_bytes());
+        offset += 8 + content.len();
+    }
+
+    std::fs::write(base.with_extension("shp"), shp)?;
+    std::fs::write(base.with_extension("shx"), shx)?;
+    std::fs::write(base.with_extension("dbf"), encode_dbf(fields, features))?;
+    std::fs::write(base.with_extension("prj"), WGS84_PRJ)?;
+    std::fs::write(base.with_extension("cpg"), "UTF-8")?;
+
+    Ok(())
+}
+
+/// Truncate a field name to the 10 characters allowed by DBF
+pub fn dbf_field_name(name: &str) -> String {
+    name.chars()
+        .filter(char::is_ascii)
+        .take(DBF_FIELD_NAME_LEN)
+        .collect()
+}
+
+/// 100-byte header shared by `.shp` and `.shx` files
+fn file_header(shape_type: i32, file_len: usize, extent: &[f64; 4]) -> Vec<u8> {
+    let mut header = Vec::with_capacity(file_len);
+    header.extend_from_slice(&9994i32.to_be_bytes());
+    header.extend_from_slice(&[0u8; 20]);
+    header.extend_from_slice(&((file_len / 2) as i32).to_be_bytes());
+    header.extend_from_slice(&1000i32.to_le_bytes());
+    header.extend_from_slice(&shape_type.to_le_bytes());
+    for value in extent {
+        header.extend_from_slice(&value.to_le_bytes());
+    }
+    // Z and M ranges are unused for 2D shapes
+    header.extend_from_slice(&[0u8; 32]);
+    header
+}
+
+/// Encode a record's content (without the record header)
+fn encode_shape(geometry: ShapeGeometry, parts: &[Vec<(f64, f64)>]) -> Vec<u8> {
+    let mut content = Vec::new();
+    content.extend_from_slice(&geometry.shape_type().to_le_bytes());
+
+    if geometry == ShapeGeometry::Point {
+        let (x, y) = parts
+            .first()
+            .and_then(|p| p.first())
+            .copied()
+            .unwrap_or_default();
+        content.extend_from_slice(&x.to_le_bytes());
+        content.extend_from_slice(&y.to_le_bytes());
+        return content;
+    }
+
+    // Shapefile outer rings run clockwise
+    let parts: Vec<Vec<(f64, f64)>> = parts
+        .iter()
+        .map(|part| {
+            let mut part = part.clone();
+            if geometry == ShapeGeometry::Polygon && signed_area(&part) > 0.0 {
+                part.reverse();
+            }
+            part
+        })
+        .collect();
+
+    for value in parts_bbox(&parts).unwrap_or([0.0; 4]) {
+        content.extend_from_slice(&value.to_le_bytes());
+    }
+
+    let num_points: usize = parts.iter().map(Vec::len).sum();
+    content.extend_from_slice(&(parts.len() as i32).to_le_bytes());
+    content.extend_from_slice(&(num_points as i32).to_le_bytes());
+
+    let mut start = 0;
+    for part in &parts {
+        content.extend_from_slice(&(start as i32).to_le_bytes());
+        start += part.len();
+    }
+    for &(x, y) in parts.iter().flatten() {
+        content.extend_from_slice(&x.to_le_bytes());
+        content.extend_from_slice(&y.to_le_bytes());
+    }
+
+    content
+}
+
+/// Bounding box [xmin, ymin, xmax, ymax] of all coordinates
+fn parts_bbox(parts: &[Vec<(f64, f64)>]) -> Option<[f64; 4]> {
+    parts.iter().flatten().fold(None, |bbox, &(x, y)| {
+        Some(match bbox {
+            Some([xmin, ymin, xmax, ymax]) => [xmin.min(x), ymin.min(y), xmax.max(x), ymax.max(y)],
+            None => [x, y, x, y],
+        })
+    })
+}
+
+/// Shoelace signed area; positive for counter-clockwise rings
+fn signed_area(ring: &[(f64, f64)]) -> f64 {
+    ring.windows(2)
+        .map(|w| w[0].0 * w[1].1 - w[1].0 * w[0].1)
+        .sum::<f64>()
+        / 2.0
+}
+
+/// Encode a dBase III table with one character column per field
+fn encode_dbf(fields: &[&str], features: &[ShapeFeature]) -> Vec<u8> {
+    let values: Vec<Vec<&[u8]>> = features
+        .iter()
+        .map(|feature| {
+            (0..fields.len())
+                .map(|i| {
+                    let value = feature.attributes.get(i).map(String::as_str).unwrap_or("");
+                    truncate_utf8(value, DBF_MAX_FIELD_LEN).as_bytes()
+                })
+                .collect()
+        })
+        .collect();
+
+    let widths: Vec<usize> = (0..fields.len())
+        .map(|i| {
+            values
+                .iter()
+                .map(|row| row[i].len())
+                .max()
+                .unwrap_or(0)
+                .max(1)
+        })
+        .collect();
+
+    let header_len = 32 + 32 * fields.len() + 1;
+    let record_len = 1 + widths.iter().sum::<usize>();
+    let today = Utc::now();
+
+    let mut dbf = Vec::with_capacity(header_len + record_len * features.len() + 1);
+    dbf.push(0x03);
+    dbf.push((today.year() - 1900) as u8);
+    dbf.push(today.month() as u8);
+    dbf.push(today.day() as u8);
+    dbf.extend_from_slice(&(features.len() as u32).to_le_bytes());
+    dbf.extend_from_slice(&(header_len as u16).to_le_bytes());
+    dbf.extend_from_slice(&(record_len as u16).to_le_bytes());
+    dbf.extend_from_slice(&[0u8; 20]);
+
+    for (field, &width) in fields.iter().zip(&widths) {
+        let mut name = [0u8; 11];
+        let truncated = dbf_field_name(field);
+        name[..truncated.len()].copy_from_slice(truncated.as_bytes());
+        dbf.extend_from_slice(&name);
+        dbf.push(b'C');
+        dbf.extend_from_slice(&[0u8; 4]);
+        dbf.push(width as u8);
+        dbf.push(0);
+        dbf.extend_from_slice(&[0u8; 14]);
+    }
+    dbf.push(0x0D);
+
+    for row in &values {
+        // Record deletion flag: space means "not deleted"
+        dbf.push(b' ');
+        for (value, &width) in row.iter().zip(&widths) {
+            dbf.extend_from_slice(value);
+            dbf.resize(dbf.len() + width - value.len(), b' ');
+        }
+    }
+    dbf.push(0x1A);
+
+    dbf
+}
+
+/// Truncate to at most `max_len` bytes without splitting a character
+fn truncate_utf8(value: &str, max_len: usize) -> &str {
+    if value.len() <= max_len {
+        return value;
+    }
+    let mut end = max_len;
+    while !value.is_char_boundary(end) {
+        end -= 1;
+    }
+    &value[..end]
+}
@@ -1,56 +1,166 @@
-use crate::{models::*, Result};
+use crate::metadata::{self, JsonMetadataStore, MetadataBackend, MetadataStore};
+use crate::tile_cache::{TileCache, DEFAULT_TILE_CACHE_MAX_BYTES};
+use crate::{config::GeofabrikConfig, models::*, osm::OsmProcessor, Result};
 use chrono::Utc;
+use serde::{Deserialize, Serialize};
 use serde_json;
 
+use std::collections::{BTreeMap, HashMap};
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::{Mutex, RwLock};
 use tracing::{info, warn};
 use walkdir::WalkDir;
 
+/// How long a built region tree is served before being rebuilt
+const REGION_TREE_TTL: Duration = Duration::from_secs(300);
+
+/// Region tree built by `get_region_tree`, with what it was built from
+struct CachedTree {
+    tree: Arc<Vec<RegionTree>>,
+    built_at: Instant,
+    metadata_modified: Option<SystemTime>,
+}
+
+/// Per-region download counters, keyed by region ID and then by file version
+type DownloadCounts = HashMap<String, HashMap<String, u64>>;
+
+/// How region directories are laid out under the data directory
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LayoutStrategy {
+    /// One directory per region directly under the data directory, named by
+    /// its ID with `/` replaced by `_` (e.g. `us_california`)
+    FlatById,
+    /// Nested under the parent chain the way Geofabrik's download URLs are
+    /// (e.g. `north-america/us/california`)
+    #[default]
+    GeofabrikHierarchy,
+}
+
+impl std::str::FromStr for LayoutStrategy {
+    type Err = crate::RiemapError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().replace('-', "_").as_str() {
+            "flat" | "flat_by_id" => Ok(Self::FlatById),
+            "geofabrik" | "geofabrik_hierarchy" => Ok(Self::GeofabrikHierarchy),
+            other => Err(crate::RiemapError::Config(format!(
+                "Unknown data layout '{}', expected 'flat_by_id' or 'geofabrik_hierarchy'",
+                other
+            ))),
+        }
+    }
+}
+
 /// Storage layer for managing regions, files, and metadata
 #[derive(Clone)]
 pub struct Storage {
     pub data_dir: PathBuf,
-    pub metadata_file: PathBuf,
+    layout: LayoutStrategy,
+    metadata: Arc<dyn MetadataStore>,
+    tree_cache: Arc<RwLock<Option<CachedTree>>>,
+    stats_lock: Arc<Mutex<()>>,
+    /// Serializes read-modify-write updates of the stored regions
+    regions_lock: Arc<Mutex<()>>,
+    geofabrik: GeofabrikConfig,
+    tile_cache: TileCache,
+    /// Let `load_regions_or_recover` move corrupt region metadata aside
+    recover_corrupt_metadata: bool,
 }
 
 impl Storage {
     /// Create a new storage instance
     pub fn new<P: AsRef<Path>>(data_dir: P) -> Result<Self> {
         let data_dir = data_dir.as_ref().to_path_buf();
-        let metadata_file = data_dir.join("metadata.json");
 
         std::fs::create_dir_all(&data_dir)?;
 
         Ok(Self {
+            metadata: Arc::new(JsonMetadataStore::new(&data_dir)),
+            tile_cache: TileCache::new(data_dir.join("tiles"), DEFAULT_TILE_CACHE_MAX_BYTES),
             data_dir,
-            metadata_file,
+            layout: LayoutStrategy::default(),
+            tree_cache: Arc::new(RwLock::new(None)),
+            stats_lock: Arc::new(Mutex::new(())),
+            regions_lock: Arc::new(Mutex::new(())),
+            geofabrik: GeofabrikConfig::default(),
+            recover_corrupt_metadata: false,
         })
     }
 
+    /// Use a Geofabrik mirror or local index when initializing regions
+    pub fn with_geofabrik_config(mut self, geofabrik: GeofabrikConfig) -> Self {
+        self.geofabrik = geofabrik;
+        self
+    }
+
+    /// Cap the rendered tiles kept under `data_dir/tiles`; 0 disables the cache
+    pub fn with_tile_cache_limit(mut self, max_bytes: u64) -> Self {
+        self.tile_cache = TileCache::new(self.data_dir.join("tiles"), max_bytes);
+        self
+    }
+
+    /// Allow `load_regions_or_recover` to back up and drop unreadable region metadata
+    pub fn with_metadata_recovery(mut self, enabled: bool) -> Self {
+        self.recover_corrupt_metadata = enabled;
+        self
+    }
+
+    /// Lay region directories out with the given strategy
+    ///
+    /// Downloads and lookups both resolve paths through `get_region_path`, so
+    /// switching layouts on an existing data directory hides files stored
+    /// under the old one until they are moved.
+    pub fn with_layout(mut self, layout: LayoutStrategy) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    /// Keep region, job and report metadata in the given backend instead of JSON files
+    pub fn with_metadata_backend(mut self, backend: MetadataBackend) -> Result<Self> {
+        self.metadata = metadata::open_store(&self.data_dir, backend)?;
+        Ok(self)
+    }
+
     /// Initialize storage with Geofabrik region hierarchy from their official JSON index
     pub async fn initialize_with_geofabrik_data(&self) -> Result<()> {
-        info!("Fetching Geofabrik region hierarchy from official JSON index with geometries");
-
-        // Use the full geometry version to get actual bounding boxes
-        let geofabrik_url = "https://download.geofabrik.de/index-v1.json";
+        let geofabrik_index: GeofabrikIndex = if let Some(index_path) = &self.geofabrik.index_path {
+            info!("Reading Geofabrik region hierarchy from {:?}", index_path);
+            let contents = tokio::fs::read_to_string(index_path).await?;
+            serde_json::from_str(&contents)?
+        } else {
+            // Use the full geometry version to get actual bounding boxes
+            let geofabrik_url = self.geofabrik.index_url();
+            info!(
+                "Fetching Geofabrik region hierarchy from {} with geometries",
+                geofabrik_url
+            );
 
-        let client = reqwest::Client::new();
-        let response = client
-            .get(geofabrik_url)
-            .timeout(std::time::Duration::from_secs(60)) // Increased timeout for larger file
-            .send()
+            let client = crate::http::build_client(self.geofabrik.connect_timeout())?;
+            let response = crate::http::send_with_retry(
+                self.geofabrik.max_attempts,
+                self.geofabrik.read_timeout(),
+                || {
+                    client
+                        .get(&geofabrik_url)
+                        .timeout(std::time::Duration::from_secs(60)) // Increased timeout for larger file
+                },
+            )
             .await?;
 
-        if !response.status().is_success() {
-            return Err(anyhow::anyhow!(
-                "Failed to fetch Geofabrik index: HTTP {}",
-                response.status()
-            ));
-        }
+            if !response.status().is_success() {
+                return Err(anyhow::anyhow!(
+                    "Failed to fetch Geofabrik index: HTTP {}",
+                    response.status()
+                ));
+            }
 
-        let geofabrik_index: GeofabrikIndex = response.json().await?;
+            response.json().await?
+        };
         info!(
-            "Successfully fetched {} regions from Geofabrik",
+            "Successfully loaded {} regions from Geofabrik index",
             geofabrik_index.features.len()
         );
 
@@ -65,9 +175,26 @@ impl Storage {
         Ok(())
     }
 
+    /// Route the download URLs of an index entry through the configured mirror
+    fn mirror_urls(&self, mut urls: GeofabrikUrls) -> GeofabrikUrls {
+        for url in [
+            &mut urls.pbf,
+            &mut urls.bz2,
+            &mut urls.shp,
+            &mut urls.updates,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            *url = self.geofabrik.rewrite_url(url);
+        }
+        urls
+    }
+
     /// Convert Geofabrik index to our Region structure
-    async fn convert_geofabrik_to_regions(&self, index: GeofabrikIndex) -> Result<Vec<Region>> {
+    async fn convert_geofabrik_to_regions(&self, mut index: GeofabrikIndex) -> Result<Vec<Region>> {
         let mut regions = Vec::new();
+        Self::disambiguate_region_ids(&mut index.features);
 
         // First pass: collect all region IDs that have children
         let mut parent_ids = std::collections::HashSet::new();
@@ -84,8 +211,9 @@ impl Storage {
             region_map.insert(feature.properties.id.clone(), feature.properties.clone());
         }
 
-        // Multi-pass admin level determination
-        let admin_levels = self.determine_admin_levels_multi_pass(&region_map);
+        // Multi-pass admin level determination, then the configured corrections
+        let mut admin_levels = self.determine_admin_levels_multi_pass(&region_map);
+        self.apply_admin_level_overrides(&mut admin_levels);
 
         for feature in index.features {
             let props = feature.properties;
@@ -126,12 +254,15 @@ impl Storage {
             region.provides_data_services = provides_data_services;
             region.iso3166_1 = props.iso3166_1_alpha2;
             region.iso3166_2 = props.iso3166_2;
-            region.urls = props.urls;
+            region.urls = props.urls.map(|urls| self.mirror_urls(urls));
+            region.population = props.population;
+            region.geometry = feature.geometry;
+            region.area_km2 = Some(region.compute_area_km2());
             region.geofabrik_url = region
                 .urls
                 .as_ref()
                 .and_then(|urls| urls.pbf.as_ref())
-                .map(|url| url.clone());
+                .cloned();
 
             // Set country codes from ISO codes
             if let Some(ref iso_codes) = region.iso3166_1 {
@@ -143,10 +274,76 @@ impl Storage {
             regions.push(region);
         }
 
+        Self::detach_orphans(&mut regions);
+
         info!("Converted {} Geofabrik features to regions", regions.len());
         Ok(regions)
     }
 
+    /// Give every repeated index ID a numbered suffix (`id-2`, `id-3`, ...)
+    ///
+    /// The first entry keeps the ID, so children naming it as their parent
+    /// stay attached to it. Suffixes follow index order and are therefore
+    /// the same on every run over the same index.
+    fn disambiguate_region_ids(features: &mut [GeofabrikFeature]) {
+        let mut taken: std::collections::HashSet<String> = features
+            .iter()
+            .map(|feature| feature.properties.id.clone())
+            .collect();
+        let mut seen = std::collections::HashSet::new();
+        let mut renamed = Vec::new();
+
+        for feature in features.iter_mut() {
+            let id = &mut feature.properties.id;
+            if seen.insert(id.clone()) {
+                continue;
+            }
+
+            let unique = (2..)
+                .map(|n| format!("{}-{}", id, n))
+                .find(|candidate| !taken.contains(candidate))
+                .unwrap_or_default();
+            taken.insert(unique.clone());
+            renamed.push(format!("{} -> {}", id, unique));
+            *id = unique;
+        }
+
+        if !renamed.is_empty() {
+            warn!(
+                "Geofabrik index contains {} duplicate region IDs, renamed: {}",
+                renamed.len(),
+                renamed.join(", ")
+            );
+        }
+    }
+
+    /// Turn regions whose parent is missing from the index into top-level regions
+    ///
+    /// Otherwise they could not be reached from any root of the region tree.
+    fn detach_orphans(regions: &mut [Region]) {
+        let ids: std::collections::HashSet<String> =
+            regions.iter().map(|region| region.id.clone()).collect();
+        let mut orphans = Vec::new();
+
+        for region in regions.iter_mut() {
+            let Some(parent_id) = &region.parent_id else {
+                continue;
+            };
+            if parent_id == &region.id || !ids.contains(parent_id) {
+                orphans.push(format!("{} (parent {})", region.id, parent_id));
+                region.parent_id = None;
+            }
+        }
+
+        if !orphans.is_empty() {
+            warn!(
+                "{} regions reference a parent missing from the Geofabrik index and were made top-level: {}",
+                orphans.len(),
+                orphans.join(", ")
+            );
+        }
+    }
+
     /// Determine admin levels using multi-pass analysis of the actual hierarchy
     fn determine_admin_levels_multi_pass(
         &self,
@@ -189,7 +386,7 @@ impl Storage {
         }
 
         // Any remaining regions without assigned levels get the deepest level
-        for (id, _) in region_map {
+        for id in region_map.keys() {
             if !admin_levels.contains_key(id) {
                 admin_levels.insert(id.clone(), AdminLevel::Subregion);
             }
@@ -198,6 +395,26 @@ impl Storage {
         admin_levels
     }
 
+    /// Replace derived levels with the ones configured in `admin_level_overrides`
+    fn apply_admin_level_overrides(
+        &self,
+        admin_levels: &mut std::collections::HashMap<String, AdminLevel>,
+    ) {
+        for (id, level) in &self.geofabrik.admin_level_overrides {
+            match admin_levels.get_mut(id) {
+                Some(derived) if derived == level => {}
+                Some(derived) => {
+                    info!(
+                        "Overriding admin level of {} from {:?} to {:?}",
+                        id, derived, level
+                    );
+                    *derived = level.clone();
+                }
+                None => warn!("Admin level override for unknown region {}", id),
+            }
+        }
+    }
+
     /// Estimate bounding box for regions (since we're using the no-geometry version)
     fn estimate_bounding_box(&self, id: &str, parent: &Option<String>) -> BoundingBox {
         // These are rough estimates - in a production system you'd want to either:
@@ -250,31 +467,214 @@ impl Storage {
         self.initialize_with_geofabrik_data().await
     }
 
-    /// Save regions to metadata file
+    /// Save regions to the metadata store
+    ///
+    /// Regions that did not change keep their stored `created_at` and
+    /// `updated_at`, so re-importing the index only advances `updated_at` of
+    /// regions that actually changed.
     pub async fn save_regions(&self, regions: &[Region]) -> Result<()> {
-        let json = serde_json::to_string_pretty(regions)?;
-        tokio::fs::write(&self.metadata_file, json).await?;
+        let guard = self.regions_lock.lock().await;
+        let stored: HashMap<String, Region> = match self.metadata.load_regions().await {
+            Ok(stored) => stored
+                .into_iter()
+                .map(|region| (region.id.clone(), region))
+                .collect(),
+            // Nothing to keep when the old regions cannot be read
+            Err(_) => HashMap::new(),
+        };
+        let regions: Vec<Region> = regions
+            .iter()
+            .cloned()
+            .map(|mut region| {
+                if let Some(old) = stored.get(&region.id) {
+                    region.created_at = old.created_at;
+                    if !region_changed(old, &region) {
+                        region.updated_at = old.updated_at;
+                    }
+                }
+                region
+            })
+            .collect();
+        self.metadata.save_regions(&regions).await?;
+        drop(guard);
+
+        // Drop the cached tree so lookups see the new hierarchy
+        self.invalidate_region_tree().await;
+
+        // Tiles draw the old regions; a failed clear leaves stale tiles, not bad metadata
+        if let Err(e) = self.tile_cache.clear().await {
+            warn!("Failed to clear the tile cache: {}", e);
+        }
         Ok(())
     }
 
-    /// Load regions from metadata file
+    /// Advance a region's `updated_at` after its data files changed, for
+    /// clients syncing regions incrementally
+    pub async fn touch_region(&self, region_id: &str) -> Result<()> {
+        let guard = self.regions_lock.lock().await;
+        let mut regions = self.metadata.load_regions().await?;
+        let Some(region) = regions.iter_mut().find(|region| region.id == region_id) else {
+            return Ok(());
+        };
+        region.updated_at = Utc::now();
+        self.metadata.save_regions(&regions).await?;
+        drop(guard);
+
+        // The tree lists `updated_at`; tiles only draw boundaries, keep them
+        self.invalidate_region_tree().await;
+        Ok(())
+    }
+
+    /// Rendered vector tiles, cleared whenever the regions change
+    pub fn tile_cache(&self) -> &TileCache {
+        &self.tile_cache
+    }
+
+    /// Run the readiness checks for the data directory
+    ///
+    /// Verifies that `data_dir` exists and is writable, that the metadata
+    /// store holds regions and can be read, and that at least `min_free_bytes` of disk
+    /// space is available. Results are keyed by check name.
+    pub async fn check_health(&self, min_free_bytes: u64) -> BTreeMap<&'static str, HealthCheck> {
+        let mut checks = BTreeMap::new();
+
+        let data_dir = if !self.data_dir.is_dir() {
+            HealthCheck::fail(format!("{:?} does not exist", self.data_dir))
+        } else {
+            let probe = self
+                .data_dir
+                .join(format!(".health-{}.tmp", uuid::Uuid::new_v4()));
+            match tokio::fs::write(&probe, b"ok").await {
+                Ok(()) => {
+                    let _ = tokio::fs::remove_file(&probe).await;
+                    HealthCheck::pass(None)
+                }
+                Err(e) => HealthCheck::fail(format!("{:?} is not writable: {}", self.data_dir, e)),
+            }
+        };
+        checks.insert("data_dir", data_dir);
+
+        let location = self.metadata.location().display();
+        let metadata = match self.load_regions().await {
+            Ok(regions) if regions.is_empty() => {
+                HealthCheck::fail(format!("{} has no regions", location))
+            }
+            Ok(regions) => HealthCheck::pass(Some(format!("{} regions", regions.len()))),
+            Err(e) => HealthCheck::fail(format!("{} is unreadable: {}", location, e)),
+        };
+        checks.insert("metadata", metadata);
+
+        let disk = match fs2::available_space(&self.data_dir) {
+            Ok(free) if free >= min_free_bytes => {
+                HealthCheck::pass(Some(format!("{} MB free", free / 1_048_576)))
+            }
+            Ok(free) => HealthCheck::fail(format!(
+                "{} MB free, below the {} MB threshold",
+                free / 1_048_576,
+                min_free_bytes / 1_048_576
+            )),
+            Err(e) => HealthCheck::fail(format!("Failed to read free disk space: {}", e)),
+        };
+        checks.insert("disk_space", disk);
+
+        checks
+    }
+
+    /// Load regions from the metadata store
     pub async fn load_regions(&self) -> Result<Vec<Region>> {
-        if !self.metadata_file.exists() {
-            return Ok(Vec::new());
+        self.metadata.load_regions().await
+    }
+
+    /// Load regions, starting over from an empty list when they cannot be parsed
+    ///
+    /// With recovery enabled, unreadable metadata (e.g. a hand-edited
+    /// `metadata.json` that is no longer valid JSON) is moved to
+    /// `metadata.json.corrupt-<timestamp>` so the regions can be initialized
+    /// again. Other failures, and any failure with recovery disabled, are
+    /// returned as errors.
+    pub async fn load_regions_or_recover(&self) -> Result<Vec<Region>> {
+        let error = match self.load_regions().await {
+            Ok(regions) => return Ok(regions),
+            Err(e) => e,
+        };
+        if !self.recover_corrupt_metadata || error.downcast_ref::<serde_json::Error>().is_none() {
+            return Err(error);
         }
 
-        let contents = tokio::fs::read_to_string(&self.metadata_file).await?;
-        let regions: Vec<Region> = serde_json::from_str(&contents)?;
+        match self.metadata.quarantine_regions().await? {
+            Some(backup) => {
+                warn!(
+                    "{:#}; moved it to {:?} and starting with no regions",
+                    error, backup
+                );
+                self.invalidate_region_tree().await;
+                Ok(Vec::new())
+            }
+            None => Err(error),
+        }
+    }
+
+    /// Find all regions whose bounding box overlaps the given area
+    pub async fn regions_intersecting(&self, bbox: &BoundingBox) -> Result<Vec<Region>> {
+        let regions = self.load_regions().await?;
+        Ok(regions
+            .into_iter()
+            .filter(|region| region.bounding_box.intersects(bbox))
+            .collect())
+    }
+
+    /// Regions containing a point, most specific (highest admin level) first
+    ///
+    /// The world region is left out, as it trivially contains every point.
+    pub async fn regions_containing(&self, lat: f64, lon: f64) -> Result<Vec<Region>> {
+        let mut regions: Vec<Region> = self
+            .load_regions()
+            .await?
+            .into_iter()
+            .filter(|region| region.admin_level != AdminLevel::World)
+            .filter(|region| region.contains_point(lat, lon))
+            .collect();
+
+        regions.sort_by(|a, b| {
+            b.admin_level_num()
+                .cmp(&a.admin_level_num())
+                .then_with(|| a.id.cmp(&b.id))
+        });
         Ok(regions)
     }
 
     /// Get region hierarchy tree
-    pub async fn get_region_tree(&self) -> Result<Vec<RegionTree>> {
+    ///
+    /// The built tree is cached until the stored regions change, the TTL
+    /// expires or `invalidate_region_tree` is called.
+    pub async fn get_region_tree(&self) -> Result<Arc<Vec<RegionTree>>> {
+        let metadata_modified = self.metadata.regions_modified();
+
+        if let Some(cached) = self.tree_cache.read().await.as_ref() {
+            if cached.metadata_modified == metadata_modified
+                && cached.built_at.elapsed() < REGION_TREE_TTL
+            {
+                return Ok(cached.tree.clone());
+            }
+        }
+
         let regions = self.load_regions().await?;
-        let tree = self.build_hierarchy(&regions, None).await?;
+        let tree = Arc::new(self.build_hierarchy(&regions, None).await?);
+
+        *self.tree_cache.write().await = Some(CachedTree {
+            tree: tree.clone(),
+            built_at: Instant::now(),
+            metadata_modified,
+        });
+
         Ok(tree)
     }
 
+    /// Force the next `get_region_tree` call to rebuild the hierarchy
+    pub async fn invalidate_region_tree(&self) {
+        *self.tree_cache.write().await = None;
+    }
+
     /// Build hierarchical tree from flat region list (complete recursive hierarchy)
     async fn build_hierarchy(
         &self,
@@ -290,15 +690,14 @@ impl Storage {
             std::collections::HashMap::new();
         for region in regions {
             if let Some(ref parent) = region.parent_id {
-                children_map
-                    .entry(parent.clone())
-                    .or_insert_with(Vec::new)
-                    .push(region);
+                children_map.entry(parent.clone()).or_default().push(region);
             }
         }
 
+        let download_totals = self.load_download_totals().await?;
+
         // Build the hierarchy iteratively
-        self.build_tree_iterative(&region_map, &children_map, parent_id)
+        self.build_tree_iterative(&region_map, &children_map, &download_totals, parent_id)
             .await
     }
 
@@ -307,6 +706,7 @@ impl Storage {
         &'a self,
         region_map: &'a std::collections::HashMap<String, &'a Region>,
         children_map: &'a std::collections::HashMap<String, Vec<&'a Region>>,
+        download_totals: &'a HashMap<String, u64>,
         parent_id: Option<&'a str>,
     ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<Vec<RegionTree>>> + 'a + Send>>
     {
@@ -330,11 +730,16 @@ impl Storage {
 
                 // Recursively build children
                 let children = self
-                    .build_tree_iterative(region_map, children_map, Some(&region.id))
+                    .build_tree_iterative(
+                        region_map,
+                        children_map,
+                        download_totals,
+                        Some(&region.id),
+                    )
                     .await?;
 
                 let download_stats = DownloadStats {
-                    total_downloads: 0,
+                    total_downloads: download_totals.get(&region.id).copied().unwrap_or(0),
                     last_updated: region.updated_at,
                     file_count: data_files.len(),
                     total_size_mb: data_files
@@ -365,38 +770,97 @@ impl Storage {
 
     /// Get specific region
     pub async fn get_region(&self, region_id: &str) -> Result<Option<RegionTree>> {
-        let regions = self.load_regions().await?;
+        let Some(region) = self.metadata.get_region(region_id).await? else {
+            return Ok(None);
+        };
 
-        for region in &regions {
-            if region.id == region_id {
-                let data_files = self.get_region_files(&region.id).await?;
-                let children = self.build_hierarchy(&regions, Some(&region.id)).await?;
+        let descendants = self.metadata.get_descendants(region_id).await?;
+        let children = self.build_hierarchy(&descendants, Some(region_id)).await?;
+        let data_files = self.get_region_files(region_id).await?;
+        let download_totals = self.load_download_totals().await?;
+
+        let download_stats = DownloadStats {
+            total_downloads: download_totals.get(region_id).copied().unwrap_or(0),
+            last_updated: region.updated_at,
+            file_count: data_files.len(),
+            total_size_mb: data_files
+                .iter()
+                .map(|f| f.file_size as f64 / 1_048_576.0)
+                .sum(),
+        };
 
-                let download_stats = DownloadStats {
-                    total_downloads: 0,
-                    last_updated: region.updated_at,
-                    file_count: data_files.len(),
-                    total_size_mb: data_files
-                        .iter()
-                        .map(|f| f.file_size as f64 / 1_048_576.0)
-                        .sum(),
-                };
+        Ok(Some(RegionTree {
+            region,
+            children,
+            data_files,
+            download_stats,
+        }))
+    }
 
-                return Ok(Some(RegionTree {
-                    region: region.clone(),
-                    children,
-                    data_files,
-                    download_stats,
-                }));
+    /// Look up a region by ID without building its subtree
+    pub async fn find_region(&self, region_id: &str) -> Result<Option<Region>> {
+        self.metadata.get_region(region_id).await
+    }
+
+    /// Latest data file of a region and of every descendant that has local data
+    ///
+    /// Returns `None` when the region does not exist. Regions are ordered by
+    /// ID and paths point at the versioned files behind `latest`.
+    pub async fn bundle_files(&self, region_id: &str) -> Result<Option<Vec<(Region, PathBuf)>>> {
+        let Some(region) = self.metadata.get_region(region_id).await? else {
+            return Ok(None);
+        };
+
+        let mut regions = self.metadata.get_descendants(region_id).await?;
+        regions.push(region);
+        regions.sort_by(|a, b| a.id.cmp(&b.id));
+
+        let mut files = Vec::new();
+        for region in regions {
+            if let Some(path) = self.get_file_path(&region.id, "latest").await? {
+                files.push((region, std::fs::canonicalize(path)?));
             }
         }
 
-        Ok(None)
+        Ok(Some(files))
+    }
+
+    /// Get the direct children of a region, without building their subtrees
+    ///
+    /// Returns `None` when the region does not exist. `has_children` is set
+    /// on each child from the stored hierarchy.
+    pub async fn get_region_children(&self, region_id: &str) -> Result<Option<Vec<Region>>> {
+        if self.metadata.get_region(region_id).await?.is_none() {
+            return Ok(None);
+        }
+
+        let mut children = self.metadata.get_children(region_id).await?;
+        children.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(Some(children))
     }
 
-    /// Get data files for a region
+    /// Get data files for a region, newest first, linked to their quality reports
     pub async fn get_region_files(&self, region_id: &str) -> Result<Vec<DataFile>> {
-        let region_path = self.get_region_path(region_id);
+        let mut files = self.scan_region_files(region_id).await?;
+
+        // Link each file to its newest quality report
+        if !files.is_empty() {
+            let reports = self.list_quality_reports(region_id).await?;
+            for file in &mut files {
+                file.quality_report_id = reports
+                    .iter()
+                    .find(|report| report.data_file_id == file.id)
+                    .map(|report| report.id.clone());
+            }
+        }
+
+        Ok(files)
+    }
+
+    /// Data files found in a region's directory, newest first, without loading reports
+    pub async fn scan_region_files(&self, region_id: &str) -> Result<Vec<DataFile>> {
+        let region_path = self.get_region_path(region_id).await?;
 
         if !region_path.exists() {
             return Ok(Vec::new());
@@ -404,44 +868,65 @@ impl Storage {
 
         let mut files = Vec::new();
 
+        // Only the region's own directory; child regions live in subdirectories
         for entry in WalkDir::new(&region_path)
+            .max_depth(1)
             .into_iter()
             .filter_map(|e| e.ok())
         {
             let path = entry.path();
 
-            if path.extension().and_then(|s| s.to_str()) == Some("pbf") {
-                if let Some(filename) = path.file_name().and_then(|s| s.to_str()) {
-                    if filename == "latest.osm.pbf" {
-                        continue; // Skip symlinks
-                    }
+            let Some(format) = crate::osm::utils::detect_format(path) else {
+                continue;
+            };
 
-                    let metadata = std::fs::metadata(path)?;
-                    let version = crate::osm::utils::extract_timestamp_from_filename(filename)
-                        .unwrap_or_else(|| "unknown".to_string());
-
-                    files.push(DataFile {
-                        id: format!("{}_{}", region_id, version),
-                        region_id: region_id.to_string(),
-                        version: version.clone(),
-                        file_path: path.to_string_lossy().to_string(),
-                        file_size: metadata.len(),
-                        format: DataFormat::OsmPbf,
-                        created_at: metadata
+            if let Some(filename) = path.file_name().and_then(|s| s.to_str()) {
+                // A symlink (Unix) or hard link/copy (Windows) of a versioned
+                // file listed on its own; a dangling link has no data at all
+                if filename == "latest.osm.pbf" {
+                    continue;
+                }
+
+                let metadata = std::fs::metadata(path)?;
+
+                // Prefer the data timestamp from the PBF header over the
+                // download date in the filename
+                let (data_timestamp, data_bbox) = match format {
+                    DataFormat::OsmPbf => (
+                        OsmProcessor::read_replication_timestamp(path),
+                        OsmProcessor::read_header_bbox(path),
+                    ),
+                    _ => (None, None),
+                };
+                let version = data_timestamp
+                    .map(|ts| ts.format("%Y-%m-%d").to_string())
+                    .or_else(|| crate::osm::utils::extract_timestamp_from_filename(filename))
+                    .unwrap_or_else(|| "unknown".to_string());
+
+                files.push(DataFile {
+                    id: format!("{}_{}", region_id, version),
+                    region_id: region_id.to_string(),
+                    version: version.clone(),
+                    file_path: path.to_string_lossy().to_string(),
+                    file_size: metadata.len(),
+                    format,
+                    created_at: data_timestamp.unwrap_or_else(|| {
+                        metadata
                             .created()
                             .map(|t| t.into())
-                            .unwrap_or_else(|_| Utc::now()),
-                        is_latest: false,
-                        quality_report_id: None,
-                        download_url: format!("/api/download/{}/{}", region_id, version),
-                        checksum: None,
-                    });
-                }
+                            .unwrap_or_else(|_| Utc::now())
+                    }),
+                    is_latest: false,
+                    quality_report_id: None,
+                    download_url: format!("/api/download/{}/{}", region_id, version),
+                    checksum: crate::osm::utils::read_checksum(path),
+                    data_bbox,
+                });
             }
         }
 
         // Sort by creation date and mark the latest
-        files.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+        files.sort_by_key(|file| std::cmp::Reverse(file.created_at));
         if let Some(latest) = files.first_mut() {
             latest.is_latest = true;
         }
@@ -449,26 +934,102 @@ impl Storage {
         Ok(files)
     }
 
-    /// Get the filesystem path for a region
-    fn get_region_path(&self, region_id: &str) -> PathBuf {
-        // Create path based on region hierarchy
-        if region_id.contains('-') {
-            let parts: Vec<&str> = region_id.split('-').collect();
-            if parts.len() == 2 {
-                // Handle cases like "germany-bayern"
-                self.data_dir.join(&parts[0]).join(&parts[1])
-            } else {
-                self.data_dir.join(region_id)
-            }
-        } else {
-            // Single level regions
-            match region_id {
-                "liechtenstein" => self.data_dir.join("europe").join("liechtenstein"),
-                "germany" => self.data_dir.join("europe").join("germany"),
-                "france" => self.data_dir.join("europe").join("france"),
-                _ => self.data_dir.join(region_id),
+    /// Cache path for a bounding box extract of a region's data file
+    ///
+    /// The key covers the source file's real path and modification time, so a
+    /// new download of the region yields a fresh extract.
+    pub fn bbox_extract_path(
+        &self,
+        region_id: &str,
+        source: &Path,
+        bbox: &BoundingBox,
+    ) -> Result<PathBuf> {
+        let params = format!(
+            "{:.7},{:.7},{:.7},{:.7}",
+            bbox.min_lon, bbox.min_lat, bbox.max_lon, bbox.max_lat
+        );
+        self.derived_cache_path("bbox", region_id, source, &params, "osm.pbf")
+    }
+
+    /// Cache path for the node density grid of a region's data file, keyed
+    /// like [`Self::bbox_extract_path`]
+    pub fn density_grid_path(
+        &self,
+        region_id: &str,
+        source: &Path,
+        cell_deg: f64,
+    ) -> Result<PathBuf> {
+        let params = format!("{:.7}", cell_deg);
+        self.derived_cache_path("density", region_id, source, &params, "json")
+    }
+
+    /// Path under `data_dir/cache/<kind>` for a file derived from `source`
+    /// with `params`, changing whenever the source file is replaced
+    fn derived_cache_path(
+        &self,
+        kind: &str,
+        region_id: &str,
+        source: &Path,
+        params: &str,
+        extension: &str,
+    ) -> Result<PathBuf> {
+        let source = std::fs::canonicalize(source)?;
+        let modified = std::fs::metadata(&source)?
+            .modified()
+            .ok()
+            .and_then(|modified| modified.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0);
+
+        let key = format!("{}|{}|{}|{}", region_id, source.display(), modified, params);
+        let filename = format!(
+            "{}-{:x}.{}",
+            region_id.replace('/', "_"),
+            md5::compute(key),
+            extension
+        );
+
+        Ok(self.data_dir.join("cache").join(kind).join(filename))
+    }
+
+    /// Get the filesystem path of a region's directory under the configured layout
+    pub async fn get_region_path(&self, region_id: &str) -> Result<PathBuf> {
+        match self.layout {
+            LayoutStrategy::FlatById => Ok(self.data_dir.join(region_id.replace('/', "_"))),
+            LayoutStrategy::GeofabrikHierarchy => self.geofabrik_region_path(region_id).await,
+        }
+    }
+
+    /// Path nested under the region's ancestors the way Geofabrik organizes
+    /// downloads (e.g. `europe/germany/bayern`)
+    async fn geofabrik_region_path(&self, region_id: &str) -> Result<PathBuf> {
+        let mut chain = vec![region_id.to_string()];
+        let mut parent = self
+            .metadata
+            .get_region(region_id)
+            .await?
+            .and_then(|region| region.parent_id);
+
+        while let Some(parent_id) = parent {
+            if chain.contains(&parent_id) {
+                warn!("Cycle in region hierarchy at {}", parent_id);
+                break;
             }
+            parent = self
+                .metadata
+                .get_region(&parent_id)
+                .await?
+                .and_then(|region| region.parent_id);
+            chain.push(parent_id);
         }
+
+        // Some Geofabrik IDs already carry their parent (e.g. "us/california"),
+        // so only the last segment of each ID names its directory
+        let path = chain.iter().rev().fold(self.data_dir.clone(), |path, id| {
+            path.join(id.rsplit('/').next().unwrap_or(id))
+        });
+
+        Ok(path)
     }
 
     /// Compare two versions of region data
@@ -478,32 +1039,54 @@ impl Storage {
         from_version: &str,
         to_version: &str,
     ) -> Result<RegionComparison> {
-        // This is a placeholder implementation
-        // In a real system, you'd load and compare the actual quality metrics
-
-        let metrics_diff = QualityMetricsDiff {
-            nodes_diff: 42, // Example values
-            ways_diff: -3,
-            relations_diff: 1,
-            completeness_diff: 0.5,
-            errors_diff: -2,
-            feature_changes: std::collections::HashMap::new(),
-        };
-
-        let change_details = vec![ChangeDetail {
-            change_type: "highway".to_string(),
-            description: "New residential roads added".to_string(),
-            impact: "Improved navigation coverage".to_string(),
-            location: Some((47.16, 9.52)),
-        }];
+        let mut reports = Vec::with_capacity(2);
+        for version in [from_version, to_version] {
+            let report = self
+                .get_version_report(region_id, version)
+                .await?
+                .ok_or_else(|| {
+                    crate::RiemapError::Storage(format!(
+                        "No quality report for {} version '{}'; process it first",
+                        region_id, version
+                    ))
+                })?;
+            reports.push(report);
+        }
 
+        let (from_metrics, to_metrics) = (&reports[0].metrics, &reports[1].metrics);
+        let metrics_diff = crate::quality::utils::compare_metrics(from_metrics, to_metrics);
+        let feature_breakdown = crate::quality::utils::feature_breakdown(from_metrics, to_metrics);
+
+        let mut feature_changes: Vec<(&String, &i64)> = metrics_diff
+            .feature_changes
+            .iter()
+            .filter(|(_, change)| **change != 0)
+            .collect();
+        feature_changes.sort();
+        let change_details = feature_changes
+            .into_iter()
+            .map(|(category, change)| ChangeDetail {
+                change_type: category.clone(),
+                description: format!("{:+} {}", change, category.replace('_', " ")),
+                impact: if *change > 0 {
+                    "Coverage increased".to_string()
+                } else {
+                    "Coverage decreased".to_string()
+                },
+                location: None,
+            })
+            .collect();
+
+        let change = |diff: Option<i64>| {
+            diff.map_or_else(|| "skipped".to_string(), |diff| format!("{:+}", diff))
+        };
         let summary = format!(
-            "Comparison between {} and {}: +{} nodes, {} ways, +{} relations",
+            "Comparison between {} and {}: {} nodes, {} ways, {} relations",
             from_version,
             to_version,
-            metrics_diff.nodes_diff,
-            metrics_diff.ways_diff,
-            metrics_diff.relations_diff
+            change(metrics_diff.nodes_diff),
+            change(metrics_diff.ways_diff),
+            change(metrics_diff.relations_diff)
         );
 
         Ok(RegionComparison {
@@ -514,50 +1097,297 @@ impl Storage {
             metrics_diff,
             summary,
             change_details,
+            feature_breakdown,
         })
     }
 
     /// Save processing job
     pub async fn save_processing_job(&self, job: &ProcessingJob) -> Result<()> {
-        let jobs_dir = self.data_dir.join("jobs");
-        std::fs::create_dir_all(&jobs_dir)?;
+        self.metadata.save_job(job).await
+    }
 
-        let job_file = jobs_dir.join(format!("{}.json", job.id));
-        let json = serde_json::to_string_pretty(job)?;
-        tokio::fs::write(job_file, json).await?;
+    /// Get processing job
+    pub async fn get_processing_job(&self, job_id: &str) -> Result<Option<ProcessingJob>> {
+        self.metadata.get_job(job_id).await
+    }
 
-        Ok(())
+    /// Path of a job's log, `data_dir/jobs/{id}.log`, whichever metadata backend is used
+    pub fn job_log_path(&self, job_id: &str) -> PathBuf {
+        self.data_dir
+            .join("jobs")
+            .join(format!("{}.log", job_id.replace('/', "_")))
+    }
+
+    /// List stored jobs newest first, optionally only those with the given status
+    pub async fn list_jobs(&self, filter: Option<JobStatus>) -> Result<Vec<ProcessingJob>> {
+        let mut jobs = self.metadata.list_jobs().await?;
+        if let Some(status) = filter {
+            jobs.retain(|job| job.status == status);
+        }
+        Ok(jobs)
+    }
+
+    /// The most recently created job of a region
+    pub async fn latest_job(&self, region_id: &str) -> Result<Option<ProcessingJob>> {
+        self.metadata.latest_job(region_id).await
     }
 
     /// Get quality report
     pub async fn get_quality_report(&self, report_id: &str) -> Result<Option<QualityReport>> {
-        let reports_dir = self.data_dir.join("reports");
-        let report_file = reports_dir.join(format!("{}.json", report_id));
+        self.metadata.get_report(report_id).await
+    }
+
+    /// List all quality reports for a region, newest first
+    pub async fn list_quality_reports(&self, region_id: &str) -> Result<Vec<QualityReport>> {
+        self.metadata.list_reports(region_id).await
+    }
 
-        if !report_file.exists() {
+    /// Quality history of a region: one point per stored report, oldest first
+    pub async fn get_quality_history(&self, region_id: &str) -> Result<Vec<QualityHistoryPoint>> {
+        let mut reports = self.list_quality_reports(region_id).await?;
+        reports.reverse();
+
+        Ok(reports
+            .iter()
+            .map(QualityHistoryPoint::from_report)
+            .collect())
+    }
+
+    /// Get the newest quality report for a region's latest data file
+    pub async fn get_latest_quality_report(
+        &self,
+        region_id: &str,
+    ) -> Result<Option<QualityReport>> {
+        let files = self.get_region_files(region_id).await?;
+        let Some(report_id) = files
+            .iter()
+            .find(|file| file.is_latest)
+            .and_then(|file| file.quality_report_id.as_deref())
+        else {
             return Ok(None);
-        }
+        };
+
+        self.get_quality_report(report_id).await
+    }
+
+    /// Find the data file record for a path in a region's directory
+    pub async fn find_data_file(&self, region_id: &str, path: &Path) -> Result<Option<DataFile>> {
+        // Resolve the `latest` symlink to the versioned file it points at
+        let path = std::fs::canonicalize(path)?;
+
+        Ok(self
+            .get_region_files(region_id)
+            .await?
+            .into_iter()
+            .find(|file| {
+                std::fs::canonicalize(&file.file_path).is_ok_and(|file_path| file_path == path)
+            }))
+    }
+
+    /// Quality report generated for a specific version of a region's data, if any
+    pub async fn get_version_report(
+        &self,
+        region_id: &str,
+        version: &str,
+    ) -> Result<Option<QualityReport>> {
+        let Some(path) = self.get_file_path(region_id, version).await? else {
+            return Ok(None);
+        };
+        let Some(file) = self.find_data_file(region_id, &path).await? else {
+            return Ok(None);
+        };
 
-        let contents = tokio::fs::read_to_string(report_file).await?;
-        let report: QualityReport = serde_json::from_str(&contents)?;
-        Ok(Some(report))
+        match file.quality_report_id {
+            Some(report_id) => self.get_quality_report(&report_id).await,
+            None => Ok(None),
+        }
     }
 
     /// Save quality report
     pub async fn save_quality_report(&self, report: &QualityReport) -> Result<()> {
+        self.metadata.save_report(report).await
+    }
+
+    /// Save an alternative rendering (e.g. HTML or CSV) next to a report's JSON
+    pub async fn save_quality_report_export(
+        &self,
+        report: &QualityReport,
+        extension: &str,
+        contents: &str,
+    ) -> Result<PathBuf> {
         let reports_dir = self.data_dir.join("reports");
         std::fs::create_dir_all(&reports_dir)?;
 
-        let report_file = reports_dir.join(format!("{}.json", report.id));
-        let json = serde_json::to_string_pretty(report)?;
-        tokio::fs::write(report_file, json).await?;
+        let export_file = reports_dir.join(format!("{}.{}", report.id, extension));
+        tokio::fs::write(&export_file, contents).await?;
+
+        Ok(export_file)
+    }
+
+    /// Record one download of a region file version under data_dir/stats
+    pub async fn increment_download_count(&self, region_id: &str, version: &str) -> Result<()> {
+        // Serialize read-modify-write cycles so concurrent downloads aren't lost
+        let _guard = self.stats_lock.lock().await;
+
+        let mut counts = self.load_download_counts().await?;
+        *counts
+            .entry(region_id.to_string())
+            .or_default()
+            .entry(version.to_string())
+            .or_insert(0) += 1;
+
+        let stats_file = self.download_stats_file();
+        if let Some(parent) = stats_file.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        // Write to a temporary file first so a crash can't truncate the counters
+        let tmp_file = stats_file.with_extension("json.tmp");
+        tokio::fs::write(&tmp_file, serde_json::to_string_pretty(&counts)?).await?;
+        tokio::fs::rename(&tmp_file, &stats_file).await?;
 
         Ok(())
     }
 
+    /// Load the per-version download counters for all regions
+    pub async fn load_download_counts(&self) -> Result<DownloadCounts> {
+        let stats_file = self.download_stats_file();
+
+        if !stats_file.exists() {
+            return Ok(HashMap::new());
+        }
+
+        let content = tokio::fs::read_to_string(&stats_file).await?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    /// Load the accumulated download total for each region across all versions
+    pub async fn load_download_totals(&self) -> Result<HashMap<String, u64>> {
+        Ok(self
+            .load_download_counts()
+            .await?
+            .into_iter()
+            .map(|(region_id, versions)| (region_id, versions.values().sum()))
+            .collect())
+    }
+
+    fn download_stats_file(&self) -> PathBuf {
+        self.data_dir.join("stats").join("downloads.json")
+    }
+
+    /// Delete all but the newest `keep` versions of a region's data files
+    ///
+    /// The scheduler picks `keep` by the region's admin level, see
+    /// `ProcessingConfig::keep_versions_for`.
+    pub async fn prune_old_versions(&self, region_id: &str, keep: usize) -> Result<Vec<PathBuf>> {
+        let files = self.get_region_files(region_id).await?;
+        let region_path = self.get_region_path(region_id).await?;
+
+        // Never delete whatever `latest` currently points at
+        let latest_path = region_path.join("latest.osm.pbf");
+
+        let mut removed = Vec::new();
+        for file in files.iter().skip(keep) {
+            let path = PathBuf::from(&file.file_path);
+            if crate::osm::utils::same_data_file(&latest_path, &path) {
+                continue;
+            }
+
+            tokio::fs::remove_file(&path).await?;
+            for sidecar in [
+                crate::osm::utils::checksum_sidecar_path(&path),
+                crate::osm::utils::sha256_sidecar_path(&path),
+                crate::osm::utils::validators_sidecar_path(&path),
+            ] {
+                if sidecar.exists() {
+                    tokio::fs::remove_file(&sidecar).await?;
+                }
+            }
+
+            info!("Pruned old version {:?} of region {}", path, region_id);
+            removed.push(path);
+        }
+
+        if !removed.is_empty() {
+            self.touch_region(region_id).await?;
+        }
+        Ok(removed)
+    }
+
+    /// Delete content-addressed blobs no region file links to any more,
+    /// returning how many blobs and bytes were removed
+    ///
+    /// A blob's only remaining link is its own entry in `data_dir/blobs`.
+    /// Link counts are only available on Unix; elsewhere nothing is removed.
+    pub async fn remove_unused_blobs(&self) -> Result<(usize, u64)> {
+        let blobs_dir = self.data_dir.join("blobs");
+        let mut entries = match tokio::fs::read_dir(&blobs_dir).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok((0, 0)),
+            Err(e) => return Err(e.into()),
+        };
+
+        let (mut removed, mut freed) = (0, 0);
+        while let Some(entry) = entries.next_entry().await? {
+            let metadata = entry.metadata().await?;
+
+            #[cfg(unix)]
+            let unused = {
+                use std::os::unix::fs::MetadataExt;
+                metadata.is_file() && metadata.nlink() == 1
+            };
+            #[cfg(not(unix))]
+            let unused = false;
+
+            if unused {
+                tokio::fs::remove_file(entry.path()).await?;
+                removed += 1;
+                freed += metadata.len();
+            }
+        }
+
+        if removed > 0 {
+            info!("Removed {} unused blobs ({} bytes)", removed, freed);
+        }
+        Ok((removed, freed))
+    }
+
+    /// Re-point a region's `latest.osm.pbf` at its newest versioned file
+    ///
+    /// Fixes links left dangling by pruning and stale copies, and recreates a
+    /// missing `latest`. When no versioned file is left, a leftover `latest` is
+    /// removed. Returns the file `latest` now points at.
+    pub async fn repair_latest(&self, region_id: &str) -> Result<Option<PathBuf>> {
+        let region_path = self.get_region_path(region_id).await?;
+        let latest_path = region_path.join("latest.osm.pbf");
+
+        // Files are sorted newest first
+        let Some(newest) = self
+            .get_region_files(region_id)
+            .await?
+            .into_iter()
+            .find(|file| matches!(file.format, DataFormat::OsmPbf))
+            .map(|file| PathBuf::from(file.file_path))
+        else {
+            if latest_path.symlink_metadata().is_ok() {
+                tokio::fs::remove_file(&latest_path).await?;
+                info!("Removed latest link of {}: no versions left", region_id);
+            }
+            return Ok(None);
+        };
+
+        if crate::osm::utils::same_data_file(&latest_path, &newest) {
+            return Ok(Some(newest));
+        }
+
+        crate::osm::utils::point_latest_to(&region_path, &newest)?;
+        info!("Pointed latest of {} at {:?}", region_id, newest);
+        Ok(Some(newest))
+    }
+
     /// Get file path for download
     pub async fn get_file_path(&self, region_id: &str, version: &str) -> Result<Option<PathBuf>> {
-        let region_path = self.get_region_path(region_id);
+        let region_path = self.get_region_path(region_id).await?;
 
         if version == "latest" {
             let latest_path = region_path.join("latest.osm.pbf");
@@ -566,21 +1396,21 @@ impl Storage {
             }
         }
 
-        // Look for specific version
-        for entry in WalkDir::new(&region_path)
+        // Look for specific version, matching either the data version or the filename
+        let files = self.get_region_files(region_id).await?;
+        let path = files
             .into_iter()
-            .filter_map(|e| e.ok())
-        {
-            let path = entry.path();
-
-            if let Some(filename) = path.file_name().and_then(|s| s.to_str()) {
-                if filename.contains(version) && filename.ends_with(".osm.pbf") {
-                    return Ok(Some(path.to_path_buf()));
-                }
-            }
-        }
-
-        Ok(None)
+            .map(|file| (file.version, PathBuf::from(file.file_path)))
+            .find(|(file_version, path)| {
+                file_version == version
+                    || path
+                        .file_name()
+                        .and_then(|s| s.to_str())
+                        .is_some_and(|filename| filename.contains(version))
+            })
+            .map(|(_, path)| path);
+
+        Ok(path)
     }
 
     /// Extract bounding box from GeoJSON geometry
@@ -591,7 +1421,7 @@ impl Storage {
         match geometry.get("type")?.as_str()? {
             "Polygon" => {
                 let coordinates = geometry.get("coordinates")?.as_array()?;
-                if let Some(outer_ring) = coordinates.get(0)?.as_array() {
+                if let Some(outer_ring) = coordinates.first()?.as_array() {
                     self.calculate_bbox_from_coordinates(outer_ring)
                 } else {
                     None
@@ -603,7 +1433,7 @@ impl Storage {
 
                 for polygon in coordinates {
                     if let Some(rings) = polygon.as_array() {
-                        if let Some(outer_ring) = rings.get(0)?.as_array() {
+                        if let Some(outer_ring) = rings.first()?.as_array() {
                             all_coords.extend(outer_ring.iter().cloned());
                         }
                     }
@@ -617,12 +1447,7 @@ impl Storage {
                     let lon = coordinates[0].as_f64()?;
                     let lat = coordinates[1].as_f64()?;
                     // For points, create a small bounding box
-                    Some(BoundingBox::new(
-                        lat - 0.001,
-                        lon - 0.001,
-                        lat + 0.001,
-                        lon + 0.001,
-                    ))
+                    self.checked_bounding_box(lat - 0.001, lon - 0.001, lat + 0.001, lon + 0.001)
                 } else {
                     None
                 }
@@ -656,14 +1481,167 @@ impl Storage {
             }
         }
 
+        // A span over 180° may really be a narrow region crossing the antimeridian;
+        // measure again with longitudes shifted to 0..360 and keep the tighter box
+        if max_lon - min_lon > 180.0 {
+            let shifted = coordinates
+                .iter()
+                .filter_map(|coord| coord.as_array()?.first()?.as_f64())
+                .map(|lon| if lon < 0.0 { lon + 360.0 } else { lon });
+            let (shifted_min, shifted_max) = shifted
+                .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), lon| {
+                    (lo.min(lon), hi.max(lon))
+                });
+
+            if shifted_max - shifted_min < max_lon - min_lon {
+                let normalize = |lon: f64| if lon > 180.0 { lon - 360.0 } else { lon };
+                min_lon = normalize(shifted_min);
+                max_lon = normalize(shifted_max);
+            }
+        }
+
         if min_lat != f64::INFINITY
             && max_lat != f64::NEG_INFINITY
             && min_lon != f64::INFINITY
             && max_lon != f64::NEG_INFINITY
         {
-            Some(BoundingBox::new(min_lat, min_lon, max_lat, max_lon))
+            self.checked_bounding_box(min_lat, min_lon, max_lat, max_lon)
         } else {
             None
         }
     }
+
+    /// Validate a bounding box parsed from Geofabrik geometry, logging rejects
+    fn checked_bounding_box(
+        &self,
+        min_lat: f64,
+        min_lon: f64,
+        max_lat: f64,
+        max_lon: f64,
+    ) -> Option<BoundingBox> {
+        BoundingBox::try_new(min_lat, min_lon, max_lat, max_lon)
+            .map_err(|e| warn!("Ignoring malformed geometry: {}", e))
+            .ok()
+    }
+}
+
+/// Whether a region differs from its stored version in more than its timestamps
+fn region_changed(old: &Region, new: &Region) -> bool {
+    let without_timestamps = |region: &Region| {
+        let mut value = serde_json::to_value(region).unwrap_or_default();
+        if let Some(object) = value.as_object_mut() {
+            object.remove("created_at");
+            object.remove("updated_at");
+        }
+        value
+    };
+    without_timestamps(old) != without_timestamps(new)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn regions(names: &str) -> Vec<Region> {
+        (0..20)
+            .map(|i| {
+                Region::new(
+                    format!("region-{}", i),
+                    format!("{} {}", names, i),
+                    AdminLevel::Country,
+                    BoundingBox::new(i as f64, 0.0, i as f64 + 1.0, 1.0),
+                )
+            })
+            .collect()
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn concurrent_region_writes_keep_metadata_complete() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = Storage::new(dir.path()).unwrap();
+        storage.save_regions(&regions("Initial")).await.unwrap();
+
+        let mut tasks = Vec::new();
+        for round in 0..25 {
+            let saver = storage.clone();
+            tasks.push(tokio::spawn(async move {
+                saver
+                    .save_regions(&regions(&format!("Round {}", round)))
+                    .await
+            }));
+            let storage = storage.clone();
+            tasks.push(tokio::spawn(async move {
+                storage
+                    .touch_region(&format!("region-{}", round % 20))
+                    .await
+            }));
+        }
+        for task in tasks {
+            task.await.unwrap().unwrap();
+        }
+
+        let contents = std::fs::read_to_string(dir.path().join("metadata.json")).unwrap();
+        let stored: Vec<Region> = serde_json::from_str(&contents).unwrap();
+        let mut ids: Vec<String> = stored.into_iter().map(|region| region.id).collect();
+        ids.sort();
+        let mut expected: Vec<String> = (0..20).map(|i| format!("region-{}", i)).collect();
+        expected.sort();
+        assert_eq!(ids, expected);
+
+        assert_eq!(storage.load_regions().await.unwrap().len(), 20);
+        // No temp files left behind by the atomic writes
+        let leftovers = std::fs::read_dir(dir.path())
+            .unwrap()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_name().to_string_lossy().ends_with(".tmp"))
+            .count();
+        assert_eq!(leftovers, 0);
+    }
+
+    #[test]
+    fn derived_cache_paths_follow_the_source_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = Storage::new(dir.path()).unwrap();
+        let source = dir.path().join("latest.osm.pbf");
+        std::fs::write(&source, b"data").unwrap();
+
+        let grid = storage
+            .density_grid_path("europe/malta", &source, 0.05)
+            .unwrap();
+        assert!(grid.starts_with(dir.path().join("cache").join("density")));
+        assert!(grid.to_string_lossy().ends_with(".json"));
+        assert_eq!(
+            grid,
+            storage
+                .density_grid_path("europe/malta", &source, 0.05)
+                .unwrap()
+        );
+        assert_ne!(
+            grid,
+            storage
+                .density_grid_path("europe/malta", &source, 0.1)
+                .unwrap()
+        );
+
+        let extract = storage
+            .bbox_extract_path(
+                "europe/malta",
+                &source,
+                &BoundingBox::new(35.8, 14.3, 36.0, 14.6),
+            )
+            .unwrap();
+        assert!(extract.starts_with(dir.path().join("cache").join("bbox")));
+
+        let file = std::fs::File::options().write(true).open(&source).unwrap();
+        file.set_modified(
+            std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(86_400),
+        )
+        .unwrap();
+        assert_ne!(
+            grid,
+            storage
+                .density_grid_path("europe/malta", &source, 0.05)
+                .unwrap()
+        );
+    }
 }
@@ -0,0 +1,103 @@
+use crate::Result;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tracing::{info, warn};
+
+/// Default age after which files left in the temp directory are swept
+pub const DEFAULT_TEMP_MAX_AGE_HOURS: u64 = 24;
+
+/// An intermediate file deleted when the guard is dropped
+///
+/// Names are prefixed with a UUID, so operations running at the same time
+/// never write to the same file. Whether the operation finishes or fails
+/// half way, the file is removed unless `persist` moved it to its final place.
+#[derive(Debug)]
+pub struct TempFile {
+    path: PathBuf,
+    persisted: bool,
+}
+
+impl TempFile {
+    /// Reserve a unique `{uuid}-{name}` path in `dir`; the file itself is
+    /// created by whoever writes to `path()`
+    pub fn new(dir: &Path, name: &str) -> Self {
+        Self {
+            path: dir.join(format!("{}-{}", uuid::Uuid::new_v4(), name)),
+            persisted: false,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Move the file to `destination`, copying when a rename is not possible
+    /// (e.g. across filesystems)
+    pub fn persist(mut self, destination: &Path) -> Result<()> {
+        if let Err(e) = std::fs::rename(&self.path, destination) {
+            std::fs::copy(&self.path, destination).map_err(|_| e)?;
+            // The copy is in place; the guard still removes the original
+            return Ok(());
+        }
+        self.persisted = true;
+        Ok(())
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        if self.persisted {
+            return;
+        }
+        if let Err(e) = std::fs::remove_file(&self.path) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                warn!("Failed to remove temp file {:?}: {}", self.path, e);
+            }
+        }
+    }
+}
+
+/// Delete files in `dir` not modified for `max_age`, left behind by crashed
+/// runs, returning how many files and bytes were removed
+///
+/// Only files directly in `dir` are considered; recent ones may belong to an
+/// operation still running in another process and are kept.
+pub fn sweep_stale(dir: &Path, max_age: Duration) -> Result<(usize, u64)> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok((0, 0)),
+        Err(e) => return Err(e.into()),
+    };
+
+    let now = SystemTime::now();
+    let (mut removed, mut freed) = (0, 0);
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        let stale = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| now.duration_since(modified).ok())
+            .is_some_and(|age| age > max_age);
+        if !metadata.is_file() || !stale {
+            continue;
+        }
+
+        match std::fs::remove_file(entry.path()) {
+            Ok(()) => {
+                removed += 1;
+                freed += metadata.len();
+            }
+            Err(e) => warn!("Failed to remove stale temp file {:?}: {}", entry.path(), e),
+        }
+    }
+
+    if removed > 0 {
+        info!(
+            "Removed {} stale temp files ({} bytes) from {:?}",
+            removed, freed, dir
+        );
+    }
+    Ok((removed, freed))
+}
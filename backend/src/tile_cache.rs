@@ -0,0 +1,199 @@
+use crate::Result;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+use walkdir::WalkDir;
+
+/// Default cap on the bytes kept in the tile cache
+pub const DEFAULT_TILE_CACHE_MAX_BYTES: u64 = 512 * 1_048_576;
+
+/// Zoom, column and row of a tile
+pub type TileId = (u8, u32, u32);
+
+/// Tiles held on disk, with their recency for eviction
+#[derive(Default)]
+struct TileIndex {
+    /// Tile -> (last use, size in bytes)
+    tiles: HashMap<TileId, (u64, u64)>,
+    /// Last use -> tile, oldest first
+    by_use: BTreeMap<u64, TileId>,
+    clock: u64,
+    total_bytes: u64,
+}
+
+impl TileIndex {
+    /// Mark a tile as just used
+    fn touch(&mut self, tile: TileId, size: u64) {
+        self.clock += 1;
+        if let Some((last_used, old_size)) = self.tiles.insert(tile, (self.clock, size)) {
+            self.by_use.remove(&last_used);
+            self.total_bytes -= old_size;
+        }
+        self.by_use.insert(self.clock, tile);
+        self.total_bytes += size;
+    }
+
+    fn remove(&mut self, tile: TileId) {
+        if let Some((last_used, size)) = self.tiles.remove(&tile) {
+            self.by_use.remove(&last_used);
+            self.total_bytes -= size;
+        }
+    }
+
+    /// Take the least recently used tile out of the index
+    fn pop_oldest(&mut self) -> Option<TileId> {
+        let (_, tile) = self.by_use.pop_first()?;
+        if let Some((_, size)) = self.tiles.remove(&tile) {
+            self.total_bytes -= size;
+        }
+        Some(tile)
+    }
+}
+
+/// Rendered vector tiles stored as `{dir}/{z}/{x}/{y}.mvt`
+///
+/// Tiles survive restarts: the directory is scanned on first use, with file
+/// modification times standing in for the last use. Once the cached tiles
+/// exceed `max_bytes`, the least recently served ones are deleted.
+#[derive(Clone)]
+pub struct TileCache {
+    dir: PathBuf,
+    max_bytes: u64,
+    /// Loaded from disk on first use
+    index: Arc<Mutex<Option<TileIndex>>>,
+}
+
+impl TileCache {
+    /// Cache tiles under `dir`, keeping at most `max_bytes`; 0 disables caching
+    pub fn new<P: AsRef<Path>>(dir: P, max_bytes: u64) -> Self {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+            max_bytes,
+            index: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    fn tile_path(&self, (z, x, y): TileId) -> PathBuf {
+        self.dir
+            .join(z.to_string())
+            .join(x.to_string())
+            .join(format!("{}.mvt", y))
+    }
+
+    /// Index the tiles already on disk, oldest modification first
+    fn scan(&self) -> TileIndex {
+        let mut found = Vec::new();
+        for entry in WalkDir::new(&self.dir)
+            .min_depth(3)
+            .max_depth(3)
+            .into_iter()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_type().is_file())
+        {
+            let Ok(relative) = entry.path().strip_prefix(&self.dir) else {
+                continue;
+            };
+            let mut parts = relative.iter().filter_map(|part| part.to_str());
+            let tile = match (parts.next(), parts.next(), parts.next()) {
+                (Some(z), Some(x), Some(file)) => file
+                    .strip_suffix(".mvt")
+                    .and_then(|y| Some((z.parse().ok()?, x.parse().ok()?, y.parse().ok()?))),
+                _ => None,
+            };
+            let (Some(tile), Ok(metadata)) = (tile, entry.metadata()) else {
+                continue;
+            };
+            found.push((metadata.modified().ok(), tile, metadata.len()));
+        }
+
+        found.sort_by_key(|(modified, _, _)| *modified);
+        let mut index = TileIndex::default();
+        for (_, tile, size) in found {
+            index.touch(tile, size);
+        }
+        if !index.tiles.is_empty() {
+            info!(
+                "Tile cache holds {} tiles ({} bytes)",
+                index.tiles.len(),
+                index.total_bytes
+            );
+        }
+        index
+    }
+
+    /// A cached tile, if one was stored
+    pub async fn get(&self, tile: TileId) -> Result<Option<Vec<u8>>> {
+        {
+            let mut guard = self.index.lock().await;
+            let index = guard.get_or_insert_with(|| self.scan());
+            match index.tiles.get(&tile) {
+                Some(&(_, size)) => index.touch(tile, size),
+                None => return Ok(None),
+            }
+        }
+
+        match tokio::fs::read(self.tile_path(tile)).await {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                // Deleted behind our back; render it again
+                if let Some(index) = self.index.lock().await.as_mut() {
+                    index.remove(tile);
+                }
+                Ok(None)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Store a rendered tile, evicting the least recently used tiles over the cap
+    pub async fn put(&self, tile: TileId, data: &[u8]) -> Result<()> {
+        let size = data.len() as u64;
+        if size > self.max_bytes {
+            return Ok(());
+        }
+
+        // Written under the lock so a concurrent `clear` cannot leave it behind
+        let mut guard = self.index.lock().await;
+        let index = guard.get_or_insert_with(|| self.scan());
+
+        let path = self.tile_path(tile);
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let temp_path = path.with_extension(format!("mvt.{}.tmp", uuid::Uuid::new_v4()));
+        tokio::fs::write(&temp_path, data).await?;
+        tokio::fs::rename(&temp_path, &path).await?;
+        index.touch(tile, size);
+
+        while index.total_bytes > self.max_bytes {
+            let Some(oldest) = index.pop_oldest() else {
+                break;
+            };
+            if let Err(e) = tokio::fs::remove_file(self.tile_path(oldest)).await {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    warn!("Failed to evict tile {:?}: {}", oldest, e);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Delete every cached tile, returning how many tiles and bytes were removed
+    pub async fn clear(&self) -> Result<(usize, u64)> {
+        let mut guard = self.index.lock().await;
+        let index = guard.get_or_insert_with(|| self.scan());
+        let removed = (index.tiles.len(), index.total_bytes);
+
+        match tokio::fs::remove_dir_all(&self.dir).await {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+        *guard = Some(TileIndex::default());
+
+        Ok(removed)
+    }
+}
@@ -36,12 +36,12 @@ export interface DataFile {
 }
 
 export interface QualityMetrics {
-    total_nodes: number;
-    total_ways: number;
-    total_relations: number;
-    tagged_nodes: number;
-    tagged_ways: number;
-    tagged_relations: number;
+    total_nodes: number | null;
+    total_ways: number | null;
+    total_relations: number | null;
+    tagged_nodes: number | null;
+    tagged_ways: number | null;
+    tagged_relations: number | null;
     completeness_score: number;
     geometry_errors: number;
     tag_errors: number;
@@ -91,9 +91,9 @@ export interface RegionComparison {
 }
 
 export interface QualityMetricsDiff {
-    nodes_diff: number;
-    ways_diff: number;
-    relations_diff: number;
+    nodes_diff: number | null;
+    ways_diff: number | null;
+    relations_diff: number | null;
     completeness_diff: number;
     errors_diff: number;
 }